@@ -0,0 +1,77 @@
+// Jackson Coxson
+
+#include "idevice.h"
+#include <arpa/inet.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+// Called on the stream's own thread for every log line
+void on_line(const char *line, void *context) {
+  int *count = (int *)context;
+  if (line == NULL) {
+    printf("Syslog stream ended\n");
+    return;
+  }
+  (*count)++;
+  printf("%s\n", line);
+}
+
+int main() {
+  // Initialize logger
+  idevice_init_logger(Debug, Disabled, NULL);
+
+  // Create the socket address (replace with your device's IP)
+  struct sockaddr_in addr;
+  memset(&addr, 0, sizeof(addr));
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(LOCKDOWN_PORT);
+  inet_pton(AF_INET, "10.7.0.2", &addr.sin_addr);
+
+  // Read pairing file (replace with your pairing file path)
+  IdevicePairingFile *pairing_file = NULL;
+  IdeviceErrorCode err =
+      idevice_pairing_file_read("pairing_file.plist", &pairing_file);
+  if (err != IdeviceSuccess) {
+    fprintf(stderr, "Failed to read pairing file: %d\n", err);
+    return 1;
+  }
+
+  // Create TCP provider
+  TcpProviderHandle *provider = NULL;
+  err = idevice_tcp_provider_new((struct sockaddr *)&addr, pairing_file,
+                                 "ExampleProvider", &provider);
+  if (err != IdeviceSuccess) {
+    fprintf(stderr, "Failed to create TCP provider: %d\n", err);
+    idevice_pairing_file_free(pairing_file);
+    return 1;
+  }
+
+  // Connect to the syslog relay
+  SyslogRelayClientHandle *client = NULL;
+  err = syslog_relay_connect_tcp(provider, &client);
+  if (err != IdeviceSuccess) {
+    fprintf(stderr, "Failed to connect to syslog relay: %d\n", err);
+    tcp_provider_free(provider);
+    return 1;
+  }
+  tcp_provider_free(provider);
+
+  // The stream takes ownership of the client
+  int count = 0;
+  IdeviceEventStreamHandle *stream = NULL;
+  err = syslog_relay_stream_start(client, on_line, &count, &stream);
+  if (err != IdeviceSuccess) {
+    fprintf(stderr, "Failed to start syslog stream: %d\n", err);
+    syslog_relay_client_free(client);
+    return 1;
+  }
+
+  sleep(10);
+
+  // After this returns on_line won't run again, so count is safe to read
+  idevice_event_stream_stop(stream);
+  printf("Received %d lines\n", count);
+  return 0;
+}
@@ -32,7 +32,7 @@ pub unsafe extern "C" fn adapter_connect(
         Ok(_) => IdeviceErrorCode::IdeviceSuccess,
         Err(e) => {
             log::error!("Adapter connect failed: {}", e);
-            IdeviceErrorCode::AdapterIOFailed
+            e.into()
         }
     }
 }
@@ -71,7 +71,7 @@ pub unsafe extern "C" fn adapter_pcap(
         Ok(_) => IdeviceErrorCode::IdeviceSuccess,
         Err(e) => {
             log::error!("Adapter pcap failed: {}", e);
-            IdeviceErrorCode::AdapterIOFailed
+            e.into()
         }
     }
 }
@@ -99,7 +99,7 @@ pub unsafe extern "C" fn adapter_close(handle: *mut AdapterHandle) -> IdeviceErr
         Ok(_) => IdeviceErrorCode::IdeviceSuccess,
         Err(e) => {
             log::error!("Adapter close failed: {}", e);
-            IdeviceErrorCode::AdapterIOFailed
+            e.into()
         }
     }
 }
@@ -136,7 +136,7 @@ pub unsafe extern "C" fn adapter_send(
         Ok(_) => IdeviceErrorCode::IdeviceSuccess,
         Err(e) => {
             log::error!("Adapter send failed: {}", e);
-            IdeviceErrorCode::AdapterIOFailed
+            e.into()
         }
     }
 }
@@ -186,7 +186,7 @@ pub unsafe extern "C" fn adapter_recv(
         }
         Err(e) => {
             log::error!("Adapter recv failed: {}", e);
-            IdeviceErrorCode::AdapterIOFailed
+            e.into()
         }
     }
 }
@@ -1,6 +1,8 @@
 // Jackson Coxson
 
-use idevice::IdeviceError;
+use std::io;
+
+use idevice::{IdeviceError, afc::errors::AfcError, xpc::error::XPCError};
 
 #[repr(C)]
 pub enum IdeviceErrorCode {
@@ -50,6 +52,23 @@ pub enum IdeviceErrorCode {
     UnknownAfcOpcode = -42,
     InvalidAfcMagic = -43,
     AfcMissingAttribute = -44,
+    InvalidService = -45,
+    Afc2Unavailable = -46,
+    PairingDialogResponsePending = -47,
+    UserDeniedPairing = -48,
+    PasswordProtected = -49,
+    CrashReportMoverBadResponse = -50,
+    // Categories of I/O and service errors callers usually want to act on
+    Timeout = -51,
+    PermissionDenied = -52,
+    ConnectionRefused = -53,
+    ConnectionClosed = -54,
+    SetProhibited = -55,
+    AfcFileTooLarge = -56,
+    BatteryInfoUnavailable = -57,
+    ProfileRemovalDisallowed = -58,
+    MobileConfigFailure = -59,
+    InvalidProfile = -60,
     // FFI specific bindings
     AdapterIOFailed = -996,
     ServiceNotFound = -997,
@@ -61,13 +80,15 @@ pub enum IdeviceErrorCode {
 impl From<IdeviceError> for IdeviceErrorCode {
     fn from(err: IdeviceError) -> Self {
         match err {
-            IdeviceError::Socket(_) => IdeviceErrorCode::Socket,
+            IdeviceError::Socket(e) => e.into(),
             IdeviceError::Rustls(_) => IdeviceErrorCode::Tls,
             IdeviceError::TlsBuilderFailed(_) => IdeviceErrorCode::TlsBuilderFailed,
             IdeviceError::Plist(_) => IdeviceErrorCode::Plist,
             IdeviceError::Utf8(_) => IdeviceErrorCode::Utf8,
             IdeviceError::UnexpectedResponse => IdeviceErrorCode::UnexpectedResponse,
             IdeviceError::GetProhibited => IdeviceErrorCode::GetProhibited,
+            IdeviceError::SetProhibited => IdeviceErrorCode::SetProhibited,
+            IdeviceError::BatteryInfoUnavailable => IdeviceErrorCode::BatteryInfoUnavailable,
             IdeviceError::SessionInactive => IdeviceErrorCode::SessionInactive,
             IdeviceError::InvalidHostID => IdeviceErrorCode::InvalidHostID,
             IdeviceError::NoEstablishedConnection => IdeviceErrorCode::NoEstablishedConnection,
@@ -90,6 +111,7 @@ impl From<IdeviceError> for IdeviceErrorCode {
             IdeviceError::ImageNotMounted => IdeviceErrorCode::ImageNotMounted,
             IdeviceError::Reqwest(_) => IdeviceErrorCode::Reqwest,
             IdeviceError::InternalError(_) => IdeviceErrorCode::InternalError,
+            IdeviceError::Xpc(XPCError::Timeout(_)) => IdeviceErrorCode::Timeout,
             IdeviceError::Xpc(_) => IdeviceErrorCode::Xpc,
             IdeviceError::NsKeyedArchiveError(_) => IdeviceErrorCode::NsKeyedArchiveError,
             IdeviceError::UnknownAuxValueType(_) => IdeviceErrorCode::UnknownAuxValueType,
@@ -102,14 +124,48 @@ impl From<IdeviceError> for IdeviceErrorCode {
             IdeviceError::UnknownErrorType(_) => IdeviceErrorCode::UnknownErrorType,
             IdeviceError::PemParseFailed(_) => IdeviceErrorCode::PemParseFailed,
             IdeviceError::MisagentFailure => IdeviceErrorCode::MisagentFailure,
+            IdeviceError::ProfileRemovalDisallowed => IdeviceErrorCode::ProfileRemovalDisallowed,
+            IdeviceError::MobileConfigFailure(_) => IdeviceErrorCode::MobileConfigFailure,
+            IdeviceError::InvalidProfile(_) => IdeviceErrorCode::InvalidProfile,
             IdeviceError::InstallationProxyOperationFailed(_) => {
                 IdeviceErrorCode::InstallationProxyOperationFailed
             }
+            IdeviceError::Afc(AfcError::ObjectNotFound) => IdeviceErrorCode::NotFound,
+            IdeviceError::Afc(AfcError::PermDenied) => IdeviceErrorCode::PermissionDenied,
+            IdeviceError::Afc(AfcError::OpTimeout) => IdeviceErrorCode::Timeout,
             IdeviceError::Afc(_) => IdeviceErrorCode::Afc,
             IdeviceError::UnknownAfcOpcode => IdeviceErrorCode::UnknownAfcOpcode,
             IdeviceError::InvalidAfcMagic => IdeviceErrorCode::InvalidAfcMagic,
             IdeviceError::AfcMissingAttribute => IdeviceErrorCode::AfcMissingAttribute,
+            IdeviceError::InvalidService => IdeviceErrorCode::InvalidService,
+            IdeviceError::Afc2Unavailable => IdeviceErrorCode::Afc2Unavailable,
+            IdeviceError::AfcFileTooLarge { .. } => IdeviceErrorCode::AfcFileTooLarge,
+            IdeviceError::PairingDialogResponsePending => {
+                IdeviceErrorCode::PairingDialogResponsePending
+            }
+            IdeviceError::UserDeniedPairing => IdeviceErrorCode::UserDeniedPairing,
+            IdeviceError::PasswordProtected => IdeviceErrorCode::PasswordProtected,
+            IdeviceError::CrashReportMoverBadResponse(_) => {
+                IdeviceErrorCode::CrashReportMoverBadResponse
+            }
+            // IdeviceError is non_exhaustive; map anything added later here
             _ => IdeviceErrorCode::InternalError,
         }
     }
 }
+
+impl From<io::Error> for IdeviceErrorCode {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::TimedOut => IdeviceErrorCode::Timeout,
+            io::ErrorKind::PermissionDenied => IdeviceErrorCode::PermissionDenied,
+            io::ErrorKind::NotFound => IdeviceErrorCode::NotFound,
+            io::ErrorKind::ConnectionRefused => IdeviceErrorCode::ConnectionRefused,
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => IdeviceErrorCode::ConnectionClosed,
+            _ => IdeviceErrorCode::Socket,
+        }
+    }
+}
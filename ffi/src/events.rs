@@ -0,0 +1,106 @@
+// Jackson Coxson
+
+use std::{
+    ffi::{CString, c_char, c_void},
+    future::Future,
+    thread::JoinHandle,
+};
+
+use idevice::IdeviceError;
+use tokio::sync::oneshot;
+
+use crate::RUNTIME;
+
+/// Called once per event with a null-terminated UTF-8 string and the
+/// `context` pointer given when the stream was started.
+///
+/// The string is only valid for the duration of the call; copy it if it is
+/// needed afterwards. When the stream ends on its own (the device went away
+/// or the service failed) the callback is invoked one last time with a NULL
+/// `event`.
+pub type IdeviceEventCallback = extern "C" fn(event: *const c_char, context: *mut c_void);
+
+/// Opaque handle to a running event stream
+///
+/// Every event of a stream is delivered on the same dedicated thread, never
+/// on the thread that started it.
+pub struct IdeviceEventStreamHandle {
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Something that produces events for an [`IdeviceEventStreamHandle`]
+pub(crate) trait EventSource: Send + 'static {
+    fn next_event(&mut self) -> impl Future<Output = Result<String, IdeviceError>>;
+}
+
+/// Wraps the caller's context so it can be moved to the event thread.
+/// Thread safety of whatever it points to is the caller's responsibility.
+struct Context(*mut c_void);
+unsafe impl Send for Context {}
+
+/// Starts a thread that pulls events from `source` and hands each one to `callback`
+pub(crate) fn spawn_event_stream<S: EventSource>(
+    mut source: S,
+    callback: IdeviceEventCallback,
+    context: *mut c_void,
+) -> IdeviceEventStreamHandle {
+    let (stop_tx, mut stop_rx) = oneshot::channel();
+    let context = Context(context);
+    let thread = std::thread::spawn(move || {
+        let context = context;
+        RUNTIME.block_on(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = &mut stop_rx => return,
+                    event = source.next_event() => event,
+                };
+                match event {
+                    Ok(event) => {
+                        // Interior NULs would truncate the string on the C side anyway
+                        let event = CString::new(event.replace('\0', "")).unwrap_or_default();
+                        callback(event.as_ptr(), context.0);
+                    }
+                    Err(e) => {
+                        log::debug!("Event stream ended: {e:?}");
+                        callback(std::ptr::null(), context.0);
+                        return;
+                    }
+                }
+            }
+        });
+    });
+    IdeviceEventStreamHandle {
+        stop: Some(stop_tx),
+        thread: Some(thread),
+    }
+}
+
+/// Stops an event stream and frees its handle
+///
+/// Blocks until the event thread has exited, so once this returns the
+/// callback will not be called again and its context may be freed. An event
+/// already being delivered when this is called finishes first.
+///
+/// # Arguments
+/// * [`stream`] - The stream to stop
+///
+/// # Safety
+/// `stream` must be a valid pointer to a handle allocated by this library,
+/// or NULL (in which case this function does nothing). It must not be called
+/// from inside the stream's own callback, which would deadlock.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn idevice_event_stream_stop(stream: *mut IdeviceEventStreamHandle) {
+    if stream.is_null() {
+        return;
+    }
+    log::debug!("Stopping event stream");
+    let mut stream = unsafe { Box::from_raw(stream) };
+    if let Some(stop) = stream.stop.take() {
+        // Fails only if the stream already ended by itself
+        let _ = stop.send(());
+    }
+    if let Some(thread) = stream.thread.take() {
+        let _ = thread.join();
+    }
+}
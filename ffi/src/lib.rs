@@ -6,6 +6,7 @@ pub mod amfi;
 pub mod core_device_proxy;
 pub mod debug_proxy;
 mod errors;
+pub mod events;
 pub mod heartbeat;
 pub mod installation_proxy;
 pub mod location_simulation;
@@ -19,6 +20,7 @@ pub mod provider;
 pub mod remote_server;
 pub mod remotexpc;
 pub mod sbservices;
+pub mod syslog_relay;
 pub mod usbmuxd;
 pub mod util;
 
@@ -19,6 +19,9 @@ pub struct LocationSimulationAdapterHandle<'a>(pub LocationSimulationClient<'a,
 /// # Safety
 /// `server` must be a valid pointer to a handle allocated by this library
 /// `handle` must be a valid pointer to a location where the handle will be stored
+///
+/// The new client borrows `server`: it must not be freed while the client
+/// is in use, until the client is freed or reconnected to another server
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn location_simulation_new(
     server: *mut RemoteServerAdapterHandle,
@@ -41,6 +44,47 @@ pub unsafe extern "C" fn location_simulation_new(
     }
 }
 
+/// Re-establishes a LocationSimulationClient on a RemoteServerClient
+///
+/// Use this after the tunnel or server connection behind `handle` was reset.
+/// `handle` keeps its address, so callers holding it don't need updating.
+///
+/// # Arguments
+/// * [`handle`] - The LocationSimulation handle to reconnect
+/// * [`server`] - The RemoteServerClient to use; may be the old one or a new one
+///
+/// # Returns
+/// An error code indicating success or failure. On failure `handle` is left
+/// as it was and still borrows its previous server
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle allocated by this library
+/// `server` must be a valid pointer to a handle allocated by this library
+///
+/// On success `handle` borrows `server` instead of its previous server, which
+/// may then be freed with `remote_server_free`. `server` must outlive `handle`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn location_simulation_reconnect(
+    handle: *mut LocationSimulationAdapterHandle<'static>,
+    server: *mut RemoteServerAdapterHandle,
+) -> IdeviceErrorCode {
+    if handle.is_null() || server.is_null() {
+        return IdeviceErrorCode::InvalidArg;
+    }
+
+    let server = unsafe { &mut (*server).0 };
+    let res = RUNTIME.block_on(async move { LocationSimulationClient::new(server).await });
+
+    match res {
+        Ok(client) => {
+            // Drops the old client and with it the borrow of the old server
+            unsafe { (*handle).0 = client };
+            IdeviceErrorCode::IdeviceSuccess
+        }
+        Err(e) => e.into(),
+    }
+}
+
 /// Frees a ProcessControlClient handle
 ///
 /// # Arguments
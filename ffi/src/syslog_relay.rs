@@ -0,0 +1,153 @@
+// Jackson Coxson
+
+use std::ffi::c_void;
+
+use idevice::{IdeviceError, IdeviceService, syslog_relay::SyslogRelayClient};
+
+use crate::{
+    IdeviceErrorCode, RUNTIME,
+    events::{EventSource, IdeviceEventCallback, IdeviceEventStreamHandle, spawn_event_stream},
+    provider::{TcpProviderHandle, UsbmuxdProviderHandle},
+};
+
+pub struct SyslogRelayClientHandle(pub SyslogRelayClient);
+
+impl EventSource for SyslogRelayClient {
+    async fn next_event(&mut self) -> Result<String, IdeviceError> {
+        self.next().await
+    }
+}
+
+/// Automatically creates and connects to the syslog relay, returning a client handle
+///
+/// # Arguments
+/// * [`provider`] - A TcpProvider
+/// * [`client`] - On success, will be set to point to a newly allocated SyslogRelayClient handle
+///
+/// # Returns
+/// An error code indicating success or failure
+///
+/// # Safety
+/// `provider` must be a valid pointer to a handle allocated by this library
+/// `client` must be a valid, non-null pointer to a location where the handle will be stored
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syslog_relay_connect_tcp(
+    provider: *mut TcpProviderHandle,
+    client: *mut *mut SyslogRelayClientHandle,
+) -> IdeviceErrorCode {
+    if provider.is_null() || client.is_null() {
+        log::error!("Null pointer provided");
+        return IdeviceErrorCode::InvalidArg;
+    }
+
+    let res: Result<SyslogRelayClient, IdeviceError> = RUNTIME.block_on(async move {
+        // Take ownership of the provider (without immediately dropping it)
+        let provider_box = unsafe { Box::from_raw(provider) };
+        let result = SyslogRelayClient::connect(&provider_box.0).await;
+        std::mem::forget(provider_box);
+        result
+    });
+
+    match res {
+        Ok(r) => {
+            let boxed = Box::new(SyslogRelayClientHandle(r));
+            unsafe { *client = Box::into_raw(boxed) };
+            IdeviceErrorCode::IdeviceSuccess
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Automatically creates and connects to the syslog relay, returning a client handle
+///
+/// # Arguments
+/// * [`provider`] - A UsbmuxdProvider
+/// * [`client`] - On success, will be set to point to a newly allocated SyslogRelayClient handle
+///
+/// # Returns
+/// An error code indicating success or failure
+///
+/// # Safety
+/// `provider` must be a valid pointer to a handle allocated by this library
+/// `client` must be a valid, non-null pointer to a location where the handle will be stored
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syslog_relay_connect_usbmuxd(
+    provider: *mut UsbmuxdProviderHandle,
+    client: *mut *mut SyslogRelayClientHandle,
+) -> IdeviceErrorCode {
+    if provider.is_null() || client.is_null() {
+        log::error!("Null pointer provided");
+        return IdeviceErrorCode::InvalidArg;
+    }
+
+    let res: Result<SyslogRelayClient, IdeviceError> = RUNTIME.block_on(async move {
+        // Take ownership of the provider (without immediately dropping it)
+        let provider_box = unsafe { Box::from_raw(provider) };
+        let result = SyslogRelayClient::connect(&provider_box.0).await;
+        std::mem::forget(provider_box);
+        result
+    });
+
+    match res {
+        Ok(r) => {
+            let boxed = Box::new(SyslogRelayClientHandle(r));
+            unsafe { *client = Box::into_raw(boxed) };
+            IdeviceErrorCode::IdeviceSuccess
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Streams log lines to a callback until stopped
+///
+/// Each line is passed to `callback` along with `context`, always from the
+/// same background thread. The callback and whatever `context` points to
+/// must therefore be safe to use from a thread other than the caller's.
+///
+/// # Arguments
+/// * [`client`] - A SyslogRelayClient handle. Ownership moves to the stream.
+/// * [`callback`] - Called for each log line; see [`IdeviceEventCallback`]
+/// * [`context`] - Passed back to every callback invocation, may be NULL
+/// * [`stream`] - On success, will be set to point to a newly allocated stream handle
+///
+/// # Returns
+/// An error code indicating success or failure
+///
+/// # Safety
+/// `client` must be a valid pointer to a handle allocated by this library and
+/// must not be used or freed afterwards
+/// `context` must stay valid until `idevice_event_stream_stop` returns
+/// `stream` must be a valid, non-null pointer to a location where the handle will be stored
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syslog_relay_stream_start(
+    client: *mut SyslogRelayClientHandle,
+    callback: IdeviceEventCallback,
+    context: *mut c_void,
+    stream: *mut *mut IdeviceEventStreamHandle,
+) -> IdeviceErrorCode {
+    if client.is_null() || stream.is_null() {
+        log::error!("Null pointer provided");
+        return IdeviceErrorCode::InvalidArg;
+    }
+
+    let client = unsafe { Box::from_raw(client) }.0;
+    let handle = spawn_event_stream(client, callback, context);
+    unsafe { *stream = Box::into_raw(Box::new(handle)) };
+    IdeviceErrorCode::IdeviceSuccess
+}
+
+/// Frees a handle
+///
+/// # Arguments
+/// * [`handle`] - The handle to free
+///
+/// # Safety
+/// `handle` must be a valid pointer to the handle that was allocated by this library,
+/// or NULL (in which case this function does nothing)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syslog_relay_client_free(handle: *mut SyslogRelayClientHandle) {
+    if !handle.is_null() {
+        log::debug!("Freeing syslog_relay_client");
+        let _ = unsafe { Box::from_raw(handle) };
+    }
+}
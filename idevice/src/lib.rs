@@ -382,6 +382,10 @@ pub enum IdeviceError {
     UnexpectedResponse,
     #[error("this request was prohibited")]
     GetProhibited,
+    #[error("the device refused to change this value")]
+    SetProhibited,
+    #[error("requested service is not available")]
+    InvalidService,
     #[error("no SSL session is active")]
     SessionInactive,
     #[error("device does not have pairing file")]
@@ -441,6 +445,18 @@ pub enum IdeviceError {
     #[error("misagent operation failed")]
     MisagentFailure,
 
+    #[cfg(feature = "mobileconfig")]
+    #[error("profile can't be removed; it's locked or the device is supervised")]
+    ProfileRemovalDisallowed,
+
+    #[cfg(feature = "mobileconfig")]
+    #[error("profile operation failed: {0}")]
+    MobileConfigFailure(String),
+
+    #[cfg(feature = "mobileconfig")]
+    #[error("not a configuration profile: {0}")]
+    InvalidProfile(String),
+
     #[cfg(feature = "installation_proxy")]
     #[error("installation proxy operation failed")]
     InstallationProxyOperationFailed(String),
@@ -461,6 +477,14 @@ pub enum IdeviceError {
     #[error("missing file attribute")]
     AfcMissingAttribute,
 
+    #[cfg(feature = "afc")]
+    #[error("AFC2 not available (device not jailbroken)")]
+    Afc2Unavailable,
+
+    #[cfg(feature = "afc")]
+    #[error("file is {size} bytes, over the {max} byte limit")]
+    AfcFileTooLarge { size: usize, max: usize },
+
     #[cfg(feature = "crashreportcopymobile")]
     #[error("crash report mover sent the wrong response")]
     CrashReportMoverBadResponse(Vec<u8>),
@@ -501,10 +525,13 @@ pub enum IdeviceError {
     #[error("failed to parse bytes as valid utf8")]
     Utf8Error,
 
-    #[cfg(feature = "debug_proxy")]
+    #[cfg(any(feature = "debug_proxy", feature = "afc"))]
     #[error("invalid argument passed")]
     InvalidArgument,
 
+    #[error("battery info unavailable on this device")]
+    BatteryInfoUnavailable,
+
     #[error("unknown error `{0}` returned from device")]
     UnknownErrorType(String),
 }
@@ -521,6 +548,8 @@ impl IdeviceError {
     fn from_device_error_type(e: &str, context: &plist::Dictionary) -> Option<Self> {
         match e {
             "GetProhibited" => Some(Self::GetProhibited),
+            "SetProhibited" => Some(Self::SetProhibited),
+            "InvalidService" => Some(Self::InvalidService),
             "InvalidHostID" => Some(Self::InvalidHostID),
             "SessionInactive" => Some(Self::SessionInactive),
             "DeviceLocked" => Some(Self::DeviceLocked),
@@ -0,0 +1,249 @@
+//! AFC round trips against a real device
+//!
+//! These need an attached, trusted device with an app that has file sharing
+//! enabled, so they only build with the `device_tests` feature and skip
+//! themselves unless `IDEVICE_DEVICE_TESTS=1` is set:
+//!
+//! ```sh
+//! IDEVICE_DEVICE_TESTS=1 IDEVICE_TEST_BUNDLE_ID=com.example.App \
+//!     cargo test -p idevice --features device_tests -- --test-threads=1 device_tests
+//! ```
+//!
+//! Everything happens in a fresh directory under the app's `/Documents`,
+//! which is removed afterwards even if a step fails.
+
+use std::{
+    io::SeekFrom,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{opcode::AfcFopenMode, AfcClient};
+use crate::{
+    house_arrest::HouseArrestClient,
+    provider::UsbmuxdProvider,
+    usbmuxd::{UsbmuxdAddr, UsbmuxdConnection},
+    IdeviceError, IdeviceService,
+};
+
+/// A provider for the first usbmuxd device and the bundle ID to test with,
+/// or `None` if the tests weren't opted into
+async fn test_device() -> Option<(UsbmuxdProvider, String)> {
+    if std::env::var("IDEVICE_DEVICE_TESTS").as_deref() != Ok("1") {
+        eprintln!("skipping: set IDEVICE_DEVICE_TESTS=1 to run against a device");
+        return None;
+    }
+    let bundle_id = std::env::var("IDEVICE_TEST_BUNDLE_ID")
+        .expect("IDEVICE_TEST_BUNDLE_ID must name an app with file sharing enabled");
+
+    let mut mux = UsbmuxdConnection::default()
+        .await
+        .expect("Unable to connect to usbmuxd");
+    let dev = mux
+        .get_devices()
+        .await
+        .expect("Unable to list devices")
+        .into_iter()
+        .next()
+        .expect("No device attached");
+    let provider = dev.to_provider(UsbmuxdAddr::default(), "idevice-device-tests");
+    Some((provider, bundle_id))
+}
+
+async fn house_arrest(provider: &UsbmuxdProvider) -> HouseArrestClient {
+    HouseArrestClient::connect(provider)
+        .await
+        .expect("Unable to connect to house_arrest")
+}
+
+/// Connects to the test app's Documents, or `None` if the tests weren't
+/// opted into
+async fn documents_client() -> Option<AfcClient> {
+    let (provider, bundle_id) = test_device().await?;
+    Some(
+        house_arrest(&provider)
+            .await
+            .vend_documents(bundle_id)
+            .await
+            .expect("Unable to vend the app's Documents"),
+    )
+}
+
+/// A directory name no earlier run left behind
+fn scratch_dir() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("/Documents/idevice-test-{}-{nanos}", std::process::id())
+}
+
+async fn write_file(client: &mut AfcClient, path: &str, data: &[u8]) -> Result<(), IdeviceError> {
+    let mut fd = client.open(path, AfcFopenMode::WrOnly).await?;
+    fd.write(data).await?;
+    fd.close().await
+}
+
+async fn read_file(client: &mut AfcClient, path: &str) -> Result<Vec<u8>, IdeviceError> {
+    let mut fd = client.open(path, AfcFopenMode::RdOnly).await?;
+    let data = fd.read().await?;
+    fd.close().await?;
+    Ok(data)
+}
+
+async fn round_trip(client: &mut AfcClient, dir: &str) -> Result<(), IdeviceError> {
+    client.mk_dir(dir).await?;
+    assert!(client.is_dir(dir).await?);
+
+    // Spans several packets so chunking is exercised too
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let original = format!("{dir}/original.bin");
+    write_file(client, &original, &data).await?;
+    assert_eq!(read_file(client, &original).await?, data);
+
+    let info = client.get_file_info(original.as_str()).await?;
+    assert_eq!(info.size, data.len());
+    assert_eq!(info.st_ifmt, "S_IFREG");
+
+    let renamed = format!("{dir}/renamed.bin");
+    client.rename(original.as_str(), renamed.as_str()).await?;
+    assert!(!client.exists(original.as_str()).await?);
+    assert_eq!(read_file(client, &renamed).await?, data);
+    let names = client.list_dir(dir).await?;
+    assert!(names.iter().any(|n| n == "renamed.bin"), "{names:?}");
+
+    let mut fd = client.open(renamed.as_str(), AfcFopenMode::RdOnly).await?;
+    let mut buf = [0u8; 1000];
+    assert_eq!(fd.read_at(100_000, &mut buf).await?, buf.len());
+    assert_eq!(buf[..], data[100_000..101_000]);
+    assert_eq!(fd.read_at(199_500, &mut buf).await?, 500);
+    assert_eq!(buf[..500], data[199_500..]);
+    assert_eq!(fd.read_at(200_000, &mut buf).await?, 0);
+    assert_eq!(fd.read_at(300_000, &mut buf).await?, 0);
+    fd.close().await?;
+
+    let mut fd = client.open(renamed.as_str(), AfcFopenMode::Rw).await?;
+    assert_eq!(fd.seek(SeekFrom::Start(1000)).await?, 1000);
+    assert_eq!(fd.read_chunk(10).await?, data[1000..1010]);
+    assert_eq!(fd.tell().await?, 1010);
+    assert_eq!(fd.seek(SeekFrom::Current(-10)).await?, 1000);
+    assert_eq!(fd.seek(SeekFrom::Current(500)).await?, 1500);
+    assert_eq!(fd.read_chunk(4).await?, data[1500..1504]);
+    assert_eq!(fd.seek(SeekFrom::End(-4)).await?, 199_996);
+    assert_eq!(fd.read_chunk(100).await?, data[199_996..]);
+    assert!(fd.read_chunk(100).await?.is_empty());
+    assert_eq!(fd.seek(SeekFrom::Start(10)).await?, 10);
+    fd.write(b"seek").await?;
+    assert_eq!(fd.tell().await?, 14);
+    fd.close().await?;
+    let mut patched = data.clone();
+    patched[10..14].copy_from_slice(b"seek");
+    assert_eq!(read_file(client, &renamed).await?, patched);
+
+    client.remove(renamed.as_str()).await?;
+    assert!(!client.exists(renamed.as_str()).await?);
+
+    let nested = format!("{dir}/a/b");
+    client.mk_dir(format!("{dir}/a")).await?;
+    client.mk_dir(nested.as_str()).await?;
+    write_file(client, &format!("{nested}/leaf.txt"), b"leaf").await?;
+    let mut calls = 0;
+    let summary = client
+        .remove_all_with_progress(format!("{dir}/a"), |done, total, _| {
+            calls += 1;
+            assert!(done <= total);
+        })
+        .await?;
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    assert_eq!(summary.removed, 3);
+    assert_eq!(calls, 3);
+
+    client.remove(dir).await?;
+    assert!(!client.exists(dir).await?);
+    Ok(())
+}
+
+/// A vended client must browse the app's sandbox, not the media directory
+/// a fresh `com.apple.afc` connection would show
+#[tokio::test]
+async fn vended_container_is_the_app_sandbox() {
+    let Some((provider, bundle_id)) = test_device().await else {
+        return;
+    };
+    let mut container = house_arrest(&provider)
+        .await
+        .vend_container(bundle_id)
+        .await
+        .expect("Unable to vend the app's container");
+    let names = container.list_dir("/").await.unwrap();
+    assert!(names.iter().any(|n| n == "Documents"), "{names:?}");
+    assert!(names.iter().any(|n| n == "Library"), "{names:?}");
+
+    let mut media = AfcClient::connect(&provider).await.unwrap();
+    assert_ne!(names, media.list_dir("/").await.unwrap());
+}
+
+/// Uploads over `path`, dropping the upload after its first chunk
+async fn interrupted_upload(client: &mut AfcClient, path: &str, data: &[u8]) {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let mut tx = Some(tx);
+    let upload = client.upload(path, data, |_, _| {
+        if let Some(tx) = tx.take() {
+            let _ = tx.send(());
+        }
+    });
+    tokio::select! {
+        res = upload => panic!("upload finished before it was interrupted: {res:?}"),
+        _ = rx => {}
+    }
+}
+
+async fn safe_overwrite(dir: &str) -> Result<(), IdeviceError> {
+    let mut client = documents_client().await.unwrap();
+    client.mk_dir(dir).await?;
+    let target = format!("{dir}/target.bin");
+    write_file(&mut client, &target, b"original").await?;
+
+    client.set_chunk_size(4096);
+    let data = vec![0x5a; 64 * 4096];
+    interrupted_upload(&mut client, &target, &data).await;
+    // The interrupted client may be mid-packet, so check on a fresh one
+    drop(client);
+    let mut client = documents_client().await.unwrap();
+    assert_eq!(read_file(&mut client, &target).await?, b"original");
+
+    client.upload(target.as_str(), &data, |_, _| {}).await?;
+    assert_eq!(read_file(&mut client, &target).await?, data);
+    assert!(!client.exists(format!("{target}.uploading")).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn interrupted_upload_keeps_the_original() {
+    if test_device().await.is_none() {
+        return;
+    }
+    let dir = scratch_dir();
+    let res = safe_overwrite(&dir).await;
+    let mut client = documents_client().await.unwrap();
+    if client.exists(dir.as_str()).await.unwrap_or(false) {
+        if let Err(e) = client.remove_all(dir.as_str()).await {
+            eprintln!("failed to clean up {dir}: {e}");
+        }
+    }
+    res.expect("safe overwrite failed");
+}
+
+#[tokio::test]
+async fn afc_round_trip_in_documents() {
+    let Some(mut client) = documents_client().await else {
+        return;
+    };
+    let dir = scratch_dir();
+    let res = round_trip(&mut client, &dir).await;
+    if client.exists(dir.as_str()).await.unwrap_or(false) {
+        if let Err(e) = client.remove_all(dir.as_str()).await {
+            eprintln!("failed to clean up {dir}: {e}");
+        }
+    }
+    res.expect("AFC round trip failed");
+}
@@ -1,15 +1,16 @@
 // Jackson Coxson
 
+use std::io::SeekFrom;
+
 use crate::IdeviceError;
 
+use log::warn;
+
 use super::{
     opcode::AfcOpcode,
     packet::{AfcPacket, AfcPacketHeader},
 };
 
-/// Maximum transfer size for file operations (64KB)
-const MAX_TRANSFER: u64 = 64 * 1024; // this is what go-ios uses
-
 /// Handle for an open file on the device.
 /// Call close before dropping
 pub struct FileDescriptor<'a> {
@@ -50,36 +51,179 @@ impl FileDescriptor<'_> {
     /// A vector containing the file's data
     pub async fn read(&mut self) -> Result<Vec<u8>, IdeviceError> {
         // Get the file size first
-        let mut bytes_left = self.client.get_file_info(&self.path).await?.size;
+        let size = self.client.get_file_info(&self.path).await?.size;
+        self.read_len(size).await
+    }
+
+    /// Reads the entire contents of the file, unless it's bigger than `max`
+    ///
+    /// The size is checked before anything is read, so an oversized file
+    /// costs one round trip rather than its contents.
+    ///
+    /// # Arguments
+    /// * `max` - Largest file size, in bytes, to read
+    ///
+    /// # Returns
+    /// The file's data, or `AfcFileTooLarge` if it's over `max`
+    pub async fn read_capped(&mut self, max: usize) -> Result<Vec<u8>, IdeviceError> {
+        let size = self.client.get_file_info(&self.path).await?.size;
+        check_size(size, max)?;
+        self.read_len(size).await
+    }
+
+    /// Reads until `len` bytes have arrived or the file ends
+    async fn read_len(&mut self, len: usize) -> Result<Vec<u8>, IdeviceError> {
+        let mut bytes_left = len;
         let mut collected_bytes = Vec::with_capacity(bytes_left);
 
         while bytes_left > 0 {
-            let mut header_payload = self.fd.to_le_bytes().to_vec();
-            header_payload.extend_from_slice(&MAX_TRANSFER.to_le_bytes());
-            let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
+            // Never more than what's left, so a file that grew since its size
+            // was checked isn't read past it
+            let chunk = self
+                .read_chunk(self.client.chunk_size.min(bytes_left))
+                .await?;
+            if chunk.is_empty() {
+                break;
+            }
+            bytes_left = bytes_left.saturating_sub(chunk.len());
+            collected_bytes.extend(chunk);
+        }
 
-            let header = AfcPacketHeader {
-                magic: super::MAGIC,
-                entire_len: header_len,
-                header_payload_len: header_len,
-                packet_num: self.client.package_number,
-                operation: AfcOpcode::Read,
-            };
-            self.client.package_number += 1;
+        Ok(collected_bytes)
+    }
 
-            let packet = AfcPacket {
-                header,
-                header_payload,
-                payload: Vec::new(),
-            };
+    /// Reads into `buf` from `offset` in the file
+    ///
+    /// Seeks to `offset` first, so the next `read_chunk` or `write` carries on
+    /// from wherever this read stopped. The file isn't stat'ed: reading stops
+    /// when `buf` is full or the device has nothing more to give.
+    ///
+    /// # Arguments
+    /// * `offset` - Position in the file to read from
+    /// * `buf` - Filled from the start, as far as the file allows
+    ///
+    /// # Returns
+    /// How many bytes were read; 0 if `offset` is at or past the end
+    pub async fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize, IdeviceError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.seek(SeekFrom::Start(offset)).await?;
 
-            self.client.send(packet).await?;
-            let res = self.client.read().await?;
-            bytes_left -= res.payload.len();
-            collected_bytes.extend(res.payload);
+        let mut read = 0;
+        while read < buf.len() {
+            let chunk = self
+                .read_chunk(self.client.chunk_size.min(buf.len() - read))
+                .await?;
+            if chunk.is_empty() {
+                break;
+            }
+            // A chunk is never longer than asked for, but don't trust it
+            let n = chunk.len().min(buf.len() - read);
+            buf[read..read + n].copy_from_slice(&chunk[..n]);
+            read += n;
         }
+        Ok(read)
+    }
 
-        Ok(collected_bytes)
+    /// Reads the next chunk of the file
+    ///
+    /// # Arguments
+    /// * `len` - Maximum number of bytes to read
+    ///
+    /// # Returns
+    /// Up to `len` bytes; empty once the end of the file is reached
+    pub async fn read_chunk(&mut self, len: usize) -> Result<Vec<u8>, IdeviceError> {
+        let mut header_payload = self.fd.to_le_bytes().to_vec();
+        header_payload.extend_from_slice(&(len as u64).to_le_bytes());
+        let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
+
+        let header = AfcPacketHeader {
+            magic: super::MAGIC,
+            entire_len: header_len,
+            header_payload_len: header_len,
+            packet_num: self.client.package_number,
+            operation: AfcOpcode::Read,
+        };
+        self.client.package_number += 1;
+
+        let packet = AfcPacket {
+            header,
+            header_payload,
+            payload: Vec::new(),
+        };
+
+        self.client.send(packet).await?;
+        Ok(self.client.read().await?.payload)
+    }
+
+    /// Moves the file offset used by the next read or write
+    ///
+    /// # Arguments
+    /// * `pos` - New position, relative to the start, current offset or end
+    ///
+    /// # Returns
+    /// The new offset from the start of the file, or `InvalidArgument` for a
+    /// start offset too large for AFC's signed offset
+    pub async fn seek(&mut self, pos: SeekFrom) -> Result<u64, IdeviceError> {
+        let (whence, offset) = seek_args(pos)?;
+        let mut header_payload = self.fd.to_le_bytes().to_vec();
+        header_payload.extend_from_slice(&whence.to_le_bytes());
+        header_payload.extend_from_slice(&offset.to_le_bytes());
+        let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
+
+        let header = AfcPacketHeader {
+            magic: super::MAGIC,
+            entire_len: header_len,
+            header_payload_len: header_len,
+            packet_num: self.client.package_number,
+            operation: AfcOpcode::FileSeek,
+        };
+        self.client.package_number += 1;
+
+        let packet = AfcPacket {
+            header,
+            header_payload,
+            payload: Vec::new(),
+        };
+
+        self.client.send(packet).await?;
+        self.client.read().await?;
+        self.tell().await
+    }
+
+    /// Gets the file offset used by the next read or write
+    ///
+    /// # Returns
+    /// The offset from the start of the file
+    pub async fn tell(&mut self) -> Result<u64, IdeviceError> {
+        let header_payload = self.fd.to_le_bytes().to_vec();
+        let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
+
+        let header = AfcPacketHeader {
+            magic: super::MAGIC,
+            entire_len: header_len,
+            header_payload_len: header_len,
+            packet_num: self.client.package_number,
+            operation: AfcOpcode::FileTell,
+        };
+        self.client.package_number += 1;
+
+        let packet = AfcPacket {
+            header,
+            header_payload,
+            payload: Vec::new(),
+        };
+
+        self.client.send(packet).await?;
+        let res = self.client.read().await?;
+        if res.header_payload.len() < 8 {
+            warn!("Tell response is less than 8 bytes");
+            return Err(IdeviceError::UnexpectedResponse);
+        }
+        Ok(u64::from_le_bytes(
+            res.header_payload[..8].try_into().unwrap(),
+        ))
     }
 
     /// Writes data to the file
@@ -87,7 +231,7 @@ impl FileDescriptor<'_> {
     /// # Arguments
     /// * `bytes` - Data to write to the file
     pub async fn write(&mut self, bytes: &[u8]) -> Result<(), IdeviceError> {
-        let chunks = bytes.chunks(MAX_TRANSFER as usize);
+        let chunks = bytes.chunks(self.client.chunk_size);
 
         for chunk in chunks {
             let header_payload = self.fd.to_le_bytes().to_vec();
@@ -114,3 +258,159 @@ impl FileDescriptor<'_> {
         Ok(())
     }
 }
+
+/// AFC's `whence` and offset for a seek to `pos`
+fn seek_args(pos: SeekFrom) -> Result<(u64, i64), IdeviceError> {
+    Ok(match pos {
+        SeekFrom::Start(o) => (
+            0,
+            i64::try_from(o).map_err(|_| IdeviceError::InvalidArgument)?,
+        ),
+        SeekFrom::Current(o) => (1, o),
+        SeekFrom::End(o) => (2, o),
+    })
+}
+
+/// Errors if a file of `size` bytes is over the `max` a caller will read
+fn check_size(size: usize, max: usize) -> Result<(), IdeviceError> {
+    if size > max {
+        Err(IdeviceError::AfcFileTooLarge { size, max })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        afc::{opcode::AfcFopenMode, tests::fake_named_files_device, AfcClient},
+        Idevice,
+    };
+
+    const FILES: &[(&[u8], &[u8])] = &[(b"digits", b"0123456789")];
+
+    /// A client talking to a fake device serving `FILES` in `/d`
+    fn fake_client() -> (AfcClient, tokio::task::JoinHandle<()>) {
+        let (host, device) = tokio::io::duplex(64 * 1024);
+        let device = tokio::spawn(fake_named_files_device(
+            Idevice::new(Box::new(device), "fake-device"),
+            FILES,
+        ));
+        (AfcClient::new(Idevice::new(Box::new(host), "test")), device)
+    }
+
+    #[test]
+    fn size_cap_allows_up_to_max() {
+        assert!(check_size(0, 1024).is_ok());
+        assert!(check_size(1023, 1024).is_ok());
+        assert!(check_size(1024, 1024).is_ok());
+    }
+
+    #[test]
+    fn size_cap_rejects_one_over() {
+        assert!(matches!(
+            check_size(1025, 1024),
+            Err(IdeviceError::AfcFileTooLarge {
+                size: 1025,
+                max: 1024
+            })
+        ));
+    }
+
+    #[test]
+    fn seek_args_match_whence() {
+        assert_eq!(seek_args(SeekFrom::Start(10)).unwrap(), (0, 10));
+        assert_eq!(seek_args(SeekFrom::Current(-4)).unwrap(), (1, -4));
+        assert_eq!(seek_args(SeekFrom::End(-1)).unwrap(), (2, -1));
+        assert!(matches!(
+            seek_args(SeekFrom::Start(i64::MAX as u64 + 1)),
+            Err(IdeviceError::InvalidArgument)
+        ));
+    }
+
+    #[tokio::test]
+    async fn seek_returns_the_absolute_offset() {
+        let (mut client, device) = fake_client();
+        let mut fd = client
+            .open("/d/digits", AfcFopenMode::RdOnly)
+            .await
+            .unwrap();
+
+        assert_eq!(fd.seek(SeekFrom::Start(4)).await.unwrap(), 4);
+        assert_eq!(fd.seek(SeekFrom::Current(3)).await.unwrap(), 7);
+        assert_eq!(fd.seek(SeekFrom::Current(-2)).await.unwrap(), 5);
+        assert_eq!(fd.tell().await.unwrap(), 5);
+        assert_eq!(fd.read_chunk(2).await.unwrap(), b"56");
+        assert_eq!(fd.tell().await.unwrap(), 7);
+        assert_eq!(fd.seek(SeekFrom::End(-1)).await.unwrap(), 9);
+        assert_eq!(fd.read_chunk(4).await.unwrap(), b"9");
+        assert_eq!(fd.seek(SeekFrom::End(0)).await.unwrap(), 10);
+
+        fd.close().await.unwrap();
+        drop(client);
+        device.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn seek_past_i64_max_is_refused() {
+        let (mut client, device) = fake_client();
+        let mut fd = client
+            .open("/d/digits", AfcFopenMode::RdOnly)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            fd.seek(SeekFrom::Start(u64::MAX)).await,
+            Err(IdeviceError::InvalidArgument)
+        ));
+        // Nothing was sent, so the descriptor is still where it was
+        assert_eq!(fd.tell().await.unwrap(), 0);
+
+        fd.close().await.unwrap();
+        drop(client);
+        device.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_at_middle_fills_the_buffer() {
+        let (mut client, device) = fake_client();
+        client.set_chunk_size(3);
+        let mut fd = client
+            .open("/d/digits", AfcFopenMode::RdOnly)
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 5];
+        assert_eq!(fd.read_at(2, &mut buf).await.unwrap(), 5);
+        assert_eq!(&buf, b"23456");
+        // Carries on from where the read stopped
+        assert_eq!(fd.tell().await.unwrap(), 7);
+        assert_eq!(fd.read_at(0, &mut buf).await.unwrap(), 5);
+        assert_eq!(&buf, b"01234");
+
+        fd.close().await.unwrap();
+        drop(client);
+        device.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_at_end_is_short_or_empty() {
+        let (mut client, device) = fake_client();
+        let mut fd = client
+            .open("/d/digits", AfcFopenMode::RdOnly)
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 4];
+        assert_eq!(fd.read_at(8, &mut buf).await.unwrap(), 2);
+        assert_eq!(&buf[..2], b"89");
+        assert_eq!(fd.read_at(10, &mut buf).await.unwrap(), 0);
+        assert_eq!(fd.read_at(1000, &mut buf).await.unwrap(), 0);
+        assert_eq!(fd.read_at(0, &mut []).await.unwrap(), 0);
+
+        fd.close().await.unwrap();
+        drop(client);
+        device.await.unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+//! Shell-style wildcard matching for AFC listings
+//!
+//! AFC has no server-side filtering, so listings are filtered after
+//! `list_dir` returns. Only `*` (any run of characters) and `?` (any single
+//! character) are special; everything else matches literally.
+
+/// Checks whether `name` matches the wildcard `pattern`
+///
+/// # Arguments
+/// * `pattern` - Pattern such as `*.jpg` or `IMG_????.HEIC`
+/// * `name` - File name to test
+/// * `case_sensitive` - Compare letters exactly instead of ignoring case
+///
+/// # Example
+/// ```rust
+/// use idevice::afc::glob::matches;
+///
+/// assert!(matches("*.jpg", "IMG_0001.JPG", false));
+/// assert!(!matches("*.jpg", "IMG_0001.JPG", true));
+/// ```
+pub fn matches(pattern: &str, name: &str, case_sensitive: bool) -> bool {
+    let fold = |s: &str| -> Vec<char> {
+        if case_sensitive {
+            s.chars().collect()
+        } else {
+            s.chars().flat_map(char::to_lowercase).collect()
+        }
+    };
+    let (pattern, name) = (fold(pattern), fold(name));
+
+    // Iterative match that backtracks to the most recent `*` on a mismatch
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.jpg", "beach.jpg", true));
+        assert!(matches("IMG_*", "IMG_0042.HEIC", true));
+        assert!(matches("IMG_????.*", "IMG_0042.HEIC", true));
+        assert!(matches("*", "", true));
+        assert!(matches("a*b*c", "aXXbYYc", true));
+        assert!(!matches("a*b*c", "aXXbYY", true));
+        assert!(!matches("IMG_?", "IMG_", true));
+        assert!(!matches("*.jpg", "beach.jpeg", true));
+    }
+
+    #[test]
+    fn case_folding() {
+        assert!(matches("*.JPG", "beach.jpg", false));
+        assert!(!matches("*.JPG", "beach.jpg", true));
+    }
+}
@@ -3,29 +3,226 @@
 //! This module provides functionality to interact with the file system of iOS devices
 //! through the AFC protocol.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use errors::AfcError;
 use file::FileDescriptor;
 use log::warn;
+use name::{decode_name, encode_path};
 use opcode::{AfcFopenMode, AfcOpcode};
 use packet::{AfcPacket, AfcPacketHeader};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::{lockdown::LockdownClient, Idevice, IdeviceError, IdeviceService};
 
+#[cfg(all(test, feature = "device_tests"))]
+mod device_tests;
 pub mod errors;
 pub mod file;
+pub mod glob;
+pub mod name;
 pub mod opcode;
 pub mod packet;
 
 /// The magic number used in AFC protocol communications
 pub const MAGIC: u64 = 0x4141504c36414643;
 
+/// `GetFileInfo` requests `get_file_info_many` sends before reading answers
+pub const FILE_INFO_PIPELINE: usize = 64;
+
+/// Links in a row `AfcClient::walk` follows before giving up on a chain
+const MAX_LINK_HOPS: usize = 8;
+
+/// Default maximum payload of a single file read/write packet (64KB)
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024; // this is what go-ios uses
+
 /// Client for interacting with the AFC service on iOS devices
+///
+/// A client owns one connection and isn't `Clone`: every operation takes
+/// `&mut self`, so a request and its response can't be split by another
+/// operation. To share a client between tasks, wrap it in an
+/// `Arc<tokio::sync::Mutex<AfcClient>>` and hold the lock for each whole
+/// operation; an open `FileDescriptor` borrows the client, so the lock is
+/// held until the file is closed. The client is `Send` and `Sync`.
 pub struct AfcClient {
     /// The underlying iDevice connection
     pub idevice: Idevice,
     package_number: u64,
+    chunk_size: usize,
+}
+
+/// Which AFC service to connect to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AfcScope {
+    /// `com.apple.afc`, restricted to the media directory
+    #[default]
+    Media,
+    /// `com.apple.afc2`, the full filesystem. Only present on jailbroken devices.
+    Full,
+}
+
+/// Maps an AFC "object not found" error to `Ok(None)`
+fn not_found_as_none<T>(res: Result<T, IdeviceError>) -> Result<Option<T>, IdeviceError> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(IdeviceError::Afc(AfcError::ObjectNotFound)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl AfcScope {
+    /// Returns the lockdown service name for this scope
+    pub fn service_name(&self) -> &'static str {
+        match self {
+            AfcScope::Media => "com.apple.afc",
+            AfcScope::Full => "com.apple.afc2",
+        }
+    }
+}
+
+/// Names in a directory listing, decoded one at a time
+///
+/// AFC returns a whole listing in a single reply; this walks the raw,
+/// NUL-separated payload so callers can process huge directories in
+/// batches instead of building every `String` up front.
+#[derive(Debug)]
+pub struct DirEntries {
+    payload: Vec<u8>,
+    pos: usize,
+}
+
+impl DirEntries {
+    fn new(payload: Vec<u8>) -> Self {
+        Self { payload, pos: 0 }
+    }
+}
+
+impl Iterator for DirEntries {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while self.pos < self.payload.len() {
+            let rest = &self.payload[self.pos..];
+            let len = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+            self.pos += len + 1;
+            if len > 0 {
+                return Some(decode_name(&rest[..len]));
+            }
+        }
+        None
+    }
+}
+
+/// Outcome of `AfcClient::remove_all_with_progress`
+#[derive(Debug, Default)]
+pub struct RemoveSummary {
+    /// Number of files and directories removed
+    pub removed: usize,
+    /// Paths that couldn't be listed or removed, with the error for each
+    pub failed: Vec<(String, IdeviceError)>,
+}
+
+/// Everything under a path, as found by `AfcClient::walk`
+#[derive(Debug, Default)]
+pub struct WalkSummary {
+    /// Each entry with its info, parents before their contents. A followed
+    /// link is listed under its own path with its destination's info; any
+    /// other link with its own
+    pub entries: Vec<(String, FileInfo)>,
+    /// Symbolic links that weren't followed: every link unless following was
+    /// asked for, otherwise those leading back into a directory already
+    /// walked or through too many other links
+    pub skipped_links: Vec<String>,
+    /// Paths that couldn't be inspected or listed, with the error for each
+    pub failed: Vec<(String, IdeviceError)>,
+}
+
+/// Joins a directory listing name onto its directory's path
+fn join_path(dir: &str, name: &str) -> String {
+    format!("{}/{name}", dir.trim_end_matches('/'))
+}
+
+/// `path` made absolute with `.` and `..` components resolved
+fn normalize_path(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Where a symbolic link at `link` pointing to `target` leads
+fn resolve_link_target(link: &str, target: &str) -> String {
+    if target.starts_with('/') {
+        return normalize_path(target);
+    }
+    let dir = link.rsplit_once('/').map_or("", |(dir, _)| dir);
+    normalize_path(&format!("{dir}/{target}"))
+}
+
+/// Reads a `GetFileInfo` response into a `FileInfo`
+fn parse_file_info(res: AfcPacket) -> Result<FileInfo, IdeviceError> {
+    // Decoded like names, since a link target is a path that may be followed
+    let strings: Vec<String> = res
+        .payload
+        .split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(decode_name)
+        .collect();
+
+    let mut kvs: HashMap<String, String> = strings
+        .chunks_exact(2)
+        .map(|chunk| (chunk[0].clone(), chunk[1].clone()))
+        .collect();
+
+    let size = kvs
+        .remove("st_size")
+        .and_then(|x| x.parse::<usize>().ok())
+        .ok_or(IdeviceError::AfcMissingAttribute)?;
+    let blocks = kvs
+        .remove("st_blocks")
+        .and_then(|x| x.parse::<usize>().ok())
+        .ok_or(IdeviceError::AfcMissingAttribute)?;
+
+    let creation = kvs
+        .remove("st_birthtime")
+        .and_then(|x| x.parse::<i64>().ok())
+        .ok_or(IdeviceError::AfcMissingAttribute)?;
+    let creation = chrono::DateTime::from_timestamp_nanos(creation).naive_local();
+
+    let modified = kvs
+        .remove("st_mtime")
+        .and_then(|x| x.parse::<i64>().ok())
+        .ok_or(IdeviceError::AfcMissingAttribute)?;
+    let modified = chrono::DateTime::from_timestamp_nanos(modified).naive_local();
+
+    let st_nlink = kvs
+        .remove("st_nlink")
+        .ok_or(IdeviceError::AfcMissingAttribute)?;
+    let st_ifmt = kvs
+        .remove("st_ifmt")
+        .ok_or(IdeviceError::AfcMissingAttribute)?;
+    let st_link_target = kvs.remove("st_link_target");
+
+    if !kvs.is_empty() {
+        warn!("File info kvs not empty: {kvs:?}");
+    }
+
+    Ok(FileInfo {
+        size,
+        blocks,
+        creation,
+        modified,
+        st_nlink,
+        st_ifmt,
+        st_link_target,
+    })
 }
 
 /// Information about a file on the device
@@ -74,13 +271,63 @@ impl IdeviceService for AfcClient {
     /// A new `AfcClient` instance on success
     async fn connect(
         provider: &dyn crate::provider::IdeviceProvider,
+    ) -> Result<Self, IdeviceError> {
+        Self::connect_scoped(provider, AfcScope::Media).await
+    }
+}
+
+impl AfcClient {
+    /// Connects to the AFC service matching the requested scope
+    ///
+    /// # Arguments
+    /// * `provider` - The iDevice provider to use for the connection
+    /// * `scope` - Whether to use the media-only or full filesystem service
+    ///
+    /// # Returns
+    /// A new `AfcClient` instance on success
+    ///
+    /// # Errors
+    /// Returns `IdeviceError::Afc2Unavailable` if `AfcScope::Full` is requested
+    /// on a device that doesn't expose `com.apple.afc2`
+    pub async fn connect_scoped(
+        provider: &dyn crate::provider::IdeviceProvider,
+        scope: AfcScope,
+    ) -> Result<Self, IdeviceError> {
+        match Self::connect_via(provider, scope.service_name()).await {
+            Err(IdeviceError::InvalidService) if scope == AfcScope::Full => {
+                Err(IdeviceError::Afc2Unavailable)
+            }
+            res => res,
+        }
+    }
+
+    /// Connects to any lockdown service that speaks AFC
+    ///
+    /// Besides `com.apple.afc` and `com.apple.afc2`, services such as
+    /// `com.apple.crashreportcopymobile` expose their files over AFC. For a
+    /// connection that is already set up, such as one vended by house_arrest,
+    /// use `AfcClient::new` instead.
+    ///
+    /// # Arguments
+    /// * `provider` - The iDevice provider to use for the connection
+    /// * `service_name` - Lockdown name of the service to start
+    ///
+    /// # Returns
+    /// A new `AfcClient` instance on success
+    ///
+    /// # Errors
+    /// Returns `IdeviceError::InvalidService` if the device doesn't offer
+    /// `service_name`
+    pub async fn connect_via(
+        provider: &dyn crate::provider::IdeviceProvider,
+        service_name: &str,
     ) -> Result<Self, IdeviceError> {
         let mut lockdown = LockdownClient::connect(provider).await?;
         lockdown
             .start_session(&provider.get_pairing_file().await?)
             .await?;
 
-        let (port, ssl) = lockdown.start_service(Self::service_name()).await?;
+        let (port, ssl) = lockdown.start_service(service_name).await?;
 
         let mut idevice = provider.connect(port).await?;
         if ssl {
@@ -89,14 +336,9 @@ impl IdeviceService for AfcClient {
                 .await?;
         }
 
-        Ok(Self {
-            idevice,
-            package_number: 0,
-        })
+        Ok(Self::new(idevice))
     }
-}
 
-impl AfcClient {
     /// Creates a new AFC client from an existing iDevice connection
     ///
     /// # Arguments
@@ -105,9 +347,25 @@ impl AfcClient {
         Self {
             idevice,
             package_number: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         }
     }
 
+    /// Sets the maximum payload of each packet used by file reads and writes
+    ///
+    /// Larger chunks mean fewer round trips, which helps most on fast USB links.
+    ///
+    /// # Arguments
+    /// * `size` - Chunk size in bytes, clamped to at least 1
+    pub fn set_chunk_size(&mut self, size: usize) {
+        self.chunk_size = size.max(1);
+    }
+
+    /// Returns the chunk size used for file reads and writes
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
     /// Lists the contents of a directory on the device
     ///
     /// # Arguments
@@ -116,8 +374,32 @@ impl AfcClient {
     /// # Returns
     /// A vector of file/directory names in the specified directory
     pub async fn list_dir(&mut self, path: impl Into<String>) -> Result<Vec<String>, IdeviceError> {
+        Ok(self.list_dir_iter(path).await?.collect())
+    }
+
+    /// Lists the contents of a directory, decoding names lazily
+    ///
+    /// # Arguments
+    /// * `path` - Path to the directory to list
+    ///
+    /// # Returns
+    /// An iterator over the file/directory names in the specified directory
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{afc::AfcClient, IdeviceError};
+    /// # async fn example(client: &mut AfcClient) -> Result<(), IdeviceError> {
+    /// let mut entries = client.list_dir_iter("/DCIM").await?;
+    /// let first_page: Vec<String> = entries.by_ref().take(1000).collect();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_dir_iter(
+        &mut self,
+        path: impl Into<String>,
+    ) -> Result<DirEntries, IdeviceError> {
         let path = path.into();
-        let header_payload = path.as_bytes().to_vec();
+        let header_payload = encode_path(&path);
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
 
         let header = AfcPacketHeader {
@@ -137,14 +419,7 @@ impl AfcClient {
 
         self.send(packet).await?;
         let res = self.read().await?;
-
-        let strings: Vec<String> = res
-            .payload
-            .split(|b| *b == 0)
-            .filter(|s| !s.is_empty())
-            .map(|s| String::from_utf8_lossy(s).into_owned())
-            .collect();
-        Ok(strings)
+        Ok(DirEntries::new(res.payload))
     }
 
     /// Creates a new directory on the device
@@ -153,7 +428,7 @@ impl AfcClient {
     /// * `path` - Path of the directory to create
     pub async fn mk_dir(&mut self, path: impl Into<String>) -> Result<(), IdeviceError> {
         let path = path.into();
-        let header_payload = path.as_bytes().to_vec();
+        let header_payload = encode_path(&path);
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
 
         let header = AfcPacketHeader {
@@ -188,8 +463,56 @@ impl AfcClient {
         &mut self,
         path: impl Into<String>,
     ) -> Result<FileInfo, IdeviceError> {
-        let path = path.into();
-        let header_payload = path.as_bytes().to_vec();
+        let packet = self.file_info_request(path.into());
+        self.send(packet).await?;
+        let res = self.read().await?;
+        parse_file_info(res)
+    }
+
+    /// Retrieves information about many files or directories, sending the
+    /// requests in batches of `FILE_INFO_PIPELINE` before reading the answers
+    ///
+    /// The device answers requests on a connection in order, so this saves a
+    /// round trip per path compared to calling `get_file_info` in a loop.
+    ///
+    /// # Arguments
+    /// * `paths` - Paths to look up
+    ///
+    /// # Returns
+    /// One result per path, in the same order; a path the device rejects
+    /// doesn't affect the others
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if the connection itself fails
+    pub async fn get_file_info_many(
+        &mut self,
+        paths: &[String],
+    ) -> Result<Vec<Result<FileInfo, IdeviceError>>, IdeviceError> {
+        let mut infos = Vec::with_capacity(paths.len());
+        for batch in paths.chunks(FILE_INFO_PIPELINE) {
+            let mut requests = Vec::new();
+            for path in batch {
+                requests.extend(self.file_info_request(path.clone()).serialize());
+            }
+            self.idevice.send_raw(&requests).await?;
+
+            for _ in batch {
+                match self.read().await {
+                    Ok(res) => infos.push(parse_file_info(res)),
+                    // Only the status was bad; the stream is still in step
+                    Err(e @ (IdeviceError::Afc(_) | IdeviceError::UnexpectedResponse)) => {
+                        infos.push(Err(e))
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(infos)
+    }
+
+    /// Builds a `GetFileInfo` request for `path`
+    fn file_info_request(&mut self, path: String) -> AfcPacket {
+        let header_payload = encode_path(&path);
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
 
         let header = AfcPacketHeader {
@@ -201,69 +524,40 @@ impl AfcClient {
         };
         self.package_number += 1;
 
-        let packet = AfcPacket {
+        AfcPacket {
             header,
             header_payload,
             payload: Vec::new(),
-        };
-
-        self.send(packet).await?;
-        let res = self.read().await?;
-
-        let strings: Vec<String> = res
-            .payload
-            .split(|b| *b == 0)
-            .filter(|s| !s.is_empty())
-            .map(|s| String::from_utf8_lossy(s).into_owned())
-            .collect();
-
-        let mut kvs: HashMap<String, String> = strings
-            .chunks_exact(2)
-            .map(|chunk| (chunk[0].clone(), chunk[1].clone()))
-            .collect();
-
-        let size = kvs
-            .remove("st_size")
-            .and_then(|x| x.parse::<usize>().ok())
-            .ok_or(IdeviceError::AfcMissingAttribute)?;
-        let blocks = kvs
-            .remove("st_blocks")
-            .and_then(|x| x.parse::<usize>().ok())
-            .ok_or(IdeviceError::AfcMissingAttribute)?;
-
-        let creation = kvs
-            .remove("st_birthtime")
-            .and_then(|x| x.parse::<i64>().ok())
-            .ok_or(IdeviceError::AfcMissingAttribute)?;
-        let creation = chrono::DateTime::from_timestamp_nanos(creation).naive_local();
-
-        let modified = kvs
-            .remove("st_mtime")
-            .and_then(|x| x.parse::<i64>().ok())
-            .ok_or(IdeviceError::AfcMissingAttribute)?;
-        let modified = chrono::DateTime::from_timestamp_nanos(modified).naive_local();
-
-        let st_nlink = kvs
-            .remove("st_nlink")
-            .ok_or(IdeviceError::AfcMissingAttribute)?;
-        let st_ifmt = kvs
-            .remove("st_ifmt")
-            .ok_or(IdeviceError::AfcMissingAttribute)?;
-        let st_link_target = kvs.remove("st_link_target");
-
-        if !kvs.is_empty() {
-            warn!("File info kvs not empty: {kvs:?}");
         }
+    }
 
-        Ok(FileInfo {
-            size,
-            blocks,
-            creation,
-            modified,
-            st_nlink,
-            st_ifmt,
-            st_link_target,
-        })
+    /// Checks whether a file or directory exists
+    ///
+    /// # Arguments
+    /// * `path` - Path to check
+    ///
+    /// # Returns
+    /// `false` if the device reports the object as not found
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` for any failure other than "object not found"
+    pub async fn exists(&mut self, path: impl Into<String>) -> Result<bool, IdeviceError> {
+        Ok(not_found_as_none(self.get_file_info(path).await)?.is_some())
+    }
+
+    /// Checks whether a path is an existing directory
+    ///
+    /// # Arguments
+    /// * `path` - Path to check
+    ///
+    /// # Returns
+    /// `false` if the path is missing or is not a directory
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` for any failure other than "object not found"
+    pub async fn is_dir(&mut self, path: impl Into<String>) -> Result<bool, IdeviceError> {
+        Ok(not_found_as_none(self.get_file_info(path).await)?
+            .is_some_and(|info| info.st_ifmt == "S_IFDIR"))
     }
 
     /// Retrieves information about the device's filesystem
@@ -331,13 +625,21 @@ impl AfcClient {
         })
     }
 
+    /// Returns the free space on the device's filesystem
+    ///
+    /// # Returns
+    /// Free space in bytes, as reported by `get_device_info`
+    pub async fn free_space(&mut self) -> Result<usize, IdeviceError> {
+        Ok(self.get_device_info().await?.free_bytes)
+    }
+
     /// Removes a file or directory
     ///
     /// # Arguments
     /// * `path` - Path to the file or directory to remove
     pub async fn remove(&mut self, path: impl Into<String>) -> Result<(), IdeviceError> {
         let path = path.into();
-        let header_payload = path.as_bytes().to_vec();
+        let header_payload = encode_path(&path);
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
 
         let header = AfcPacketHeader {
@@ -367,7 +669,7 @@ impl AfcClient {
     /// * `path` - Path to the directory to remove
     pub async fn remove_all(&mut self, path: impl Into<String>) -> Result<(), IdeviceError> {
         let path = path.into();
-        let header_payload = path.as_bytes().to_vec();
+        let header_payload = encode_path(&path);
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
 
         let header = AfcPacketHeader {
@@ -391,6 +693,156 @@ impl AfcClient {
         Ok(())
     }
 
+    /// Recursively removes a file or directory one entry at a time
+    ///
+    /// Unlike `remove_all`, the tree is walked on the host so progress can be
+    /// reported, and a failure on one entry doesn't stop the rest from being
+    /// removed. Directories are removed after their contents, so a directory
+    /// holding an entry that failed is reported as failed too.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the file or directory to remove
+    /// * `on_progress` - Called after each entry with the number of entries
+    ///   handled so far, the total, and the entry's path
+    ///
+    /// # Returns
+    /// How many entries were removed and which ones failed
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` only if `path` itself can't be inspected
+    pub async fn remove_all_with_progress(
+        &mut self,
+        path: impl Into<String>,
+        mut on_progress: impl FnMut(usize, usize, &str),
+    ) -> Result<RemoveSummary, IdeviceError> {
+        let path = path.into();
+        let mut summary = RemoveSummary::default();
+
+        // Links are removed rather than followed, so a link can't lead the
+        // removal outside `path`. Parents come before their contents here, so
+        // removing in reverse empties each directory before it is removed
+        let walk = self.walk(path, false).await?;
+        summary.failed = walk.failed;
+        let entries: Vec<String> = walk.entries.into_iter().map(|(entry, _)| entry).collect();
+
+        let total = entries.len();
+        for (done, entry) in entries.iter().rev().enumerate() {
+            match self.remove(entry.as_str()).await {
+                Ok(()) => summary.removed += 1,
+                Err(e) => {
+                    warn!("Failed to remove {entry}: {e}");
+                    summary.failed.push((entry.clone(), e));
+                }
+            }
+            on_progress(done + 1, total, entry);
+        }
+
+        Ok(summary)
+    }
+
+    /// Lists everything under a file or directory, recursively
+    ///
+    /// Symbolic links are reported in `skipped_links` and not descended into
+    /// unless `follow_symlinks` is set. When following, a link back to a
+    /// directory that has already been walked is skipped instead, so a
+    /// sandbox with a link cycle still finishes.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the file or directory to walk
+    /// * `follow_symlinks` - Walk into the destinations of links
+    ///
+    /// # Returns
+    /// Every entry found, the links skipped and the paths that failed
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` only if `path` itself can't be inspected or
+    /// the connection fails
+    pub async fn walk(
+        &mut self,
+        path: impl Into<String>,
+        follow_symlinks: bool,
+    ) -> Result<WalkSummary, IdeviceError> {
+        let path = path.into();
+        let mut summary = WalkSummary::default();
+        let info = self.get_file_info(path.as_str()).await?;
+        // Directories walked so far by where they really are, to notice a
+        // link leading back to one
+        let mut visited = HashSet::new();
+        // (path as reached, path the entry really has, its info)
+        let mut pending = vec![(path.clone(), normalize_path(&path), info)];
+        while let Some((shown, real, info)) = pending.pop() {
+            let (real, info) = if info.st_ifmt == "S_IFLNK" && follow_symlinks {
+                match self.link_destination(&real, &info).await {
+                    Ok(Some((dest, dest_info)))
+                        if !(dest_info.st_ifmt == "S_IFDIR" && visited.contains(&dest)) =>
+                    {
+                        (dest, dest_info)
+                    }
+                    Ok(_) => {
+                        summary.skipped_links.push(shown.clone());
+                        summary.entries.push((shown, info));
+                        continue;
+                    }
+                    Err(e) => {
+                        summary.failed.push((shown, e));
+                        continue;
+                    }
+                }
+            } else {
+                (real, info)
+            };
+
+            if info.st_ifmt == "S_IFLNK" {
+                summary.skipped_links.push(shown.clone());
+            } else if info.st_ifmt == "S_IFDIR" {
+                visited.insert(real.clone());
+                match self.list_dir(real.as_str()).await {
+                    Ok(names) => {
+                        let names: Vec<String> = names
+                            .into_iter()
+                            .filter(|n| n != "." && n != "..")
+                            .collect();
+                        let children: Vec<String> =
+                            names.iter().map(|n| join_path(&real, n)).collect();
+                        let infos = self.get_file_info_many(&children).await?;
+                        for ((name, child), info) in names.iter().zip(children).zip(infos) {
+                            let child_shown = join_path(&shown, name);
+                            match info {
+                                Ok(info) => pending.push((child_shown, child, info)),
+                                Err(e) => summary.failed.push((child_shown, e)),
+                            }
+                        }
+                    }
+                    Err(e) => summary.failed.push((shown.clone(), e)),
+                }
+            }
+            summary.entries.push((shown, info));
+        }
+        Ok(summary)
+    }
+
+    /// Follows the link at `path` through any further links to what it
+    /// finally points at, or `None` if there are more than `MAX_LINK_HOPS`
+    async fn link_destination(
+        &mut self,
+        path: &str,
+        info: &FileInfo,
+    ) -> Result<Option<(String, FileInfo)>, IdeviceError> {
+        let (mut path, mut info) = (path.to_string(), info.clone());
+        for _ in 0..MAX_LINK_HOPS {
+            let target = info
+                .st_link_target
+                .as_deref()
+                .ok_or(IdeviceError::AfcMissingAttribute)?;
+            path = resolve_link_target(&path, target);
+            info = self.get_file_info(path.as_str()).await?;
+            if info.st_ifmt != "S_IFLNK" {
+                return Ok(Some((path, info)));
+            }
+        }
+        Ok(None)
+    }
+
     /// Opens a file on the device
     ///
     /// # Arguments
@@ -406,7 +858,7 @@ impl AfcClient {
     ) -> Result<FileDescriptor, IdeviceError> {
         let path = path.into();
         let mut header_payload = (mode as u64).to_le_bytes().to_vec();
-        header_payload.extend(path.as_bytes());
+        header_payload.extend(encode_path(&path));
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
 
         let header = AfcPacketHeader {
@@ -438,6 +890,76 @@ impl AfcClient {
         })
     }
 
+    /// Copies a file to another path on the device
+    ///
+    /// AFC has no server-side copy, so the contents are streamed through the
+    /// host one chunk at a time. Nothing is written to the host's disk and at
+    /// most one chunk is held in memory.
+    ///
+    /// # Arguments
+    /// * `from` - Path of the file to copy
+    /// * `to` - Destination path, replaced if it exists
+    ///
+    /// # Returns
+    /// The number of bytes copied
+    pub async fn copy(
+        &mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+    ) -> Result<usize, IdeviceError> {
+        let from = from.into();
+        let to = to.into();
+
+        let src = self.open(&from, AfcFopenMode::RdOnly).await?.fd;
+        let dst = match self.open(&to, AfcFopenMode::WrOnly).await {
+            Ok(fd) => fd.fd,
+            Err(e) => {
+                let _ = self.descriptor(src, &from).close().await;
+                return Err(e);
+            }
+        };
+
+        let res = self.stream_fd(src, &from, dst, &to).await;
+        let closed_src = self.descriptor(src, &from).close().await;
+        let closed_dst = self.descriptor(dst, &to).close().await;
+        let copied = res?;
+        closed_src?;
+        closed_dst?;
+        Ok(copied)
+    }
+
+    /// Pumps chunks from one open file into another until the source is exhausted
+    async fn stream_fd(
+        &mut self,
+        src: u64,
+        src_path: &str,
+        dst: u64,
+        dst_path: &str,
+    ) -> Result<usize, IdeviceError> {
+        let chunk_size = self.chunk_size;
+        let mut copied = 0;
+        loop {
+            let chunk = self
+                .descriptor(src, src_path)
+                .read_chunk(chunk_size)
+                .await?;
+            if chunk.is_empty() {
+                return Ok(copied);
+            }
+            self.descriptor(dst, dst_path).write(&chunk).await?;
+            copied += chunk.len();
+        }
+    }
+
+    /// Rebuilds a handle for an already open file descriptor
+    fn descriptor(&mut self, fd: u64, path: &str) -> FileDescriptor<'_> {
+        FileDescriptor {
+            client: self,
+            fd,
+            path: path.to_string(),
+        }
+    }
+
     /// Creates a hard or symbolic link
     ///
     /// # Arguments
@@ -454,9 +976,9 @@ impl AfcClient {
         let source = source.into();
 
         let mut header_payload = (kind as u64).to_le_bytes().to_vec();
-        header_payload.extend(target.as_bytes());
+        header_payload.extend(encode_path(&target));
         header_payload.push(0);
-        header_payload.extend(source.as_bytes());
+        header_payload.extend(encode_path(&source));
         header_payload.push(0);
 
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
@@ -495,9 +1017,9 @@ impl AfcClient {
         let target = target.into();
         let source = source.into();
 
-        let mut header_payload = source.as_bytes().to_vec();
+        let mut header_payload = encode_path(&source);
         header_payload.push(0);
-        header_payload.extend(target.as_bytes());
+        header_payload.extend(encode_path(&target));
         header_payload.push(0);
 
         let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
@@ -523,6 +1045,82 @@ impl AfcClient {
         Ok(())
     }
 
+    /// Writes `data` to `path`, replacing any existing file only once the
+    /// whole upload has succeeded
+    ///
+    /// The data goes to `<path>.uploading` first and is renamed over `path`
+    /// at the end, so a failed or interrupted upload leaves the original file
+    /// untouched. The temporary file is removed if the upload fails; if the
+    /// connection itself is lost it may be left behind.
+    ///
+    /// # Arguments
+    /// * `path` - Destination path on the device
+    /// * `data` - Contents to write
+    /// * `on_progress` - Called after each chunk with the bytes written so far
+    ///   and the total
+    pub async fn upload(
+        &mut self,
+        path: impl Into<String>,
+        data: &[u8],
+        on_progress: impl FnMut(u64, u64),
+    ) -> Result<(), IdeviceError> {
+        self.upload_reader(path, data, data.len() as u64, on_progress)
+            .await
+    }
+
+    /// Like [`upload`](Self::upload), but streams the contents from `reader`
+    /// one chunk at a time, so large files never have to be held in memory
+    ///
+    /// # Arguments
+    /// * `path` - Destination path on the device
+    /// * `reader` - Source of the contents, read until it ends
+    /// * `total` - Expected length, only used for progress
+    /// * `on_progress` - Called after each chunk with the bytes written so far
+    ///   and `total`
+    pub async fn upload_reader(
+        &mut self,
+        path: impl Into<String>,
+        mut reader: impl AsyncRead + Unpin,
+        total: u64,
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Result<(), IdeviceError> {
+        let path = path.into();
+        let temp = format!("{path}.uploading");
+        let chunk_size = self.chunk_size;
+
+        let mut fd = self.open(&temp, AfcFopenMode::WrOnly).await?;
+        let mut written = 0;
+        let mut chunk = Vec::with_capacity(chunk_size);
+        let res = loop {
+            chunk.clear();
+            match (&mut reader)
+                .take(chunk_size as u64)
+                .read_to_end(&mut chunk)
+                .await
+            {
+                Ok(0) => break Ok(()),
+                Ok(_) => {}
+                Err(e) => break Err(IdeviceError::from(e)),
+            }
+            if let Err(e) = fd.write(&chunk).await {
+                break Err(e);
+            }
+            written += chunk.len() as u64;
+            on_progress(written, total);
+        };
+        let closed = fd.close().await;
+        let res = match res.and(closed) {
+            Ok(()) => self.rename(&temp, &path).await,
+            Err(e) => Err(e),
+        };
+        if res.is_err() {
+            if let Err(e) = self.remove(&temp).await {
+                warn!("Failed to remove {temp} after a failed upload: {e}");
+            }
+        }
+        res
+    }
+
     /// Reads a response packet from the device
     ///
     /// # Returns
@@ -555,3 +1153,632 @@ impl AfcClient {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn clients_can_move_between_tasks() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AfcClient>();
+        assert_send_sync::<FileDescriptor<'static>>();
+    }
+
+    /// A fake device's answer to one request: opcode, header payload, payload
+    pub(super) type Reply = (AfcOpcode, Vec<u8>, Vec<u8>);
+
+    pub(super) fn status(code: AfcError) -> Reply {
+        (
+            AfcOpcode::Status,
+            (code as u64).to_le_bytes().to_vec(),
+            Vec::new(),
+        )
+    }
+
+    pub(super) fn data(payload: impl Into<Vec<u8>>) -> Reply {
+        (AfcOpcode::Data, Vec::new(), payload.into())
+    }
+
+    /// `GetFileInfo` payload for a `size` byte entry of type `ifmt`
+    pub(super) fn file_info(size: usize, ifmt: &str) -> Reply {
+        data(format!(
+            "st_size\0{size}\0st_blocks\00\0st_nlink\01\0st_ifmt\0{ifmt}\0\
+             st_mtime\00\0st_birthtime\00\0"
+        ))
+    }
+
+    /// Answers every request with `respond`'s reply until the client hangs up
+    pub(super) async fn serve(mut device: Idevice, mut respond: impl FnMut(AfcPacket) -> Reply) {
+        while let Ok(req) = AfcPacket::read(&mut device).await {
+            let packet_num = req.header.packet_num;
+            let (operation, header_payload, payload) = respond(req);
+            let header_len = header_payload.len() as u64 + AfcPacketHeader::LEN;
+            let res = AfcPacket {
+                header: AfcPacketHeader {
+                    magic: MAGIC,
+                    entire_len: header_len + payload.len() as u64,
+                    header_payload_len: header_len,
+                    packet_num,
+                    operation,
+                },
+                header_payload,
+                payload,
+            };
+            // The client may hang up without waiting for its last reply
+            if device.send_raw(&res.serialize()).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Answers `MakeDir` with success, checking each request arrives whole and
+    /// in packet order, and returns the paths it was sent
+    async fn fake_device(device: Idevice) -> Vec<String> {
+        let mut paths = Vec::new();
+        serve(device, |req| {
+            assert_eq!(req.header.packet_num, paths.len() as u64);
+            assert_eq!(req.header.operation, AfcOpcode::MakeDir);
+            paths.push(String::from_utf8(req.header_payload).unwrap());
+            status(AfcError::Success)
+        })
+        .await;
+        paths
+    }
+
+    /// Answers `GetFileInfo`: paths containing "missing" aren't found, and the
+    /// rest are files as big as their last path component says
+    async fn fake_file_info_device(device: Idevice) {
+        serve(device, |req| {
+            assert_eq!(req.header.operation, AfcOpcode::GetFileInfo);
+            let path = String::from_utf8(req.header_payload).unwrap();
+            if path.contains("missing") {
+                status(AfcError::ObjectNotFound)
+            } else {
+                file_info(path.rsplit('/').next().unwrap().parse().unwrap(), "S_IFREG")
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn file_info_many_keeps_order_past_errors() {
+        // Enough for several pipelined batches
+        let paths: Vec<String> = (0..FILE_INFO_PIPELINE * 2 + 10)
+            .map(|i| {
+                if i % 7 == 3 {
+                    format!("/missing/{i}")
+                } else {
+                    format!("/files/{i}")
+                }
+            })
+            .collect();
+        let (host, device) = tokio::io::duplex(4096);
+        let device = tokio::spawn(fake_file_info_device(Idevice::new(
+            Box::new(device),
+            "fake-device",
+        )));
+        let mut client = AfcClient::new(Idevice::new(Box::new(host), "test"));
+
+        let infos = client.get_file_info_many(&paths).await.unwrap();
+        drop(client);
+        device.await.unwrap();
+        assert_eq!(infos.len(), paths.len());
+        for (i, info) in infos.into_iter().enumerate() {
+            if i % 7 == 3 {
+                assert!(
+                    matches!(info, Err(IdeviceError::Afc(AfcError::ObjectNotFound))),
+                    "{i}: {info:?}"
+                );
+            } else {
+                assert_eq!(info.unwrap().size, i, "{i}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shared_client_does_not_interleave_frames() {
+        const PER_TASK: u64 = 20;
+        // Smaller than a request, so each one is written in several pieces
+        let (host, device) = tokio::io::duplex(64);
+        let device = tokio::spawn(fake_device(Idevice::new(Box::new(device), "fake-device")));
+        let client = Arc::new(Mutex::new(AfcClient::new(Idevice::new(
+            Box::new(host),
+            "test",
+        ))));
+
+        let tasks: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    for i in 0..PER_TASK {
+                        let path = format!("/{}/{i}", name.repeat(100));
+                        client.lock().await.mk_dir(path).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        drop(client);
+
+        let paths = device.await.unwrap();
+        assert_eq!(paths.len() as u64, PER_TASK * 2);
+        for name in ["a", "b"] {
+            let prefix = format!("/{}/", name.repeat(100));
+            let mine: Vec<_> = paths.iter().filter(|p| p.starts_with(&prefix)).collect();
+            assert_eq!(mine.len() as u64, PER_TASK, "{paths:?}");
+        }
+    }
+
+    #[test]
+    fn not_found_maps_to_none() {
+        assert!(matches!(
+            not_found_as_none::<()>(Err(IdeviceError::Afc(AfcError::ObjectNotFound))),
+            Ok(None)
+        ));
+        assert!(matches!(not_found_as_none(Ok(5)), Ok(Some(5))));
+    }
+
+    #[test]
+    fn dir_entries_skip_empty_names() {
+        let entries = DirEntries::new(b".\0..\0DCIM\0\0Books".to_vec());
+        assert_eq!(
+            entries.collect::<Vec<_>>(),
+            vec![".", "..", "DCIM", "Books"]
+        );
+        assert_eq!(DirEntries::new(Vec::new()).count(), 0);
+    }
+
+    /// An AFC client for the first USB device, or `None` unless the
+    /// benchmarks were opted into like the device tests
+    #[cfg(feature = "afc_bench")]
+    async fn bench_client() -> Option<AfcClient> {
+        use crate::usbmuxd::{UsbmuxdAddr, UsbmuxdConnection};
+
+        if std::env::var("IDEVICE_DEVICE_TESTS").as_deref() != Ok("1") {
+            eprintln!("skipping: set IDEVICE_DEVICE_TESTS=1 to run against a device");
+            return None;
+        }
+        let mut mux = UsbmuxdConnection::default().await.unwrap();
+        let dev = mux.get_devices().await.unwrap().remove(0);
+        let provider = dev.to_provider(UsbmuxdAddr::default(), "afc-bench");
+        Some(AfcClient::connect(&provider).await.unwrap())
+    }
+
+    /// Compares `get_file_info_many` with a `get_file_info` loop over the
+    /// media directory's `DCIM` tree of the first USB device. The saving is
+    /// one round trip per entry, so it grows with the link's latency: most
+    /// over Wi-Fi, least on a fast USB connection.
+    ///
+    /// Run with `IDEVICE_DEVICE_TESTS=1 cargo test --features afc_bench -- --nocapture bench_file_info`
+    #[cfg(feature = "afc_bench")]
+    #[tokio::test]
+    async fn bench_file_info_many() {
+        let Some(mut client) = bench_client().await else {
+            return;
+        };
+
+        let mut paths = Vec::new();
+        for dir in client.list_dir("/DCIM").await.unwrap() {
+            if dir == "." || dir == ".." {
+                continue;
+            }
+            let dir = format!("/DCIM/{dir}");
+            if let Ok(names) = client.list_dir(dir.as_str()).await {
+                paths.extend(names.into_iter().map(|n| format!("{dir}/{n}")));
+            }
+        }
+
+        let start = std::time::Instant::now();
+        for path in &paths {
+            let _ = client.get_file_info(path.as_str()).await;
+        }
+        let naive = start.elapsed().as_secs_f64();
+
+        let start = std::time::Instant::now();
+        let infos = client.get_file_info_many(&paths).await.unwrap();
+        let batched = start.elapsed().as_secs_f64();
+
+        assert_eq!(infos.len(), paths.len());
+        println!(
+            "{} paths: loop {:.0} ms, batched {:.0} ms ({:.1}x)",
+            paths.len(),
+            naive * 1000.0,
+            batched * 1000.0,
+            naive / batched
+        );
+    }
+
+    /// Reports throughput for a given chunk size against the first USB device.
+    ///
+    /// Run with `IDEVICE_DEVICE_TESTS=1 AFC_BENCH_CHUNK=<bytes> cargo test --features afc_bench -- --nocapture bench`
+    #[cfg(feature = "afc_bench")]
+    #[tokio::test]
+    async fn bench_chunk_size() {
+        const BENCH_BYTES: usize = 16 * 1024 * 1024;
+        let chunk_size = std::env::var("AFC_BENCH_CHUNK")
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+
+        let Some(mut client) = bench_client().await else {
+            return;
+        };
+        client.set_chunk_size(chunk_size);
+
+        let data = vec![0x5a; BENCH_BYTES];
+        let path = "/afc_bench.bin";
+        let mb = BENCH_BYTES as f64 / (1024.0 * 1024.0);
+
+        let start = std::time::Instant::now();
+        let mut fd = client.open(path, AfcFopenMode::WrOnly).await.unwrap();
+        fd.write(&data).await.unwrap();
+        fd.close().await.unwrap();
+        let write = start.elapsed().as_secs_f64();
+
+        let start = std::time::Instant::now();
+        let mut fd = client.open(path, AfcFopenMode::RdOnly).await.unwrap();
+        let read_back = fd.read().await.unwrap();
+        fd.close().await.unwrap();
+        let read = start.elapsed().as_secs_f64();
+
+        client.remove(path).await.unwrap();
+        assert_eq!(read_back.len(), BENCH_BYTES);
+        println!(
+            "chunk {chunk_size}: write {:.1} MB/s, read {:.1} MB/s",
+            mb / write,
+            mb / read
+        );
+    }
+
+    enum Node {
+        Dir(&'static [&'static str]),
+        File,
+        Link(&'static str),
+    }
+
+    /// Answers `ReadDir` and `GetFileInfo` for the tree in `nodes` until the
+    /// client hangs up, failing if it asks more than `max_requests` times
+    async fn fake_tree_device(
+        device: Idevice,
+        nodes: HashMap<&'static str, Node>,
+        max_requests: usize,
+    ) {
+        let mut requests = 0;
+        serve(device, |req| {
+            requests += 1;
+            assert!(requests <= max_requests, "the walk didn't stop");
+            let path = String::from_utf8(req.header_payload).unwrap();
+            match (req.header.operation, nodes.get(path.as_str())) {
+                (AfcOpcode::ReadDir, Some(Node::Dir(names))) => data(
+                    [".", ".."]
+                        .iter()
+                        .chain(names.iter())
+                        .map(|n| format!("{n}\0"))
+                        .collect::<String>(),
+                ),
+                (AfcOpcode::GetFileInfo, Some(node)) => match node {
+                    Node::Dir(_) => file_info(1, "S_IFDIR"),
+                    Node::File => file_info(1, "S_IFREG"),
+                    Node::Link(target) => {
+                        file_info(1, &format!("S_IFLNK\0st_link_target\0{target}"))
+                    }
+                },
+                _ => status(AfcError::ObjectNotFound),
+            }
+        })
+        .await
+    }
+
+    /// `/d` with links back to itself from two levels, and a link out to
+    /// `/e`, which links to itself
+    fn cyclic_tree() -> HashMap<&'static str, Node> {
+        HashMap::from([
+            ("/d", Node::Dir(&["f", "loop", "sub", "out"])),
+            ("/d/f", Node::File),
+            ("/d/loop", Node::Link("/d")),
+            ("/d/sub", Node::Dir(&["back"])),
+            ("/d/sub/back", Node::Link("..")),
+            ("/d/out", Node::Link("../e")),
+            ("/e", Node::Dir(&["g", "self"])),
+            ("/e/g", Node::File),
+            ("/e/self", Node::Link(".")),
+        ])
+    }
+
+    async fn walk_cyclic_tree(follow_symlinks: bool) -> (Vec<String>, Vec<String>) {
+        let (host, device) = tokio::io::duplex(64 * 1024);
+        let device = tokio::spawn(fake_tree_device(
+            Idevice::new(Box::new(device), "fake-device"),
+            cyclic_tree(),
+            100,
+        ));
+        let mut client = AfcClient::new(Idevice::new(Box::new(host), "test"));
+        let walk = client.walk("/d", follow_symlinks).await.unwrap();
+        drop(client);
+        device.await.unwrap();
+
+        assert!(walk.failed.is_empty(), "{:?}", walk.failed);
+        let mut entries: Vec<String> = walk.entries.into_iter().map(|(p, _)| p).collect();
+        let mut skipped = walk.skipped_links;
+        entries.sort();
+        skipped.sort();
+        (entries, skipped)
+    }
+
+    #[tokio::test]
+    async fn walk_skips_links_by_default() {
+        let (entries, skipped) = walk_cyclic_tree(false).await;
+        assert_eq!(
+            entries,
+            ["/d", "/d/f", "/d/loop", "/d/out", "/d/sub", "/d/sub/back"]
+        );
+        assert_eq!(skipped, ["/d/loop", "/d/out", "/d/sub/back"]);
+    }
+
+    #[tokio::test]
+    async fn walk_breaks_symlink_cycles() {
+        let (entries, skipped) = walk_cyclic_tree(true).await;
+        assert_eq!(
+            entries,
+            [
+                "/d",
+                "/d/f",
+                "/d/loop",
+                "/d/out",
+                "/d/out/g",
+                "/d/out/self",
+                "/d/sub",
+                "/d/sub/back"
+            ]
+        );
+        assert_eq!(skipped, ["/d/loop", "/d/out/self", "/d/sub/back"]);
+    }
+
+    #[test]
+    fn link_targets_resolve_against_their_directory() {
+        assert_eq!(resolve_link_target("/d/sub/back", ".."), "/d");
+        assert_eq!(resolve_link_target("/d/out", "../e"), "/e");
+        assert_eq!(resolve_link_target("/e/self", "."), "/e");
+        assert_eq!(resolve_link_target("/d/loop", "/d/./x/../"), "/d");
+        assert_eq!(resolve_link_target("/top", "../../.."), "/");
+    }
+
+    /// Serves `files` in `/d` for listing, `GetFileInfo`, reading and
+    /// seeking, matching names byte for byte, until the client hangs up
+    pub(super) async fn fake_named_files_device(device: Idevice, files: &[(&[u8], &[u8])]) {
+        let path_of = |name: &[u8]| [b"/d/".as_slice(), name].concat();
+        let found = |path: &[u8]| files.iter().position(|(n, _)| path_of(n) == path);
+        let mut offsets: HashMap<u64, usize> = HashMap::new();
+        serve(device, |req| match req.header.operation {
+            AfcOpcode::ReadDir if req.header_payload == b"/d" => {
+                let mut listing = b".\0..\0".to_vec();
+                for (name, _) in files {
+                    listing.extend_from_slice(name);
+                    listing.push(0);
+                }
+                data(listing)
+            }
+            AfcOpcode::GetFileInfo => match found(&req.header_payload) {
+                Some(i) => file_info(files[i].1.len(), "S_IFREG"),
+                None => status(AfcError::ObjectNotFound),
+            },
+            AfcOpcode::FileOpen => match found(&req.header_payload[8..]) {
+                Some(i) => {
+                    offsets.insert(i as u64, 0);
+                    (
+                        AfcOpcode::FileOpenRes,
+                        (i as u64).to_le_bytes().to_vec(),
+                        Vec::new(),
+                    )
+                }
+                None => status(AfcError::ObjectNotFound),
+            },
+            AfcOpcode::Read => {
+                let fd = u64::from_le_bytes(req.header_payload[..8].try_into().unwrap());
+                let len = u64::from_le_bytes(req.header_payload[8..16].try_into().unwrap());
+                let contents = files[fd as usize].1;
+                let offset = offsets.get_mut(&fd).unwrap();
+                let start = (*offset).min(contents.len());
+                let end = (start + len as usize).min(contents.len());
+                *offset += end - start;
+                data(contents[start..end].to_vec())
+            }
+            AfcOpcode::FileSeek => {
+                let arg = |i: usize| req.header_payload[i * 8..i * 8 + 8].try_into().unwrap();
+                let fd = u64::from_le_bytes(arg(0));
+                let whence = u64::from_le_bytes(arg(1));
+                let delta = i64::from_le_bytes(arg(2));
+                let offset = offsets.get_mut(&fd).unwrap();
+                let base = match whence {
+                    0 => 0,
+                    1 => *offset,
+                    _ => files[fd as usize].1.len(),
+                };
+                match usize::try_from(base as i64 + delta) {
+                    Ok(pos) => {
+                        *offset = pos;
+                        status(AfcError::Success)
+                    }
+                    Err(_) => status(AfcError::InvalidArg),
+                }
+            }
+            AfcOpcode::FileTell => {
+                let fd = u64::from_le_bytes(req.header_payload[..8].try_into().unwrap());
+                (
+                    AfcOpcode::FileTellRes,
+                    (offsets[&fd] as u64).to_le_bytes().to_vec(),
+                    Vec::new(),
+                )
+            }
+            AfcOpcode::FileClose => status(AfcError::Success),
+            _ => status(AfcError::ObjectNotFound),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn unusual_names_list_and_download() {
+        const FILES: &[(&[u8], &[u8])] = &[
+            ("Café 📷.jpg".as_bytes(), b"jpeg data"),
+            (b"raw\xff\xfe.bin", b"not utf-8"),
+        ];
+        let (host, device) = tokio::io::duplex(64 * 1024);
+        let device = tokio::spawn(fake_named_files_device(
+            Idevice::new(Box::new(device), "fake-device"),
+            FILES,
+        ));
+        let mut client = AfcClient::new(Idevice::new(Box::new(host), "test"));
+
+        let names = client.list_dir("/d").await.unwrap();
+        assert!(names.iter().any(|n| n == "Café 📷.jpg"), "{names:?}");
+        for (name, data) in FILES {
+            let listed = names
+                .iter()
+                .find(|n| encode_path(n) == *name)
+                .unwrap_or_else(|| panic!("{name:?} missing from {names:?}"));
+            let path = join_path("/d", listed);
+            assert_eq!(
+                client.get_file_info(path.as_str()).await.unwrap().size,
+                data.len()
+            );
+            let mut fd = client
+                .open(path.as_str(), AfcFopenMode::RdOnly)
+                .await
+                .unwrap();
+            assert_eq!(fd.read().await.unwrap(), *data);
+            fd.close().await.unwrap();
+        }
+        drop(client);
+        device.await.unwrap();
+    }
+
+    type FakeFs = Arc<std::sync::Mutex<HashMap<Vec<u8>, Vec<u8>>>>;
+
+    /// Stores files written to it in `fs`, supporting just what an upload
+    /// needs, until the client hangs up
+    async fn fake_fs_device(device: Idevice, fs: FakeFs) {
+        let mut open: HashMap<u64, Vec<u8>> = HashMap::new();
+        serve(device, |req| {
+            let mut fs = fs.lock().unwrap();
+            let arg =
+                |i: usize| u64::from_le_bytes(req.header_payload[i..i + 8].try_into().unwrap());
+            match req.header.operation {
+                AfcOpcode::FileOpen => {
+                    let path = req.header_payload[8..].to_vec();
+                    fs.insert(path.clone(), Vec::new());
+                    let fd = open.len() as u64 + 1;
+                    open.insert(fd, path);
+                    (
+                        AfcOpcode::FileOpenRes,
+                        fd.to_le_bytes().to_vec(),
+                        Vec::new(),
+                    )
+                }
+                AfcOpcode::Write => {
+                    let path = &open[&arg(0)];
+                    fs.get_mut(path).unwrap().extend_from_slice(&req.payload);
+                    status(AfcError::Success)
+                }
+                AfcOpcode::FileClose => status(AfcError::Success),
+                AfcOpcode::RenamePath => {
+                    let mut parts = req.header_payload.split(|b| *b == 0);
+                    let (from, to) = (parts.next().unwrap(), parts.next().unwrap());
+                    match fs.remove(from) {
+                        Some(contents) => {
+                            fs.insert(to.to_vec(), contents);
+                            status(AfcError::Success)
+                        }
+                        None => status(AfcError::ObjectNotFound),
+                    }
+                }
+                AfcOpcode::RemovePath => match fs.remove(&req.header_payload) {
+                    Some(_) => status(AfcError::Success),
+                    None => status(AfcError::ObjectNotFound),
+                },
+                _ => status(AfcError::OpNotSupported),
+            }
+        })
+        .await
+    }
+
+    fn fake_fs_client(fs: &FakeFs) -> (AfcClient, tokio::task::JoinHandle<()>) {
+        let (host, device) = tokio::io::duplex(64 * 1024);
+        let device = tokio::spawn(fake_fs_device(
+            Idevice::new(Box::new(device), "fake-device"),
+            fs.clone(),
+        ));
+        let mut client = AfcClient::new(Idevice::new(Box::new(host), "test"));
+        client.set_chunk_size(16);
+        (client, device)
+    }
+
+    #[tokio::test]
+    async fn interrupted_upload_keeps_the_original() {
+        let fs: FakeFs = Default::default();
+        fs.lock()
+            .unwrap()
+            .insert(b"/target".to_vec(), b"original".to_vec());
+        let data: Vec<u8> = (0..=255).collect();
+
+        // Drop the upload once its first chunk is written
+        let (mut client, device) = fake_fs_client(&fs);
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut tx = Some(tx);
+        let upload = client.upload_reader("/target", &data[..], data.len() as u64, |_, _| {
+            if let Some(tx) = tx.take() {
+                let _ = tx.send(());
+            }
+        });
+        tokio::select! {
+            res = upload => panic!("upload finished before it was interrupted: {res:?}"),
+            _ = rx => {}
+        }
+        drop(client);
+        device.await.unwrap();
+        assert_eq!(fs.lock().unwrap()[b"/target".as_slice()], b"original");
+
+        let (mut client, device) = fake_fs_client(&fs);
+        let mut progress = Vec::new();
+        client
+            .upload_reader("/target", &data[..], data.len() as u64, |done, total| {
+                progress.push((done, total))
+            })
+            .await
+            .unwrap();
+        drop(client);
+        device.await.unwrap();
+
+        let fs = fs.lock().unwrap();
+        assert_eq!(fs[b"/target".as_slice()], data);
+        assert!(!fs.contains_key(b"/target.uploading".as_slice()));
+        assert_eq!(progress.len(), data.len() / 16);
+        assert_eq!(progress.last(), Some(&(256, 256)));
+    }
+
+    #[test]
+    fn join_path_handles_root_and_trailing_slash() {
+        assert_eq!(join_path("/", "DCIM"), "/DCIM");
+        assert_eq!(join_path("/DCIM/", "100APPLE"), "/DCIM/100APPLE");
+        assert_eq!(join_path("/DCIM", "100APPLE"), "/DCIM/100APPLE");
+    }
+
+    #[test]
+    fn other_errors_are_kept() {
+        assert!(matches!(
+            not_found_as_none::<()>(Err(IdeviceError::Afc(AfcError::PermDenied))),
+            Err(IdeviceError::Afc(AfcError::PermDenied))
+        ));
+        assert!(matches!(
+            not_found_as_none::<()>(Err(IdeviceError::UnexpectedResponse)),
+            Err(IdeviceError::UnexpectedResponse)
+        ));
+    }
+}
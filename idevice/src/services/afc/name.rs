@@ -0,0 +1,115 @@
+//! Lossless conversion between AFC's byte paths and `String`
+//!
+//! The device sends names as raw bytes, which are nearly always UTF-8 but
+//! aren't guaranteed to be. Decoding them lossily would make such files
+//! impossible to open or delete, since the replacement characters don't
+//! name anything on the device. Instead each byte that isn't part of valid
+//! UTF-8 becomes a character from the end of Unicode's Supplementary Private
+//! Use Area-B, and turns back into the same byte when the path is sent.
+//!
+//! A name that really contains one of those 256 characters has its UTF-8
+//! bytes escaped the same way, so it also goes back to the device unchanged.
+//! Only paths built by hand can't use them literally.
+
+use std::borrow::Cow;
+
+/// Character standing in for raw byte `b` is `RAW_BYTE_BASE + b`
+const RAW_BYTE_BASE: u32 = 0x10FF00;
+
+/// Decodes a name or path from the device, keeping any invalid bytes
+///
+/// # Example
+/// ```rust
+/// use idevice::afc::name::{decode_name, encode_path};
+///
+/// let name = decode_name(b"caf\xc3\xa9 \xff.txt");
+/// assert_eq!(encode_path(&name), b"caf\xc3\xa9 \xff.txt");
+/// ```
+pub fn decode_name(bytes: &[u8]) -> String {
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if raw_byte(c).is_some() {
+                // Would read back as a single raw byte, so keep its bytes
+                let mut buf = [0; 4];
+                name.extend(c.encode_utf8(&mut buf).bytes().map(raw_byte_char));
+            } else {
+                name.push(c);
+            }
+        }
+        name.extend(chunk.invalid().iter().map(|b| raw_byte_char(*b)));
+    }
+    name
+}
+
+/// Encodes a path for the device, turning decoded raw bytes back into bytes
+pub fn encode_path(path: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(path.len());
+    for c in path.chars() {
+        match raw_byte(c) {
+            Some(b) => bytes.push(b),
+            None => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+/// `name` for showing to a person, with raw bytes as U+FFFD
+pub fn display_name(name: &str) -> Cow<'_, str> {
+    if !name.chars().any(|c| raw_byte(c).is_some()) {
+        return Cow::Borrowed(name);
+    }
+    Cow::Owned(
+        name.chars()
+            .map(|c| match raw_byte(c) {
+                Some(_) => char::REPLACEMENT_CHARACTER,
+                None => c,
+            })
+            .collect(),
+    )
+}
+
+fn raw_byte_char(b: u8) -> char {
+    // Every value up to U+10FFFF outside the surrogates is a char
+    char::from_u32(RAW_BYTE_BASE + b as u32).unwrap()
+}
+
+fn raw_byte(c: char) -> Option<u8> {
+    (c as u32)
+        .checked_sub(RAW_BYTE_BASE)
+        .and_then(|b| u8::try_from(b).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names_are_unchanged() {
+        let name = "Café 📷.jpg";
+        assert_eq!(decode_name(name.as_bytes()), name);
+        assert_eq!(encode_path(name), name.as_bytes());
+        assert_eq!(display_name(name), name);
+    }
+
+    #[test]
+    fn invalid_bytes_round_trip() {
+        let bytes = b"/DCIM/bad\xff\xc3(\xe2\x82.jpg";
+        let name = decode_name(bytes);
+        assert_eq!(encode_path(&name), bytes);
+        assert_eq!(
+            display_name(&name),
+            "/DCIM/bad\u{fffd}\u{fffd}(\u{fffd}\u{fffd}.jpg"
+        );
+    }
+
+    #[test]
+    fn escape_range_characters_round_trip() {
+        let name = "/notes/\u{10FF41}\u{10FFFF}.txt";
+        let decoded = decode_name(name.as_bytes());
+        assert_eq!(encode_path(&decoded), name.as_bytes());
+    }
+}
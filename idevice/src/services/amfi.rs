@@ -81,6 +81,12 @@ impl AmfiClient {
     }
 
     /// Enables developer mode, triggering a reboot on iOS 18+
+    ///
+    /// The device restarts straight away and, once unlocked, asks the user
+    /// to confirm turning Developer Mode on. Devices with a passcode refuse;
+    /// use `reveal_developer_mode_option_in_ui` and have the user turn it on
+    /// in Settings instead. `LockdownClient::developer_mode_status` reports
+    /// the result.
     pub async fn enable_developer_mode(&mut self) -> Result<(), IdeviceError> {
         let mut request = Dictionary::new();
         request.insert("action".into(), 1.into());
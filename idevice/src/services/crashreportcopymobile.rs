@@ -36,32 +36,11 @@ impl IdeviceService for CrashReportCopyMobileClient {
     ///
     /// # Errors
     /// Returns `IdeviceError` if the connection fails at any stage.
-    ///
-    /// # Process
-    /// 1. Connects to the lockdownd service.
-    /// 2. Starts a lockdown session.
-    /// 3. Requests the CrashReportCopyMobile service.
-    /// 4. Establishes a connection to the service.
-    /// 5. Performs SSL handshake if required.
     async fn connect(
         provider: &dyn crate::provider::IdeviceProvider,
     ) -> Result<Self, IdeviceError> {
-        let mut lockdown = LockdownClient::connect(provider).await?;
-        lockdown
-            .start_session(&provider.get_pairing_file().await?)
-            .await?;
-
-        let (port, ssl) = lockdown.start_service(Self::service_name()).await?;
-
-        let mut idevice = provider.connect(port).await?;
-        if ssl {
-            idevice
-                .start_session(&provider.get_pairing_file().await?)
-                .await?;
-        }
-
         Ok(Self {
-            afc_client: AfcClient::new(idevice),
+            afc_client: AfcClient::connect_via(provider, Self::service_name()).await?,
         })
     }
 }
@@ -98,6 +77,38 @@ impl CrashReportCopyMobileClient {
         Ok(res)
     }
 
+    /// Lists crash report files in the crash logs directory and every
+    /// directory below it, such as `Retired`.
+    ///
+    /// # Returns
+    /// Paths relative to the crash logs directory, e.g. `Retired/App-2025-01-01-101010.ips`.
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if listing a directory fails.
+    pub async fn ls_recursive(&mut self) -> Result<Vec<String>, IdeviceError> {
+        let mut reports = Vec::new();
+        let mut dirs = vec![String::new()];
+        while let Some(dir) = dirs.pop() {
+            for name in self.afc_client.list_dir(format!("/{dir}")).await? {
+                if name == "." || name == ".." || name.is_empty() {
+                    continue;
+                }
+                let path = if dir.is_empty() {
+                    name
+                } else {
+                    format!("{dir}/{name}")
+                };
+                if self.afc_client.is_dir(format!("/{path}")).await? {
+                    dirs.push(path);
+                } else {
+                    reports.push(path);
+                }
+            }
+        }
+        reports.sort();
+        Ok(reports)
+    }
+
     /// Retrieves the contents of a specified crash log file.
     ///
     /// # Arguments
@@ -115,7 +126,26 @@ impl CrashReportCopyMobileClient {
             .open(format!("/{log}"), crate::afc::opcode::AfcFopenMode::RdOnly)
             .await?;
 
-        f.read().await
+        let res = f.read().await?;
+        f.close().await?;
+        Ok(res)
+    }
+
+    /// Retrieves a crash log and removes it from the device, so it won't be
+    /// pulled again.
+    ///
+    /// The log is only removed once it has been read in full.
+    ///
+    /// # Arguments
+    /// * `log` - Name of the log file to retrieve.
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if the file cannot be read or deleted.
+    pub async fn take(&mut self, log: impl Into<String>) -> Result<Vec<u8>, IdeviceError> {
+        let log = log.into();
+        let res = self.pull(log.as_str()).await?;
+        self.remove(log).await?;
+        Ok(res)
     }
 
     /// Removes a specified crash log file from the device.
@@ -136,6 +166,39 @@ impl CrashReportCopyMobileClient {
     }
 }
 
+/// Name of the process a crash report is about, taken from its file name.
+///
+/// Reports are named `<process>-<YYYY>-<MM>-<DD>-<HHMMSS>[.<n>].<ext>`; the
+/// process name may itself contain dashes and dots. Names that don't follow
+/// the pattern are returned without their extension.
+///
+/// # Example
+/// ```rust
+/// # use idevice::crashreportcopymobile::report_process_name;
+/// assert_eq!(report_process_name("Retired/My-App-2025-01-31-101010.ips"), "My-App");
+/// ```
+pub fn report_process_name(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    // The optional counter after the time
+    let dated = match stem.rsplit_once('.') {
+        Some((dated, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => dated,
+        _ => stem,
+    };
+    let mut parts = dated.rsplitn(5, '-');
+    let date: Vec<&str> = parts.by_ref().take(4).collect();
+    match parts.next() {
+        Some(process)
+            if !process.is_empty()
+                && date.len() == 4
+                && date.iter().all(|p| p.bytes().all(|b| b.is_ascii_digit())) =>
+        {
+            process
+        }
+        _ => stem,
+    }
+}
+
 const EXPECTED_FLUSH: [u8; 4] = [0x70, 0x69, 0x6E, 0x67]; // 'ping'
 
 /// Triggers a flush of crash logs from system storage.
@@ -180,3 +243,45 @@ pub async fn flush_reports(
         Err(IdeviceError::CrashReportMoverBadResponse(res))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_name_from_report() {
+        assert_eq!(
+            report_process_name("SpringBoard-2025-01-31-101010.ips"),
+            "SpringBoard"
+        );
+        assert_eq!(
+            report_process_name("Retired/My-App-2025-01-31-101010.000.ips"),
+            "My-App"
+        );
+    }
+
+    #[test]
+    fn process_name_keeps_dots() {
+        assert_eq!(
+            report_process_name("com.apple.WebKit.WebContent-2025-01-31-101010.ips"),
+            "com.apple.WebKit.WebContent"
+        );
+        assert_eq!(
+            report_process_name("com.apple.WebKit.WebContent-2025-01-31-101010.002.ips"),
+            "com.apple.WebKit.WebContent"
+        );
+        assert_eq!(
+            report_process_name("com.example.app.ips"),
+            "com.example.app"
+        );
+    }
+
+    #[test]
+    fn process_name_falls_back_to_stem() {
+        assert_eq!(report_process_name("JetsamEvent.ips"), "JetsamEvent");
+        assert_eq!(
+            report_process_name("log-aggregated-2025.ips"),
+            "log-aggregated-2025"
+        );
+    }
+}
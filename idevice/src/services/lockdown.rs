@@ -53,6 +53,30 @@ struct LockdownRequest {
     request: String,
 }
 
+/// Battery state from the `com.apple.mobile.battery` domain
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BatteryInfo {
+    /// Charge level in percent
+    #[serde(rename = "BatteryCurrentCapacity")]
+    pub level: u8,
+    #[serde(rename = "BatteryIsCharging", default)]
+    pub is_charging: bool,
+    #[serde(rename = "FullyCharged", default)]
+    pub fully_charged: bool,
+}
+
+/// Whether the device's Developer Mode is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeveloperModeStatus {
+    Enabled,
+    /// Debugging, Instruments and other developer services are refused until
+    /// it's turned on
+    Disabled,
+    /// The device predates Developer Mode (iOS 15 and earlier), so nothing is
+    /// gated behind it
+    NotApplicable,
+}
+
 impl LockdownClient {
     /// The default TCP port for the lockdown service
     pub const LOCKDOWND_PORT: u16 = 62078;
@@ -89,12 +113,115 @@ impl LockdownClient {
         key: impl Into<String>,
         domain: Option<String>,
     ) -> Result<Value, IdeviceError> {
-        let key = key.into();
+        let message = self.send_get_value(Some(key.into()), domain).await?;
+        match message.get("Value") {
+            Some(m) => Ok(m.to_owned()),
+            None => Err(IdeviceError::UnexpectedResponse),
+        }
+    }
 
+    /// Retrieves a value from a domain, or the whole domain without a key
+    ///
+    /// # Arguments
+    /// * `domain` - The domain to read (e.g., "com.apple.mobile.battery")
+    /// * `key` - The value to read from it, or `None` for all of them
+    ///
+    /// # Returns
+    /// The value, or `None` if the device has nothing for it
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - Communication fails
+    /// - The device refuses to read the domain (`GetProhibited`)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{lockdown::LockdownClient, IdeviceError};
+    /// # async fn example(client: &mut LockdownClient) -> Result<(), IdeviceError> {
+    /// let battery = client
+    ///     .get_domain_value("com.apple.mobile.battery", None)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_domain_value(
+        &mut self,
+        domain: impl Into<String>,
+        key: Option<String>,
+    ) -> Result<Option<Value>, IdeviceError> {
+        match self.send_get_value(key, Some(domain.into())).await {
+            Ok(mut message) => Ok(message.remove("Value")),
+            Err(IdeviceError::UnknownErrorType(e)) if e == "MissingValue" => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends a `GetValue` request and returns the device's response
+    async fn send_get_value(
+        &mut self,
+        key: Option<String>,
+        domain: Option<String>,
+    ) -> Result<plist::Dictionary, IdeviceError> {
         let mut request = plist::Dictionary::new();
         request.insert("Label".into(), self.idevice.label.clone().into());
         request.insert("Request".into(), "GetValue".into());
+
+        if let Some(key) = key {
+            request.insert("Key".into(), key.into());
+        }
+        if let Some(domain) = domain {
+            request.insert("Domain".into(), domain.into());
+        }
+
+        self.idevice
+            .send_plist(plist::Value::Dictionary(request))
+            .await?;
+        self.idevice.read_plist().await
+    }
+
+    /// Sets a value on the device
+    ///
+    /// Most keys can only be changed inside an active session and within a
+    /// specific domain.
+    ///
+    /// # Arguments
+    /// * `key` - The name of the value to set (e.g., "EnableWifiConnections")
+    /// * `value` - The new value
+    /// * `domain` - The domain the key belongs to, if any
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - Communication fails
+    /// - The device refuses to change the value (`SetProhibited`)
+    /// - The response is malformed
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{lockdown::LockdownClient, IdeviceError};
+    /// # async fn example(client: &mut LockdownClient) -> Result<(), IdeviceError> {
+    /// client
+    ///     .set_value(
+    ///         "EnableWifiConnections",
+    ///         true.into(),
+    ///         Some("com.apple.mobile.wireless_lockdown".to_string()),
+    ///     )
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_value(
+        &mut self,
+        key: impl Into<String>,
+        value: Value,
+        domain: Option<String>,
+    ) -> Result<(), IdeviceError> {
+        let key = key.into();
+
+        let mut request = plist::Dictionary::new();
+        request.insert("Label".into(), self.idevice.label.clone().into());
+        request.insert("Request".into(), "SetValue".into());
         request.insert("Key".into(), key.into());
+        request.insert("Value".into(), value);
 
         if let Some(domain) = domain {
             request.insert("Domain".into(), domain.into());
@@ -104,12 +231,92 @@ impl LockdownClient {
             .send_plist(plist::Value::Dictionary(request))
             .await?;
         let message: plist::Dictionary = self.idevice.read_plist().await?;
-        match message.get("Value") {
-            Some(m) => Ok(m.to_owned()),
-            None => Err(IdeviceError::UnexpectedResponse),
+        match message.get("Request").and_then(|r| r.as_string()) {
+            Some("SetValue") => Ok(()),
+            _ => Err(IdeviceError::UnexpectedResponse),
         }
     }
 
+    /// Reads the battery state in a single request to the battery domain
+    ///
+    /// # Returns
+    /// The charge level and charging state
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - Communication fails
+    /// - The device doesn't expose the domain (`BatteryInfoUnavailable`)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{lockdown::LockdownClient, IdeviceError};
+    /// # async fn example(client: &mut LockdownClient) -> Result<(), IdeviceError> {
+    /// let battery = client.battery_info().await?;
+    /// println!("{}%, charging: {}", battery.level, battery.is_charging);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn battery_info(&mut self) -> Result<BatteryInfo, IdeviceError> {
+        let value = match self.get_domain_value(BATTERY_DOMAIN, None).await {
+            Ok(v) => v,
+            // Restricted on some devices and OS versions
+            Err(IdeviceError::GetProhibited) => return Err(IdeviceError::BatteryInfoUnavailable),
+            Err(e) => return Err(e),
+        };
+        parse_battery_info(value.as_ref())
+    }
+
+    /// Checks whether the device's screen is locked behind its passcode
+    ///
+    /// Reads `PasswordProtected`, which lockdown reports as true while a
+    /// device with a passcode is locked, so polling it costs one request on
+    /// an open session.
+    ///
+    /// # Returns
+    /// `true` if the device is locked, including when lockdown refuses the
+    /// read because of it
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - Communication fails
+    /// - The response is malformed
+    pub async fn is_locked(&mut self) -> Result<bool, IdeviceError> {
+        match self.get_value("PasswordProtected", None).await {
+            Ok(Value::Boolean(locked)) => Ok(locked),
+            Ok(_) => Err(IdeviceError::UnexpectedResponse),
+            Err(e) if is_lock_refusal(&e) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads whether Developer Mode is on, which iOS 16 and later require for
+    /// developer services such as debugging and Instruments
+    ///
+    /// # Returns
+    /// The status, `NotApplicable` on devices without Developer Mode
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - Communication fails
+    /// - The response is malformed
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{lockdown::{DeveloperModeStatus, LockdownClient}, IdeviceError};
+    /// # async fn example(client: &mut LockdownClient) -> Result<(), IdeviceError> {
+    /// if client.developer_mode_status().await? == DeveloperModeStatus::Disabled {
+    ///     println!("Turn on Developer Mode first");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn developer_mode_status(&mut self) -> Result<DeveloperModeStatus, IdeviceError> {
+        let value = self
+            .get_domain_value(AMFI_DOMAIN, Some("DeveloperModeStatus".into()))
+            .await?;
+        parse_developer_mode_status(value.as_ref())
+    }
+
     /// Retrieves all available values from the device
     ///
     /// # Returns
@@ -344,6 +551,105 @@ impl LockdownClient {
             }
         }
     }
+
+    /// Removes the device's trust in a pairing record
+    ///
+    /// The record's certificates stop working for `start_session`; the
+    /// caller is responsible for deleting its copy of the file. Unpairing a
+    /// record the device no longer knows succeeds, so this can be retried.
+    ///
+    /// # Arguments
+    /// * `pairing_file` - The record to revoke
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if communication fails or the device refuses
+    pub async fn unpair(
+        &mut self,
+        pairing_file: &crate::pairing_file::PairingFile,
+    ) -> Result<(), IdeviceError> {
+        // The host ID is all the device matches the record on
+        let mut pair_record = plist::Dictionary::new();
+        pair_record.insert("HostID".into(), pairing_file.host_id.clone().into());
+
+        let mut req = plist::Dictionary::new();
+        req.insert("Label".into(), self.idevice.label.clone().into());
+        req.insert("Request".into(), "Unpair".into());
+        req.insert("PairRecord".into(), plist::Value::Dictionary(pair_record));
+        req.insert("ProtocolVersion".into(), "2".into());
+
+        self.idevice.send_plist(req.into()).await?;
+        match self.idevice.read_plist().await {
+            Ok(_) | Err(IdeviceError::InvalidHostID) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Lockdown domain holding the battery keys
+const BATTERY_DOMAIN: &str = "com.apple.mobile.battery";
+
+/// Lockdown domain holding the Developer Mode state
+const AMFI_DOMAIN: &str = "com.apple.security.mac.amfi";
+
+/// Reads the `DeveloperModeStatus` value; older devices don't have one
+fn parse_developer_mode_status(value: Option<&Value>) -> Result<DeveloperModeStatus, IdeviceError> {
+    match value {
+        Some(Value::Boolean(true)) => Ok(DeveloperModeStatus::Enabled),
+        Some(Value::Boolean(false)) => Ok(DeveloperModeStatus::Disabled),
+        None => Ok(DeveloperModeStatus::NotApplicable),
+        Some(_) => Err(IdeviceError::UnexpectedResponse),
+    }
+}
+
+/// Reads a battery domain dictionary; a missing or incomplete one means the
+/// device doesn't report its battery
+fn parse_battery_info(value: Option<&Value>) -> Result<BatteryInfo, IdeviceError> {
+    match value {
+        Some(v @ Value::Dictionary(_)) => {
+            plist::from_value(v).map_err(|_| IdeviceError::BatteryInfoUnavailable)
+        }
+        _ => Err(IdeviceError::BatteryInfoUnavailable),
+    }
+}
+
+/// Checks whether the device is locked behind its passcode.
+///
+/// Connects and starts a session for a one-off check; callers that keep a
+/// session open should use [`LockdownClient::is_locked`] on it instead.
+///
+/// # Arguments
+/// * `provider` - The device provider used for connection and pairing info.
+///
+/// # Returns
+/// `Ok(true)` if the device refused because it's locked. A device without a
+/// pairing record can't be checked and is reported as unlocked.
+///
+/// # Errors
+/// Returns `IdeviceError` if the device can't be reached or refuses for
+/// another reason
+pub async fn is_device_locked(
+    provider: &dyn crate::provider::IdeviceProvider,
+) -> Result<bool, IdeviceError> {
+    let pairing_file = match provider.get_pairing_file().await {
+        Ok(p) => p,
+        Err(_) => return Ok(false),
+    };
+    let mut lockdown = LockdownClient::connect(provider).await?;
+    match lockdown.start_session(&pairing_file).await {
+        Ok(()) => lockdown.is_locked().await,
+        Err(e) if is_lock_refusal(&e) => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `e` is lockdown refusing a request because the device is locked
+pub fn is_lock_refusal(e: &IdeviceError) -> bool {
+    match e {
+        IdeviceError::DeviceLocked => true,
+        #[cfg(feature = "pair")]
+        IdeviceError::PasswordProtected => true,
+        _ => false,
+    }
 }
 
 impl From<Idevice> for LockdownClient {
@@ -352,3 +658,55 @@ impl From<Idevice> for LockdownClient {
         Self::new(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery_info_parses_domain() {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("BatteryCurrentCapacity".into(), 87.into());
+        dict.insert("BatteryIsCharging".into(), true.into());
+        dict.insert("ExternalConnected".into(), true.into());
+        let info = parse_battery_info(Some(&Value::Dictionary(dict))).unwrap();
+        assert_eq!(
+            info,
+            BatteryInfo {
+                level: 87,
+                is_charging: true,
+                fully_charged: false
+            }
+        );
+    }
+
+    #[test]
+    fn battery_info_missing_is_unavailable() {
+        assert!(matches!(
+            parse_battery_info(None),
+            Err(IdeviceError::BatteryInfoUnavailable)
+        ));
+        assert!(matches!(
+            parse_battery_info(Some(&Value::Dictionary(plist::Dictionary::new()))),
+            Err(IdeviceError::BatteryInfoUnavailable)
+        ));
+    }
+
+    #[test]
+    fn developer_mode_status_parses() {
+        let status = |v: Option<Value>| parse_developer_mode_status(v.as_ref());
+        assert_eq!(
+            status(Some(true.into())).unwrap(),
+            DeveloperModeStatus::Enabled
+        );
+        assert_eq!(
+            status(Some(false.into())).unwrap(),
+            DeveloperModeStatus::Disabled
+        );
+        assert_eq!(status(None).unwrap(), DeveloperModeStatus::NotApplicable);
+        assert!(matches!(
+            status(Some("yes".into())),
+            Err(IdeviceError::UnexpectedResponse)
+        ));
+    }
+}
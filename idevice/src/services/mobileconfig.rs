@@ -0,0 +1,534 @@
+//! iOS Configuration Profile (MCInstall) Client
+//!
+//! Provides functionality for interacting with the MCInstall service on iOS
+//! devices, which manages installed configuration profiles.
+
+use log::warn;
+use plist::{Dictionary, Value};
+use serde::Deserialize;
+
+use crate::{lockdown::LockdownClient, Idevice, IdeviceError, IdeviceService};
+
+/// Client for interacting with the iOS MCInstall service
+///
+/// Configuration profiles are the `.mobileconfig` payloads shown under
+/// Settings > General > VPN & Device Management, not the provisioning
+/// profiles handled by `misagent`.
+pub struct MobileConfigClient {
+    /// The underlying device connection with established MCInstall service
+    pub idevice: Idevice,
+}
+
+/// A configuration profile installed on the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileInfo {
+    /// The profile's `PayloadIdentifier`, used to remove it
+    pub identifier: String,
+    pub uuid: String,
+    pub version: u64,
+    /// Name shown in Settings, if the profile has one
+    pub name: Option<String>,
+    pub organization: Option<String>,
+    pub description: Option<String>,
+    /// The profile was installed as non-removable, usually by an MDM
+    /// server on a supervised device
+    pub removal_disallowed: bool,
+    pub encrypted: bool,
+}
+
+impl ProfileInfo {
+    /// Whether `remove_profile` can be expected to succeed
+    pub fn is_removable(&self) -> bool {
+        !self.removal_disallowed && !self.encrypted
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfileMetadata {
+    #[serde(rename = "PayloadUUID", default)]
+    uuid: String,
+    #[serde(rename = "PayloadVersion", default = "default_version")]
+    version: u64,
+    #[serde(rename = "PayloadDisplayName")]
+    name: Option<String>,
+    #[serde(rename = "PayloadOrganization")]
+    organization: Option<String>,
+    #[serde(rename = "PayloadDescription")]
+    description: Option<String>,
+    #[serde(rename = "PayloadRemovalDisallowed", default)]
+    removal_disallowed: bool,
+}
+
+fn default_version() -> u64 {
+    1
+}
+
+/// How the device took a profile sent with `install_profile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileInstall {
+    /// The profile is installed
+    Installed,
+    /// The device refused to install it directly but kept it for the user to
+    /// install in Settings, as iOS 12.2 and later do for unsupervised
+    /// devices
+    NeedsUserApproval,
+}
+
+impl IdeviceService for MobileConfigClient {
+    /// Returns the MCInstall service name as registered with lockdownd
+    fn service_name() -> &'static str {
+        "com.apple.mobile.MCInstall"
+    }
+
+    /// Establishes a connection to the MCInstall service
+    ///
+    /// # Arguments
+    /// * `provider` - Device connection provider
+    ///
+    /// # Returns
+    /// A connected `MobileConfigClient` instance
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if any step of the connection process fails
+    async fn connect(
+        provider: &dyn crate::provider::IdeviceProvider,
+    ) -> Result<Self, IdeviceError> {
+        let mut lockdown = LockdownClient::connect(provider).await?;
+        lockdown
+            .start_session(&provider.get_pairing_file().await?)
+            .await?;
+        let (port, ssl) = lockdown.start_service(Self::service_name()).await?;
+
+        let mut idevice = provider.connect(port).await?;
+        if ssl {
+            idevice
+                .start_session(&provider.get_pairing_file().await?)
+                .await?;
+        }
+
+        Ok(Self::new(idevice))
+    }
+}
+
+impl MobileConfigClient {
+    /// Creates a new MCInstall client from an existing device connection
+    ///
+    /// # Arguments
+    /// * `idevice` - Pre-established device connection
+    pub fn new(idevice: Idevice) -> Self {
+        Self { idevice }
+    }
+
+    /// Lists the configuration profiles installed on the device
+    ///
+    /// # Returns
+    /// The profiles in the order the device reports them
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - Communication fails
+    /// - The device rejects the request (`MobileConfigFailure`)
+    /// - The response is malformed
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{mobileconfig::MobileConfigClient, IdeviceError};
+    /// # async fn example(client: &mut MobileConfigClient) -> Result<(), IdeviceError> {
+    /// for profile in client.get_profile_list().await? {
+    ///     println!("{}: {:?}", profile.identifier, profile.name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_profile_list(&mut self) -> Result<Vec<ProfileInfo>, IdeviceError> {
+        let mut req = Dictionary::new();
+        req.insert("RequestType".into(), "GetProfileList".into());
+        self.idevice.send_plist(Value::Dictionary(req)).await?;
+
+        let res = self.idevice.read_plist().await?;
+        parse_profile_list(res)
+    }
+
+    /// Removes the configuration profile with the given identifier
+    ///
+    /// # Arguments
+    /// * `identifier` - The profile's `PayloadIdentifier`
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - No installed profile has this identifier (`NotFound`)
+    /// - The profile can't be removed because it's locked or the device is
+    ///   supervised (`ProfileRemovalDisallowed`)
+    /// - The device rejects the removal (`MobileConfigFailure`)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::{mobileconfig::MobileConfigClient, IdeviceError};
+    /// # async fn example(client: &mut MobileConfigClient) -> Result<(), IdeviceError> {
+    /// client.remove_profile("com.example.wifi").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn remove_profile(&mut self, identifier: &str) -> Result<(), IdeviceError> {
+        // The device wants the UUID and version as well as the identifier
+        let profile = self
+            .get_profile_list()
+            .await?
+            .into_iter()
+            .find(|p| p.identifier == identifier)
+            .ok_or(IdeviceError::NotFound)?;
+        if profile.removal_disallowed {
+            return Err(IdeviceError::ProfileRemovalDisallowed);
+        }
+
+        let mut target = Dictionary::new();
+        target.insert("PayloadType".into(), "Configuration".into());
+        target.insert("PayloadIdentifier".into(), profile.identifier.into());
+        target.insert("PayloadUUID".into(), profile.uuid.into());
+        target.insert("PayloadVersion".into(), profile.version.into());
+        let mut data = Vec::new();
+        plist::to_writer_xml(&mut data, &target)?;
+
+        let mut req = Dictionary::new();
+        req.insert("RequestType".into(), "RemoveProfile".into());
+        req.insert("ProfileIdentifier".into(), Value::Data(data));
+        self.idevice.send_plist(Value::Dictionary(req)).await?;
+
+        let res = self.idevice.read_plist().await?;
+        check_status(&res)
+    }
+
+    /// Installs a configuration profile
+    ///
+    /// If the device won't install it directly, the profile is stored for
+    /// the user to finish installing in Settings instead.
+    ///
+    /// # Arguments
+    /// * `data` - The contents of a `.mobileconfig` file, signed or not
+    ///
+    /// # Returns
+    /// Whether the profile was installed or is waiting for the user
+    ///
+    /// # Errors
+    /// Returns `IdeviceError` if:
+    /// - `data` isn't a configuration profile (`InvalidProfile`)
+    /// - The device rejects the profile (`MobileConfigFailure`)
+    /// - Communication fails
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::mobileconfig::{MobileConfigClient, ProfileInstall};
+    /// # async fn example(client: &mut MobileConfigClient) -> Result<(), Box<dyn std::error::Error>> {
+    /// let data = std::fs::read("wifi.mobileconfig")?;
+    /// if client.install_profile(&data).await? == ProfileInstall::NeedsUserApproval {
+    ///     println!("Finish installing the profile in Settings");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn install_profile(&mut self, data: &[u8]) -> Result<ProfileInstall, IdeviceError> {
+        validate_profile(data)?;
+
+        let mut req = Dictionary::new();
+        req.insert("RequestType".into(), "InstallProfile".into());
+        req.insert("Payload".into(), Value::Data(data.to_vec()));
+        self.idevice.send_plist(Value::Dictionary(req)).await?;
+        let res = self.idevice.read_plist().await?;
+        let rejection = match check_status(&res) {
+            Ok(()) => return Ok(ProfileInstall::Installed),
+            Err(e) if !requires_settings_install(&res) => return Err(e),
+            Err(e) => e,
+        };
+
+        // iOS 12.2 and later only install profiles from Settings; storing it
+        // puts it under "Profile Downloaded" there
+        let mut req = Dictionary::new();
+        req.insert("RequestType".into(), "StoreProfile".into());
+        req.insert("ProfileData".into(), Value::Data(data.to_vec()));
+        req.insert("Purpose".into(), "PostSetupInstallation".into());
+        self.idevice.send_plist(Value::Dictionary(req)).await?;
+        match check_status(&self.idevice.read_plist().await?) {
+            Ok(()) => Ok(ProfileInstall::NeedsUserApproval),
+            // The first refusal says more about what's wrong with the profile
+            Err(_) => Err(rejection),
+        }
+    }
+}
+
+/// Checks that `data` looks like a `.mobileconfig`: a plist whose
+/// `PayloadType` is `Configuration`, or a signed one wrapping such a plist
+///
+/// This is only a sanity check so an obviously wrong file isn't sent; the
+/// device does the real validation.
+pub fn validate_profile(data: &[u8]) -> Result<(), IdeviceError> {
+    if let Ok(profile) = plist::from_bytes::<Dictionary>(data) {
+        return match profile.get("PayloadType").and_then(|t| t.as_string()) {
+            Some("Configuration") if profile.contains_key("PayloadIdentifier") => Ok(()),
+            Some("Configuration") => Err(IdeviceError::InvalidProfile(
+                "the profile has no PayloadIdentifier".into(),
+            )),
+            _ => Err(IdeviceError::InvalidProfile(
+                "the plist isn't a configuration profile".into(),
+            )),
+        };
+    }
+    // Signed profiles are a DER-encoded CMS structure around the plist
+    let embeds_plist = |needle: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+    if data.first() == Some(&0x30) && embeds_plist(b"PayloadType") {
+        Ok(())
+    } else {
+        Err(IdeviceError::InvalidProfile(
+            "the file is neither a plist nor a signed profile".into(),
+        ))
+    }
+}
+
+/// Checks an MCInstall response's `Status`, turning a rejection into
+/// `MobileConfigFailure` with the device's explanation
+fn check_status(res: &Dictionary) -> Result<(), IdeviceError> {
+    match res.get("Status").and_then(|s| s.as_string()) {
+        Some("Acknowledged") => Ok(()),
+        Some(status) => {
+            let reason = res
+                .get("ErrorChain")
+                .and_then(|c| c.as_array())
+                .and_then(|c| c.first())
+                .and_then(|e| e.as_dictionary())
+                .and_then(|e| e.get("LocalizedDescription"))
+                .and_then(|d| d.as_string())
+                .unwrap_or(status);
+            Err(IdeviceError::MobileConfigFailure(reason.to_string()))
+        }
+        None => {
+            warn!("MCInstall response had no status");
+            Err(IdeviceError::UnexpectedResponse)
+        }
+    }
+}
+
+/// Whether an `InstallProfile` rejection is iOS 12.2+ refusing to install
+/// profiles outside Settings, rather than a problem with the profile
+///
+/// The device explains it in the untranslated `USEnglishDescription` of the
+/// error chain, which is checked so the result doesn't depend on its language.
+fn requires_settings_install(res: &Dictionary) -> bool {
+    let Some(chain) = res.get("ErrorChain").and_then(|c| c.as_array()) else {
+        return false;
+    };
+    chain
+        .iter()
+        .filter_map(|e| e.as_dictionary())
+        .filter_map(|e| e.get("USEnglishDescription"))
+        .filter_map(|d| d.as_string())
+        .any(|d| d.to_ascii_lowercase().contains("settings"))
+}
+
+fn parse_profile_list(mut res: Dictionary) -> Result<Vec<ProfileInfo>, IdeviceError> {
+    check_status(&res)?;
+    let Some(Value::Dictionary(metadata)) = res.remove("ProfileMetadata") else {
+        warn!("GetProfileList response had no profile metadata");
+        return Err(IdeviceError::UnexpectedResponse);
+    };
+    let manifest = match res.remove("ProfileManifest") {
+        Some(Value::Dictionary(d)) => d,
+        _ => Dictionary::new(),
+    };
+    let identifiers: Vec<String> = match res.remove("OrderedIdentifiers") {
+        Some(Value::Array(ids)) => ids.into_iter().filter_map(|id| id.into_string()).collect(),
+        _ => metadata.keys().cloned().collect(),
+    };
+
+    let mut profiles = Vec::with_capacity(identifiers.len());
+    for identifier in identifiers {
+        let Some(meta) = metadata.get(&identifier) else {
+            warn!("No metadata for profile {identifier}");
+            continue;
+        };
+        let meta: ProfileMetadata = plist::from_value(meta)?;
+        let encrypted = manifest
+            .get(&identifier)
+            .and_then(|m| m.as_dictionary())
+            .and_then(|m| m.get("IsEncrypted"))
+            .and_then(|e| e.as_boolean())
+            .unwrap_or(false);
+        profiles.push(ProfileInfo {
+            identifier,
+            uuid: meta.uuid,
+            version: meta.version,
+            name: meta.name,
+            organization: meta.organization,
+            description: meta.description,
+            removal_disallowed: meta.removal_disallowed,
+            encrypted,
+        });
+    }
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, removal_disallowed: bool) -> Value {
+        let mut meta = Dictionary::new();
+        meta.insert("PayloadUUID".into(), format!("{name}-uuid").into());
+        meta.insert("PayloadVersion".into(), 1.into());
+        meta.insert("PayloadDisplayName".into(), name.into());
+        meta.insert("PayloadOrganization".into(), "Example Org".into());
+        if removal_disallowed {
+            meta.insert("PayloadRemovalDisallowed".into(), true.into());
+        }
+        Value::Dictionary(meta)
+    }
+
+    #[test]
+    fn profile_list_keeps_device_order() {
+        let mut all = Dictionary::new();
+        all.insert("com.example.wifi".into(), metadata("Wi-Fi", false));
+        all.insert("com.example.mdm".into(), metadata("MDM", true));
+        let mut encrypted = Dictionary::new();
+        encrypted.insert("IsEncrypted".into(), true.into());
+        let mut manifest = Dictionary::new();
+        manifest.insert("com.example.wifi".into(), Value::Dictionary(encrypted));
+
+        let mut res = Dictionary::new();
+        res.insert("Status".into(), "Acknowledged".into());
+        res.insert(
+            "OrderedIdentifiers".into(),
+            Value::Array(vec!["com.example.mdm".into(), "com.example.wifi".into()]),
+        );
+        res.insert("ProfileMetadata".into(), Value::Dictionary(all));
+        res.insert("ProfileManifest".into(), Value::Dictionary(manifest));
+
+        let profiles = parse_profile_list(res).unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].identifier, "com.example.mdm");
+        assert_eq!(profiles[0].name.as_deref(), Some("MDM"));
+        assert_eq!(profiles[0].organization.as_deref(), Some("Example Org"));
+        assert!(profiles[0].removal_disallowed);
+        assert!(!profiles[0].is_removable());
+        assert_eq!(profiles[1].uuid, "Wi-Fi-uuid");
+        assert!(profiles[1].encrypted);
+        assert!(!profiles[1].is_removable());
+    }
+
+    fn profile_plist(payload_type: &str) -> Vec<u8> {
+        let mut profile = Dictionary::new();
+        profile.insert("PayloadType".into(), payload_type.into());
+        profile.insert("PayloadIdentifier".into(), "com.example.wifi".into());
+        profile.insert("PayloadContent".into(), Value::Array(Vec::new()));
+        let mut data = Vec::new();
+        plist::to_writer_xml(&mut data, &profile).unwrap();
+        data
+    }
+
+    #[test]
+    fn validates_plausible_profiles() {
+        assert!(validate_profile(&profile_plist("Configuration")).is_ok());
+
+        let mut signed = vec![0x30, 0x80, 0x06, 0x09];
+        signed.extend(profile_plist("Configuration"));
+        assert!(validate_profile(&signed).is_ok());
+
+        for bad in [
+            profile_plist("com.apple.wifi.managed"),
+            b"not a profile".to_vec(),
+            Vec::new(),
+        ] {
+            assert!(matches!(
+                validate_profile(&bad),
+                Err(IdeviceError::InvalidProfile(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejection_carries_the_device_reason() {
+        let mut error = Dictionary::new();
+        error.insert(
+            "LocalizedDescription".into(),
+            "The profile cannot be removed.".into(),
+        );
+        let mut res = Dictionary::new();
+        res.insert("Status".into(), "Error".into());
+        res.insert(
+            "ErrorChain".into(),
+            Value::Array(vec![Value::Dictionary(error)]),
+        );
+        match check_status(&res) {
+            Err(IdeviceError::MobileConfigFailure(reason)) => {
+                assert_eq!(reason, "The profile cannot be removed.")
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    fn rejection(english: &str) -> Value {
+        let mut error = Dictionary::new();
+        error.insert("LocalizedDescription".into(), "Installation refusée".into());
+        error.insert("USEnglishDescription".into(), english.into());
+        let mut res = Dictionary::new();
+        res.insert("Status".into(), "Error".into());
+        res.insert(
+            "ErrorChain".into(),
+            Value::Array(vec![Value::Dictionary(error)]),
+        );
+        Value::Dictionary(res)
+    }
+
+    fn acknowledged() -> Value {
+        let mut res = Dictionary::new();
+        res.insert("Status".into(), "Acknowledged".into());
+        Value::Dictionary(res)
+    }
+
+    /// Answers each request in turn with `replies`, returning the request types
+    async fn fake_mcinstall(mut device: Idevice, replies: Vec<Value>) -> Vec<String> {
+        let mut requests = Vec::new();
+        for reply in replies {
+            let req = device.read_plist().await.unwrap();
+            let kind = req.get("RequestType").and_then(|t| t.as_string());
+            requests.push(kind.unwrap_or_default().to_string());
+            device.send_plist(reply).await.unwrap();
+        }
+        requests
+    }
+
+    async fn install_with(
+        replies: Vec<Value>,
+    ) -> (Result<ProfileInstall, IdeviceError>, Vec<String>) {
+        let (host, device) = tokio::io::duplex(64 * 1024);
+        let device = tokio::spawn(fake_mcinstall(
+            Idevice::new(Box::new(device), "fake-device"),
+            replies,
+        ));
+        let mut client = MobileConfigClient::new(Idevice::new(Box::new(host), "test"));
+        let res = client
+            .install_profile(&profile_plist("Configuration"))
+            .await;
+        (res, device.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn settings_only_devices_get_the_profile_stored() {
+        let (res, requests) = install_with(vec![
+            rejection("The profile must be installed using the Settings app."),
+            acknowledged(),
+        ])
+        .await;
+        assert!(matches!(res, Ok(ProfileInstall::NeedsUserApproval)));
+        assert_eq!(requests, ["InstallProfile", "StoreProfile"]);
+    }
+
+    #[tokio::test]
+    async fn rejected_profiles_are_not_stored() {
+        let (res, requests) = install_with(vec![rejection("The profile is malformed.")]).await;
+        match res {
+            Err(IdeviceError::MobileConfigFailure(reason)) => {
+                assert_eq!(reason, "Installation refusée")
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(requests, ["InstallProfile"]);
+    }
+}
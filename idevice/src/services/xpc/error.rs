@@ -1,66 +1,61 @@
 // DebianArch
 
 use super::http2::error::Http2Error;
-use std::{
-    array::TryFromSliceError, error::Error, ffi::FromVecWithNulError, io, num::TryFromIntError,
-    str::Utf8Error,
-};
+use std::{array::TryFromSliceError, error::Error, io, str::Utf8Error};
 
 #[derive(Debug)]
 pub enum XPCError {
+    /// A message or object header did not start with the expected magic
+    InvalidMagic(u32),
+    /// An object header carried a version other than the one we speak
+    UnexpectedVersion(u32),
+    /// An object carried a type tag we don't know how to decode
+    InvalidType(u32),
+    /// The buffer ended before a complete message could be decoded
+    Incomplete,
+    /// A string or dictionary key was not valid UTF-8
+    Utf8(Utf8Error),
+    /// A message header claimed a body longer than we're willing to buffer
+    FrameTooLarge(u64),
+    /// No reply to the request with this message ID arrived in time
+    Timeout(u64),
+    /// A value could not be converted to a plist on its way to XPC
+    Serialize(plist::Error),
     Io(io::Error),
     Http2Error(Http2Error),
-    ParseError(ParseError),
-    Custom(String),
 }
 
-#[derive(Debug)]
-pub enum ParseError {
-    TryFromSliceError(TryFromSliceError),
-    TryFromIntError(TryFromIntError),
-    FromVecWithNulError(FromVecWithNulError),
-    Utf8Error(Utf8Error),
-}
-
-impl From<TryFromSliceError> for XPCError {
-    fn from(value: TryFromSliceError) -> Self {
-        Self::ParseError(ParseError::TryFromSliceError(value))
-    }
-}
-
-impl From<TryFromIntError> for XPCError {
-    fn from(value: TryFromIntError) -> Self {
-        Self::ParseError(ParseError::TryFromIntError(value))
+impl XPCError {
+    /// Whether more bytes may turn this error into a successful decode
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, Self::Incomplete)
     }
 }
 
-impl From<ParseError> for XPCError {
-    fn from(value: ParseError) -> Self {
-        Self::ParseError(value)
-    }
-}
-
-impl From<FromVecWithNulError> for XPCError {
-    fn from(value: FromVecWithNulError) -> Self {
-        Self::ParseError(ParseError::FromVecWithNulError(value))
+impl From<TryFromSliceError> for XPCError {
+    fn from(_: TryFromSliceError) -> Self {
+        Self::Incomplete
     }
 }
 
 impl From<Utf8Error> for XPCError {
     fn from(value: Utf8Error) -> Self {
-        Self::ParseError(ParseError::Utf8Error(value))
+        Self::Utf8(value)
     }
 }
 
 impl From<io::Error> for XPCError {
     fn from(value: io::Error) -> Self {
-        Self::Io(value)
+        match value.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Incomplete,
+            _ => Self::Io(value),
+        }
     }
 }
 
-impl From<&str> for XPCError {
-    fn from(value: &str) -> Self {
-        Self::Custom(value.to_string())
+impl From<plist::Error> for XPCError {
+    fn from(value: plist::Error) -> Self {
+        Self::Serialize(value)
     }
 }
 
@@ -72,32 +67,30 @@ impl From<Http2Error> for XPCError {
 
 impl std::fmt::Display for XPCError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "XPCError({})",
-            match self {
-                Self::Io(io) => io.to_string(),
-                Self::Http2Error(http2) => http2.to_string(),
-                Self::ParseError(e) => e.to_string(),
-                Self::Custom(s) => s.clone(),
+        match self {
+            Self::InvalidMagic(magic) => write!(f, "XPCError(invalid magic {magic:#010x})"),
+            Self::UnexpectedVersion(v) => write!(f, "XPCError(unexpected version {v})"),
+            Self::InvalidType(t) => write!(f, "XPCError(invalid type {t:#010x})"),
+            Self::Incomplete => write!(f, "XPCError(incomplete message)"),
+            Self::Utf8(e) => write!(f, "XPCError({e})"),
+            Self::FrameTooLarge(len) => {
+                write!(f, "XPCError(message body of {len} bytes is too large)")
             }
-        )
+            Self::Timeout(id) => write!(f, "XPCError(timed out waiting for reply to {id})"),
+            Self::Serialize(e) => write!(f, "XPCError(cannot serialize: {e})"),
+            Self::Io(io) => write!(f, "XPCError({io})"),
+            Self::Http2Error(http2) => write!(f, "XPCError({http2})"),
+        }
     }
 }
 
-impl std::fmt::Display for ParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "ParseError({})",
-            match self {
-                Self::TryFromSliceError(e) => e.to_string(),
-                Self::TryFromIntError(e) => e.to_string(),
-                Self::FromVecWithNulError(e) => e.to_string(),
-                Self::Utf8Error(e) => e.to_string(),
-            }
-        )
+impl Error for XPCError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Utf8(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Serialize(e) => Some(e),
+            _ => None,
+        }
     }
 }
-
-impl Error for XPCError {}
@@ -1,9 +1,9 @@
 // DebianArch
 
 use std::{
-    ffi::CString,
     io::{BufRead, Cursor, Read},
     ops::{BitOr, BitOrAssign},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use super::error::XPCError;
@@ -56,12 +56,14 @@ impl PartialEq for XPCFlag {
 
 #[repr(u32)]
 pub enum XPCType {
+    Null = 0x00001000,
     Bool = 0x00002000,
     Dictionary = 0x0000f000,
     Array = 0x0000e000,
 
     Int64 = 0x00003000,
     UInt64 = 0x00004000,
+    Date = 0x00007000,
 
     String = 0x00009000,
     Data = 0x00008000,
@@ -73,15 +75,17 @@ impl TryFrom<u32> for XPCType {
 
     fn try_from(value: u32) -> Result<Self, Self::Error> {
         match value {
+            0x00001000 => Ok(Self::Null),
             0x00002000 => Ok(Self::Bool),
             0x0000f000 => Ok(Self::Dictionary),
             0x0000e000 => Ok(Self::Array),
             0x00003000 => Ok(Self::Int64),
             0x00004000 => Ok(Self::UInt64),
+            0x00007000 => Ok(Self::Date),
             0x00009000 => Ok(Self::String),
             0x00008000 => Ok(Self::Data),
             0x0000a000 => Ok(Self::Uuid),
-            _ => Err("Invalid XPCType")?,
+            _ => Err(XPCError::InvalidType(value)),
         }
     }
 }
@@ -90,12 +94,15 @@ pub type Dictionary = IndexMap<String, XPCObject>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum XPCObject {
+    Null,
     Bool(bool),
     Dictionary(Dictionary),
     Array(Vec<XPCObject>),
 
     Int64(i64),
     UInt64(u64),
+    /// Nanoseconds since the Unix epoch
+    Date(i64),
 
     String(String),
     Data(Vec<u8>),
@@ -117,7 +124,7 @@ impl From<plist::Value> for XPCObject {
             }
             plist::Value::Boolean(v) => XPCObject::Bool(v),
             plist::Value::Data(v) => XPCObject::Data(v),
-            plist::Value::Date(_) => todo!(),
+            plist::Value::Date(v) => XPCObject::Date(system_time_to_nanos(v.into())),
             plist::Value::Real(_) => todo!(),
             plist::Value::Integer(v) => XPCObject::Int64(v.as_signed().unwrap()),
             plist::Value::String(v) => XPCObject::String(v),
@@ -128,18 +135,50 @@ impl From<plist::Value> for XPCObject {
 }
 
 impl XPCObject {
+    /// Starts building a dictionary
+    ///
+    /// # Example
+    /// ```rust
+    /// use idevice::xpc::XPCObject;
+    ///
+    /// let request = XPCObject::dict()
+    ///     .set("Command", "Ping")
+    ///     .set("Verbose", true)
+    ///     .set("Options", XPCObject::dict().set("Timeout", 30_i64).build())
+    ///     .set("Payload", vec![0xaa_u8, 0xbb])
+    ///     .build();
+    /// assert_eq!(request.get("Command").and_then(|c| c.as_string()), Some("Ping"));
+    /// ```
+    pub fn dict() -> DictionaryBuilder {
+        DictionaryBuilder::default()
+    }
+
+    /// Converts the object to a plist value.
+    ///
+    /// Plists have no null type, so `Null` entries are omitted from dictionaries
+    /// and arrays. A bare `Null` converts to an empty dictionary.
     pub fn to_plist(&self) -> plist::Value {
         match self {
+            Self::Null => plist::Value::Dictionary(plist::Dictionary::new()),
             Self::Bool(v) => plist::Value::Boolean(*v),
             Self::Uuid(uuid) => plist::Value::String(uuid.to_string()),
             Self::UInt64(v) => plist::Value::Integer({ *v }.into()),
             Self::Int64(v) => plist::Value::Integer({ *v }.into()),
+            Self::Date(v) => plist::Value::Date(nanos_to_system_time(*v).into()),
             Self::String(v) => plist::Value::String(v.clone()),
             Self::Data(v) => plist::Value::Data(v.clone()),
-            Self::Array(v) => plist::Value::Array(v.iter().map(|item| item.to_plist()).collect()),
+            Self::Array(v) => plist::Value::Array(
+                v.iter()
+                    .filter(|item| !matches!(item, Self::Null))
+                    .map(|item| item.to_plist())
+                    .collect(),
+            ),
             Self::Dictionary(v) => {
                 let mut dict = plist::Dictionary::new();
                 for (k, v) in v.into_iter() {
+                    if matches!(v, Self::Null) {
+                        continue;
+                    }
                     dict.insert(k.clone(), v.to_plist());
                 }
                 plist::Value::Dictionary(dict)
@@ -147,11 +186,12 @@ impl XPCObject {
         }
     }
 
-    pub fn to_value<T: Serialize>(value: &T) -> Self {
-        match plist::to_value(value) {
-            Ok(v) => Self::from(v),
-            Err(_) => panic!("oof"),
-        }
+    /// Converts any serializable value, going through its plist form
+    ///
+    /// # Errors
+    /// Returns `XPCError::Serialize` if `value` can't be represented as a plist
+    pub fn to_value<T: Serialize>(value: &T) -> Result<Self, XPCError> {
+        Ok(Self::from(plist::to_value(value)?))
     }
 
     pub fn encode(&self) -> Result<Vec<u8>, XPCError> {
@@ -164,9 +204,12 @@ impl XPCObject {
 
     fn encode_object(&self, buf: &mut Vec<u8>) -> Result<(), XPCError> {
         match self {
+            XPCObject::Null => {
+                buf.extend_from_slice(&(XPCType::Null as u32).to_le_bytes());
+            }
             XPCObject::Bool(val) => {
                 buf.extend_from_slice(&(XPCType::Bool as u32).to_le_bytes());
-                buf.push(if *val { 0 } else { 1 });
+                buf.push(if *val { 1 } else { 0 });
                 buf.extend_from_slice(&[0].repeat(3));
             }
             XPCObject::Dictionary(dict) => {
@@ -198,6 +241,10 @@ impl XPCObject {
                 buf.extend_from_slice(&(XPCType::UInt64 as u32).to_le_bytes());
                 buf.extend_from_slice(&num.to_le_bytes());
             }
+            XPCObject::Date(nanos) => {
+                buf.extend_from_slice(&(XPCType::Date as u32).to_le_bytes());
+                buf.extend_from_slice(&nanos.to_le_bytes());
+            }
             XPCObject::String(item) => {
                 let l = item.len() + 1;
                 let padding = Self::calculate_padding(l);
@@ -216,8 +263,8 @@ impl XPCObject {
                 buf.extend_from_slice(&[0].repeat(padding));
             }
             XPCObject::Uuid(uuid) => {
+                // A fixed 16 bytes with no length, as the decoder expects
                 buf.extend_from_slice(&(XPCType::Uuid as u32).to_le_bytes());
-                buf.extend_from_slice(&16_u32.to_le_bytes());
                 buf.extend_from_slice(uuid.as_bytes());
             }
         }
@@ -225,14 +272,18 @@ impl XPCObject {
     }
 
     pub fn decode(buf: &[u8]) -> Result<Self, XPCError> {
+        if buf.len() < 8 {
+            return Err(XPCError::Incomplete);
+        }
+
         let magic = u32::from_le_bytes(buf[0..4].try_into()?);
         if magic != 0x42133742 {
-            Err("Invalid magic for XPCObject")?
+            return Err(XPCError::InvalidMagic(magic));
         }
 
         let version = u32::from_le_bytes(buf[4..8].try_into()?);
         if version != 0x00000005 {
-            Err("Unexpected version for XPCObject")?
+            return Err(XPCError::UnexpectedVersion(version));
         }
 
         Self::decode_object(&mut Cursor::new(&buf[8..]))
@@ -244,6 +295,7 @@ impl XPCObject {
         let xpc_type = u32::from_le_bytes(buf_32);
         let xpc_type: XPCType = xpc_type.try_into()?;
         match xpc_type {
+            XPCType::Null => Ok(XPCObject::Null),
             XPCType::Dictionary => {
                 let mut ret = IndexMap::new();
 
@@ -254,7 +306,10 @@ impl XPCObject {
                 for _i in 0..num_entries {
                     let mut key_buf = Vec::new();
                     BufRead::read_until(&mut cursor, 0, &mut key_buf)?;
-                    let key = CString::from_vec_with_nul(key_buf)?.to_str()?.to_string();
+                    if key_buf.last() != Some(&0) {
+                        return Err(XPCError::Incomplete);
+                    }
+                    let key = Self::decode_c_string(&key_buf)?;
                     let padding = Self::calculate_padding(key.len() + 1);
 
                     BufRead::consume(&mut cursor, padding);
@@ -284,6 +339,11 @@ impl XPCObject {
                 cursor.read_exact(&mut buf)?;
                 Ok(XPCObject::UInt64(u64::from_le_bytes(buf)))
             }
+            XPCType::Date => {
+                let mut buf: [u8; 8] = Default::default();
+                cursor.read_exact(&mut buf)?;
+                Ok(XPCObject::Date(i64::from_le_bytes(buf)))
+            }
             XPCType::String => {
                 // 'l' includes utf8 '\0' character.
                 cursor.read_exact(&mut buf_32)?;
@@ -292,7 +352,7 @@ impl XPCObject {
 
                 let mut key_buf = vec![0; l];
                 cursor.read_exact(&mut key_buf)?;
-                let key = CString::from_vec_with_nul(key_buf)?.to_str()?.to_string();
+                let key = Self::decode_c_string(&key_buf)?;
                 BufRead::consume(&mut cursor, padding);
                 Ok(XPCObject::String(key))
             }
@@ -347,6 +407,44 @@ impl XPCObject {
         }
     }
 
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            XPCObject::Data(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_uuid(&self) -> Option<uuid::Uuid> {
+        match self {
+            XPCObject::Uuid(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Looks up `key` if this object is a dictionary
+    ///
+    /// # Returns
+    /// The value for `key`, or `None` if this is not a dictionary or the key is missing
+    ///
+    /// # Example
+    /// ```rust
+    /// # use idevice::xpc::XPCObject;
+    /// let services = XPCObject::dict()
+    ///     .set(
+    ///         "com.apple.internal.dt.remote.debugproxy",
+    ///         XPCObject::dict().set("Port", "49152").build(),
+    ///     )
+    ///     .build();
+    /// let port = services
+    ///     .get("com.apple.internal.dt.remote.debugproxy")
+    ///     .and_then(|x| x.get("Port"))
+    ///     .and_then(|x| x.as_string());
+    /// assert_eq!(port, Some("49152"));
+    /// ```
+    pub fn get(&self, key: &str) -> Option<&XPCObject> {
+        self.as_dictionary().and_then(|d| d.get(key))
+    }
+
     pub fn as_signed_integer(&self) -> Option<i64> {
         match self {
             XPCObject::String(s) => s.parse().ok(),
@@ -363,18 +461,122 @@ impl XPCObject {
         }
     }
 
+    /// Decodes a UTF-8 string up to its first NUL, or the whole buffer if there is none
+    fn decode_c_string(buf: &[u8]) -> Result<String, XPCError> {
+        let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        Ok(std::str::from_utf8(&buf[..end])?.to_string())
+    }
+
     fn calculate_padding(len: usize) -> usize {
         let c = ((len as f64) / 4.0).ceil();
         (c * 4.0 - (len as f64)) as usize
     }
 }
 
+fn system_time_to_nanos(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i64,
+        Err(e) => -(e.duration().as_nanos() as i64),
+    }
+}
+
+fn nanos_to_system_time(nanos: i64) -> SystemTime {
+    if nanos >= 0 {
+        UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_nanos(nanos.unsigned_abs())
+    }
+}
+
 impl From<Dictionary> for XPCObject {
     fn from(value: Dictionary) -> Self {
         XPCObject::Dictionary(value)
     }
 }
 
+impl From<Vec<XPCObject>> for XPCObject {
+    fn from(value: Vec<XPCObject>) -> Self {
+        XPCObject::Array(value)
+    }
+}
+
+impl From<&str> for XPCObject {
+    fn from(value: &str) -> Self {
+        XPCObject::String(value.to_string())
+    }
+}
+
+impl From<String> for XPCObject {
+    fn from(value: String) -> Self {
+        XPCObject::String(value)
+    }
+}
+
+impl From<bool> for XPCObject {
+    fn from(value: bool) -> Self {
+        XPCObject::Bool(value)
+    }
+}
+
+impl From<i64> for XPCObject {
+    fn from(value: i64) -> Self {
+        XPCObject::Int64(value)
+    }
+}
+
+impl From<u64> for XPCObject {
+    fn from(value: u64) -> Self {
+        XPCObject::UInt64(value)
+    }
+}
+
+impl From<Vec<u8>> for XPCObject {
+    fn from(value: Vec<u8>) -> Self {
+        XPCObject::Data(value)
+    }
+}
+
+impl From<uuid::Uuid> for XPCObject {
+    fn from(value: uuid::Uuid) -> Self {
+        XPCObject::Uuid(value)
+    }
+}
+
+/// Builds an `XPCObject::Dictionary` one entry at a time
+///
+/// Created with `XPCObject::dict`. Entries keep the order they were set in,
+/// which is the order they're encoded in.
+#[derive(Debug, Clone, Default)]
+pub struct DictionaryBuilder {
+    dict: Dictionary,
+}
+
+impl DictionaryBuilder {
+    /// Sets `key` to `value`, replacing any earlier value for the key
+    ///
+    /// # Arguments
+    /// * `key` - Dictionary key
+    /// * `value` - Anything convertible to an `XPCObject`, including a nested
+    ///   builder's output
+    pub fn set(mut self, key: impl Into<String>, value: impl Into<XPCObject>) -> Self {
+        self.dict.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets `key` only if `value` is `Some`
+    pub fn set_opt(self, key: impl Into<String>, value: Option<impl Into<XPCObject>>) -> Self {
+        match value {
+            Some(value) => self.set(key, value),
+            None => self,
+        }
+    }
+
+    /// Finishes the dictionary
+    pub fn build(self) -> XPCObject {
+        XPCObject::Dictionary(self.dict)
+    }
+}
+
 #[derive(Debug)]
 pub struct XPCMessage {
     pub flags: u32,
@@ -397,19 +599,19 @@ impl XPCMessage {
 
     pub fn decode(data: &[u8]) -> Result<XPCMessage, XPCError> {
         if data.len() < 24 {
-            Err("XPCMessage must be at least 24 bytes.")?
+            return Err(XPCError::Incomplete);
         }
 
         let magic = u32::from_le_bytes(data[0..4].try_into()?);
         if magic != 0x29b00b92_u32 {
-            Err("XPCMessage magic is invalid.")?
+            return Err(XPCError::InvalidMagic(magic));
         }
 
         let flags = u32::from_le_bytes(data[4..8].try_into()?);
         let body_len = u64::from_le_bytes(data[8..16].try_into()?);
         let message_id = u64::from_le_bytes(data[16..24].try_into()?);
-        if body_len + 24 > data.len().try_into()? {
-            Err("XPCMessage body length given is incorrect.")?
+        if body_len + 24 > data.len() as u64 {
+            return Err(XPCError::Incomplete);
         }
 
         // for some reason the above if check doesn't work ???
@@ -446,3 +648,195 @@ impl XPCMessage {
         Ok(out)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_encode_is_stable() {
+        let mut inner = Dictionary::new();
+        inner.insert("nothing".into(), XPCObject::Null);
+        inner.insert("when".into(), XPCObject::Date(1_700_000_000_123_456_789));
+
+        let mut dict = Dictionary::new();
+        dict.insert("null".into(), XPCObject::Null);
+        dict.insert("date".into(), XPCObject::Date(-42));
+        dict.insert("flag".into(), XPCObject::Bool(true));
+        dict.insert("signed".into(), XPCObject::Int64(-7));
+        dict.insert("unsigned".into(), XPCObject::UInt64(7));
+        dict.insert("name".into(), XPCObject::String("abc".into()));
+        dict.insert("bytes".into(), XPCObject::Data(vec![1, 2, 3, 4, 5]));
+        dict.insert("id".into(), XPCObject::Uuid(uuid::Uuid::from_u128(0x1234)));
+        dict.insert(
+            "list".into(),
+            XPCObject::Array(vec![XPCObject::Null, XPCObject::Dictionary(inner)]),
+        );
+
+        let first = XPCObject::Dictionary(dict).encode().unwrap();
+        let decoded = XPCObject::decode(&first).unwrap();
+        let second = decoded.encode().unwrap();
+        assert_eq!(first, second);
+
+        let dict = decoded.as_dictionary().unwrap();
+        assert!(matches!(dict.get("null"), Some(XPCObject::Null)));
+        assert!(matches!(dict.get("date"), Some(XPCObject::Date(-42))));
+        assert!(matches!(dict.get("flag"), Some(XPCObject::Bool(true))));
+    }
+
+    #[test]
+    fn uuid_and_bool_wire_layout() {
+        let id = uuid::Uuid::from_u128(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10);
+        let encoded = XPCObject::Uuid(id).encode().unwrap();
+        // Header, then the type and the bare 16 bytes
+        assert_eq!(&encoded[8..12], &(XPCType::Uuid as u32).to_le_bytes());
+        assert_eq!(&encoded[12..], id.as_bytes());
+
+        let encoded = XPCObject::Bool(true).encode().unwrap();
+        assert_eq!(&encoded[12..], &[1, 0, 0, 0]);
+        let encoded = XPCObject::Bool(false).encode().unwrap();
+        assert_eq!(&encoded[12..], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decode_errors_are_categorized() {
+        let encoded = XPCMessage::new(None, Some(XPCObject::String("hello".into())), None)
+            .encode(1)
+            .unwrap();
+        for len in 0..encoded.len() {
+            assert!(matches!(
+                XPCMessage::decode(&encoded[..len]),
+                Err(XPCError::Incomplete)
+            ));
+        }
+
+        let mut bad_magic = encoded.clone();
+        bad_magic[0] ^= 0xff;
+        assert!(matches!(
+            XPCMessage::decode(&bad_magic),
+            Err(XPCError::InvalidMagic(_))
+        ));
+
+        let mut bad_version = XPCObject::Bool(true).encode().unwrap();
+        bad_version[4] = 6;
+        assert!(matches!(
+            XPCObject::decode(&bad_version),
+            Err(XPCError::UnexpectedVersion(6))
+        ));
+
+        let mut bad_type = XPCObject::Bool(true).encode().unwrap();
+        bad_type[8..12].copy_from_slice(&0x0000_0bad_u32.to_le_bytes());
+        assert!(matches!(
+            XPCObject::decode(&bad_type),
+            Err(XPCError::InvalidType(0x0000_0bad))
+        ));
+
+        let mut bad_utf8 = XPCObject::String("abc".into()).encode().unwrap();
+        bad_utf8[16] = 0xff;
+        assert!(matches!(
+            XPCObject::decode(&bad_utf8),
+            Err(XPCError::Utf8(_))
+        ));
+    }
+
+    #[test]
+    fn dictionary_builder() {
+        let id = uuid::Uuid::from_u128(0x42);
+        let built = XPCObject::dict()
+            .set("name", "abc")
+            .set("owned", String::from("def"))
+            .set("flag", false)
+            .set("signed", -3_i64)
+            .set("unsigned", 3_u64)
+            .set("bytes", vec![1_u8, 2])
+            .set("id", id)
+            .set("list", vec![XPCObject::Null])
+            .set_opt("missing", None::<bool>)
+            .set("name", "replaced")
+            .build();
+
+        let dict = built.as_dictionary().unwrap();
+        let keys: Vec<&str> = dict.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            ["name", "owned", "flag", "signed", "unsigned", "bytes", "id", "list"]
+        );
+        assert_eq!(
+            built.get("name").and_then(|v| v.as_string()),
+            Some("replaced")
+        );
+        assert_eq!(built.get("flag").and_then(|v| v.as_bool()), Some(&false));
+        assert_eq!(
+            built.get("signed").and_then(|v| v.as_signed_integer()),
+            Some(-3)
+        );
+        assert_eq!(
+            built.get("unsigned").and_then(|v| v.as_unsigned_integer()),
+            Some(3)
+        );
+        assert_eq!(
+            built.get("bytes").and_then(|v| v.as_data()),
+            Some(&[1, 2][..])
+        );
+        assert_eq!(built.get("id").and_then(|v| v.as_uuid()), Some(id));
+    }
+
+    #[test]
+    fn to_value_reports_serialize_errors() {
+        struct Unserializable;
+
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("not today"))
+            }
+        }
+
+        assert!(matches!(
+            XPCObject::to_value(&Unserializable),
+            Err(XPCError::Serialize(_))
+        ));
+        assert!(matches!(
+            XPCObject::to_value(&"hello"),
+            Ok(XPCObject::String(s)) if s == "hello"
+        ));
+    }
+
+    #[test]
+    fn accessors() {
+        let id = uuid::Uuid::from_u128(0xabcd);
+        let mut inner = Dictionary::new();
+        inner.insert("id".into(), XPCObject::Uuid(id));
+        inner.insert("bytes".into(), XPCObject::Data(vec![9, 8, 7]));
+        let mut outer = Dictionary::new();
+        outer.insert("inner".into(), XPCObject::Dictionary(inner));
+        let obj = XPCObject::Dictionary(outer);
+
+        let inner = obj.get("inner").unwrap();
+        assert_eq!(inner.get("id").and_then(|x| x.as_uuid()), Some(id));
+        assert_eq!(
+            inner.get("bytes").and_then(|x| x.as_data()),
+            Some(&[9u8, 8, 7][..])
+        );
+        assert!(obj.get("missing").is_none());
+        assert!(inner.get("bytes").unwrap().get("anything").is_none());
+        assert!(inner.get("id").unwrap().as_data().is_none());
+    }
+
+    #[test]
+    fn to_plist_omits_null_and_maps_date() {
+        let mut dict = Dictionary::new();
+        dict.insert("null".into(), XPCObject::Null);
+        dict.insert("date".into(), XPCObject::Date(1_000_000_000));
+
+        let plist = XPCObject::Dictionary(dict).to_plist();
+        let plist = plist.as_dictionary().unwrap();
+        assert!(plist.get("null").is_none());
+
+        let date: SystemTime = plist.get("date").unwrap().as_date().unwrap().into();
+        assert_eq!(date, UNIX_EPOCH + Duration::from_secs(1));
+        assert!(matches!(
+            XPCObject::from(plist::Value::Date(date.into())),
+            XPCObject::Date(1_000_000_000)
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+//! Incremental decoding of raw XPC message frames
+//!
+//! Every XPC message starts with a fixed 24 byte header (magic, flags, body
+//! length, message id) followed by the body. [`XPCFrameReader`] buffers bytes
+//! from an [`AsyncRead`] until a whole frame is available, so callers never
+//! hand a partial frame to [`XPCMessage::decode`].
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{error::XPCError, format::XPCMessage};
+
+const HEADER_LEN: usize = 24;
+const MESSAGE_MAGIC: u32 = 0x29b00b92;
+/// Largest body we'll buffer; a bigger length means a corrupt header
+pub const MAX_BODY_LEN: u64 = 64 * 1024 * 1024;
+
+/// Yields complete [`XPCMessage`]s from a byte stream that may deliver them in pieces
+pub struct XPCFrameReader<R: AsyncRead + Unpin> {
+    inner: R,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> XPCFrameReader<R> {
+    /// Creates a frame reader over `inner`
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Reads until a full frame has been buffered and decodes it
+    ///
+    /// # Returns
+    /// The next message, or `None` if the stream ended cleanly between frames
+    ///
+    /// # Errors
+    /// Returns `XPCError` if:
+    /// - The underlying read fails
+    /// - The stream ends in the middle of a frame
+    /// - The frame is not a valid XPC message
+    pub async fn next_message(&mut self) -> Result<Option<XPCMessage>, XPCError> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(len) = self.complete_frame_len()? {
+                let frame: Vec<u8> = self.buf.drain(..len).collect();
+                return XPCMessage::decode(&frame).map(Some);
+            }
+
+            let read = self.inner.read(&mut chunk).await?;
+            if read == 0 {
+                return match self.buf.is_empty() {
+                    true => Ok(None),
+                    false => Err(XPCError::Incomplete),
+                };
+            }
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Bytes received but not yet returned as part of a message
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consumes the reader and returns the underlying stream
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Length of the first buffered frame, if all of it has arrived
+    fn complete_frame_len(&self) -> Result<Option<usize>, XPCError> {
+        if self.buf.len() >= 4 {
+            // Check the magic early so garbage isn't read as a huge body length
+            let magic = u32::from_le_bytes(self.buf[0..4].try_into()?);
+            if magic != MESSAGE_MAGIC {
+                return Err(XPCError::InvalidMagic(magic));
+            }
+        }
+        if self.buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let body_len = u64::from_le_bytes(self.buf[8..16].try_into()?);
+        if body_len > MAX_BODY_LEN {
+            return Err(XPCError::FrameTooLarge(body_len));
+        }
+        let total = usize::try_from(body_len)
+            .ok()
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .ok_or(XPCError::FrameTooLarge(body_len))?;
+        Ok((self.buf.len() >= total).then_some(total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+    use crate::xpc::format::XPCObject;
+
+    /// Hands out one byte per read, returning `Pending` before each one
+    struct Trickle {
+        data: Vec<u8>,
+        pos: usize,
+        ready: bool,
+    }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let this = self.get_mut();
+            if !this.ready {
+                this.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            this.ready = false;
+            if let Some(b) = this.data.get(this.pos) {
+                buf.put_slice(&[*b]);
+                this.pos += 1;
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn sample_frame() -> Vec<u8> {
+        let message = XPCObject::dict()
+            .set("Command", "Ping")
+            .set("Payload", vec![0xaa_u8; 37])
+            .build();
+        XPCMessage::new(None, Some(message), None)
+            .encode(7)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn byte_at_a_time_yields_one_message() {
+        let mut reader = XPCFrameReader::new(Trickle {
+            data: sample_frame(),
+            pos: 0,
+            ready: false,
+        });
+
+        let message = reader.next_message().await.unwrap().unwrap();
+        assert_eq!(message.message_id, Some(7));
+        let command = message
+            .message
+            .as_ref()
+            .and_then(|m| m.get("Command"))
+            .and_then(|c| c.as_string());
+        assert_eq!(command, Some("Ping"));
+
+        assert!(reader.next_message().await.unwrap().is_none());
+        assert!(reader.buffered().is_empty());
+    }
+
+    #[tokio::test]
+    async fn back_to_back_frames_are_split() {
+        let mut data = sample_frame();
+        data.extend_from_slice(&XPCMessage::new(None, None, None).encode(8).unwrap());
+        let mut reader = XPCFrameReader::new(&data[..]);
+
+        let first = reader.next_message().await.unwrap().unwrap();
+        let second = reader.next_message().await.unwrap().unwrap();
+        assert_eq!(first.message_id, Some(7));
+        assert_eq!(second.message_id, Some(8));
+        assert!(second.message.is_none());
+        assert!(reader.next_message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_body_length_is_rejected() {
+        for body_len in [MAX_BODY_LEN + 1, u64::MAX] {
+            let mut data = sample_frame();
+            data[8..16].copy_from_slice(&body_len.to_le_bytes());
+            let mut reader = XPCFrameReader::new(&data[..]);
+            assert!(matches!(
+                reader.next_message().await,
+                Err(XPCError::FrameTooLarge(len)) if len == body_len
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated_stream_is_incomplete() {
+        let data = sample_frame();
+        let mut reader = XPCFrameReader::new(&data[..data.len() - 1]);
+        assert!(matches!(
+            reader.next_message().await,
+            Err(XPCError::Incomplete)
+        ));
+    }
+}
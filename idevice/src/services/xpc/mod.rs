@@ -3,18 +3,22 @@
 //! Provides functionality for interacting with Apple's XPC protocol over HTTP/2,
 //! which is used for inter-process communication between iOS/macOS components.
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 mod http2;
 
 use crate::{IdeviceError, ReadWrite};
 use error::XPCError;
-use format::{XPCFlag, XPCMessage, XPCObject};
+pub use format::{Dictionary, DictionaryBuilder, XPCFlag, XPCMessage, XPCObject};
 use http2::h2::{SettingsFrame, WindowUpdateFrame};
 use log::{debug, warn};
 use serde::Deserialize;
 
 pub mod error;
 mod format;
+pub mod frame;
 
 /// Represents an XPC connection to a device with available services
 pub struct XPCDevice<R: ReadWrite> {
@@ -44,6 +48,10 @@ pub struct XPCConnection<R: ReadWrite> {
     pub(crate) inner: http2::Connection<R>,
     root_message_id: u64,
     reply_message_id: u64,
+    /// Requests sent with [`Self::send_request`] that nobody is waiting on yet
+    unclaimed_requests: HashSet<u64>,
+    /// Replies to unclaimed requests that arrived while waiting for another
+    pending_replies: HashMap<u64, XPCMessage>,
 }
 
 impl<R: ReadWrite> XPCDevice<R> {
@@ -66,11 +74,7 @@ impl<R: ReadWrite> XPCDevice<R> {
         let data = connection.read_message(http2::ROOT_CHANNEL).await?;
 
         let data = match data.message {
-            Some(d) => match d
-                .as_dictionary()
-                .and_then(|x| x.get("Services"))
-                .and_then(|x| x.as_dictionary())
-            {
+            Some(d) => match d.get("Services").and_then(|x| x.as_dictionary()) {
                 Some(d) => d.to_owned(),
                 None => return Err(IdeviceError::UnexpectedResponse),
             },
@@ -102,7 +106,6 @@ impl<R: ReadWrite> XPCDevice<R> {
                     };
                     let uses_remote_xpc = match service
                         .get("Properties")
-                        .and_then(|x| x.as_dictionary())
                         .and_then(|x| x.get("UsesRemoteXPC"))
                         .and_then(|x| x.as_bool())
                     {
@@ -112,7 +115,6 @@ impl<R: ReadWrite> XPCDevice<R> {
 
                     let features = service
                         .get("Properties")
-                        .and_then(|x| x.as_dictionary())
                         .and_then(|x| x.get("Features"))
                         .and_then(|x| x.as_array())
                         .map(|f| {
@@ -124,7 +126,6 @@ impl<R: ReadWrite> XPCDevice<R> {
 
                     let service_version = service
                         .get("Properties")
-                        .and_then(|x| x.as_dictionary())
                         .and_then(|x| x.get("ServiceVersion"))
                         .and_then(|x| x.as_signed_integer())
                         .map(|e| e.to_owned());
@@ -202,6 +203,8 @@ impl<R: ReadWrite> XPCConnection<R> {
             inner: client,
             root_message_id: 1,
             reply_message_id: 1,
+            unclaimed_requests: HashSet::new(),
+            pending_replies: HashMap::new(),
         };
 
         // Perform XPC handshake
@@ -266,6 +269,86 @@ impl<R: ReadWrite> XPCConnection<R> {
         Ok(())
     }
 
+    /// Sends a request and waits for the reply carrying its message ID
+    ///
+    /// # Arguments
+    /// * `message` - The request body
+    /// * `timeout` - How long to wait for the matching reply
+    ///
+    /// # Returns
+    /// The reply message
+    ///
+    /// # Errors
+    /// Returns `XPCError` if:
+    /// - Sending or reading fails
+    /// - No matching reply arrives within `timeout`
+    pub async fn request(
+        &mut self,
+        message: XPCObject,
+        timeout: Duration,
+    ) -> Result<XPCMessage, XPCError> {
+        let message_id = self.send_request(message).await?;
+        self.recv_reply(message_id, timeout).await
+    }
+
+    /// Sends a request flagged `WantingReply` without waiting for the reply
+    ///
+    /// # Returns
+    /// The message ID to pass to [`Self::recv_reply`]
+    pub async fn send_request(&mut self, message: XPCObject) -> Result<u64, XPCError> {
+        let message_id = self.root_message_id;
+        self.root_message_id += 1;
+
+        let message = XPCMessage::new(
+            Some(XPCFlag::AlwaysSet | XPCFlag::DataFlag | XPCFlag::WantingReply),
+            Some(message),
+            None,
+        );
+        self.inner
+            .write_streamid(Self::ROOT_CHANNEL, message.encode(message_id)?)
+            .await?;
+        self.unclaimed_requests.insert(message_id);
+        Ok(message_id)
+    }
+
+    /// Waits for the reply to a request sent with [`Self::send_request`]
+    ///
+    /// Replies to other requests read along the way are kept and returned by
+    /// later calls for their IDs, so requests may be answered out of order.
+    /// Each request can be waited on once: after a timeout, or if this
+    /// future is dropped, a late reply to it is discarded rather than kept.
+    ///
+    /// # Errors
+    /// Returns `XPCError::Timeout` if the reply does not arrive within `timeout`
+    pub async fn recv_reply(
+        &mut self,
+        message_id: u64,
+        timeout: Duration,
+    ) -> Result<XPCMessage, XPCError> {
+        self.unclaimed_requests.remove(&message_id);
+        if let Some(reply) = self.pending_replies.remove(&message_id) {
+            return Ok(reply);
+        }
+
+        let wait = async {
+            loop {
+                let reply = self.read_message(Self::ROOT_CHANNEL).await?;
+                match reply.message_id {
+                    Some(id) if id == message_id => return Ok::<_, XPCError>(reply),
+                    Some(id) if self.unclaimed_requests.contains(&id) => {
+                        debug!("Holding reply for message {id} while waiting for {message_id}");
+                        self.pending_replies.insert(id, reply);
+                    }
+                    Some(id) => debug!("Dropping reply for abandoned message {id}"),
+                    None => warn!("Dropping reply without a message ID"),
+                }
+            }
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| XPCError::Timeout(message_id))?
+    }
+
     /// Reads an XPC message from the specified stream
     pub async fn read_message(&mut self, stream_id: u32) -> Result<XPCMessage, XPCError> {
         let mut buf = self.inner.read_streamid(stream_id).await?;
@@ -280,10 +363,14 @@ impl<R: ReadWrite> XPCConnection<R> {
                     }
                     return Ok(decoded);
                 }
-                Err(err) => {
-                    log::warn!("Error decoding message: {:?}", err);
+                Err(err) if err.is_incomplete() => {
+                    debug!("Partial message, reading more: {}", err);
                     buf.extend_from_slice(&self.inner.read_streamid(stream_id).await?);
                 }
+                Err(err) => {
+                    warn!("Error decoding message: {:?}", err);
+                    return Err(err);
+                }
             }
         }
     }
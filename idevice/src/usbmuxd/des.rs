@@ -24,4 +24,8 @@ pub struct DevicePropertiesResponse {
     pub network_address: Option<plist::Data>,
     #[serde(rename = "SerialNumber")]
     pub serial_number: String,
+    #[serde(rename = "ConnectionSpeed")]
+    pub connection_speed: Option<u64>,
+    #[serde(rename = "InterfaceIndex")]
+    pub interface_index: Option<u64>,
 }
@@ -6,6 +6,7 @@
 use std::{
     net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
+    time::Duration,
 };
 
 #[cfg(not(unix))]
@@ -41,6 +42,10 @@ pub struct UsbmuxdDevice {
     pub udid: String,
     /// usbmuxd-assigned device ID
     pub device_id: u32,
+    /// Link speed in bits per second, if the muxer reports one
+    pub connection_speed: Option<u64>,
+    /// Index of the host network interface the device was found on, if any
+    pub interface_index: Option<u64>,
 }
 
 /// Active connection to the usbmuxd service
@@ -92,6 +97,57 @@ impl UsbmuxdAddr {
         Ok(UsbmuxdConnection::new(socket, tag))
     }
 
+    /// Creates a new usbmuxd connection, retrying with exponential backoff
+    ///
+    /// # Arguments
+    /// * `max_attempts` - Total number of connection attempts (at least one is made)
+    /// * `backoff` - Delay before the second attempt, doubled after each failure
+    /// * `on_retry` - Invoked with the failed attempt number and the upcoming delay
+    ///
+    /// # Returns
+    /// Connected `UsbmuxdConnection` or the error from the last attempt
+    pub async fn connect_with_retry_and_notify(
+        &self,
+        max_attempts: u32,
+        backoff: Duration,
+        on_retry: impl Fn(u32, Duration),
+    ) -> Result<UsbmuxdConnection, IdeviceError> {
+        let max_attempts = max_attempts.max(1);
+        let mut delay = backoff;
+        let mut attempt = 1;
+        loop {
+            match self.connect(0).await {
+                Ok(c) => return Ok(c),
+                Err(e) if attempt >= max_attempts => return Err(e),
+                Err(e) => {
+                    warn!("usbmuxd connection attempt {attempt}/{max_attempts} failed: {e:?}");
+                    on_retry(attempt, delay);
+                    tokio::time::sleep(delay).await;
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Parses a usbmuxd address
+    ///
+    /// Anything containing a `:` is a TCP `ip:port`; on Unix, anything else is
+    /// a socket path.
+    ///
+    /// # Returns
+    /// The parsed UsbmuxdAddr or parse error
+    pub fn parse(addr: &str) -> Result<Self, AddrParseError> {
+        #[cfg(unix)]
+        if addr.contains(':') {
+            Ok(Self::TcpSocket(SocketAddr::from_str(addr)?))
+        } else {
+            Ok(Self::UnixSocket(addr.to_string()))
+        }
+        #[cfg(not(unix))]
+        Ok(Self::TcpSocket(SocketAddr::from_str(addr)?))
+    }
+
     /// Creates a UsbmuxdAddr from environment variable
     ///
     /// Checks `USBMUXD_SOCKET_ADDRESS` environment variable, falls back to default
@@ -99,19 +155,10 @@ impl UsbmuxdAddr {
     /// # Returns
     /// Configured UsbmuxdAddr or parse error
     pub fn from_env_var() -> Result<Self, AddrParseError> {
-        Ok(match std::env::var("USBMUXD_SOCKET_ADDRESS") {
-            Ok(var) => {
-                #[cfg(unix)]
-                if var.contains(':') {
-                    Self::TcpSocket(SocketAddr::from_str(&var)?)
-                } else {
-                    Self::UnixSocket(var)
-                }
-                #[cfg(not(unix))]
-                Self::TcpSocket(SocketAddr::from_str(&var)?)
-            }
-            Err(_) => Self::default(),
-        })
+        match std::env::var("USBMUXD_SOCKET_ADDRESS") {
+            Ok(var) => Self::parse(&var),
+            Err(_) => Ok(Self::default()),
+        }
     }
 }
 
@@ -158,6 +205,43 @@ impl UsbmuxdConnection {
         })
     }
 
+    /// Creates a default usbmuxd connection, retrying with exponential backoff
+    ///
+    /// Useful right after a device is plugged in, when the daemon may still be
+    /// (re)starting and refusing connections.
+    ///
+    /// # Arguments
+    /// * `max_attempts` - Total number of connection attempts (at least one is made)
+    /// * `backoff` - Delay before the second attempt, doubled after each failure
+    ///
+    /// # Returns
+    /// Connected `UsbmuxdConnection` or the error from the last attempt
+    pub async fn connect_with_retry(
+        max_attempts: u32,
+        backoff: Duration,
+    ) -> Result<Self, IdeviceError> {
+        Self::connect_with_retry_and_notify(max_attempts, backoff, |_, _| {}).await
+    }
+
+    /// Creates a default usbmuxd connection, retrying with exponential backoff
+    ///
+    /// # Arguments
+    /// * `max_attempts` - Total number of connection attempts (at least one is made)
+    /// * `backoff` - Delay before the second attempt, doubled after each failure
+    /// * `on_retry` - Invoked with the failed attempt number and the upcoming delay
+    ///
+    /// # Returns
+    /// Connected `UsbmuxdConnection` or the error from the last attempt
+    pub async fn connect_with_retry_and_notify(
+        max_attempts: u32,
+        backoff: Duration,
+        on_retry: impl Fn(u32, Duration),
+    ) -> Result<Self, IdeviceError> {
+        UsbmuxdAddr::default()
+            .connect_with_retry_and_notify(max_attempts, backoff, on_retry)
+            .await
+    }
+
     /// Creates a new usbmuxd connection
     ///
     /// # Arguments
@@ -241,6 +325,8 @@ impl UsbmuxdConnection {
                 connection_type,
                 udid: dev.properties.serial_number,
                 device_id: dev.device_id,
+                connection_speed: dev.properties.connection_speed,
+                interface_index: dev.properties.interface_index,
             })
         }
 
@@ -249,6 +335,9 @@ impl UsbmuxdConnection {
 
     /// Gets a specific device by UDID
     ///
+    /// A device on both USB and the network is listed twice; the USB entry
+    /// is returned.
+    ///
     /// # Arguments
     /// * `udid` - The device UDID to find
     ///
@@ -256,10 +345,7 @@ impl UsbmuxdConnection {
     /// The matching device or error if not found
     pub async fn get_device(&mut self, udid: &str) -> Result<UsbmuxdDevice, IdeviceError> {
         let devices = self.get_devices().await?;
-        match devices.into_iter().find(|x| x.udid == udid) {
-            Some(d) => Ok(d),
-            None => Err(IdeviceError::DeviceNotFound),
-        }
+        pick_device(devices, udid).ok_or(IdeviceError::DeviceNotFound)
     }
 
     /// Gets the pairing record for a device
@@ -391,3 +477,49 @@ impl UsbmuxdDevice {
         }
     }
 }
+
+/// The entry for `udid`, preferring USB when it's listed more than once
+fn pick_device(devices: Vec<UsbmuxdDevice>, udid: &str) -> Option<UsbmuxdDevice> {
+    let mut matches = devices.into_iter().filter(|d| d.udid == udid);
+    let first = matches.next()?;
+    if first.connection_type == Connection::Usb {
+        return Some(first);
+    }
+    Some(
+        matches
+            .find(|d| d.connection_type == Connection::Usb)
+            .unwrap_or(first),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn device(udid: &str, device_id: u32, connection_type: Connection) -> UsbmuxdDevice {
+        UsbmuxdDevice {
+            connection_type,
+            udid: udid.to_string(),
+            device_id,
+            connection_speed: None,
+            interface_index: None,
+        }
+    }
+
+    #[test]
+    fn duplicate_udid_resolves_to_usb() {
+        let wifi = Connection::Network(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)));
+        let devices = vec![
+            device("other", 1, Connection::Usb),
+            device("dup", 2, wifi.clone()),
+            device("dup", 3, Connection::Usb),
+        ];
+        assert_eq!(pick_device(devices.clone(), "dup").unwrap().device_id, 3);
+        assert_eq!(pick_device(devices, "missing").map(|d| d.device_id), None);
+
+        let network_only = vec![device("dup", 2, wifi)];
+        assert_eq!(pick_device(network_only, "dup").unwrap().device_id, 2);
+    }
+}
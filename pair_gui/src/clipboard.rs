@@ -0,0 +1,57 @@
+// src/clipboard.rs
+use std::path::{Path, PathBuf};
+
+use arboard::Clipboard;
+
+/// Resolve pasted text to a local file, accepting quoted paths and `file://` URLs.
+pub fn path_from_text(text: &str) -> Option<PathBuf> {
+    let trimmed = text.trim().trim_matches('"');
+    let trimmed = trimmed.strip_prefix("file://").unwrap_or(trimmed);
+    let path = Path::new(trimmed);
+    if path.is_file() {
+        Some(path.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Work out which local file a paste should upload.
+///
+/// `pasted` is the text egui delivered with a paste event, if any. Without it
+/// the system clipboard is queried directly, falling back to image data,
+/// which is written to a timestamped PNG in the temp directory.
+pub fn resolve_paste(pasted: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(text) = pasted {
+        return path_from_text(text)
+            .ok_or_else(|| format!("Clipboard text is not a local file: {}", text.trim()));
+    }
+
+    let mut clipboard = Clipboard::new().map_err(|e| format!("Clipboard unavailable: {e}"))?;
+    if let Ok(text) = clipboard.get_text() {
+        return path_from_text(&text)
+            .ok_or_else(|| format!("Clipboard text is not a local file: {}", text.trim()));
+    }
+    match clipboard.get_image() {
+        Ok(image) => save_png(image.width, image.height, &image.bytes),
+        Err(_) => Err("Clipboard holds neither a file path nor an image".into()),
+    }
+}
+
+/// Write RGBA pixels to `pasted-<timestamp>.png` in the temp directory.
+fn save_png(width: usize, height: usize, rgba: &[u8]) -> Result<PathBuf, String> {
+    let name = format!(
+        "pasted-{}.png",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let path = std::env::temp_dir().join(name);
+    let file = std::fs::File::create(&path)
+        .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut w| w.write_image_data(rgba))
+        .map_err(|e| format!("Failed to encode pasted image: {e}"))?;
+    Ok(path)
+}
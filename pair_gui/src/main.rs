@@ -1,28 +1,31 @@
 // src/main.rs
 
-mod types;
+mod clipboard;
 mod prefs;
-mod util;
+mod types;
 mod ui;
+mod util;
 mod worker;
 
 // add this:
 use worker::worker_loop::worker_loop;
 
-use prefs::load_prefs;
-use util::canonical_or_create;
 use crossbeam::channel::unbounded;
-use tokio::runtime::Runtime;
 use eframe::{run_native, NativeOptions};
+use prefs::load_prefs;
+use tokio::runtime::Runtime;
 use ui::app::PairApp;
+use util::{canonical_or_create, DEFAULT_OUTPUT_DIR};
 
 fn main() -> eframe::Result<()> {
-    env_logger::init();
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
     let prefs = load_prefs();
     let default_dir = prefs
         .output_dir
         .clone()
-        .unwrap_or_else(|| canonical_or_create("pairings"));
+        .unwrap_or_else(|| canonical_or_create(DEFAULT_OUTPUT_DIR));
     let (tx_cmd, rx_cmd) = unbounded();
     let (tx_evt, rx_evt) = unbounded();
 
@@ -33,5 +36,12 @@ fn main() -> eframe::Result<()> {
     });
 
     let app = PairApp::new(tx_cmd, rx_evt, default_dir);
-    run_native("iOS Pair Utility", NativeOptions::default(), Box::new(|_| Ok(Box::new(app))))
+    run_native(
+        "iOS Pair Utility",
+        NativeOptions::default(),
+        Box::new(|cc| {
+            egui_extras::install_image_loaders(&cc.egui_ctx);
+            Ok(Box::new(app))
+        }),
+    )
 }
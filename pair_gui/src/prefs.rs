@@ -2,14 +2,289 @@
 
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-#[derive(Serialize, Deserialize, Default)]
+use crate::util::canonical_or_create;
+
+/// Overrides the saved `output_dir`, for setups where nobody picks one in the GUI.
+pub const OUTPUT_DIR_ENV: &str = "PAIR_GUI_OUTPUT_DIR";
+
+/// Label connections give usbmuxd and lockdown unless `client_label` is set.
+pub const DEFAULT_CLIENT_LABEL: &str = "pair-gui";
+
+/// Name of the file being written in the AFC transcript directory.
+pub const TRANSCRIPT_FILE: &str = "afc-transcript.log";
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Prefs {
     pub output_dir: Option<PathBuf>,
+    /// Launch downloaded files with the OS default handler.
+    pub open_after_download: bool,
+    /// List a device's files as soon as it's selected.
+    pub auto_connect_afc: bool,
+    /// Seconds between automatic re-listings of the Files view; 0 disables it.
+    pub afc_poll_secs: u64,
+    /// AFC read/write packet size in bytes; `None` keeps the library default.
+    pub afc_chunk_size: Option<usize>,
+    /// Uploads and downloads run at once; the rest wait in the queue.
+    pub max_concurrent_transfers: usize,
+    /// Upload even when the device reports too little free space.
+    pub skip_space_check: bool,
+    /// Continue partial downloads instead of starting over.
+    pub resume_downloads: bool,
+    /// Walk into symbolic links when zipping folders instead of skipping them.
+    pub follow_symlinks: bool,
+    /// Seconds a cached directory listing may be shown while it's re-fetched.
+    pub afc_listing_ttl_secs: u64,
+    /// Bundle IDs last browsed through house_arrest, most recent first.
+    pub recent_bundle_ids: Vec<String>,
+    /// Turn on Wi-Fi connections on the device after pairing it.
+    pub enable_wifi_sync: bool,
+    /// Also copy new pairing records to the OS's usbmuxd directory.
+    pub install_to_system_lockdown: bool,
+    /// Devices seen before, most recently connected first.
+    pub recent_devices: Vec<RecentDevice>,
+    /// Names picked by the user, shown instead of the device's own, by UDID.
+    pub nicknames: HashMap<String, String>,
+    /// Light or dark look for the window.
+    pub theme: ThemePref,
+    /// File format for saved device reports.
+    pub report_format: ReportFormat,
+    /// How nested device info values are listed under All Properties.
+    pub info_flatten_mode: FlattenMode,
+    /// How data values are written out under All Properties.
+    pub info_data_encoding: DataEncoding,
+    /// Data values up to this many bytes are shown in full under All
+    /// Properties; larger ones stay collapsed until expanded. 0 collapses all.
+    pub info_data_inline_max: usize,
+    /// usbmuxd socket path or `ip:port`; empty uses the platform default.
+    pub usbmuxd_socket: String,
+    /// Label connections give usbmuxd and lockdown; empty uses the default.
+    pub client_label: String,
+    /// Record every finished AFC operation in a transcript file.
+    pub afc_transcript: bool,
+    /// Where the transcript goes; `None` uses `transcripts` in the save directory.
+    pub afc_transcript_dir: Option<PathBuf>,
+}
+
+/// File format of a saved device report.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ReportFormat {
+    /// Key/value rows.
+    Csv,
+    #[default]
+    Json,
+    /// XML property list.
+    Plist,
+}
+
+impl ReportFormat {
+    pub const ALL: [ReportFormat; 3] = [ReportFormat::Csv, ReportFormat::Json, ReportFormat::Plist];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "CSV",
+            ReportFormat::Json => "JSON",
+            ReportFormat::Plist => "plist",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+            ReportFormat::Plist => "plist",
+        }
+    }
+}
+
+/// How nested device info values become flat keys.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FlattenMode {
+    /// Every nested value under a path such as `Outer.Inner` or `List[0]`.
+    #[default]
+    Dotted,
+    /// Only the top-level keys, as iTunes shows them; nested values are
+    /// summarized.
+    TopLevel,
+}
+
+impl FlattenMode {
+    pub const ALL: [FlattenMode; 2] = [FlattenMode::Dotted, FlattenMode::TopLevel];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FlattenMode::Dotted => "Dotted keys",
+            FlattenMode::TopLevel => "Top-level only",
+        }
+    }
+}
+
+/// Text encoding for plist data shown in full.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DataEncoding {
+    #[default]
+    Base64,
+    Hex,
+}
+
+impl DataEncoding {
+    pub const ALL: [DataEncoding; 2] = [DataEncoding::Base64, DataEncoding::Hex];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DataEncoding::Base64 => "Base64",
+            DataEncoding::Hex => "Hex",
+        }
+    }
+}
+
+/// Which visuals the window uses.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemePref {
+    /// Follow the OS setting.
+    #[default]
+    System,
+    Light,
+    Dark,
 }
 
+impl ThemePref {
+    pub const ALL: [ThemePref; 3] = [ThemePref::System, ThemePref::Light, ThemePref::Dark];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemePref::System => "System",
+            ThemePref::Light => "Light",
+            ThemePref::Dark => "Dark",
+        }
+    }
+
+    /// Whether to use dark visuals, asking the OS for `System`.
+    pub fn is_dark(self) -> bool {
+        match self {
+            ThemePref::System => dark_light::detect() == dark_light::Mode::Dark,
+            ThemePref::Light => false,
+            ThemePref::Dark => true,
+        }
+    }
+}
+
+/// A device that has been connected before.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecentDevice {
+    pub udid: String,
+    /// List label from when it was last connected, e.g. "iPhone (iPhone14,2)".
+    pub name: String,
+    /// Unix time it was last seen connected.
+    pub last_seen: i64,
+}
+
+/// How many entries `recent_bundle_ids` keeps.
+const MAX_RECENT_BUNDLE_IDS: usize = 8;
+/// How many entries `recent_devices` keeps.
+const MAX_RECENT_DEVICES: usize = 32;
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Self {
+            output_dir: None,
+            open_after_download: false,
+            auto_connect_afc: false,
+            afc_poll_secs: 0,
+            afc_chunk_size: None,
+            max_concurrent_transfers: 2,
+            skip_space_check: false,
+            resume_downloads: false,
+            follow_symlinks: false,
+            afc_listing_ttl_secs: 30,
+            recent_bundle_ids: Vec::new(),
+            enable_wifi_sync: false,
+            install_to_system_lockdown: false,
+            recent_devices: Vec::new(),
+            nicknames: HashMap::new(),
+            theme: ThemePref::System,
+            report_format: ReportFormat::Json,
+            info_flatten_mode: FlattenMode::Dotted,
+            info_data_encoding: DataEncoding::Base64,
+            info_data_inline_max: 0,
+            usbmuxd_socket: String::new(),
+            client_label: String::new(),
+            afc_transcript: false,
+            afc_transcript_dir: None,
+        }
+    }
+}
+
+impl Prefs {
+    /// Directory the AFC transcript is written to when it's on.
+    pub fn transcript_dir(&self, output_dir: &Path) -> PathBuf {
+        self.afc_transcript_dir
+            .clone()
+            .unwrap_or_else(|| output_dir.join("transcripts"))
+    }
+
+    /// Move `bundle_id` to the front of the recent list, dropping the oldest.
+    pub fn remember_bundle_id(&mut self, bundle_id: &str) {
+        self.recent_bundle_ids.retain(|b| b != bundle_id);
+        self.recent_bundle_ids.insert(0, bundle_id.to_string());
+        self.recent_bundle_ids.truncate(MAX_RECENT_BUNDLE_IDS);
+    }
+
+    /// Record `udid` as connected at `now`, moving it to the front of the
+    /// history. `name` replaces the stored label when given.
+    pub fn remember_device(&mut self, udid: &str, name: Option<&str>, now: i64) {
+        let mut device = match self.recent_devices.iter().position(|d| d.udid == udid) {
+            Some(i) => self.recent_devices.remove(i),
+            None => RecentDevice {
+                udid: udid.to_string(),
+                name: udid.to_string(),
+                last_seen: now,
+            },
+        };
+        if let Some(name) = name {
+            device.name = name.to_string();
+        }
+        device.last_seen = now;
+        self.recent_devices.insert(0, device);
+        self.recent_devices.truncate(MAX_RECENT_DEVICES);
+    }
+
+    /// Give `udid` a nickname; an empty one removes it.
+    pub fn set_nickname(&mut self, udid: &str, nickname: &str) {
+        let nickname = nickname.trim();
+        if nickname.is_empty() {
+            self.nicknames.remove(udid);
+        } else {
+            self.nicknames
+                .insert(udid.to_string(), nickname.to_string());
+        }
+    }
+}
+
+/// Saved prefs, or the defaults if there are none.
+///
+/// The save directory comes from `PAIR_GUI_OUTPUT_DIR` if it's set, then
+/// the saved `output_dir`, then `DEFAULT_OUTPUT_DIR`, which `main` falls back
+/// to. The environment's directory is created if missing, and prefs saved
+/// while it's set record it as `output_dir`.
 pub fn load_prefs() -> Prefs {
+    let mut prefs = read_prefs();
+    if let Some(dir) = std::env::var(OUTPUT_DIR_ENV)
+        .ok()
+        .filter(|d| !d.trim().is_empty())
+    {
+        prefs.output_dir = Some(canonical_or_create(&dir));
+    }
+    prefs
+}
+
+fn read_prefs() -> Prefs {
     if let Some(proj_dirs) = ProjectDirs::from("", "", "pair_gui") {
         let path = proj_dirs.config_dir().join("prefs.json");
         if let Ok(data) = fs::read_to_string(&path) {
@@ -1,36 +1,722 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use chrono::NaiveDateTime;
+use idevice::{
+    afc::AfcScope,
+    house_arrest::HouseArrestClient,
+    lockdown::{BatteryInfo, DeveloperModeStatus},
+    mobileconfig::ProfileInfo,
+    IdeviceService,
+};
+use serde::Deserialize;
+
+use crate::prefs::{FlattenMode, ReportFormat};
+
 /// Commands sent from the GUI to the worker thread.
 #[derive(Debug)]
 pub enum Command {
-    Refresh,
+    /// Rescan devices. `probe_trust` re-checks every device's trust state
+    /// instead of reusing cached results.
+    Refresh {
+        probe_trust: bool,
+    },
+    /// Rescan devices only to stop the work of any that went away, without
+    /// refreshing the list. Sent instead of `Refresh` while operations run.
+    CheckDevices,
+    /// Pair with `udid`, saving the record to `out_dir`. With `enable_wifi`
+    /// the device is also told to accept network connections afterwards;
+    /// with `install_system` the record is copied to usbmuxd's directory too.
     Pair {
         udid: String,
         out_dir: PathBuf,
+        enable_wifi: bool,
+        install_system: bool,
+    },
+    /// Pair each of `udids` as `Pair` would, a few at a time, reporting
+    /// every device with a `PairResult`.
+    PairAll {
+        udids: Vec<String>,
+        out_dir: PathBuf,
+        enable_wifi: bool,
+        install_system: bool,
+    },
+    /// Validate an existing pairing file for `udid` and save it to `out_dir`.
+    ImportPairingFile {
+        udid: String,
+        path: PathBuf,
+        out_dir: PathBuf,
+    },
+    /// Revoke the device's trust in our pairing and delete the record saved
+    /// in `out_dir`.
+    Unpair {
+        udid: String,
+        out_dir: PathBuf,
     },
     GetDeviceInfo {
         udid: String,
     },
+    /// Ask the device to turn on Developer Mode, which restarts it.
+    EnableDeveloperMode {
+        udid: String,
+    },
     /// List a directory over AFC (no manual pairing‐file I/O needed).
     AfcList {
         udid: String,
         path: String,
-        container: Option<String>,
-        documents: Option<String>,
+        source: AfcSource,
+    },
+    /// Vend `bundle_id`'s Documents and list its root, answered like
+    /// `AfcList`. The vended client is pooled per bundle, so later
+    /// `AfcList`s of the same app reuse it.
+    AppDocuments {
+        udid: String,
+        bundle_id: String,
+    },
+    /// Upload a local file to `remote_path` over AFC.
+    AfcUpload {
+        udid: String,
+        local_path: PathBuf,
+        remote_path: String,
+        source: AfcSource,
+        /// Don't compare the upload size against the device's free space.
+        skip_space_check: bool,
+    },
+    /// Download `url` over http(s) and write it to `remote` over AFC.
+    AfcUploadFromUrl {
+        udid: String,
+        url: String,
+        remote: String,
+        source: AfcSource,
+    },
+    /// Download `remote_path` over AFC into `out_dir`.
+    AfcDownload {
+        udid: String,
+        remote_path: String,
+        source: AfcSource,
+        out_dir: PathBuf,
+        /// Append to an existing partial local copy.
+        resume: bool,
+    },
+    /// Download `names` in the remote directory `dir`, folders included, into
+    /// one zip archive in `out_dir`.
+    AfcDownloadZip {
+        udid: String,
+        dir: String,
+        names: Vec<String>,
+        source: AfcSource,
+        out_dir: PathBuf,
+        /// Archive what symbolic links point at; otherwise they're skipped.
+        follow_symlinks: bool,
+    },
+    /// List `path` if it is a directory, otherwise download it into `out_dir`.
+    AfcOpen {
+        udid: String,
+        path: String,
+        source: AfcSource,
+        out_dir: PathBuf,
+        resume: bool,
+    },
+    /// Copy `path` to a free "<name> copy" alongside it on the device.
+    AfcDuplicate {
+        udid: String,
+        path: String,
+        source: AfcSource,
+    },
+    /// Delete a file or directory tree, reporting each entry with
+    /// `AfcProgress` and carrying on past entries that fail.
+    AfcDelete {
+        udid: String,
+        path: String,
+        source: AfcSource,
+    },
+    /// Create the directory `path`, answered with `AfcOperationResult`.
+    AfcMkDir {
+        udid: String,
+        path: String,
+        source: AfcSource,
+    },
+    /// Rename `path` to `to` in place, answered with `AfcOperationResult`.
+    AfcRename {
+        udid: String,
+        path: String,
+        to: String,
+        source: AfcSource,
+    },
+    /// Fetch a small file into memory for the inline preview.
+    AfcPreview {
+        udid: String,
+        path: String,
+        source: AfcSource,
+    },
+    /// Fetch the next `PREVIEW_MAX_BYTES` of `path` from `offset` for the hex viewer.
+    AfcPreviewRange {
+        udid: String,
+        path: String,
+        source: AfcSource,
+        offset: u64,
+    },
+    /// Replace a text file on the device with `contents`. With
+    /// `expected_modified` the save is refused if the file changed since.
+    AfcSaveText {
+        udid: String,
+        path: String,
+        contents: String,
+        source: AfcSource,
+        expected_modified: Option<NaiveDateTime>,
+    },
+    /// Drop the device's cached AFC clients so the next operation reconnects.
+    AfcDisconnect {
+        udid: String,
+    },
+    /// Check whether the device offers the full-filesystem AFC2 service.
+    ProbeAfc2 {
+        udid: String,
+    },
+    /// Stage an .ipa over AFC and install it with installation_proxy.
+    InstallApp {
+        udid: String,
+        ipa_path: PathBuf,
+    },
+    /// Flush and list the device's crash reports.
+    ListCrashReports {
+        udid: String,
+    },
+    /// Save crash reports to `out_dir`, deleting them from the device
+    /// afterwards with `remove`.
+    DownloadCrashReports {
+        udid: String,
+        reports: Vec<String>,
+        out_dir: PathBuf,
+        remove: bool,
+    },
+    /// List the device's installed configuration profiles.
+    ListProfiles {
+        udid: String,
+    },
+    /// Remove the configuration profile `identifier`, then list the rest.
+    RemoveProfile {
+        udid: String,
+        identifier: String,
+    },
+    /// Install the `.mobileconfig` at `path`, then list the profiles.
+    InstallProfile {
+        udid: String,
+        path: PathBuf,
+    },
+    /// Look up user-installed apps for the bundle ID picker.
+    ListApps {
+        udid: String,
+    },
+    /// Packet size for later AFC transfers; `None` restores the default.
+    SetAfcChunkSize {
+        bytes: Option<usize>,
+    },
+    /// How many uploads and downloads may run at once; more wait their turn.
+    SetMaxConcurrentTransfers {
+        limit: usize,
+    },
+    /// Stop a queued or running transfer.
+    CancelTransfer {
+        id: TransferId,
+    },
+    /// Fetch the device's info and write it to `out_dir` as a report.
+    SaveDeviceReport {
+        udid: String,
+        out_dir: PathBuf,
+        format: ReportFormat,
+    },
+    /// How later device info flattens nested values.
+    SetInfoFlattenMode {
+        mode: FlattenMode,
+    },
+    /// Talk to usbmuxd at `socket` (a path or `ip:port`, empty for the
+    /// default) from now on, answering with `UsbmuxdCheck`.
+    SetUsbmuxdSocket {
+        socket: String,
+    },
+    /// Label new connections with `label` (empty for the default) from now on.
+    SetClientLabel {
+        label: String,
+    },
+    /// Record finished AFC operations in a transcript in `dir`, or stop
+    /// with `None`.
+    SetAfcTranscript {
+        dir: Option<PathBuf>,
+    },
+    /// Check the environment and report back with `SelfTestReport`.
+    SelfTest {
+        out_dir: PathBuf,
+    },
+    /// Show a local file in the OS file browser.
+    RevealPath {
+        path: PathBuf,
+    },
+    /// Move a local file to the OS trash.
+    TrashPath {
+        path: PathBuf,
+    },
+}
+
+/// Which AFC service a Files command talks to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AfcSource {
+    /// `com.apple.afc` or, on jailbroken devices, `com.apple.afc2`.
+    Scope(AfcScope),
+    /// An app's whole sandbox, vended by house_arrest.
+    Container(String),
+    /// An app's Documents folder, vended by house_arrest.
+    Documents(String),
+}
+
+impl AfcSource {
+    /// Distinguishes afc/afc2/house_arrest vends for the same device in the
+    /// connection pool and the listing cache.
+    pub fn service_key(&self) -> String {
+        match self {
+            AfcSource::Scope(scope) => scope.service_name().to_string(),
+            AfcSource::Container(bundle_id) => format!(
+                "{}:container:{bundle_id}",
+                HouseArrestClient::service_name()
+            ),
+            AfcSource::Documents(bundle_id) => format!(
+                "{}:documents:{bundle_id}",
+                HouseArrestClient::service_name()
+            ),
+        }
+    }
+}
+
+/// Commonly used lockdown values, deserialized from `GetValue` with no domain.
+///
+/// Every field is optional since older devices and unpaired sessions omit
+/// some of them. `raw` keeps the flattened dictionary for everything else.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeviceInfo {
+    pub device_name: Option<String>,
+    pub product_type: Option<String>,
+    pub product_version: Option<String>,
+    pub build_version: Option<String>,
+    #[serde(rename = "UniqueChipID")]
+    pub unique_chip_id: Option<u64>,
+    #[serde(rename = "WiFiAddress")]
+    pub wifi_address: Option<String>,
+    pub bluetooth_address: Option<String>,
+    pub serial_number: Option<String>,
+    #[serde(skip)]
+    pub raw: HashMap<String, String>,
+    /// Data values in `raw`, by the same keys, for showing in full.
+    #[serde(skip)]
+    pub data: HashMap<String, Vec<u8>>,
+    /// `None` if the device doesn't report its battery.
+    #[serde(skip)]
+    pub battery: Option<BatteryInfo>,
+    /// `None` if the status couldn't be read.
+    #[serde(skip)]
+    pub developer_mode: Option<DeveloperModeStatus>,
+}
+
+impl DeviceInfo {
+    /// Label/value pairs for the overview card, skipping missing fields.
+    pub fn overview(&self) -> Vec<(&'static str, String)> {
+        [
+            ("Device Name", self.device_name.clone()),
+            ("Product Type", self.product_type.clone()),
+            ("iOS Version", self.product_version.clone()),
+            ("Build", self.build_version.clone()),
+            ("Serial Number", self.serial_number.clone()),
+            ("ECID", self.unique_chip_id.map(|id| format!("{id:#X}"))),
+            ("Wi-Fi Address", self.wifi_address.clone()),
+            ("Bluetooth Address", self.bluetooth_address.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| value.map(|v| (label, v)))
+        .collect()
+    }
+
+    /// Pretty JSON of every property, headed by the UDID and when it was copied.
+    pub fn to_json(&self, udid: &str) -> String {
+        let properties: std::collections::BTreeMap<_, _> = self.raw.iter().collect();
+        let payload = serde_json::json!({
+            "udid": udid,
+            "copied_at": chrono::Local::now().to_rfc3339(),
+            "properties": properties,
+        });
+        serde_json::to_string_pretty(&payload).unwrap_or_default()
+    }
+
+    /// Every property as a file to attach to a ticket, headed by the UDID,
+    /// when it was generated and which version of this app wrote it.
+    pub fn to_report(&self, udid: &str, format: ReportFormat) -> Result<Vec<u8>, plist::Error> {
+        let generated = chrono::Local::now().to_rfc3339();
+        let app_version = concat!("pair_gui ", env!("CARGO_PKG_VERSION"));
+        let properties: std::collections::BTreeMap<_, _> = self.raw.iter().collect();
+        Ok(match format {
+            ReportFormat::Csv => {
+                let mut out = String::from("key,value\n");
+                let header = [
+                    ("udid", udid),
+                    ("generated_at", generated.as_str()),
+                    ("app_version", app_version),
+                ];
+                let rows = header
+                    .into_iter()
+                    .chain(properties.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+                for (key, value) in rows {
+                    out.push_str(&format!("{},{}\n", csv_field(key), csv_field(value)));
+                }
+                out.into_bytes()
+            }
+            ReportFormat::Json => {
+                let payload = serde_json::json!({
+                    "udid": udid,
+                    "generated_at": generated,
+                    "app_version": app_version,
+                    "properties": properties,
+                });
+                serde_json::to_vec_pretty(&payload).unwrap_or_default()
+            }
+            ReportFormat::Plist => {
+                let mut dict = plist::Dictionary::new();
+                dict.insert("UDID".into(), udid.into());
+                dict.insert("GeneratedAt".into(), generated.into());
+                dict.insert("AppVersion".into(), app_version.into());
+                let properties: plist::Dictionary = properties
+                    .into_iter()
+                    .map(|(k, v)| (k.clone(), plist::Value::String(v.clone())))
+                    .collect();
+                dict.insert("Properties".into(), properties.into());
+                let mut out = Vec::new();
+                plist::Value::Dictionary(dict).to_writer_xml(&mut out)?;
+                out
+            }
+        })
+    }
+}
+
+/// Quote a CSV field if it holds a separator, quote or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// A user-installed app, as reported by installation_proxy.
+#[derive(Debug, Clone)]
+pub struct InstalledApp {
+    pub bundle_id: String,
+    pub name: String,
+    /// `UIFileSharingEnabled`; house_arrest only vends Documents for these.
+    pub file_sharing: bool,
+}
+
+/// What usbmuxd reports about a device's connection; either part may be missing.
+#[derive(Debug, Clone, Default)]
+pub struct UsbLink {
+    /// Bits per second.
+    pub speed: Option<u64>,
+    /// Host network interface index, for devices found over the network.
+    pub interface: Option<u64>,
+    /// usbmuxd also lists the device over Wi-Fi; operations still use USB.
+    pub wifi: bool,
+}
+
+/// Fastest link speed USB 2 offers, in bits per second.
+const USB2_SPEED: u64 = 480_000_000;
+
+impl UsbLink {
+    /// Short description like "USB 3 (5 Gbit/s), also on Wi-Fi", `None` if
+    /// nothing is known.
+    pub fn label(&self) -> Option<String> {
+        let speed = self.speed.map(|bps| {
+            let generation = if bps <= USB2_SPEED { "USB 2" } else { "USB 3" };
+            if bps >= 1_000_000_000 {
+                format!("{generation} ({} Gbit/s)", bps as f64 / 1e9)
+            } else {
+                format!("{generation} ({} Mbit/s)", bps / 1_000_000)
+            }
+        });
+        let interface = self.interface.map(|i| format!("interface {i}"));
+        let wifi = self.wifi.then(|| "also on Wi-Fi".to_string());
+        let parts: Vec<String> = [speed, interface, wifi].into_iter().flatten().collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    /// Whether the device is on USB 2 or slower, so big transfers will crawl.
+    pub fn is_slow(&self) -> bool {
+        self.speed.is_some_and(|bps| bps <= USB2_SPEED)
+    }
+}
+
+/// One environment check run by `Command::SelfTest`.
+#[derive(Debug, Clone)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    /// What was found, or why the check failed.
+    pub result: Result<String, String>,
+}
+
+/// Results of `Command::SelfTest`.
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    pub checks: Vec<SelfTestCheck>,
+    /// UDID, list label and trust state of each detected device.
+    pub devices: Vec<(String, String, TrustState)>,
+}
+
+impl SelfTestReport {
+    /// Plain text for pasting into a bug report.
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "pair_gui {} on {} {}\n\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        for check in &self.checks {
+            let (mark, detail) = match &check.result {
+                Ok(detail) => ("ok  ", detail),
+                Err(detail) => ("FAIL", detail),
+            };
+            out.push_str(&format!("[{mark}] {}: {detail}\n", check.name));
+        }
+        out.push_str(&format!("\nDevices ({}):\n", self.devices.len()));
+        for (udid, display, trust) in &self.devices {
+            out.push_str(&format!("  {udid}  {display}  {trust:?}\n"));
+        }
+        out
+    }
+}
+
+/// How pairing one device ended.
+#[derive(Debug, Clone)]
+pub enum PairOutcome {
+    /// Paired; `note` says how enabling Wi-Fi sync went, if it was asked for.
+    Paired {
+        note: String,
     },
-    // (You can add Download/Upload/Mkdir/etc. variants here later.)
+    /// The device is passcode-locked and has to be unlocked first.
+    Locked,
+    Failed(String),
+}
+
+/// Whether the host can open a lockdown session with a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrustState {
+    /// A pairing record exists and the device accepted a session.
+    Trusted,
+    /// No usable pairing record; the device needs to be paired.
+    Untrusted,
+    /// Paired, but the device refused because it is passcode-locked.
+    Locked,
+    /// The probe failed for another reason.
+    Unknown,
+}
+
+/// How the Files view should render a previewed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewKind {
+    Text,
+    Image,
+    /// A binary or XML plist, shown as a tree.
+    Plist,
+    /// Small enough to fetch, but nothing we can render.
+    Binary,
+    /// Over the preview limit; `bytes` is empty.
+    TooLarge,
+}
+
+/// How a file operation started from the Files view ended.
+#[derive(Debug, Clone)]
+pub enum AfcOutcome {
+    /// The file was written; `modified` is its new timestamp on the device.
+    Saved { modified: NaiveDateTime },
+    /// The file changed on the device after it was previewed, so it was left alone.
+    Conflict,
+    /// The directory `path` was created.
+    Created,
+    /// `path` was renamed to `to`.
+    Renamed { to: String },
+    /// `path` and `removed` entries in all were deleted.
+    Deleted { removed: usize },
+    /// A message for the status line, saying what failed.
+    Failed(String),
+}
+
+/// Identifies one upload or download, assigned by the worker when it's queued.
+pub type TransferId = u64;
+
+/// Where an upload or download is in the transfer queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferState {
+    /// Waiting for a free transfer slot.
+    Queued,
+    Active,
+    /// Finished; the message says what was written where.
+    Done(String),
+    Failed(String),
+    Cancelled,
+}
+
+impl TransferState {
+    /// Whether the transfer is over, one way or another.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, TransferState::Queued | TransferState::Active)
+    }
+}
+
+/// The two steps of installing an app, reported separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallPhase {
+    /// Copying the .ipa to the staging directory over AFC.
+    Upload,
+    /// installation_proxy unpacking and registering the app.
+    Install,
+}
+
+/// Long-running operations the GUI waits on before resuming auto-refresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Pair,
+    Upload,
+    Download,
+    Install,
 }
 
 /// Events sent from the worker back to the GUI.
 #[derive(Debug)]
 pub enum GuiEvent {
-    Devices(Vec<(String, String)>),
+    /// Connected devices with their list labels, and how each is attached
+    /// where usbmuxd said.
+    Devices {
+        list: Vec<(String, String)>,
+        links: HashMap<String, UsbLink>,
+    },
     Status(String),
+    /// One device's list label, sent as soon as it's known during a refresh.
+    DeviceSummary {
+        udid: String,
+        display: String,
+    },
     DeviceInfo {
         udid: String,
-        info: HashMap<String, String>,
+        info: DeviceInfo,
+    },
+    /// The lockdown values behind `DeviceInfo`, nesting intact.
+    DeviceInfoTree {
+        udid: String,
+        value: plist::Value,
+    },
+    /// Part of a directory listing; `done` marks the last batch for `path`.
+    AfcListBatch {
+        udid: String,
+        path: String,
+        entries: Vec<String>,
+        done: bool,
+    },
+    /// Status line for the Files view showing `udid`.
+    AfcStatus {
+        udid: String,
+        message: String,
+    },
+    AfcDownloaded {
+        udid: String,
+        remote_path: String,
+        local_path: PathBuf,
+    },
+    /// Progress of work on `path`: bytes of the transfer `transfer`, or
+    /// entries of an `AfcDelete` when that's `None`.
+    AfcProgress {
+        udid: String,
+        transfer: Option<TransferId>,
+        done: u64,
+        total: u64,
+        path: String,
+    },
+    /// An upload or download entered the transfer queue.
+    TransferQueued {
+        id: TransferId,
+        udid: String,
+        label: String,
+    },
+    TransferUpdate {
+        id: TransferId,
+        state: TransferState,
+    },
+    AfcPreviewData {
+        udid: String,
+        path: String,
+        source: AfcSource,
+        bytes: Vec<u8>,
+        kind: PreviewKind,
+        modified: NaiveDateTime,
+    },
+    /// Bytes of `path` from `offset`, answering `AfcPreviewRange`.
+    AfcPreviewRange {
+        udid: String,
+        path: String,
+        offset: u64,
+        bytes: Vec<u8>,
+        /// The whole file's size.
+        size: u64,
+    },
+    /// Whether the device's AFC connection worked on the last operation.
+    AfcConnection {
+        udid: String,
+        connected: bool,
+    },
+    /// How a save, new folder, rename or delete of `path` went.
+    AfcOperationResult {
+        udid: String,
+        path: String,
+        outcome: AfcOutcome,
+    },
+    Trust {
+        udid: String,
+        state: TrustState,
+    },
+    /// Whether a device is passcode-locked, from the refresh poll.
+    LockState {
+        udid: String,
+        locked: bool,
+    },
+    SelfTestReport(SelfTestReport),
+    /// Whether usbmuxd answered after its socket was changed.
+    UsbmuxdCheck(Result<String, String>),
+    /// A local file was moved to the trash, answering `TrashPath`.
+    Trashed {
+        path: PathBuf,
+    },
+    /// Crash report paths on a device, relative to its crash logs directory.
+    CrashReports {
+        udid: String,
+        reports: Vec<String>,
+    },
+    /// Configuration profiles installed on a device.
+    Profiles {
+        udid: String,
+        profiles: Vec<ProfileInfo>,
+    },
+    /// One device of a `PairAll` batch is done.
+    PairResult {
+        udid: String,
+        outcome: PairOutcome,
+    },
+    Afc2Available {
+        udid: String,
+        available: bool,
+    },
+    InstallProgress {
+        phase: InstallPhase,
+        percent: u8,
+    },
+    InstalledApps {
+        udid: String,
+        apps: Vec<InstalledApp>,
     },
-    AfcListResponse(Vec<String>),
-    AfcStatus(String),
+    /// Sent once a long-running operation completes, successfully or not.
+    Finished(Operation),
 }
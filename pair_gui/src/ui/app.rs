@@ -1,162 +1,3565 @@
-use std::{collections::HashMap, path::PathBuf, time::{Duration, Instant}};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+use crossbeam::channel::{Receiver, Sender};
+use eframe::{
+    egui::{self, ScrollArea},
+    App,
+};
+use idevice::afc::{glob, name::display_name, AfcScope, DEFAULT_CHUNK_SIZE};
+use idevice::crashreportcopymobile::report_process_name;
+use idevice::lockdown::DeveloperModeStatus;
+use idevice::mobileconfig::ProfileInfo;
+use rfd::FileDialog;
+
+use crate::{
+    clipboard::resolve_paste,
+    prefs::{
+        load_prefs, save_prefs, DataEncoding, FlattenMode, Prefs, ReportFormat, ThemePref,
+        DEFAULT_CLIENT_LABEL, TRANSCRIPT_FILE,
+    },
+    types::{
+        AfcOutcome, AfcSource, Command, DeviceInfo, GuiEvent, InstallPhase, InstalledApp,
+        Operation, PairOutcome, PreviewKind, SelfTestReport, TransferId, TransferState, TrustState,
+        UsbLink,
+    },
+    ui::{
+        cli,
+        listing_cache::{parent_dir, ListingCache},
+        plist_tree::plist_tree_ui,
+    },
+    util::{
+        data_summary, format_bytes, format_data, hex_line, is_risky_extension,
+        open_with_default_app, parse_download_url, parse_udid, system_lockdown_dir, url_file_name,
+        HEX_LINE_BYTES,
+    },
+};
+
+/// Which half of the window is showing below the device list.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Pairing,
+    Files,
+    CrashLogs,
+    Profiles,
+}
+
+/// The AFC service picked in the Files view.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AfcService {
+    Media,
+    Full,
+    Container,
+    Documents,
+}
+
+impl AfcService {
+    /// Whether the service needs an app's bundle ID.
+    fn uses_bundle(self) -> bool {
+        matches!(self, AfcService::Container | AfcService::Documents)
+    }
+}
+
+/// Suggestions shown under the bundle ID field at most.
+const MAX_BUNDLE_SUGGESTIONS: usize = 12;
+/// Suggestions shown under the path field at most.
+const MAX_PATH_SUGGESTIONS: usize = 12;
+/// Typing pause before the path field lists the directory being typed in.
+const PATH_COMPLETION_DELAY: Duration = Duration::from_millis(300);
+/// How long a device must stay selected before it's listed automatically.
+const AUTO_CONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// Progress of the running app install, one value per phase.
+#[derive(Default)]
+struct InstallProgress {
+    upload: u8,
+    /// `None` until the upload is done and installation_proxy has started.
+    install: Option<u8>,
+}
+
+/// One upload or download in the Transfers panel.
+struct Transfer {
+    id: TransferId,
+    udid: String,
+    label: String,
+    state: TransferState,
+    /// Bytes done and total, once the transfer has reported any.
+    progress: Option<(u64, u64)>,
+}
+
+/// A "Pair all" run: how many devices it covers and how each one went so far.
+struct PairBatch {
+    total: usize,
+    results: Vec<(String, PairOutcome)>,
+}
+
+impl PairBatch {
+    fn done(&self) -> bool {
+        self.results.len() >= self.total
+    }
+}
+
+/// A file fetched into memory for the Files view preview.
+struct Preview {
+    path: String,
+    source: AfcSource,
+    kind: PreviewKind,
+    text: String,
+    bytes: Arc<[u8]>,
+    /// Modification time on the device when fetched or last saved.
+    modified: NaiveDateTime,
+    /// `text` has edits that haven't been saved.
+    dirty: bool,
+    saving: bool,
+    /// The last save was refused because the file changed on the device.
+    conflict: bool,
+    /// The file parsed as a plist, for plists shown as a tree.
+    plist: Option<plist::Value>,
+    /// The file's size on the device, once known; `bytes` may be the start
+    /// of it in the hex viewer.
+    size: u64,
+    /// More of the file was asked for and hasn't arrived yet.
+    loading_more: bool,
+}
+
+/// One Files view: the one in the main window, or a device's own window.
+struct FilesPanel {
+    /// Device commands go to. The main panel follows the selected device.
+    device: Option<String>,
+    path: String,
+    service: AfcService,
+    entries: Vec<String>,
+    /// Listing being assembled from `AfcListBatch` events.
+    incoming: Option<(String, Vec<String>)>,
+    /// `entries` came from the listing cache and is being revalidated.
+    showing_cached: bool,
+    /// Wildcard filter applied to the listing on the client.
+    filter: String,
+    filter_case: bool,
+    status: String,
+    /// Device the current listing belongs to.
+    listing_udid: Option<String>,
+    selected_entry: Option<String>,
+    /// Entries picked with Ctrl/Cmd- or Shift-click, and the directory
+    /// they're in; a different `path` means nothing is picked.
+    picked: HashSet<String>,
+    picked_dir: String,
+    list_pending: bool,
+    last_poll: Instant,
+    /// Entry opened with Enter; becomes `path` if it lists as a directory.
+    pending_open: Option<String>,
+    preview: Option<Preview>,
+    /// Validated bundle ID the Container and Documents services browse.
+    bundle: Option<String>,
+    bundle_input: String,
+    /// Directory whose entries the path field suggests, and those entries.
+    completion_dir: Option<String>,
+    completion_entries: Vec<String>,
+    /// When the path was last edited, until its directory has been listed.
+    path_typed_at: Option<Instant>,
+    downloads: Vec<PathBuf>,
+    confirm_open: Option<PathBuf>,
+    /// Remote path waiting for the user to confirm its deletion.
+    confirm_delete: Option<String>,
+    /// Entries handled and total of the running delete.
+    delete_progress: Option<(u64, u64)>,
+    /// Name typed for a new folder in the current directory.
+    new_folder: String,
+    /// http(s) URL typed to download into the current directory.
+    upload_url: String,
+    /// Path being renamed and the new name typed for it.
+    renaming: Option<(String, String)>,
+}
+
+impl Default for FilesPanel {
+    fn default() -> Self {
+        Self {
+            device: None,
+            path: "/".into(),
+            service: AfcService::Media,
+            entries: Vec::new(),
+            incoming: None,
+            showing_cached: false,
+            filter: String::new(),
+            filter_case: false,
+            status: String::new(),
+            listing_udid: None,
+            selected_entry: None,
+            picked: HashSet::new(),
+            picked_dir: String::new(),
+            list_pending: false,
+            last_poll: Instant::now(),
+            pending_open: None,
+            preview: None,
+            bundle: None,
+            bundle_input: String::new(),
+            completion_dir: None,
+            completion_entries: Vec::new(),
+            path_typed_at: None,
+            downloads: Vec::new(),
+            confirm_open: None,
+            confirm_delete: None,
+            delete_progress: None,
+            new_folder: String::new(),
+            upload_url: String::new(),
+            renaming: None,
+        }
+    }
+}
+
+impl FilesPanel {
+    /// Where Files commands go, or `None` while an app service has no bundle ID yet.
+    fn source(&self) -> Option<AfcSource> {
+        match self.service {
+            AfcService::Media => Some(AfcSource::Scope(AfcScope::Media)),
+            AfcService::Full => Some(AfcSource::Scope(AfcScope::Full)),
+            AfcService::Container => self.bundle.clone().map(AfcSource::Container),
+            AfcService::Documents => self.bundle.clone().map(AfcSource::Documents),
+        }
+    }
+
+    /// Picked entries of the current directory, in listing order.
+    fn picked_entries(&self) -> Vec<String> {
+        if self.picked_dir != self.path {
+            return Vec::new();
+        }
+        self.entries
+            .iter()
+            .filter(|e| self.picked.contains(*e))
+            .cloned()
+            .collect()
+    }
+
+    /// Cache key for where listings currently come from.
+    fn listing_source(&self) -> String {
+        self.source().map(|s| s.service_key()).unwrap_or_default()
+    }
+
+    /// Launch a downloaded file, asking first if it looks executable.
+    fn open_download(&mut self, path: PathBuf) {
+        if is_risky_extension(&path) {
+            self.confirm_open = Some(path);
+        } else {
+            open_with_default_app(&path);
+        }
+    }
+}
+
+pub struct PairApp {
+    tx: Sender<Command>,
+    rx: Receiver<GuiEvent>,
+    devices: Vec<(String, String)>,
+    selected: Option<String>,
+    status: String,
+    output_dir: PathBuf,
+    show_device_info: bool,
+    device_info: HashMap<String, DeviceInfo>,
+    /// Lockdown values by UDID as the device sent them, for the tree view.
+    device_info_tree: HashMap<String, plist::Value>,
+    trust: HashMap<String, TrustState>,
+    /// Devices the last refresh found passcode-locked.
+    locked: HashSet<String>,
+    /// Devices with a GetDeviceInfo request in flight or already answered.
+    info_requested: HashSet<String>,
+    /// All Properties rows whose data the user expanded.
+    expanded_data: HashSet<String>,
+    last_tick: Instant,
+    first_frame: bool,
+    /// Set until the first device list arrives.
+    scanning: bool,
+    /// Operations sent to the worker that haven't reported `Finished` yet.
+    /// The periodic refresh is held off while any are pending.
+    busy_ops: Vec<Operation>,
+    /// The Files view in the main window.
+    files: FilesPanel,
+    /// Device selected last frame and when it was selected, for
+    /// `auto_connect_afc`.
+    auto_connect: Option<(Option<String>, Instant)>,
+    /// Files views popped out into their own windows, by device.
+    popouts: HashMap<String, FilesPanel>,
+    installed_apps: HashMap<String, Vec<InstalledApp>>,
+    /// Devices with a ListApps request in flight or already answered.
+    apps_requested: HashSet<String>,
+    afc2_available: HashMap<String, bool>,
+    /// Devices with a ProbeAfc2 request in flight or already answered.
+    afc2_requested: HashSet<String>,
+    /// Devices whose last AFC operation failed with a connection error.
+    afc_disconnected: HashSet<String>,
+    listing_cache: ListingCache,
+    view: View,
+    prefs: Prefs,
+    install_progress: Option<InstallProgress>,
+    /// Device whose nickname is being edited, with the text typed so far.
+    renaming: Option<(String, String)>,
+    /// Whether dark visuals are applied; `None` until the first frame.
+    dark_visuals: Option<bool>,
+    /// The running or last "Pair all", until its summary is closed.
+    pair_batch: Option<PairBatch>,
+    /// How each connected device is attached, as of the last scan.
+    usb_links: HashMap<String, UsbLink>,
+    /// Whether a self-test is running, and its report until the dialog is closed.
+    self_test_running: bool,
+    self_test: Option<SelfTestReport>,
+    /// The usbmuxd socket being edited in the settings, and how the last
+    /// one applied answered.
+    usbmuxd_input: String,
+    usbmuxd_check: Option<Result<String, String>>,
+    /// The client label being edited in the settings.
+    client_label_input: String,
+    /// UDID pasted to pair a device that may not be listed yet.
+    pair_udid_input: String,
+    /// Device waiting for the user to confirm unpairing it.
+    confirm_unpair: Option<String>,
+    /// Crash report paths per device, and devices with a listing requested.
+    crash_reports: HashMap<String, Vec<String>>,
+    crash_requested: HashSet<String>,
+    /// Delete crash reports from the device once they're downloaded.
+    crash_remove_after: bool,
+    /// Configuration profiles per device, and devices with a listing requested.
+    profiles: HashMap<String, Vec<ProfileInfo>>,
+    profiles_requested: HashSet<String>,
+    /// Device and profile waiting for the user to confirm removing it.
+    confirm_remove_profile: Option<(String, ProfileInfo)>,
+    /// Uploads and downloads in the order they were queued, until cleared.
+    transfers: Vec<Transfer>,
+}
+
+impl PairApp {
+    pub fn new(tx: Sender<Command>, rx: Receiver<GuiEvent>, default_dir: PathBuf) -> Self {
+        let prefs = load_prefs();
+        let _ = tx.send(Command::SetAfcChunkSize {
+            bytes: prefs.afc_chunk_size,
+        });
+        let _ = tx.send(Command::SetInfoFlattenMode {
+            mode: prefs.info_flatten_mode,
+        });
+        let _ = tx.send(Command::SetMaxConcurrentTransfers {
+            limit: prefs.max_concurrent_transfers,
+        });
+        if !prefs.usbmuxd_socket.is_empty() {
+            let _ = tx.send(Command::SetUsbmuxdSocket {
+                socket: prefs.usbmuxd_socket.clone(),
+            });
+        }
+        let usbmuxd_input = prefs.usbmuxd_socket.clone();
+        if !prefs.client_label.is_empty() {
+            let _ = tx.send(Command::SetClientLabel {
+                label: prefs.client_label.clone(),
+            });
+        }
+        let client_label_input = prefs.client_label.clone();
+        if prefs.afc_transcript {
+            let _ = tx.send(Command::SetAfcTranscript {
+                dir: Some(prefs.transcript_dir(&default_dir)),
+            });
+        }
+        Self {
+            tx,
+            rx,
+            devices: Vec::new(),
+            selected: None,
+            status: String::new(),
+            output_dir: default_dir,
+            show_device_info: true,
+            device_info: HashMap::new(),
+            device_info_tree: HashMap::new(),
+            trust: HashMap::new(),
+            locked: HashSet::new(),
+            info_requested: HashSet::new(),
+            expanded_data: HashSet::new(),
+            last_tick: Instant::now(),
+            first_frame: true,
+            scanning: true,
+            busy_ops: Vec::new(),
+            files: FilesPanel::default(),
+            auto_connect: None,
+            popouts: HashMap::new(),
+            installed_apps: HashMap::new(),
+            apps_requested: HashSet::new(),
+            afc2_available: HashMap::new(),
+            afc_disconnected: HashSet::new(),
+            afc2_requested: HashSet::new(),
+            listing_cache: ListingCache::new(Duration::from_secs(prefs.afc_listing_ttl_secs)),
+            view: View::Pairing,
+            prefs,
+            install_progress: None,
+            renaming: None,
+            dark_visuals: None,
+            pair_batch: None,
+            usb_links: HashMap::new(),
+            self_test_running: false,
+            self_test: None,
+            usbmuxd_input,
+            client_label_input,
+            pair_udid_input: String::new(),
+            usbmuxd_check: None,
+            confirm_unpair: None,
+            crash_reports: HashMap::new(),
+            crash_requested: HashSet::new(),
+            crash_remove_after: false,
+            profiles: HashMap::new(),
+            profiles_requested: HashSet::new(),
+            confirm_remove_profile: None,
+            transfers: Vec::new(),
+        }
+    }
+
+    /// Run `f` on the Files panel for `udid`: its own window's if it has
+    /// one, otherwise the main window's. `None` always picks the main panel.
+    ///
+    /// The panel is moved out of `self` for the call so `f` can use both.
+    fn with_panel<R>(
+        &mut self,
+        udid: Option<&str>,
+        f: impl FnOnce(&mut Self, &mut FilesPanel) -> R,
+    ) -> R {
+        let popout = udid
+            .filter(|u| self.popouts.contains_key(*u))
+            .map(str::to_string);
+        let mut panel = match &popout {
+            Some(udid) => self.popouts.remove(udid).unwrap_or_default(),
+            None => {
+                let mut panel = std::mem::take(&mut self.files);
+                if panel.device != self.selected {
+                    panel.completion_dir = None;
+                }
+                panel.device = self.selected.clone();
+                panel
+            }
+        };
+        let res = f(self, &mut panel);
+        match popout {
+            Some(udid) => {
+                self.popouts.insert(udid, panel);
+            }
+            None => self.files = panel,
+        }
+        res
+    }
+
+    /// Upload whatever the clipboard refers to into the panel's current directory.
+    fn upload_from_clipboard(&mut self, panel: &mut FilesPanel, pasted: Option<&str>) {
+        let Some(udid) = panel.device.clone() else {
+            panel.status = "Select a device before pasting".into();
+            return;
+        };
+        let Some(source) = panel.source() else {
+            panel.status = "Pick an app before pasting".into();
+            return;
+        };
+        match resolve_paste(pasted) {
+            Ok(local_path) => {
+                let name = local_path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let remote_path = format!("{}/{}", panel.path.trim_end_matches('/'), name);
+                self.listing_cache.invalidate(
+                    &udid,
+                    &source.service_key(),
+                    parent_dir(&remote_path),
+                );
+                let _ = self.tx.send(Command::AfcUpload {
+                    udid,
+                    local_path,
+                    remote_path,
+                    source,
+                    skip_space_check: self.prefs.skip_space_check,
+                });
+                self.busy_ops.push(Operation::Upload);
+            }
+            Err(e) => panel.status = e,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let _ = self.tx.send(Command::Refresh { probe_trust: true });
+    }
+
+    fn pair_selected(&mut self) {
+        if let Some(udid) = self.selected.clone() {
+            self.pair_udid(udid);
+        }
+    }
+
+    /// Pair `udid`, which the worker looks up through usbmuxd, so it needn't
+    /// be in the device list yet.
+    fn pair_udid(&mut self, udid: String) {
+        if self.locked.contains(&udid) {
+            self.status = format!("{udid} is locked, unlock it to pair");
+            return;
+        }
+        self.status = format!("Pairing {udid}");
+        let _ = self.tx.send(Command::Pair {
+            udid,
+            out_dir: self.output_dir.clone(),
+            enable_wifi: self.prefs.enable_wifi_sync,
+            install_system: self.prefs.install_to_system_lockdown,
+        });
+        self.busy_ops.push(Operation::Pair);
+    }
+
+    /// Pair every connected device that isn't trusted yet.
+    fn pair_all(&mut self) {
+        let udids = self.unpaired_devices();
+        if udids.is_empty() {
+            return;
+        }
+        self.pair_batch = Some(PairBatch {
+            total: udids.len(),
+            results: Vec::new(),
+        });
+        self.status = format!("Pairing {} device(s)", udids.len());
+        let _ = self.tx.send(Command::PairAll {
+            udids,
+            out_dir: self.output_dir.clone(),
+            enable_wifi: self.prefs.enable_wifi_sync,
+            install_system: self.prefs.install_to_system_lockdown,
+        });
+        self.busy_ops.push(Operation::Pair);
+    }
+
+    /// Connected devices not known to be trusted, leaving out locked ones.
+    fn unpaired_devices(&self) -> Vec<String> {
+        self.devices
+            .iter()
+            .map(|(udid, _)| udid)
+            .filter(|udid| {
+                self.trust.get(*udid) != Some(&TrustState::Trusted) && !self.locked.contains(*udid)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Progress of the running "Pair all", or its summary once it's done.
+    fn pair_batch_view(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let Some(batch) = &self.pair_batch else {
+            return;
+        };
+        if !batch.done() {
+            ui.add(
+                egui::ProgressBar::new(batch.results.len() as f32 / batch.total as f32)
+                    .text(format!(
+                        "Paired {} of {} device(s)",
+                        batch.results.len(),
+                        batch.total
+                    ))
+                    .desired_width(240.0),
+            );
+            return;
+        }
+        let mut open = true;
+        let mut close = false;
+        egui::Window::new("Pair all")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let count = |f: fn(&PairOutcome) -> bool| {
+                    batch.results.iter().filter(|(_, o)| f(o)).count()
+                };
+                ui.label(format!(
+                    "{} paired, {} locked, {} failed",
+                    count(|o| matches!(o, PairOutcome::Paired { .. })),
+                    count(|o| matches!(o, PairOutcome::Locked)),
+                    count(|o| matches!(o, PairOutcome::Failed(_))),
+                ));
+                ui.separator();
+                egui::Grid::new("pair_all_results")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (udid, outcome) in &batch.results {
+                            ui.label(self.device_label(udid));
+                            match outcome {
+                                PairOutcome::Paired { note } => {
+                                    ui.colored_label(egui::Color32::GREEN, format!("Paired{note}"));
+                                }
+                                PairOutcome::Locked => {
+                                    ui.colored_label(
+                                        ui.visuals().warn_fg_color,
+                                        "Locked, unlock and retry",
+                                    );
+                                }
+                                PairOutcome::Failed(e) => {
+                                    ui.colored_label(ui.visuals().error_fg_color, e);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                close = ui.button("Close").clicked();
+            });
+        if !open || close {
+            self.pair_batch = None;
+        }
+    }
+
+    /// Save the edited usbmuxd socket and have the worker switch to it.
+    fn apply_usbmuxd_socket(&mut self) {
+        let socket = self.usbmuxd_input.trim().to_string();
+        self.prefs.usbmuxd_socket = socket.clone();
+        save_prefs(&self.prefs);
+        let _ = self.tx.send(Command::SetUsbmuxdSocket { socket });
+        self.usbmuxd_check = Some(Ok("Checking...".into()));
+    }
+
+    /// Save the edited client label and have the worker use it.
+    fn apply_client_label(&mut self) {
+        let label = self.client_label_input.trim().to_string();
+        self.prefs.client_label = label.clone();
+        save_prefs(&self.prefs);
+        let _ = self.tx.send(Command::SetClientLabel { label });
+    }
+
+    /// Save the transcript settings and have the worker start or stop it.
+    fn apply_afc_transcript(&mut self) {
+        save_prefs(&self.prefs);
+        let dir = self
+            .prefs
+            .afc_transcript
+            .then(|| self.prefs.transcript_dir(&self.output_dir));
+        let _ = self.tx.send(Command::SetAfcTranscript { dir });
+    }
+
+    fn run_self_test(&mut self) {
+        let _ = self.tx.send(Command::SelfTest {
+            out_dir: self.output_dir.clone(),
+        });
+        self.self_test_running = true;
+    }
+
+    /// Shown in place of the device list while nothing is connected.
+    fn no_devices_view(&mut self, ui: &mut egui::Ui) {
+        let service = if cfg!(target_os = "windows") {
+            "Is the Apple Mobile Device Service running? It comes with iTunes or the Apple Devices app."
+        } else if cfg!(target_os = "macos") {
+            "Does the device show up in Finder?"
+        } else {
+            "Is usbmuxd installed and running?"
+        };
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.strong("No devices found");
+            ui.label("Connect a device with a USB cable, then check:");
+            ui.label("\u{2022} Is the device unlocked?");
+            ui.label("\u{2022} Has it been trusted? Tap Trust on the device when asked.");
+            ui.label(format!("\u{2022} {service}"));
+            ui.label("\u{2022} Does the cable carry data, not just power?");
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                if ui
+                    .add(egui::Button::new("Refresh").min_size(egui::vec2(96.0, 28.0)))
+                    .clicked()
+                {
+                    self.refresh();
+                }
+                if ui
+                    .add_enabled(
+                        !self.self_test_running,
+                        egui::Link::new("Run the self-test"),
+                    )
+                    .on_hover_text("Check usbmuxd, tunneld and the save directory")
+                    .clicked()
+                {
+                    self.run_self_test();
+                }
+            });
+        });
+    }
+
+    /// The self-test dialog, once a report has arrived.
+    fn self_test_view(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.self_test else {
+            return;
+        };
+        let mut open = true;
+        let (mut copy, mut rerun, mut close) = (false, false, false);
+        egui::Window::new("Self-test")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                egui::Grid::new("self_test_checks")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for check in &report.checks {
+                            match &check.result {
+                                Ok(detail) => {
+                                    ui.colored_label(egui::Color32::GREEN, "✔");
+                                    ui.label(check.name);
+                                    ui.monospace(detail);
+                                }
+                                Err(detail) => {
+                                    ui.colored_label(ui.visuals().error_fg_color, "✘");
+                                    ui.label(check.name);
+                                    ui.monospace(detail);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                if report.devices.is_empty() {
+                    ui.label("No devices detected");
+                }
+                egui::Grid::new("self_test_devices")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (udid, display, trust) in &report.devices {
+                            ui.label(display);
+                            ui.monospace(udid);
+                            ui.label(format!("{trust:?}"));
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    copy = ui.button("Copy report").clicked();
+                    rerun = ui
+                        .add_enabled(!self.self_test_running, egui::Button::new("Run again"))
+                        .clicked();
+                    close = ui.button("Close").clicked();
+                });
+            });
+        if copy {
+            ctx.copy_text(report.to_text());
+            self.status = "Self-test report copied to clipboard".into();
+        }
+        if rerun {
+            self.run_self_test();
+        }
+        if !open || close {
+            self.self_test = None;
+        }
+    }
+
+    /// Pick a pairing file on disk and have the worker check and save it.
+    fn import_pairing_file(&mut self) {
+        let Some(udid) = self.selected.clone() else {
+            return;
+        };
+        let Some(path) = FileDialog::new()
+            .add_filter("Pairing file", &["mobiledevicepairing", "plist"])
+            .pick_file()
+        else {
+            return;
+        };
+        self.status = format!("Importing {}...", path.display());
+        let _ = self.tx.send(Command::ImportPairingFile {
+            udid,
+            path,
+            out_dir: self.output_dir.clone(),
+        });
+    }
+
+    /// Queued, running and finished uploads and downloads, across devices.
+    fn transfers_panel(&mut self, ctx: &egui::Context) {
+        if self.transfers.is_empty() {
+            return;
+        }
+        egui::TopBottomPanel::bottom("transfers")
+            .resizable(true)
+            .show(ctx, |ui| {
+                let active = self
+                    .transfers
+                    .iter()
+                    .filter(|t| t.state == TransferState::Active)
+                    .count();
+                let queued = self
+                    .transfers
+                    .iter()
+                    .filter(|t| t.state == TransferState::Queued)
+                    .count();
+                ui.horizontal(|ui| {
+                    ui.strong("Transfers");
+                    ui.label(format!("{active} active, {queued} queued"));
+                    if ui.button("Clear finished").clicked() {
+                        self.transfers.retain(|t| !t.state.is_finished());
+                    }
+                });
+                let mut cancel = None;
+                ScrollArea::vertical()
+                    .id_salt("transfers")
+                    .max_height(160.0)
+                    .show(ui, |ui| {
+                        for t in &self.transfers {
+                            ui.horizontal(|ui| {
+                                match &t.state {
+                                    TransferState::Queued => {
+                                        ui.weak("Queued");
+                                    }
+                                    TransferState::Active => {
+                                        let bar = match t.progress {
+                                            Some((done, total)) if total > 0 => {
+                                                egui::ProgressBar::new(done as f32 / total as f32)
+                                                    .text(format!(
+                                                        "{} of {}",
+                                                        format_bytes(done),
+                                                        format_bytes(total)
+                                                    ))
+                                            }
+                                            _ => egui::ProgressBar::new(0.0).text("Starting"),
+                                        };
+                                        ui.add(bar.desired_width(200.0));
+                                    }
+                                    TransferState::Done(message) => {
+                                        ui.label("Done").on_hover_text(message);
+                                    }
+                                    TransferState::Failed(e) => {
+                                        ui.colored_label(ui.visuals().error_fg_color, "Failed")
+                                            .on_hover_text(e);
+                                    }
+                                    TransferState::Cancelled => {
+                                        ui.weak("Cancelled");
+                                    }
+                                }
+                                if !t.state.is_finished() && ui.small_button("Cancel").clicked() {
+                                    cancel = Some(t.id);
+                                }
+                                ui.label(&t.label).on_hover_text(self.device_label(&t.udid));
+                            });
+                        }
+                    });
+                if let Some(id) = cancel {
+                    let _ = self.tx.send(Command::CancelTransfer { id });
+                }
+            });
+    }
+
+    /// Crash reports on the selected device, grouped by the process that crashed.
+    fn crash_logs_view(&mut self, ui: &mut egui::Ui) {
+        let Some(udid) = self.selected.clone() else {
+            ui.label("Select a device to see its crash logs.");
+            return;
+        };
+        if self.crash_requested.insert(udid.clone()) {
+            let _ = self
+                .tx
+                .send(Command::ListCrashReports { udid: udid.clone() });
+        }
+        let reports = self.crash_reports.get(&udid).cloned().unwrap_or_default();
+        let mut download = None;
+        ui.horizontal(|ui| {
+            if ui
+                .button("Refresh")
+                .on_hover_text("Have the device move new crash logs over, then list them")
+                .clicked()
+            {
+                let _ = self
+                    .tx
+                    .send(Command::ListCrashReports { udid: udid.clone() });
+                self.status = "Listing crash reports...".into();
+            }
+            if ui
+                .add_enabled(!reports.is_empty(), egui::Button::new("Download all"))
+                .clicked()
+            {
+                download = Some(reports.clone());
+            }
+            ui.checkbox(
+                &mut self.crash_remove_after,
+                "Delete from device after downloading",
+            )
+            .on_hover_text("Each report is deleted only once it has been saved here");
+        });
+        if !self.crash_reports.contains_key(&udid) {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Loading crash reports...");
+            });
+        } else if reports.is_empty() {
+            ui.label("No crash reports on this device.");
+        }
+
+        let mut by_process: std::collections::BTreeMap<&str, Vec<&String>> = Default::default();
+        for report in &reports {
+            by_process
+                .entry(report_process_name(report))
+                .or_default()
+                .push(report);
+        }
+        for (process, reports) in by_process {
+            egui::CollapsingHeader::new(format!("{process} ({})", reports.len()))
+                .id_salt(("crash_process", process))
+                .show(ui, |ui| {
+                    if ui.small_button("Download these").clicked() {
+                        download = Some(reports.iter().map(|r| r.to_string()).collect());
+                    }
+                    for report in reports {
+                        ui.horizontal(|ui| {
+                            ui.monospace(report.as_str());
+                            if ui.small_button("Download").clicked() {
+                                download = Some(vec![report.clone()]);
+                            }
+                        });
+                    }
+                });
+        }
+
+        if let Some(reports) = download {
+            self.status = format!("Downloading {} crash report(s)...", reports.len());
+            let _ = self.tx.send(Command::DownloadCrashReports {
+                udid,
+                reports,
+                out_dir: self.output_dir.clone(),
+                remove: self.crash_remove_after,
+            });
+        }
+    }
+
+    /// Configuration profiles installed on the selected device.
+    fn profiles_view(&mut self, ui: &mut egui::Ui) {
+        let Some(udid) = self.selected.clone() else {
+            ui.label("Select a device to see its profiles.");
+            return;
+        };
+        if self.profiles_requested.insert(udid.clone()) {
+            let _ = self.tx.send(Command::ListProfiles { udid: udid.clone() });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Refresh").clicked() {
+                let _ = self.tx.send(Command::ListProfiles { udid: udid.clone() });
+                self.status = "Listing profiles...".into();
+            }
+            if ui.button("Install Profile").clicked() {
+                let picked = FileDialog::new()
+                    .add_filter("Configuration profile", &["mobileconfig"])
+                    .pick_file();
+                if let Some(path) = picked {
+                    self.status = format!("Installing {}...", path.display());
+                    let _ = self.tx.send(Command::InstallProfile {
+                        udid: udid.clone(),
+                        path,
+                    });
+                }
+            }
+        });
+        let Some(profiles) = self.profiles.get(&udid) else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Loading profiles...");
+            });
+            return;
+        };
+        if profiles.is_empty() {
+            ui.label("No configuration profiles on this device.");
+            return;
+        }
+
+        let mut remove = None;
+        egui::Grid::new("profiles").striped(true).num_columns(4).show(ui, |ui| {
+            ui.strong("Name");
+            ui.strong("Organization");
+            ui.strong("Identifier");
+            ui.label("");
+            ui.end_row();
+            for profile in profiles {
+                let name = ui.label(profile.name.as_deref().unwrap_or("(unnamed)"));
+                if let Some(description) = &profile.description {
+                    name.on_hover_text(description);
+                }
+                ui.label(profile.organization.as_deref().unwrap_or(""));
+                ui.monospace(&profile.identifier);
+                if profile.removal_disallowed {
+                    ui.weak("Locked").on_hover_text(
+                        "Installed as non-removable, usually by the organization supervising the device",
+                    );
+                } else if profile.encrypted {
+                    ui.weak("Encrypted").on_hover_text("Encrypted profiles can only be removed on the device");
+                } else if ui.small_button("Remove").clicked() {
+                    remove = Some(profile.clone());
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(profile) = remove {
+            self.confirm_remove_profile = Some((udid, profile));
+        }
+    }
+
+    /// Ask before removing `confirm_remove_profile`, then have the worker do it.
+    fn remove_profile_dialog(&mut self, ctx: &egui::Context) {
+        let Some((udid, profile)) = self.confirm_remove_profile.clone() else {
+            return;
+        };
+        let mut open = true;
+        let (mut confirmed, mut cancelled) = (false, false);
+        egui::Window::new("Remove profile?")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "\"{}\" ({}) will be removed from {}, along with the settings, accounts and \
+                     certificates it installed.",
+                    profile.name.as_deref().unwrap_or("(unnamed)"),
+                    profile.identifier,
+                    self.device_label(&udid),
+                ));
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("Remove").clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+        if confirmed {
+            self.status = format!("Removing profile {}...", profile.identifier);
+            let _ = self.tx.send(Command::RemoveProfile {
+                udid,
+                identifier: profile.identifier,
+            });
+        }
+        if confirmed || cancelled || !open {
+            self.confirm_remove_profile = None;
+        }
+    }
+
+    /// Ask before unpairing `confirm_unpair`, then have the worker do it.
+    fn unpair_dialog(&mut self, ctx: &egui::Context) {
+        let Some(udid) = self.confirm_unpair.clone() else {
+            return;
+        };
+        let mut open = true;
+        let (mut confirmed, mut cancelled) = (false, false);
+        egui::Window::new("Unpair device?")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} will stop trusting this computer and its pairing file in {} will be deleted. \
+                     Pairing again needs the Trust prompt on the device.",
+                    self.device_label(&udid),
+                    self.output_dir.display()
+                ));
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("Unpair").clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+        if confirmed {
+            let _ = self.tx.send(Command::Unpair {
+                udid: udid.clone(),
+                out_dir: self.output_dir.clone(),
+            });
+            self.status = format!("Unpairing {udid}...");
+        }
+        if confirmed || cancelled || !open {
+            self.confirm_unpair = None;
+        }
+    }
+
+    /// Pick an .ipa and have the worker stage and install it.
+    fn install_app(&mut self) {
+        let Some(udid) = self.selected.clone() else {
+            return;
+        };
+        let Some(ipa_path) = FileDialog::new()
+            .add_filter("iOS app", &["ipa"])
+            .pick_file()
+        else {
+            return;
+        };
+        let _ = self.tx.send(Command::InstallApp { udid, ipa_path });
+        self.install_progress = Some(InstallProgress::default());
+        self.busy_ops.push(Operation::Install);
+    }
+
+    /// Descend into the selected entry, or download it if it's a file.
+    fn open_selected_entry(&mut self, panel: &mut FilesPanel) {
+        let (Some(udid), Some(entry), Some(source)) = (
+            panel.device.clone(),
+            panel.selected_entry.clone(),
+            panel.source(),
+        ) else {
+            return;
+        };
+        let path = format!("{}/{}", panel.path.trim_end_matches('/'), entry);
+        let _ = self.tx.send(Command::AfcOpen {
+            udid,
+            path: path.clone(),
+            source,
+            out_dir: self.output_dir.clone(),
+            resume: self.prefs.resume_downloads,
+        });
+        panel.pending_open = Some(path);
+    }
+
+    /// Name to show for `udid`: its nickname, else its current or last known label.
+    fn device_label(&self, udid: &str) -> String {
+        if let Some(nickname) = self.prefs.nicknames.get(udid) {
+            return nickname.clone();
+        }
+        self.devices
+            .iter()
+            .find(|(u, _)| u == udid)
+            .map(|(_, display)| display.clone())
+            .or_else(|| {
+                self.prefs
+                    .recent_devices
+                    .iter()
+                    .find(|d| d.udid == udid)
+                    .map(|d| d.name.clone())
+            })
+            .unwrap_or_else(|| udid.to_string())
+    }
+
+    /// Note `udid` as connected now in the device history. Returns whether
+    /// anything besides the timestamp changed, i.e. the history needs saving.
+    fn note_device(&mut self, udid: &str, display: &str, now: i64) -> bool {
+        // Until its name is known a device is listed by UDID; don't store that
+        let name = (display != udid).then_some(display);
+        let changed = match self.prefs.recent_devices.iter().find(|d| d.udid == udid) {
+            Some(known) => name.is_some_and(|n| n != known.name),
+            None => true,
+        };
+        self.prefs.remember_device(udid, name, now);
+        changed
+    }
+
+    /// One row of the device list. Devices that aren't connected are shown
+    /// greyed out with when they were last seen.
+    fn device_row(&mut self, ui: &mut egui::Ui, udid: &str, connected: bool) {
+        ui.horizontal(|ui| {
+            let (color, hint) = match self.trust.get(udid) {
+                _ if !connected => (egui::Color32::DARK_GRAY, "Not connected"),
+                Some(TrustState::Trusted) => (egui::Color32::GREEN, "Trusted"),
+                Some(TrustState::Untrusted) => (egui::Color32::RED, "Not trusted, pair first"),
+                Some(TrustState::Locked) => (
+                    egui::Color32::YELLOW,
+                    "Locked, enter the passcode on the device",
+                ),
+                Some(TrustState::Unknown) | None => (egui::Color32::GRAY, "Trust state unknown"),
+            };
+            ui.colored_label(color, "●").on_hover_text(hint);
+            if let Some((renaming, input)) = &mut self.renaming {
+                if renaming == udid {
+                    let edit = ui.add(
+                        egui::TextEdit::singleline(input)
+                            .hint_text("nickname, empty to clear")
+                            .desired_width(200.0),
+                    );
+                    let submitted =
+                        edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Save").clicked() || submitted {
+                        self.prefs.set_nickname(udid, input);
+                        save_prefs(&self.prefs);
+                        self.renaming = None;
+                    } else if ui.button("Cancel").clicked() {
+                        self.renaming = None;
+                    }
+                    return;
+                }
+            }
+            let label = self.device_label(udid);
+            if connected {
+                ui.selectable_value(&mut self.selected, Some(udid.to_string()), label)
+                    .on_hover_text(udid);
+            } else {
+                let last_seen = self
+                    .prefs
+                    .recent_devices
+                    .iter()
+                    .find(|d| d.udid == udid)
+                    .map(|d| format_last_seen(d.last_seen))
+                    .unwrap_or_default();
+                ui.add_enabled(false, egui::SelectableLabel::new(false, label))
+                    .on_disabled_hover_text(format!("{udid}\nLast seen {last_seen}"));
+            }
+            if ui.small_button("Rename").clicked() {
+                let current = self.prefs.nicknames.get(udid).cloned().unwrap_or_default();
+                self.renaming = Some((udid.to_string(), current));
+            }
+            if connected {
+                ui.menu_button("Copy command", |ui| {
+                    let pairing_file = self.output_dir.join(format!("{udid}.mobiledevicepairing"));
+                    let pairing_file = pairing_file.exists().then_some(pairing_file.as_path());
+                    for cmd in cli::commands_for(udid, pairing_file, &self.prefs.usbmuxd_socket) {
+                        if ui.button(cmd.label).on_hover_text(&cmd.line).clicked() {
+                            ui.ctx().copy_text(cmd.line);
+                            self.status = format!("Copied \"{}\" to the clipboard", cmd.label);
+                            ui.close_menu();
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Copy the equivalent command line");
+            }
+            if connected {
+                if let Some(link) = self.usb_links.get(udid) {
+                    match link.label() {
+                        Some(label) if link.is_slow() => {
+                            ui.colored_label(ui.visuals().warn_fg_color, label)
+                                .on_hover_text(
+                                    "Connected via slow USB 2; large transfers will take a while",
+                                );
+                        }
+                        Some(label) => {
+                            ui.weak(label);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            if connected && self.afc_disconnected.contains(udid) {
+                ui.weak("AFC disconnected");
+            }
+        });
+    }
+
+    /// Developer Mode state of `udid`, with a button to ask for it when it's
+    /// off. Returns whether the button was clicked.
+    fn developer_mode_badge(
+        &self,
+        ui: &mut egui::Ui,
+        udid: &str,
+        status: DeveloperModeStatus,
+    ) -> bool {
+        let mut request = false;
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Developer Mode");
+                match status {
+                    DeveloperModeStatus::Enabled => {
+                        ui.colored_label(egui::Color32::GREEN, "On");
+                    }
+                    DeveloperModeStatus::NotApplicable => {
+                        ui.weak("Not needed before iOS 16");
+                    }
+                    DeveloperModeStatus::Disabled => {
+                        ui.colored_label(ui.visuals().warn_fg_color, "Off").on_hover_text(
+                            "Debugging, Instruments and other developer services fail until it's turned on",
+                        );
+                        request = ui
+                            .button("Request enable")
+                            .on_hover_text(format!(
+                                "{} restarts, then asks to confirm once it's unlocked. \
+                                 Devices with a passcode need it turned on in Settings instead.",
+                                self.device_label(udid)
+                            ))
+                            .clicked();
+                    }
+                }
+            });
+        });
+        request
+    }
+
+    /// Whether the selected device's files are shown in their own window.
+    fn selected_popped_out(&self) -> bool {
+        self.selected
+            .as_ref()
+            .is_some_and(|u| self.popouts.contains_key(u))
+    }
+
+    /// Switch to light or dark visuals per the theme pref, if not already in use.
+    fn apply_theme(&mut self, ctx: &egui::Context) {
+        let dark = self.prefs.theme.is_dark();
+        if self.dark_visuals != Some(dark) {
+            ctx.set_visuals(if dark {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
+            self.dark_visuals = Some(dark);
+        }
+    }
+
+    /// Global shortcuts. Skipped while a text field has focus so typing isn't hijacked.
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let (refresh, pair, switch_view, enter) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::F5)
+                    | i.consume_key(egui::Modifiers::COMMAND, egui::Key::R),
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::P),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+            )
+        });
+        if refresh {
+            self.refresh();
+        }
+        if pair {
+            self.pair_selected();
+        }
+        if switch_view {
+            self.view = match self.view {
+                View::Pairing => View::Files,
+                View::Files => View::CrashLogs,
+                View::CrashLogs => View::Profiles,
+                View::Profiles => View::Pairing,
+            };
+        }
+        if enter && self.view == View::Files && !self.selected_popped_out() {
+            self.with_panel(None, |app, panel| app.open_selected_entry(panel));
+        }
+    }
+
+    /// Ask the worker for full device info unless it's cached or pending.
+    fn request_device_info(&mut self, udid: &str) {
+        if self.info_requested.insert(udid.to_string()) {
+            let _ = self.tx.send(Command::GetDeviceInfo {
+                udid: udid.to_string(),
+            });
+        }
+    }
+
+    /// Ask the worker for the installed apps on `udid` unless already asked.
+    fn request_apps(&mut self, udid: &str) {
+        if self.apps_requested.insert(udid.to_string()) {
+            let _ = self.tx.send(Command::ListApps {
+                udid: udid.to_string(),
+            });
+        }
+    }
+
+    /// Switch a Files view to another AFC service, starting again at the root.
+    fn select_service(&mut self, panel: &mut FilesPanel, service: AfcService) {
+        panel.service = service;
+        panel.bundle = None;
+        panel.path = "/".into();
+        panel.entries.clear();
+        panel.selected_entry = None;
+        panel.completion_dir = None;
+        if !service.uses_bundle() {
+            self.request_listing(panel);
+        } else if !panel.bundle_input.trim().is_empty() {
+            self.browse_app(panel);
+        }
+    }
+
+    /// Browse the sandbox of the typed bundle ID with the selected app service.
+    ///
+    /// The ID must belong to an installed app, and for Documents that app must
+    /// enable file sharing; house_arrest refuses anything else with an
+    /// unhelpful error. Documents don't wait for the app list, since the
+    /// worker reports a missing app itself.
+    fn browse_app(&mut self, panel: &mut FilesPanel) {
+        let Some(udid) = panel.device.clone() else {
+            return;
+        };
+        let bundle_id = panel.bundle_input.trim().to_string();
+        if bundle_id.is_empty() {
+            return;
+        }
+        let documents = panel.service == AfcService::Documents;
+        match self
+            .installed_apps
+            .get(&udid)
+            .map(|apps| apps.iter().find(|a| a.bundle_id == bundle_id))
+        {
+            None if documents => self.request_apps(&udid),
+            None => {
+                self.request_apps(&udid);
+                panel.status = "Still loading installed apps, try again in a moment".into();
+                return;
+            }
+            Some(None) => {
+                panel.status = format!(
+                    "{bundle_id} isn't installed on this device; pick an app from the list"
+                );
+                return;
+            }
+            Some(Some(app)) if documents && !app.file_sharing => {
+                panel.status = format!(
+                    "{} ({bundle_id}) doesn't enable file sharing, so its Documents can't be browsed",
+                    app.name
+                );
+                return;
+            }
+            Some(Some(_)) => {}
+        }
+        self.prefs.remember_bundle_id(&bundle_id);
+        save_prefs(&self.prefs);
+        panel.bundle = Some(bundle_id.clone());
+        panel.path = "/".into();
+        panel.entries.clear();
+        panel.selected_entry = None;
+        panel.completion_dir = None;
+        if !documents {
+            self.request_listing(panel);
+            return;
+        }
+        let _ = self.tx.send(Command::AppDocuments {
+            udid: udid.clone(),
+            bundle_id,
+        });
+        panel.showing_cached = false;
+        panel.listing_udid = Some(udid);
+        panel.pending_open = None;
+        panel.list_pending = true;
+        panel.last_poll = Instant::now();
+    }
+
+    /// Ask the worker to list the panel's path on its device, showing a
+    /// cached listing meanwhile if there's a fresh one.
+    fn request_listing(&mut self, panel: &mut FilesPanel) {
+        let Some(udid) = panel.device.clone() else {
+            return;
+        };
+        let Some(source) = panel.source() else {
+            panel.status = "Enter the bundle ID of the app to browse".into();
+            return;
+        };
+        panel.showing_cached = false;
+        if let Some(entries) = self
+            .listing_cache
+            .get(&udid, &source.service_key(), &panel.path)
+        {
+            panel.entries = entries;
+            panel.showing_cached = true;
+        }
+        let _ = self.tx.send(Command::AfcList {
+            udid: udid.clone(),
+            path: panel.path.clone(),
+            source,
+        });
+        panel.listing_udid = Some(udid);
+        panel.pending_open = None;
+        panel.list_pending = true;
+        panel.last_poll = Instant::now();
+    }
+
+    /// Re-list the panel's directory on the configured interval while it
+    /// still shows its device.
+    fn poll_listing(&mut self, panel: &mut FilesPanel, ctx: &egui::Context) {
+        let interval = self.prefs.afc_poll_secs;
+        if interval == 0 || panel.device.is_none() || panel.device != panel.listing_udid {
+            return;
+        }
+        let interval = Duration::from_secs(interval);
+        if !panel.list_pending && panel.last_poll.elapsed() >= interval {
+            self.request_listing(panel);
+        }
+        ctx.request_repaint_after(interval);
+    }
+
+    /// With `auto_connect_afc` on, list the selected device's files once it
+    /// has stayed selected for `AUTO_CONNECT_DELAY`, using the main panel's
+    /// service. Flipping through devices only lists the one it stops on.
+    fn auto_connect_afc(&mut self, ctx: &egui::Context) {
+        if !self.prefs.auto_connect_afc {
+            self.auto_connect = None;
+            return;
+        }
+        match &self.auto_connect {
+            Some((udid, _)) if *udid == self.selected => {}
+            _ => {
+                self.auto_connect = Some((self.selected.clone(), Instant::now()));
+            }
+        }
+        let Some((Some(udid), since)) = self.auto_connect.clone() else {
+            return;
+        };
+        if self.selected_popped_out() || !self.devices.iter().any(|(u, _)| *u == udid) {
+            return;
+        }
+        let waited = since.elapsed();
+        if waited < AUTO_CONNECT_DELAY {
+            ctx.request_repaint_after(AUTO_CONNECT_DELAY - waited);
+            return;
+        }
+        // Once listed, `listing_udid` keeps the same selection from being listed again
+        self.with_panel(None, |app, panel| {
+            if panel.listing_udid.as_ref() != Some(&udid) && !panel.list_pending {
+                app.request_listing(panel);
+                panel.status = format!("Listing {}...", panel.path);
+            }
+        });
+    }
+
+    /// Give `panel` its own window, leaving a fresh panel behind if it was the main one.
+    fn pop_out(&mut self, panel: &mut FilesPanel) {
+        if let Some(udid) = panel.device.clone() {
+            self.popouts.insert(udid, std::mem::take(panel));
+        }
+    }
+
+    /// Draw a Files view. Settings shared by every view only appear in the
+    /// main window (`main`).
+    fn files_view(&mut self, ui: &mut egui::Ui, panel: &mut FilesPanel, main: bool) {
+        if let Some(udid) = panel.device.clone() {
+            if self.afc2_requested.insert(udid.clone()) {
+                let _ = self.tx.send(Command::ProbeAfc2 { udid });
+            }
+        }
+        let afc2 = panel
+            .device
+            .as_ref()
+            .and_then(|u| self.afc2_available.get(u))
+            .copied();
+        let mut service = panel.service;
+        let mut pop_out = false;
+        ui.horizontal(|ui| {
+            ui.label("Service:");
+            ui.radio_value(&mut service, AfcService::Media, "Media (afc)")
+                .on_hover_text("The media folder every device shares over USB");
+            let afc2_hint = match afc2 {
+                Some(true) => "The whole filesystem, through the AFC2 service jailbreaks install",
+                Some(false) => "Only available on jailbroken devices with AFC2 installed",
+                None => "Checking whether this device offers AFC2...",
+            };
+            let full = ui
+                .add_enabled(
+                    afc2 == Some(true),
+                    egui::RadioButton::new(service == AfcService::Full, "Full FS (afc2)"),
+                )
+                .on_hover_text(afc2_hint)
+                .on_disabled_hover_text(afc2_hint);
+            if full.clicked() {
+                service = AfcService::Full;
+            }
+            ui.radio_value(&mut service, AfcService::Container, "App container")
+                .on_hover_text("An app's whole sandbox; usually only for development builds");
+            ui.radio_value(&mut service, AfcService::Documents, "App documents")
+                .on_hover_text("The Documents folder of an app with file sharing enabled");
+            if main
+                && ui
+                    .add_enabled(
+                        panel.device.is_some(),
+                        egui::Button::new("Open in new window"),
+                    )
+                    .on_hover_text("Browse this device in its own window, next to others")
+                    .clicked()
+            {
+                pop_out = true;
+            }
+        });
+        if pop_out {
+            self.pop_out(panel);
+            return;
+        }
+        if service != panel.service {
+            self.select_service(panel, service);
+        }
+        if panel.service.uses_bundle() {
+            self.bundle_picker(ui, panel);
+        }
+        ui.horizontal(|ui| {
+            ui.label("Path:");
+            let submitted = self.path_input(ui, panel);
+            if ui
+                .add_enabled(panel.device.is_some(), egui::Button::new("List"))
+                .clicked()
+                || submitted
+            {
+                self.request_listing(panel);
+                panel.status = format!("Listing {}...", panel.path);
+            }
+        });
+        if let Some(udid) = panel
+            .device
+            .clone()
+            .filter(|u| self.afc_disconnected.contains(u))
+        {
+            ui.horizontal(|ui| {
+                ui.colored_label(ui.visuals().warn_fg_color, "AFC connection lost");
+                if ui
+                    .button("Reconnect")
+                    .on_hover_text("Drop the cached connection and list this folder again")
+                    .clicked()
+                {
+                    let _ = self.tx.send(Command::AfcDisconnect { udid });
+                    self.request_listing(panel);
+                    panel.status = format!("Reconnecting, listing {}...", panel.path);
+                }
+            });
+        }
+        if main {
+            self.files_settings(ui);
+        }
+        let afc_source = panel.source();
+        let can_transfer = panel.device.is_some() && afc_source.is_some();
+        if panel
+            .device
+            .as_ref()
+            .and_then(|u| self.usb_links.get(u))
+            .is_some_and(UsbLink::is_slow)
+        {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Connected via slow USB 2; large transfers will take a while",
+            );
+        }
+        if ui
+            .add_enabled(can_transfer, egui::Button::new("Paste from clipboard"))
+            .clicked()
+        {
+            self.upload_from_clipboard(panel, None);
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut panel.new_folder)
+                    .hint_text("New folder name")
+                    .desired_width(160.0),
+            );
+            let name = panel.new_folder.trim().to_string();
+            if ui
+                .add_enabled(
+                    can_transfer && is_valid_entry_name(&name),
+                    egui::Button::new("Create folder"),
+                )
+                .clicked()
+            {
+                if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                    self.listing_cache
+                        .invalidate(udid, &source.service_key(), &panel.path);
+                    let _ = self.tx.send(Command::AfcMkDir {
+                        udid: udid.clone(),
+                        path: format!("{}/{}", panel.path.trim_end_matches('/'), name),
+                        source: source.clone(),
+                    });
+                    panel.new_folder.clear();
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut panel.upload_url)
+                    .hint_text("https://example.com/file.zip")
+                    .desired_width(240.0),
+            );
+            if ui
+                .add_enabled(
+                    can_transfer && !panel.upload_url.trim().is_empty(),
+                    egui::Button::new("Upload from URL"),
+                )
+                .on_hover_text(
+                    "Download the file into this folder; it streams through this computer",
+                )
+                .clicked()
+            {
+                if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                    match parse_download_url(&panel.upload_url) {
+                        Ok(url) => match url_file_name(&url) {
+                            Some(name) => {
+                                let remote =
+                                    format!("{}/{}", panel.path.trim_end_matches('/'), name);
+                                self.listing_cache.invalidate(
+                                    udid,
+                                    &source.service_key(),
+                                    &panel.path,
+                                );
+                                let _ = self.tx.send(Command::AfcUploadFromUrl {
+                                    udid: udid.clone(),
+                                    url: url.to_string(),
+                                    remote,
+                                    source: source.clone(),
+                                });
+                                self.busy_ops.push(Operation::Upload);
+                                panel.upload_url.clear();
+                            }
+                            None => panel.status = "The URL doesn't end in a file name".into(),
+                        },
+                        Err(e) => panel.status = e,
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            ui.add(
+                egui::TextEdit::singleline(&mut panel.filter)
+                    .hint_text("*.jpg, IMG_*")
+                    .desired_width(160.0),
+            )
+            .on_hover_text("* matches any run of characters, ? a single one; plain text matches anywhere in the name");
+            ui.checkbox(&mut panel.filter_case, "Match case");
+        });
+        let visible: Vec<&String> = match filter_pattern(&panel.filter) {
+            Some(pattern) => {
+                let visible: Vec<&String> = panel
+                    .entries
+                    .iter()
+                    .filter(|e| glob::matches(&pattern, e, panel.filter_case))
+                    .collect();
+                ui.label(format!(
+                    "{} of {} entries match",
+                    visible.len(),
+                    panel.entries.len()
+                ));
+                visible
+            }
+            None => panel.entries.iter().collect(),
+        };
+        let picked = panel.picked_entries();
+        ui.horizontal(|ui| {
+            let (text, hover) = if picked.is_empty() {
+                (
+                    "Download all as .zip".to_string(),
+                    "Archive the listed entries, folders included",
+                )
+            } else {
+                (
+                    format!("Download {} selected as .zip", picked.len()),
+                    "Archive the selected entries, folders included",
+                )
+            };
+            if ui
+                .add_enabled(can_transfer && !visible.is_empty(), egui::Button::new(text))
+                .on_hover_text(format!("{hover}; compression happens on this computer"))
+                .clicked()
+            {
+                if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                    let names = if picked.is_empty() {
+                        visible.iter().map(|e| e.to_string()).collect()
+                    } else {
+                        picked.clone()
+                    };
+                    let _ = self.tx.send(Command::AfcDownloadZip {
+                        udid: udid.clone(),
+                        dir: panel.path.clone(),
+                        names,
+                        source: source.clone(),
+                        out_dir: self.output_dir.clone(),
+                        follow_symlinks: self.prefs.follow_symlinks,
+                    });
+                    self.busy_ops.push(Operation::Download);
+                }
+            }
+            if picked.is_empty() {
+                ui.weak("Ctrl/Cmd- or Shift-click to select several");
+            } else if ui.small_button("Clear selection").clicked() {
+                panel.picked.clear();
+            }
+        });
+        let picked: HashSet<String> = picked.into_iter().collect();
+        let cache_key = panel.listing_source();
+        let row_height = ui.spacing().interact_size.y;
+        let mut clicked = None;
+        let mut delete = None;
+        let mut rename = None;
+        let deleting = panel.delete_progress.is_some();
+        // Only the visible rows are laid out, so huge directories stay responsive
+        ScrollArea::vertical()
+            .id_salt("afc_entries")
+            .max_height(400.0)
+            .show_rows(ui, row_height, visible.len(), |ui, rows| {
+                for &entry in &visible[rows] {
+                    ui.horizontal(|ui| {
+                        let is_selected =
+                            panel.selected_entry.as_ref() == Some(entry) || picked.contains(entry);
+                        if ui
+                            .selectable_label(
+                                is_selected,
+                                egui::RichText::new(display_name(entry)).monospace(),
+                            )
+                            .on_hover_text("Enter opens a folder or downloads a file")
+                            .clicked()
+                        {
+                            clicked = Some(entry.clone());
+                        }
+                        if ui
+                            .add_enabled(can_transfer, egui::Button::new("Preview"))
+                            .clicked()
+                        {
+                            if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                                let _ = self.tx.send(Command::AfcPreview {
+                                    udid: udid.clone(),
+                                    path: format!("{}/{}", panel.path.trim_end_matches('/'), entry),
+                                    source: source.clone(),
+                                });
+                            }
+                        }
+                        if ui
+                            .add_enabled(can_transfer, egui::Button::new("Duplicate"))
+                            .on_hover_text(
+                                "Copy on the device; the data streams through this computer",
+                            )
+                            .clicked()
+                        {
+                            if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                                self.listing_cache.invalidate(udid, &cache_key, &panel.path);
+                                let _ = self.tx.send(Command::AfcDuplicate {
+                                    udid: udid.clone(),
+                                    path: format!("{}/{}", panel.path.trim_end_matches('/'), entry),
+                                    source: source.clone(),
+                                });
+                            }
+                        }
+                        if ui
+                            .add_enabled(can_transfer, egui::Button::new("Download"))
+                            .clicked()
+                        {
+                            if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                                let _ = self.tx.send(Command::AfcDownload {
+                                    udid: udid.clone(),
+                                    remote_path: format!(
+                                        "{}/{}",
+                                        panel.path.trim_end_matches('/'),
+                                        entry
+                                    ),
+                                    source: source.clone(),
+                                    out_dir: self.output_dir.clone(),
+                                    resume: self.prefs.resume_downloads,
+                                });
+                                self.busy_ops.push(Operation::Download);
+                            }
+                        }
+                        if ui
+                            .add_enabled(can_transfer, egui::Button::new("Rename"))
+                            .clicked()
+                        {
+                            rename = Some((
+                                format!("{}/{}", panel.path.trim_end_matches('/'), entry),
+                                entry.clone(),
+                            ));
+                        }
+                        if ui
+                            .add_enabled(can_transfer && !deleting, egui::Button::new("Delete"))
+                            .clicked()
+                        {
+                            delete =
+                                Some(format!("{}/{}", panel.path.trim_end_matches('/'), entry));
+                        }
+                    });
+                }
+            });
+        if let Some(entry) = clicked {
+            let modifiers = ui.input(|i| i.modifiers);
+            if panel.picked_dir != panel.path {
+                panel.picked.clear();
+                panel.picked_dir = panel.path.clone();
+            }
+            if modifiers.command {
+                // The plainly selected entry joins the first pick
+                if panel.picked.is_empty() {
+                    if let Some(sel) = panel.selected_entry.clone() {
+                        panel.picked.insert(sel);
+                    }
+                }
+                if !panel.picked.remove(&entry) {
+                    panel.picked.insert(entry.clone());
+                }
+                panel.selected_entry = Some(entry);
+            } else if modifiers.shift {
+                // Range from the last plain or Ctrl/Cmd click, which stays the anchor
+                let anchor = panel
+                    .selected_entry
+                    .as_ref()
+                    .and_then(|a| visible.iter().position(|e| *e == a));
+                let target = visible.iter().position(|e| **e == entry);
+                match (anchor, target) {
+                    (Some(a), Some(b)) => {
+                        for e in &visible[a.min(b)..=a.max(b)] {
+                            panel.picked.insert((*e).clone());
+                        }
+                    }
+                    _ => {
+                        panel.picked.insert(entry.clone());
+                        panel.selected_entry = Some(entry);
+                    }
+                }
+            } else {
+                panel.picked.clear();
+                panel.selected_entry = Some(entry);
+            }
+        }
+        if rename.is_some() {
+            panel.renaming = rename;
+        }
+        if let Some((path, name)) = &mut panel.renaming {
+            let mut close = false;
+            ui.horizontal(|ui| {
+                ui.label(format!("Rename {path} to"));
+                ui.text_edit_singleline(name);
+                let new_name = name.trim();
+                let (dir, old_name) = path.rsplit_once('/').unwrap_or(("", path.as_str()));
+                if ui
+                    .add_enabled(
+                        is_valid_entry_name(new_name) && new_name != old_name,
+                        egui::Button::new("Rename"),
+                    )
+                    .clicked()
+                {
+                    if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                        self.listing_cache
+                            .invalidate(udid, &cache_key, parent_dir(path));
+                        let _ = self.tx.send(Command::AfcRename {
+                            udid: udid.clone(),
+                            path: path.clone(),
+                            to: format!("{dir}/{new_name}"),
+                            source: source.clone(),
+                        });
+                    }
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+            if close {
+                panel.renaming = None;
+            }
+        }
+        if delete.is_some() {
+            panel.confirm_delete = delete;
+        }
+        if let Some(path) = panel.confirm_delete.clone() {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("Delete {path} and everything in it from the device?"),
+                );
+                if ui.button("Delete").clicked() {
+                    if let (Some(udid), Some(source)) = (&panel.device, &afc_source) {
+                        self.listing_cache.invalidate(udid, &cache_key, &panel.path);
+                        panel.status = format!("Deleting {path}...");
+                        let _ = self.tx.send(Command::AfcDelete {
+                            udid: udid.clone(),
+                            path,
+                            source: source.clone(),
+                        });
+                        panel.delete_progress = Some((0, 0));
+                    }
+                    panel.confirm_delete = None;
+                }
+                if ui.button("Cancel").clicked() {
+                    panel.confirm_delete = None;
+                }
+            });
+        }
+        if let Some((done, total)) = panel.delete_progress {
+            let fraction = if total == 0 {
+                0.0
+            } else {
+                done as f32 / total as f32
+            };
+            ui.add(egui::ProgressBar::new(fraction).text(format!("Deleting {done} of {total}")));
+        }
+        self.preview_view(ui, panel);
+        if !panel.downloads.is_empty() {
+            ui.label("Downloaded:");
+            let mut open = None;
+            for path in &panel.downloads {
+                ui.horizontal(|ui| {
+                    ui.monospace(path.display().to_string());
+                    if ui.button("Open").clicked() {
+                        open = Some(path.clone());
+                    }
+                    if ui.button("Show in folder").clicked() {
+                        let _ = self.tx.send(Command::RevealPath { path: path.clone() });
+                    }
+                    if ui
+                        .button("Delete local copy")
+                        .on_hover_text("Moves the file to the trash")
+                        .clicked()
+                    {
+                        // Listed until the worker says it's gone
+                        let _ = self.tx.send(Command::TrashPath { path: path.clone() });
+                    }
+                });
+            }
+            if let Some(path) = open {
+                panel.open_download(path);
+            }
+        }
+        if let Some(path) = panel.confirm_open.clone() {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("{} may be executable. Open anyway?", path.display()),
+                );
+                if ui.button("Open").clicked() {
+                    open_with_default_app(&path);
+                    panel.confirm_open = None;
+                }
+                if ui.button("Cancel").clicked() {
+                    panel.confirm_open = None;
+                }
+            });
+        }
+        if !panel.status.is_empty() {
+            ui.label(&panel.status);
+        }
+    }
+
+    /// Path field suggesting entries of the directory being typed in; Tab
+    /// completes as far as the suggestions agree. Returns whether Enter was pressed.
+    fn path_input(&mut self, ui: &mut egui::Ui, panel: &mut FilesPanel) -> bool {
+        let id = ui.make_persistent_id("afc_path");
+        let popup_id = ui.make_persistent_id("afc_path_suggestions");
+        let (dir, prefix) = split_typed_path(&panel.path);
+        let prefix_len = prefix.len();
+        let suggestions = path_suggestions(&panel.completion_entries, prefix);
+        let ready = panel.completion_dir.as_deref() == Some(dir.as_str());
+
+        // Taken before the field sees it, which would otherwise move focus away
+        let has_focus = ui.memory(|m| m.has_focus(id));
+        if has_focus
+            && ready
+            && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Tab))
+        {
+            let common = common_prefix(&suggestions);
+            if common.len() > prefix_len {
+                panel.path = join_remote(&dir, common);
+                move_cursor_to_end(ui.ctx(), id, &panel.path);
+                panel.path_typed_at = Some(Instant::now());
+            }
+        }
+
+        let input = ui.add(
+            egui::TextEdit::singleline(&mut panel.path)
+                .id(id)
+                .desired_width(260.0),
+        );
+        if input.changed() {
+            panel.path_typed_at = Some(Instant::now());
+            ui.memory_mut(|m| m.open_popup(popup_id));
+        }
+        if let Some(typed_at) = panel.path_typed_at {
+            let waited = typed_at.elapsed();
+            if waited >= PATH_COMPLETION_DELAY {
+                panel.path_typed_at = None;
+                self.request_completions(panel);
+            } else {
+                ui.ctx()
+                    .request_repaint_after(PATH_COMPLETION_DELAY - waited);
+            }
+        }
+
+        let mut picked = None;
+        if input.has_focus() && ready && !suggestions.is_empty() {
+            egui::popup_below_widget(
+                ui,
+                popup_id,
+                &input,
+                egui::PopupCloseBehavior::CloseOnClickOutside,
+                |ui| {
+                    ui.set_min_width(260.0);
+                    for name in &suggestions {
+                        if ui
+                            .selectable_label(false, egui::RichText::new(name.as_str()).monospace())
+                            .clicked()
+                        {
+                            picked = Some(join_remote(&dir, name));
+                        }
+                    }
+                    if suggestions.len() == MAX_PATH_SUGGESTIONS {
+                        ui.weak("Keep typing to narrow down");
+                    }
+                },
+            );
+        }
+        if let Some(path) = picked {
+            panel.path = path;
+            ui.memory_mut(|m| m.close_popup());
+            ui.memory_mut(|m| m.request_focus(id));
+            move_cursor_to_end(ui.ctx(), id, &panel.path);
+            panel.path_typed_at = Some(Instant::now());
+        }
+        input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))
+    }
+
+    /// Load the entries of the directory the path field is in, from the
+    /// listing cache or else the device.
+    fn request_completions(&mut self, panel: &mut FilesPanel) {
+        let (Some(udid), Some(source)) = (panel.device.clone(), panel.source()) else {
+            return;
+        };
+        let (dir, _) = split_typed_path(&panel.path);
+        if panel.completion_dir.as_deref() == Some(dir.as_str()) {
+            return;
+        }
+        panel.completion_entries = Vec::new();
+        panel.completion_dir = Some(dir.clone());
+        if let Some(entries) = self.listing_cache.get(&udid, &source.service_key(), &dir) {
+            panel.completion_entries = entries;
+            return;
+        }
+        let _ = self.tx.send(Command::AfcList {
+            udid,
+            path: dir,
+            source,
+        });
+    }
+
+    /// Files settings stored in the prefs, which apply to every Files view.
+    fn files_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Auto-refresh every");
+            let changed = ui
+                .add(
+                    egui::DragValue::new(&mut self.prefs.afc_poll_secs)
+                        .range(0..=3600)
+                        .suffix(" s"),
+                )
+                .on_hover_text("0 turns auto-refresh off")
+                .changed();
+            if changed {
+                save_prefs(&self.prefs);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Keep listings for");
+            let changed = ui
+                .add(egui::DragValue::new(&mut self.prefs.afc_listing_ttl_secs).range(0..=600).suffix(" s"))
+                .on_hover_text("Cached listings show instantly while the directory is re-fetched; 0 disables the cache")
+                .changed();
+            if changed {
+                self.listing_cache.set_ttl(Duration::from_secs(self.prefs.afc_listing_ttl_secs));
+                save_prefs(&self.prefs);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Transfer chunk size");
+            let mut kib = self.prefs.afc_chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE) / 1024;
+            let changed = ui
+                .add(
+                    egui::DragValue::new(&mut kib)
+                        .range(4..=8192)
+                        .suffix(" KiB"),
+                )
+                .on_hover_text("Larger chunks are faster on USB 3; the default is 64 KiB")
+                .changed();
+            if ui.button("Default").clicked() {
+                self.prefs.afc_chunk_size = None;
+                save_prefs(&self.prefs);
+                let _ = self.tx.send(Command::SetAfcChunkSize { bytes: None });
+            } else if changed {
+                self.prefs.afc_chunk_size = Some(kib * 1024);
+                save_prefs(&self.prefs);
+                let _ = self.tx.send(Command::SetAfcChunkSize {
+                    bytes: self.prefs.afc_chunk_size,
+                });
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Transfers at once");
+            let changed = ui
+                .add(egui::DragValue::new(&mut self.prefs.max_concurrent_transfers).range(1..=8))
+                .on_hover_text("Further uploads and downloads wait in the Transfers panel")
+                .changed();
+            if changed {
+                save_prefs(&self.prefs);
+                let _ = self.tx.send(Command::SetMaxConcurrentTransfers {
+                    limit: self.prefs.max_concurrent_transfers,
+                });
+            }
+        });
+        if ui
+            .checkbox(
+                &mut self.prefs.open_after_download,
+                "Open files after download",
+            )
+            .changed()
+        {
+            save_prefs(&self.prefs);
+        }
+        if ui
+            .checkbox(
+                &mut self.prefs.auto_connect_afc,
+                "List files when a device is selected",
+            )
+            .on_hover_text("Connects with the service chosen above, once the selection settles")
+            .changed()
+        {
+            save_prefs(&self.prefs);
+        }
+        if ui
+            .checkbox(&mut self.prefs.resume_downloads, "Resume partial downloads")
+            .on_hover_text("Append to an existing local file instead of downloading it again")
+            .changed()
+        {
+            save_prefs(&self.prefs);
+        }
+        if ui
+            .checkbox(&mut self.prefs.follow_symlinks, "Follow symbolic links in .zip downloads")
+            .on_hover_text("Archive what links point at. Links leading back into the folder are still skipped.")
+            .changed()
+        {
+            save_prefs(&self.prefs);
+        }
+        if ui
+            .checkbox(
+                &mut self.prefs.skip_space_check,
+                "Skip free space check before uploads",
+            )
+            .on_hover_text("For devices that report free space incorrectly")
+            .changed()
+        {
+            save_prefs(&self.prefs);
+        }
+    }
+
+    /// Bundle ID field with an autocomplete popup of the device's installed apps.
+    fn bundle_picker(&mut self, ui: &mut egui::Ui, panel: &mut FilesPanel) {
+        ui.horizontal(|ui| {
+            ui.label("Bundle ID:");
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut panel.bundle_input)
+                    .hint_text("bundle ID, e.g. com.example.app")
+                    .desired_width(260.0),
+            );
+            let popup_id = ui.make_persistent_id("bundle_suggestions");
+            if input.gained_focus() {
+                if let Some(udid) = panel.device.clone() {
+                    self.request_apps(&udid);
+                }
+            }
+            if input.has_focus() && (input.gained_focus() || input.changed()) {
+                ui.memory_mut(|m| m.open_popup(popup_id));
+            }
+            let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let can_browse = panel.device.is_some() && !panel.bundle_input.trim().is_empty();
+            if ui
+                .add_enabled(can_browse, egui::Button::new("Browse"))
+                .clicked()
+                || (submitted && can_browse)
+            {
+                self.browse_app(panel);
+            }
 
-use crossbeam::channel::{Receiver, Sender};
-use eframe::{egui::{self, ScrollArea}, App};
-use rfd::FileDialog;
+            let apps = panel
+                .device
+                .as_ref()
+                .and_then(|udid| self.installed_apps.get(udid));
+            let suggestions: Vec<InstalledApp> = match apps {
+                Some(apps) => {
+                    matching_apps(apps, &self.prefs.recent_bundle_ids, &panel.bundle_input)
+                        .into_iter()
+                        .cloned()
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+            let loading = apps.is_none();
+            let mut picked = None;
+            egui::popup_below_widget(
+                ui,
+                popup_id,
+                &input,
+                egui::PopupCloseBehavior::CloseOnClickOutside,
+                |ui| {
+                    ui.set_min_width(320.0);
+                    if loading {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Loading installed apps...");
+                        });
+                    } else if suggestions.is_empty() {
+                        ui.weak("No installed app matches");
+                    }
+                    for app in &suggestions {
+                        let response = ui.horizontal(|ui| {
+                            let clicked = ui
+                                .selectable_label(
+                                    false,
+                                    format!("{} ({})", app.name, app.bundle_id),
+                                )
+                                .clicked();
+                            if app.file_sharing {
+                                ui.label("file sharing");
+                            } else {
+                                ui.weak("no file sharing").on_hover_text(
+                                    "Only apps with file sharing enabled expose their Documents",
+                                );
+                            }
+                            clicked
+                        });
+                        if response.inner {
+                            picked = Some(app.bundle_id.clone());
+                        }
+                    }
+                },
+            );
+            if let Some(bundle_id) = picked {
+                panel.bundle_input = bundle_id;
+                ui.memory_mut(|m| m.close_popup());
+            }
+        });
+    }
 
-use crate::{
-    prefs::{load_prefs, save_prefs, Prefs},
-    types::{Command, GuiEvent},
-};
+    fn preview_view(&mut self, ui: &mut egui::Ui, panel: &mut FilesPanel) {
+        let Some(preview) = &mut panel.preview else {
+            return;
+        };
+        let mut close = false;
+        // `Some(true)` overwrites even if the file changed on the device
+        let mut save = None;
+        let mut load_more = false;
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.monospace(&preview.path);
+                if preview.kind == PreviewKind::Text
+                    && ui
+                        .add_enabled(preview.dirty && !preview.saving, egui::Button::new("Save"))
+                        .clicked()
+                {
+                    save = Some(false);
+                }
+                if ui.button("Close").clicked() {
+                    close = true;
+                }
+            });
+            if preview.conflict {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "The file changed on the device since it was opened.",
+                    );
+                    if ui.button("Overwrite").clicked() {
+                        save = Some(true);
+                    }
+                });
+            }
+            match preview.kind {
+                PreviewKind::Text => {
+                    ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        let response = ui.add_enabled(
+                            !preview.saving,
+                            egui::TextEdit::multiline(&mut preview.text)
+                                .code_editor()
+                                .desired_width(f32::INFINITY),
+                        );
+                        if response.changed() {
+                            preview.dirty = true;
+                        }
+                    });
+                }
+                PreviewKind::Plist => {
+                    if let Some(plist) = &preview.plist {
+                        ScrollArea::vertical()
+                            .max_height(300.0)
+                            .show(ui, |ui| plist_tree_ui(ui, plist));
+                    }
+                    if !preview.bytes.starts_with(b"bplist")
+                        && ui
+                            .button("Edit as text")
+                            .on_hover_text("Open the XML in the text editor")
+                            .clicked()
+                    {
+                        preview.kind = PreviewKind::Text;
+                    }
+                }
+                PreviewKind::Image => {
+                    ui.add(
+                        egui::Image::from_bytes(
+                            format!("bytes://afc{}", preview.path),
+                            egui::load::Bytes::Shared(preview.bytes.clone()),
+                        )
+                        .max_height(300.0),
+                    );
+                }
+                PreviewKind::Binary => {
+                    let loaded = preview.bytes.len();
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Showing {} of {} as hex",
+                            format_bytes(loaded as u64),
+                            format_bytes(preview.size)
+                        ));
+                        if (loaded as u64) < preview.size
+                            && ui
+                                .add_enabled(!preview.loading_more, egui::Button::new("Load more"))
+                                .clicked()
+                        {
+                            load_more = true;
+                        }
+                    });
+                    // Only the visible lines are laid out, so megabytes scroll smoothly
+                    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                    let rows = loaded.div_ceil(HEX_LINE_BYTES);
+                    ScrollArea::vertical()
+                        .max_height(300.0)
+                        .auto_shrink([false, true])
+                        .show_rows(ui, row_height, rows, |ui, range| {
+                            for row in range {
+                                let offset = row * HEX_LINE_BYTES;
+                                let line =
+                                    &preview.bytes[offset..(offset + HEX_LINE_BYTES).min(loaded)];
+                                ui.add(egui::Label::new(
+                                    egui::RichText::new(hex_line(offset, line)).monospace(),
+                                ))
+                                .on_hover_text("Right-click to copy the offset")
+                                .context_menu(|ui| {
+                                    if ui.button(format!("Copy offset 0x{offset:08x}")).clicked() {
+                                        ui.ctx().copy_text(format!("0x{offset:08x}"));
+                                        ui.close_menu();
+                                    }
+                                });
+                            }
+                        });
+                }
+                PreviewKind::TooLarge => {
+                    ui.horizontal(|ui| {
+                        ui.label("Too large to preview whole; download it, or");
+                        if ui
+                            .add_enabled(
+                                !preview.loading_more,
+                                egui::Button::new("View the start as hex"),
+                            )
+                            .clicked()
+                        {
+                            load_more = true;
+                        }
+                    });
+                }
+            }
+        });
+        if let (true, Some(udid)) = (load_more, &panel.device) {
+            preview.loading_more = true;
+            let _ = self.tx.send(Command::AfcPreviewRange {
+                udid: udid.clone(),
+                path: preview.path.clone(),
+                source: preview.source.clone(),
+                offset: preview.bytes.len() as u64,
+            });
+        }
+        if let (Some(force), Some(udid)) = (save, &panel.device) {
+            preview.saving = true;
+            preview.conflict = false;
+            let _ = self.tx.send(Command::AfcSaveText {
+                udid: udid.clone(),
+                path: preview.path.clone(),
+                contents: preview.text.clone(),
+                source: preview.source.clone(),
+                expected_modified: (!force).then_some(preview.modified),
+            });
+            panel.status = format!("Saving {}...", preview.path);
+        }
+        if close {
+            panel.preview = None;
+        }
+    }
 
-pub struct PairApp {
-    tx: Sender<Command>,
-    rx: Receiver<GuiEvent>,
-    devices: Vec<(String, String)>,
-    selected: Option<String>,
-    status: String,
-    output_dir: PathBuf,
-    show_device_info: bool,
-    device_info: HashMap<String, HashMap<String, String>>,
-    last_tick: Instant,
-    first_frame: bool,
-}
+    /// Fold one batch of a directory listing into the panel waiting for it.
+    fn receive_listing(
+        &mut self,
+        panel: &mut FilesPanel,
+        udid: &str,
+        path: String,
+        batch: Vec<String>,
+        done: bool,
+    ) {
+        let (_, mut entries) = panel
+            .incoming
+            .take()
+            .filter(|(p, _)| *p == path)
+            .unwrap_or_else(|| (path.clone(), Vec::new()));
+        entries.extend(batch);
+        sort_entries(&mut entries);
+        if panel.pending_open.as_ref() == Some(&path) {
+            panel.pending_open = None;
+            panel.path = path.clone();
+            panel.selected_entry = None;
+            panel.entries.clear();
+            panel.showing_cached = false;
+        }
+        if !done {
+            // Show a fresh listing as it arrives; a cached one stays up until the end
+            if path == panel.path && !panel.showing_cached {
+                panel.entries = entries.clone();
+            }
+            panel.incoming = Some((path, entries));
+            return;
+        }
+        panel.list_pending = false;
+        if path == panel.path {
+            panel.showing_cached = false;
+        }
+        if panel.completion_dir.as_ref() == Some(&path) {
+            panel.completion_entries = entries.clone();
+        }
+        self.listing_cache
+            .insert(udid, &panel.listing_source(), &path, entries.clone());
+        // Replies for a directory we've since navigated away from are stale
+        if path == panel.path && entries != panel.entries {
+            if let Some(sel) = &panel.selected_entry {
+                if !entries.contains(sel) {
+                    panel.selected_entry = None;
+                }
+            }
+            panel.entries = entries;
+        }
+    }
 
-impl PairApp {
-    pub fn new(tx: Sender<Command>, rx: Receiver<GuiEvent>, default_dir: PathBuf) -> Self {
-        Self {
-            tx,
-            rx,
-            devices: Vec::new(),
-            selected: None,
-            status: String::new(),
-            output_dir: default_dir,
-            show_device_info: true,
-            device_info: HashMap::new(),
-            last_tick: Instant::now(),
-            first_frame: true,
+    fn receive_download(
+        &mut self,
+        panel: &mut FilesPanel,
+        remote_path: String,
+        local_path: PathBuf,
+    ) {
+        if panel.pending_open.as_ref() == Some(&remote_path) {
+            panel.pending_open = None;
+        }
+        panel.status = format!("Downloaded {remote_path} to {}", local_path.display());
+        if self.prefs.open_after_download {
+            if is_risky_extension(&local_path) {
+                panel.status = format!(
+                    "Downloaded {}; not opening automatically because it may be executable",
+                    local_path.display()
+                );
+            } else {
+                open_with_default_app(&local_path);
+            }
+        }
+        panel.downloads.push(local_path);
+    }
+
+    /// Draw every popped-out Files view in its own native window.
+    ///
+    /// Closing a window only drops its view; the device's pooled AFC clients
+    /// stay for whichever view browses it next.
+    fn show_popouts(&mut self, ctx: &egui::Context) {
+        let udids: Vec<String> = self.popouts.keys().cloned().collect();
+        for udid in udids {
+            let Some(mut panel) = self.popouts.remove(&udid) else {
+                continue;
+            };
+            let title = format!("Files - {}", self.device_label(&udid));
+            let mut open = true;
+            ctx.show_viewport_immediate(
+                files_viewport_id(&udid),
+                egui::ViewportBuilder::default()
+                    .with_title(&title)
+                    .with_inner_size([640.0, 720.0]),
+                |ctx, class| {
+                    if class == egui::ViewportClass::Embedded {
+                        // The backend can't open another window; show it inside the main one
+                        egui::Window::new(&title)
+                            .id(egui::Id::new(("files_window", &udid)))
+                            .open(&mut open)
+                            .show(ctx, |ui| {
+                                ScrollArea::vertical()
+                                    .show(ui, |ui| self.files_view(ui, &mut panel, false));
+                            });
+                    } else {
+                        egui::CentralPanel::default().show(ctx, |ui| {
+                            ScrollArea::vertical()
+                                .auto_shrink([false, false])
+                                .show(ui, |ui| self.files_view(ui, &mut panel, false));
+                        });
+                        if !ctx.wants_keyboard_input() {
+                            if ctx.input_mut(|i| {
+                                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter)
+                            }) {
+                                self.open_selected_entry(&mut panel);
+                            }
+                            if let Some(text) = pasted_text(ctx) {
+                                self.upload_from_clipboard(&mut panel, Some(&text));
+                            }
+                        }
+                        if ctx.input(|i| i.viewport().close_requested()) {
+                            open = false;
+                        }
+                    }
+                    self.poll_listing(&mut panel, ctx);
+                },
+            );
+            if open {
+                self.popouts.insert(udid, panel);
+            }
         }
     }
 }
 
 impl App for PairApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let busy = !self.busy_ops.is_empty();
         if self.first_frame || self.last_tick.elapsed() > Duration::from_secs(3) {
-            let _ = self.tx.send(Command::Refresh);
+            // Piggybacks on the scan tick to notice the OS switching themes
+            self.apply_theme(ctx);
+            if self.first_frame {
+                self.status = "Scanning...".into();
+            }
+            // While operations run, only check for unplugged devices so
+            // their work is stopped, without the list and status churn
+            let cmd = if busy && !self.first_frame {
+                Command::CheckDevices
+            } else {
+                Command::Refresh { probe_trust: false }
+            };
+            let _ = self.tx.send(cmd);
             self.last_tick = Instant::now();
             self.first_frame = false;
         }
 
         while let Ok(ev) = self.rx.try_recv() {
             match ev {
-                GuiEvent::Devices(list) => {
+                GuiEvent::Devices { list, links } => {
+                    self.usb_links = links;
+                    self.scanning = false;
+                    let same_devices = list.len() == self.devices.len()
+                        && list
+                            .iter()
+                            .all(|(u, _)| self.devices.iter().any(|(d, _)| d == u));
                     self.devices = list;
+                    let now = chrono::Utc::now().timestamp();
+                    let mut history_changed = !same_devices;
+                    for (udid, display) in self.devices.clone() {
+                        history_changed |= self.note_device(&udid, &display, now);
+                    }
+                    // Timestamps alone aren't worth a write every scan; a device
+                    // leaving is, and keeps the last time it was seen
+                    if history_changed {
+                        save_prefs(&self.prefs);
+                    }
                     self.show_device_info = true;
+                    let devices = &self.devices;
+                    self.popouts
+                        .retain(|udid, _| devices.iter().any(|(u, _)| u == udid));
                     if let Some(sel) = &self.selected {
                         if !self.devices.iter().any(|(udid, _)| udid == sel) {
+                            self.listing_cache.remove_device(sel);
+                            self.installed_apps.remove(sel);
+                            self.apps_requested.remove(sel);
+                            self.afc2_available.remove(sel);
+                            self.afc2_requested.remove(sel);
+                            self.afc_disconnected.remove(sel);
+                            self.crash_reports.remove(sel);
+                            self.crash_requested.remove(sel);
+                            self.profiles.remove(sel);
+                            self.profiles_requested.remove(sel);
                             self.selected = None;
                         }
                     }
                     if self.selected.is_none() && !self.devices.is_empty() {
                         self.selected = Some(self.devices[0].0.clone());
                     }
-                    self.status = format!("{} device(s) connected", self.devices.len());
+                    self.status = if self.devices.is_empty() {
+                        "No devices connected".into()
+                    } else {
+                        format!("{} device(s) connected", self.devices.len())
+                    };
                 }
                 GuiEvent::Status(s) => self.status = s,
+                GuiEvent::DeviceSummary { udid, display } => {
+                    if self.note_device(&udid, &display, chrono::Utc::now().timestamp()) {
+                        save_prefs(&self.prefs);
+                    }
+                    match self.devices.iter_mut().find(|(u, _)| *u == udid) {
+                        Some(entry) => entry.1 = display,
+                        None => self.devices.push((udid, display)),
+                    }
+                }
                 GuiEvent::DeviceInfo { udid, info } => {
                     self.device_info.insert(udid.clone(), info);
                     self.status = format!("Device info retrieved for {}", udid);
                 }
+                GuiEvent::DeviceInfoTree { udid, value } => {
+                    self.device_info_tree.insert(udid, value);
+                }
+                GuiEvent::AfcListBatch {
+                    udid,
+                    path,
+                    entries,
+                    done,
+                } => {
+                    self.with_panel(Some(udid.as_str()), |app, panel| {
+                        app.receive_listing(panel, &udid, path, entries, done)
+                    });
+                }
+                GuiEvent::AfcStatus { udid, message } => {
+                    self.with_panel(Some(udid.as_str()), |_, panel| {
+                        panel.list_pending = false;
+                        // A delete reports only progress until it finishes
+                        panel.delete_progress = None;
+                        if let Some(preview) = &mut panel.preview {
+                            preview.loading_more = false;
+                        }
+                        panel.status = message;
+                    });
+                }
+                GuiEvent::AfcPreviewRange {
+                    udid,
+                    path,
+                    offset,
+                    bytes,
+                    size,
+                } => {
+                    self.with_panel(Some(udid.as_str()), |_, panel| {
+                        let Some(preview) = &mut panel.preview else {
+                            return;
+                        };
+                        // Ignore a range for a preview that has since changed
+                        if preview.path != path || offset != preview.bytes.len() as u64 {
+                            return;
+                        }
+                        let mut all = preview.bytes.to_vec();
+                        all.extend(bytes);
+                        preview.bytes = all.into();
+                        preview.size = size;
+                        preview.kind = PreviewKind::Binary;
+                        preview.loading_more = false;
+                    });
+                }
+                GuiEvent::AfcProgress {
+                    udid,
+                    transfer: Some(id),
+                    done,
+                    total,
+                    ..
+                } => {
+                    if let Some(t) = self
+                        .transfers
+                        .iter_mut()
+                        .find(|t| t.id == id && t.udid == udid)
+                    {
+                        t.progress = Some((done, total));
+                    }
+                }
+                GuiEvent::AfcProgress {
+                    udid,
+                    transfer: None,
+                    done,
+                    total,
+                    path,
+                } => {
+                    self.with_panel(Some(udid.as_str()), |_, panel| {
+                        panel.delete_progress = Some((done, total));
+                        panel.status = format!("Deleting {path}");
+                    });
+                }
+                GuiEvent::TransferQueued { id, udid, label } => {
+                    self.transfers.push(Transfer {
+                        id,
+                        udid,
+                        label,
+                        state: TransferState::Queued,
+                        progress: None,
+                    });
+                }
+                GuiEvent::TransferUpdate { id, state } => {
+                    if let Some(t) = self.transfers.iter_mut().find(|t| t.id == id) {
+                        // A cancel can race the transfer's own ending; keep whichever came first
+                        if !t.state.is_finished() {
+                            t.state = state;
+                        }
+                    }
+                }
+                GuiEvent::AfcDownloaded {
+                    udid,
+                    remote_path,
+                    local_path,
+                } => {
+                    self.with_panel(Some(udid.as_str()), |app, panel| {
+                        app.receive_download(panel, remote_path, local_path)
+                    });
+                }
+                GuiEvent::AfcPreviewData {
+                    udid,
+                    path,
+                    source,
+                    bytes,
+                    kind,
+                    modified,
+                } => {
+                    let plist = (kind == PreviewKind::Plist)
+                        .then(|| plist::from_bytes::<plist::Value>(&bytes).ok())
+                        .flatten();
+                    // A plist that doesn't parse is shown as text or hex instead
+                    let kind = match kind {
+                        PreviewKind::Plist if plist.is_none() => {
+                            if std::str::from_utf8(&bytes).is_ok() {
+                                PreviewKind::Text
+                            } else {
+                                PreviewKind::Binary
+                            }
+                        }
+                        kind => kind,
+                    };
+                    let size = bytes.len() as u64;
+                    // XML plists can be switched to the text editor
+                    let text = match kind {
+                        PreviewKind::Text | PreviewKind::Plist => {
+                            String::from_utf8_lossy(&bytes).into_owned()
+                        }
+                        _ => String::new(),
+                    };
+                    self.with_panel(Some(udid.as_str()), |_, panel| {
+                        panel.preview = Some(Preview {
+                            path,
+                            source,
+                            kind,
+                            text,
+                            bytes: bytes.into(),
+                            modified,
+                            dirty: false,
+                            saving: false,
+                            conflict: false,
+                            plist,
+                            size,
+                            loading_more: false,
+                        });
+                    });
+                }
+                GuiEvent::AfcOperationResult {
+                    udid,
+                    path,
+                    outcome,
+                } => {
+                    self.with_panel(Some(udid.as_str()), |_, panel| {
+                        let preview = panel.preview.as_mut().filter(|p| p.path == path);
+                        match outcome {
+                            AfcOutcome::Saved { modified } => {
+                                if let Some(preview) = preview {
+                                    preview.saving = false;
+                                    preview.dirty = false;
+                                    preview.modified = modified;
+                                }
+                                panel.status = format!("Saved {path}");
+                            }
+                            AfcOutcome::Conflict => {
+                                if let Some(preview) = preview {
+                                    preview.saving = false;
+                                    preview.conflict = true;
+                                }
+                                panel.status = format!("{path} changed on the device, not saved");
+                            }
+                            AfcOutcome::Created => {
+                                panel.status = format!("Created {path}");
+                            }
+                            AfcOutcome::Renamed { to } => {
+                                if preview.is_some() {
+                                    panel.preview = None;
+                                }
+                                if panel.selected_entry.as_deref() == path.rsplit('/').next() {
+                                    panel.selected_entry =
+                                        to.rsplit('/').next().map(str::to_string);
+                                }
+                                panel.status = format!("Renamed {path} to {to}");
+                            }
+                            AfcOutcome::Deleted { removed } => {
+                                if preview.is_some() {
+                                    panel.preview = None;
+                                }
+                                panel.delete_progress = None;
+                                panel.status = format!("Deleted {path} ({removed} items)");
+                            }
+                            AfcOutcome::Failed(message) => {
+                                if let Some(preview) = preview {
+                                    preview.saving = false;
+                                }
+                                panel.delete_progress = None;
+                                panel.status = message;
+                            }
+                        }
+                    });
+                }
+                GuiEvent::Trust { udid, state } => {
+                    self.trust.insert(udid, state);
+                }
+                GuiEvent::LockState { udid, locked } => {
+                    if locked {
+                        self.locked.insert(udid);
+                    } else {
+                        self.locked.remove(&udid);
+                    }
+                }
+                GuiEvent::CrashReports { udid, reports } => {
+                    self.crash_reports.insert(udid, reports);
+                }
+                GuiEvent::Profiles { udid, profiles } => {
+                    self.profiles.insert(udid, profiles);
+                }
+                GuiEvent::SelfTestReport(report) => {
+                    self.self_test_running = false;
+                    self.self_test = Some(report);
+                }
+                GuiEvent::UsbmuxdCheck(res) => {
+                    self.usbmuxd_check = Some(res);
+                    self.refresh();
+                }
+                GuiEvent::Trashed { path } => {
+                    for panel in std::iter::once(&mut self.files).chain(self.popouts.values_mut()) {
+                        panel.downloads.retain(|p| *p != path);
+                    }
+                }
+                GuiEvent::PairResult { udid, outcome } => {
+                    if let Some(batch) = &mut self.pair_batch {
+                        batch.results.push((udid, outcome));
+                    }
+                }
+                GuiEvent::AfcConnection { udid, connected } => {
+                    if connected {
+                        self.afc_disconnected.remove(&udid);
+                    } else {
+                        self.afc_disconnected.insert(udid);
+                    }
+                }
+                GuiEvent::Afc2Available { udid, available } => {
+                    if !available {
+                        self.with_panel(Some(udid.as_str()), |app, panel| {
+                            if panel.service == AfcService::Full
+                                && panel.device.as_ref() == Some(&udid)
+                            {
+                                app.select_service(panel, AfcService::Media);
+                            }
+                        });
+                    }
+                    self.afc2_available.insert(udid, available);
+                }
+                GuiEvent::InstallProgress { phase, percent } => {
+                    let progress = self
+                        .install_progress
+                        .get_or_insert_with(InstallProgress::default);
+                    match phase {
+                        InstallPhase::Upload => progress.upload = percent,
+                        InstallPhase::Install => progress.install = Some(percent),
+                    }
+                }
+                GuiEvent::InstalledApps { udid, apps } => {
+                    self.installed_apps.insert(udid, apps);
+                }
+                GuiEvent::Finished(op) => {
+                    if op == Operation::Install {
+                        self.install_progress = None;
+                        // The app list is stale now
+                        if let Some(udid) = &self.selected {
+                            self.installed_apps.remove(udid);
+                            self.apps_requested.remove(udid);
+                        }
+                    }
+                    if let Some(i) = self.busy_ops.iter().position(|o| *o == op) {
+                        self.busy_ops.remove(i);
+                    }
+                    // Don't refresh the instant the last operation completes
+                    self.last_tick = Instant::now();
+                }
             }
         }
 
+        self.handle_shortcuts(ctx);
+        self.self_test_view(ctx);
+        self.unpair_dialog(ctx);
+        self.remove_profile_dialog(ctx);
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("Settings", |ui| {
+                    ui.label("Theme");
+                    for theme in ThemePref::ALL {
+                        if ui.radio_value(&mut self.prefs.theme, theme, theme.label()).clicked() {
+                            save_prefs(&self.prefs);
+                            self.apply_theme(ctx);
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    ui.label("usbmuxd socket");
+                    ui.horizontal(|ui| {
+                        let input = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut self.usbmuxd_input)
+                                    .hint_text("Default")
+                                    .desired_width(200.0),
+                            )
+                            .on_hover_text("A socket path or ip:port, e.g. for containers or forwarded sockets");
+                        let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui.button("Apply").clicked() || submitted {
+                            self.apply_usbmuxd_socket();
+                        }
+                    });
+                    match &self.usbmuxd_check {
+                        Some(Ok(msg)) => {
+                            ui.label(msg);
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(ui.visuals().error_fg_color, e);
+                        }
+                        None => {}
+                    }
+                    ui.label("Client label");
+                    ui.horizontal(|ui| {
+                        let input = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut self.client_label_input)
+                                    .hint_text(DEFAULT_CLIENT_LABEL)
+                                    .desired_width(200.0),
+                            )
+                            .on_hover_text("How this app's connections are named in usbmuxd's logs");
+                        let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui.button("Apply").clicked() || submitted {
+                            self.apply_client_label();
+                        }
+                    });
+                    ui.separator();
+                    if ui
+                        .checkbox(&mut self.prefs.afc_transcript, "Record AFC transcript")
+                        .on_hover_text("Log every finished file operation with its time, device, path and result, for troubleshooting")
+                        .changed()
+                    {
+                        self.apply_afc_transcript();
+                    }
+                    let transcript_dir = self.prefs.transcript_dir(&self.output_dir);
+                    ui.horizontal(|ui| {
+                        ui.monospace(transcript_dir.join(TRANSCRIPT_FILE).display().to_string());
+                        if ui.small_button("Change...").clicked() {
+                            if let Some(dir) = FileDialog::new().set_directory(&transcript_dir).pick_folder() {
+                                self.prefs.afc_transcript_dir = Some(dir);
+                                self.apply_afc_transcript();
+                            }
+                        }
+                        if ui
+                            .add_enabled(transcript_dir.is_dir(), egui::Button::new("Show").small())
+                            .clicked()
+                        {
+                            let _ = self.tx.send(Command::RevealPath { path: transcript_dir.clone() });
+                        }
+                    });
+                    ui.separator();
+                    if ui
+                        .add_enabled(!self.self_test_running, egui::Button::new("Run self-test"))
+                        .on_hover_text("Check usbmuxd, tunneld and the save directory, for bug reports")
+                        .clicked()
+                    {
+                        self.run_self_test();
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+        self.transfers_panel(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
                 ui.heading("iOS Pair Utility");
 
+                let selected_locked = self.selected.as_ref().is_some_and(|u| self.locked.contains(u));
+                if selected_locked {
+                    ui.colored_label(ui.visuals().warn_fg_color, "Device is locked \u{2014} unlock to continue");
+                }
+
                 ui.horizontal(|ui| {
                     ui.label(format!("Save directory: {}", self.output_dir.display()));
                 });
 
                 ui.horizontal(|ui| {
-                    if ui.button("Refresh").clicked() {
-                        let _ = self.tx.send(Command::Refresh);
+                    if ui.button("Refresh").on_hover_text("F5 / Ctrl+R").clicked() {
+                        self.refresh();
                     }
                     if ui.button("Browse").clicked() {
                         if let Some(dir) = FileDialog::new().set_directory(&self.output_dir).pick_folder() {
                             self.output_dir = dir.clone();
-                            save_prefs(&Prefs { output_dir: Some(self.output_dir.clone()) });
+                            self.prefs.output_dir = Some(dir);
+                            save_prefs(&self.prefs);
                             self.status = format!("Output dir set to {}", self.output_dir.display());
+                            if self.prefs.afc_transcript && self.prefs.afc_transcript_dir.is_none() {
+                                // The transcript follows the save directory
+                                self.apply_afc_transcript();
+                            }
                         }
                     }
                     ui.separator();
-                    if ui.add_enabled(self.selected.is_some(), egui::Button::new("Pair")).clicked() {
-                        if let Some(udid) = &self.selected {
-                            let _ = self.tx.send(Command::Pair {
-                                udid: udid.clone(),
-                                out_dir: self.output_dir.clone(),
-                            });
-                            self.status = format!("Pairing {}", udid);
-                        }
+                    if ui
+                        .add_enabled(self.selected.is_some() && !selected_locked, egui::Button::new("Pair"))
+                        .on_hover_text("Ctrl+P")
+                        .clicked()
+                    {
+                        self.pair_selected();
                     }
-                    if ui.add_enabled(self.selected.is_some(), egui::Button::new("AFC List /Documents")).clicked() {
-                        if let Some(udid) = &self.selected {
-                            let _ = self.tx.send(Command::AfcList {
-                                udid: udid.clone(),
-                                bundle_id: "com.apple.DocumentsApp".to_string(),
-                                container: false,
-                            });
-                            self.status = "Listing /Documents...".into();
+                    let unpaired = self.unpaired_devices().len();
+                    if ui
+                        .add_enabled(unpaired > 0 && self.pair_batch.is_none(), egui::Button::new("Pair all"))
+                        .on_hover_text(format!("Pair the {unpaired} connected device(s) that aren't trusted yet"))
+                        .clicked()
+                    {
+                        self.pair_all();
+                    }
+                    if ui
+                        .checkbox(&mut self.prefs.enable_wifi_sync, "Enable Wi-Fi sync")
+                        .on_hover_text("After pairing, let the device be reached over the network")
+                        .changed()
+                    {
+                        save_prefs(&self.prefs);
+                    }
+                    let system_dir = system_lockdown_dir()
+                        .map(|d| d.display().to_string())
+                        .unwrap_or_else(|| "the system lockdown directory".into());
+                    if ui
+                        .checkbox(&mut self.prefs.install_to_system_lockdown, "Install for other tools")
+                        .on_hover_text(format!(
+                            "Also copy new pairing records to {system_dir} for libimobiledevice and friends. Usually needs administrator rights"
+                        ))
+                        .changed()
+                    {
+                        save_prefs(&self.prefs);
+                    }
+                    if ui
+                        .add_enabled(self.selected.is_some(), egui::Button::new("Import pairing file..."))
+                        .on_hover_text("Use a pairing file made elsewhere for the selected device")
+                        .clicked()
+                    {
+                        self.import_pairing_file();
+                    }
+                    let paired = matches!(
+                        self.selected.as_ref().and_then(|u| self.trust.get(u)),
+                        Some(TrustState::Trusted | TrustState::Locked)
+                    );
+                    if ui
+                        .add_enabled(paired, egui::Button::new("Unpair..."))
+                        .on_hover_text("Make the selected device forget this computer")
+                        .clicked()
+                    {
+                        self.confirm_unpair = self.selected.clone();
+                    }
+                    let installing = self.install_progress.is_some();
+                    if ui
+                        .add_enabled(self.selected.is_some() && !installing, egui::Button::new("Install app..."))
+                        .on_hover_text("Sideload an .ipa onto the selected device")
+                        .clicked()
+                    {
+                        self.install_app();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Pair by UDID:");
+                    let edit = ui.add(
+                        egui::TextEdit::singleline(&mut self.pair_udid_input)
+                            .hint_text("paste a UDID")
+                            .desired_width(280.0),
+                    );
+                    let udid = parse_udid(&self.pair_udid_input).map(str::to_string);
+                    let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui
+                        .add_enabled(udid.is_some(), egui::Button::new("Pair"))
+                        .on_hover_text("Pair a connected device even if the list hasn't caught up yet")
+                        .clicked()
+                        || entered
+                    {
+                        if let Some(udid) = udid {
+                            self.pair_udid_input.clear();
+                            self.pair_udid(udid);
                         }
+                    } else if udid.is_none() && !self.pair_udid_input.trim().is_empty() {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            "Not a UDID: expected 40 hex digits, or 8 and 16 joined by a dash",
+                        );
                     }
                 });
+                self.pair_batch_view(ctx, ui);
+                if let Some(progress) = &self.install_progress {
+                    egui::Grid::new("install_progress").num_columns(2).show(ui, |ui| {
+                        ui.label("Upload");
+                        ui.add(
+                            egui::ProgressBar::new(progress.upload as f32 / 100.0)
+                                .show_percentage()
+                                .desired_width(240.0),
+                        );
+                        ui.end_row();
+                        ui.label("Install");
+                        let bar = match progress.install {
+                            Some(p) => egui::ProgressBar::new(p as f32 / 100.0).show_percentage(),
+                            None => egui::ProgressBar::new(0.0).text("Waiting for upload"),
+                        };
+                        ui.add(bar.desired_width(240.0));
+                        ui.end_row();
+                    });
+                }
 
                 ui.separator();
                 ui.label("Connected USB devices:");
-                for (udid, display) in &self.devices {
-                    ui.selectable_value(&mut self.selected, Some(udid.clone()), display);
-                }
-
-                if self.show_device_info {
-                    if let Some(udid) = &self.selected {
-                        if let Some(info) = self.device_info.get(udid) {
-                            ui.collapsing("Device Information", |ui| {
-                                for key in &[
-                                    "ProductName", "ProductVersion", "BuildVersion",
-                                    "SerialNumber", "DeviceName", "UniqueDeviceID",
-                                ] {
-                                    if let Some(value) = info.get(*key) {
-                                        ui.horizontal(|ui| {
-                                            ui.label(format!("{}: ", key));
-                                            ui.monospace(value);
+                if self.scanning && self.devices.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Scanning for devices...");
+                    });
+                } else if self.devices.is_empty() {
+                    self.no_devices_view(ui);
+                }
+                let connected: Vec<String> = self.devices.iter().map(|(udid, _)| udid.clone()).collect();
+                for udid in &connected {
+                    self.device_row(ui, udid, true);
+                }
+                let offline: Vec<String> = self
+                    .prefs
+                    .recent_devices
+                    .iter()
+                    .filter(|d| !connected.contains(&d.udid))
+                    .map(|d| d.udid.clone())
+                    .collect();
+                if !offline.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.weak("Seen before:");
+                        if ui
+                            .small_button("Clear history")
+                            .on_hover_text("Forget devices that aren't connected; nicknames are kept")
+                            .clicked()
+                        {
+                            self.prefs.recent_devices.retain(|d| connected.contains(&d.udid));
+                            save_prefs(&self.prefs);
+                        }
+                    });
+                    for udid in &offline {
+                        self.device_row(ui, udid, false);
+                    }
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.view, View::Pairing, "Pairing")
+                        .on_hover_text("Tab to switch");
+                    ui.selectable_value(&mut self.view, View::Files, "Files")
+                        .on_hover_text("Tab to switch");
+                    ui.selectable_value(&mut self.view, View::CrashLogs, "Crash Logs")
+                        .on_hover_text("Tab to switch");
+                    ui.selectable_value(&mut self.view, View::Profiles, "Profiles")
+                        .on_hover_text("Tab to switch");
+                });
+
+                if self.view == View::Pairing && self.show_device_info {
+                    if let Some(udid) = self.selected.clone() {
+                        let section = egui::CollapsingHeader::new("Device Information")
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Get Info").clicked() {
+                                        self.info_requested.remove(&udid);
+                                        self.request_device_info(&udid);
+                                    }
+                                    let cached = self.device_info.get(&udid);
+                                    if ui
+                                        .add_enabled(cached.is_some(), egui::Button::new("Copy all info (JSON)"))
+                                        .clicked()
+                                    {
+                                        if let Some(info) = cached {
+                                            ctx.copy_text(info.to_json(&udid));
+                                            self.status = "Device info copied to clipboard".into();
+                                        }
+                                    }
+                                    ui.separator();
+                                    if ui
+                                        .button("Save device report")
+                                        .on_hover_text(format!("Write the full info to {}", self.output_dir.display()))
+                                        .clicked()
+                                    {
+                                        let _ = self.tx.send(Command::SaveDeviceReport {
+                                            udid: udid.clone(),
+                                            out_dir: self.output_dir.clone(),
+                                            format: self.prefs.report_format,
+                                        });
+                                        self.status = "Saving device report...".into();
+                                    }
+                                    let before = self.prefs.report_format;
+                                    egui::ComboBox::from_id_salt("report_format")
+                                        .selected_text(before.label())
+                                        .show_ui(ui, |ui| {
+                                            for format in ReportFormat::ALL {
+                                                ui.selectable_value(&mut self.prefs.report_format, format, format.label());
+                                            }
+                                        });
+                                    if self.prefs.report_format != before {
+                                        save_prefs(&self.prefs);
+                                    }
+                                    ui.separator();
+                                    let before = self.prefs.info_flatten_mode;
+                                    egui::ComboBox::from_id_salt("info_flatten_mode")
+                                        .selected_text(before.label())
+                                        .show_ui(ui, |ui| {
+                                            for mode in FlattenMode::ALL {
+                                                ui.selectable_value(&mut self.prefs.info_flatten_mode, mode, mode.label());
+                                            }
+                                        })
+                                        .response
+                                        .on_hover_text("How nested values are listed under All Properties");
+                                    if self.prefs.info_flatten_mode != before {
+                                        save_prefs(&self.prefs);
+                                        let _ = self.tx.send(Command::SetInfoFlattenMode {
+                                            mode: self.prefs.info_flatten_mode,
                                         });
+                                        self.info_requested.remove(&udid);
+                                        self.request_device_info(&udid);
+                                    }
+                                    ui.separator();
+                                    let before = (self.prefs.info_data_encoding, self.prefs.info_data_inline_max);
+                                    egui::ComboBox::from_id_salt("info_data_encoding")
+                                        .selected_text(before.0.label())
+                                        .show_ui(ui, |ui| {
+                                            for encoding in DataEncoding::ALL {
+                                                ui.selectable_value(&mut self.prefs.info_data_encoding, encoding, encoding.label());
+                                            }
+                                        })
+                                        .response
+                                        .on_hover_text("How data values such as certificates are written out");
+                                    ui.add(
+                                        egui::DragValue::new(&mut self.prefs.info_data_inline_max)
+                                            .range(0..=4096)
+                                            .prefix("show up to ")
+                                            .suffix(" bytes"),
+                                    )
+                                    .on_hover_text("Data this small is shown in full; larger values can be expanded row by row");
+                                    if (self.prefs.info_data_encoding, self.prefs.info_data_inline_max) != before {
+                                        save_prefs(&self.prefs);
+                                    }
+                                });
+                                let Some(info) = self.device_info.get(&udid) else {
+                                    ui.horizontal(|ui| {
+                                        ui.spinner();
+                                        ui.label("Loading device information...");
+                                    });
+                                    return;
+                                };
+                                egui::Frame::group(ui.style()).show(ui, |ui| {
+                                    egui::Grid::new("device_overview").num_columns(2).show(ui, |ui| {
+                                        for (label, value) in info.overview() {
+                                            ui.label(label);
+                                            ui.monospace(value);
+                                            ui.end_row();
+                                        }
+                                    });
+                                });
+                                if let Some(status) = info.developer_mode {
+                                    if self.developer_mode_badge(ui, &udid, status) {
+                                        let _ = self.tx.send(Command::EnableDeveloperMode { udid: udid.clone() });
+                                        self.status = "Requesting Developer Mode...".into();
                                     }
                                 }
+                                let Some(info) = self.device_info.get(&udid) else {
+                                    return;
+                                };
+                                if let Some(battery) = &info.battery {
+                                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                                        ui.horizontal(|ui| {
+                                            ui.label("Battery");
+                                            ui.add(
+                                                egui::ProgressBar::new(battery.level as f32 / 100.0)
+                                                    .text(format!("{}%", battery.level))
+                                                    .desired_width(160.0),
+                                            );
+                                            if battery.fully_charged {
+                                                ui.label("Fully charged");
+                                            } else if battery.is_charging {
+                                                ui.label("Charging");
+                                            }
+                                        });
+                                    });
+                                }
                                 ui.separator();
+                                let (encoding, inline_max) = (self.prefs.info_data_encoding, self.prefs.info_data_inline_max);
+                                let expanded = &mut self.expanded_data;
                                 ui.collapsing("All Properties", |ui| {
-                                    let mut keys: Vec<&String> = info.keys().collect();
+                                    let mut keys: Vec<&String> = info.raw.keys().collect();
                                     keys.sort();
                                     for key in keys {
-                                        if !["ProductName", "ProductVersion", "BuildVersion", "SerialNumber", "DeviceName", "UniqueDeviceID"]
-                                            .contains(&key.as_str())
-                                        {
-                                            if let Some(value) = info.get(key) {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(format!("{}: ", key));
-                                                    ui.monospace(value);
-                                                });
+                                        ui.horizontal(|ui| {
+                                            ui.label(format!("{}: ", key));
+                                            match info.data.get(key) {
+                                                Some(data) => data_value_ui(ui, key, data, encoding, inline_max, expanded),
+                                                None => {
+                                                    ui.monospace(&info.raw[key]);
+                                                }
                                             }
-                                        }
+                                        });
                                     }
                                 });
+                                if let Some(tree) = self.device_info_tree.get(&udid) {
+                                    ui.collapsing("Property Tree", |ui| plist_tree_ui(ui, tree));
+                                }
+                            });
+                        if section.body_returned.is_some() {
+                            self.request_device_info(&udid);
+                        }
+                    }
+                }
+
+                if self.view == View::Files {
+                    match self.selected.clone().filter(|u| self.popouts.contains_key(u)) {
+                        Some(udid) => {
+                            ui.horizontal(|ui| {
+                                ui.label("This device's files are open in their own window.");
+                                if ui.button("Show window").clicked() {
+                                    ctx.send_viewport_cmd_to(files_viewport_id(&udid), egui::ViewportCommand::Focus);
+                                }
                             });
                         }
+                        None => {
+                            self.with_panel(None, |app, panel| app.files_view(ui, panel, true));
+                            // Paste events only reach us when no text field has focus.
+                            if !ctx.wants_keyboard_input() {
+                                if let Some(text) = pasted_text(ctx) {
+                                    self.with_panel(None, |app, panel| app.upload_from_clipboard(panel, Some(&text)));
+                                }
+                            }
+                        }
                     }
                 }
 
+                if self.view == View::CrashLogs {
+                    self.crash_logs_view(ui);
+                }
+                if self.view == View::Profiles {
+                    self.profiles_view(ui);
+                }
+
                 ui.separator();
                 ui.label(&self.status);
             });
         });
+
+        if self.view == View::Files && !self.selected_popped_out() {
+            self.with_panel(None, |app, panel| app.poll_listing(panel, ctx));
+        }
+        self.auto_connect_afc(ctx);
+        self.show_popouts(ctx);
+    }
+}
+
+/// Viewport of the window showing `udid`'s files.
+fn files_viewport_id(udid: &str) -> egui::ViewportId {
+    egui::ViewportId::from_hash_of(("files", udid))
+}
+
+/// Text pasted into the viewport this frame, if any.
+fn pasted_text(ctx: &egui::Context) -> Option<String> {
+    ctx.input(|i| {
+        i.events.iter().find_map(|e| match e {
+            egui::Event::Paste(text) => Some(text.clone()),
+            _ => None,
+        })
+    })
+}
+
+/// "2025-03-01 14:05" in local time for a Unix timestamp.
+fn format_last_seen(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// Installed apps matching `query` by bundle ID or name, recently used first.
+fn matching_apps<'a>(
+    apps: &'a [InstalledApp],
+    recent: &[String],
+    query: &str,
+) -> Vec<&'a InstalledApp> {
+    let query = query.trim().to_lowercase();
+    let mut matches: Vec<&InstalledApp> = apps
+        .iter()
+        .filter(|a| {
+            a.bundle_id.to_lowercase().contains(&query) || a.name.to_lowercase().contains(&query)
+        })
+        .collect();
+    // Stable sort keeps the alphabetical order within each group
+    matches.sort_by_key(|a| {
+        recent
+            .iter()
+            .position(|r| *r == a.bundle_id)
+            .unwrap_or(usize::MAX)
+    });
+    matches.truncate(MAX_BUNDLE_SUGGESTIONS);
+    matches
+}
+
+/// Whether `name` can be created in a directory as-is: not empty, not `.` or
+/// `..`, and without a path separator.
+fn is_valid_entry_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
+
+/// Glob for the Files filter box; text without wildcards matches anywhere.
+fn filter_pattern(filter: &str) -> Option<String> {
+    let filter = filter.trim();
+    if filter.is_empty() {
+        None
+    } else if filter.contains(['*', '?']) {
+        Some(filter.to_string())
+    } else {
+        Some(format!("*{filter}*"))
+    }
+}
+
+/// Split what's typed in the path field into the directory it's in and the
+/// start of the entry name being typed.
+fn split_typed_path(typed: &str) -> (String, &str) {
+    match typed.rsplit_once('/') {
+        Some(("", name)) => ("/".to_string(), name),
+        Some((dir, name)) => (dir.to_string(), name),
+        None => ("/".to_string(), typed),
+    }
+}
+
+/// Entries starting with `prefix`, ignoring case, without `.` and `..`.
+fn path_suggestions(entries: &[String], prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    entries
+        .iter()
+        .filter(|e| *e != "." && *e != ".." && e.to_lowercase().starts_with(&prefix))
+        .take(MAX_PATH_SUGGESTIONS)
+        .cloned()
+        .collect()
+}
+
+/// Longest start shared by all `names`, or "" if there are none.
+fn common_prefix(names: &[String]) -> &str {
+    let Some((first, rest)) = names.split_first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for name in rest {
+        len = first
+            .char_indices()
+            .zip(name.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(name.len()), |((i, _), _)| i.min(len));
+    }
+    &first[..len]
+}
+
+/// A data value under All Properties: in full if it's at most `inline_max`
+/// bytes or its row was expanded, otherwise by its size.
+fn data_value_ui(
+    ui: &mut egui::Ui,
+    key: &str,
+    data: &[u8],
+    encoding: DataEncoding,
+    inline_max: usize,
+    expanded: &mut HashSet<String>,
+) {
+    let small = data.len() <= inline_max;
+    let open = small || expanded.contains(key);
+    if open {
+        ui.add(
+            egui::Label::new(egui::RichText::new(format_data(data, encoding)).monospace()).wrap(),
+        );
+    } else {
+        ui.monospace(data_summary(data));
+    }
+    if !small
+        && ui
+            .small_button(if open { "Collapse" } else { "Expand" })
+            .clicked()
+    {
+        if open {
+            expanded.remove(key);
+        } else {
+            expanded.insert(key.to_string());
+        }
     }
+    if open && ui.small_button("Copy").clicked() {
+        ui.ctx().copy_text(format_data(data, encoding));
+    }
+}
+
+/// `dir/name` on the device.
+fn join_remote(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// Put the text cursor of field `id` after the last character of `text`.
+fn move_cursor_to_end(ctx: &egui::Context, id: egui::Id, text: &str) {
+    if let Some(mut state) = egui::TextEdit::load_state(ctx, id) {
+        let end = egui::text::CCursor::new(text.chars().count());
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::one(end)));
+        state.store(ctx, id);
+    }
+}
+
+/// Listing order for the Files view: case-insensitive by name.
+fn sort_entries(entries: &mut [String]) {
+    entries.sort_by_cached_key(|e| e.to_lowercase());
 }
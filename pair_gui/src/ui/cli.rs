@@ -0,0 +1,101 @@
+// src/ui/cli.rs
+
+use std::path::Path;
+
+/// A command line for the "Copy command" menu.
+pub struct CliCommand {
+    pub label: &'static str,
+    pub line: String,
+}
+
+/// Commands for `udid` with libimobiledevice and with this crate's tools.
+///
+/// `usbmuxd_socket` is the socket from the settings, empty for the default.
+/// With a saved `pairing_file`, a network variant is added; the device's IP
+/// isn't known over USB, so it's left as a placeholder.
+pub fn commands_for(
+    udid: &str,
+    pairing_file: Option<&Path>,
+    usbmuxd_socket: &str,
+) -> Vec<CliCommand> {
+    let udid = quote(udid);
+    // libusbmuxd wants a "UNIX:" prefix on paths; the tools only take ip:port
+    let (limd_env, tools_env) = match usbmuxd_socket.trim() {
+        "" => (String::new(), String::new()),
+        s if s.contains(':') => (env_prefix(s), env_prefix(s)),
+        s => (env_prefix(&format!("UNIX:{s}")), String::new()),
+    };
+    let limd = |args: &str| format!("{limd_env}{args}");
+    let tools = |args: &str| format!("{tools_env}{args}");
+
+    let mut commands = vec![
+        CliCommand {
+            label: "idevicepair pair",
+            line: limd(&format!("idevicepair -u {udid} pair")),
+        },
+        CliCommand {
+            label: "idevicepair validate",
+            line: limd(&format!("idevicepair -u {udid} validate")),
+        },
+        CliCommand {
+            label: "idevicepair unpair",
+            line: limd(&format!("idevicepair -u {udid} unpair")),
+        },
+        CliCommand {
+            label: "ideviceinfo",
+            line: limd(&format!("ideviceinfo -u {udid}")),
+        },
+        CliCommand {
+            label: "tools: pair",
+            line: tools(&format!("pair {udid}")),
+        },
+        CliCommand {
+            label: "tools: unpair",
+            line: tools(&format!("unpair {udid}")),
+        },
+        CliCommand {
+            label: "tools: ideviceinfo",
+            line: tools(&format!("ideviceinfo {udid}")),
+        },
+        CliCommand {
+            label: "tools: afc list /",
+            line: tools(&format!("afc {udid} list /")),
+        },
+        CliCommand {
+            label: "tools: crash_logs list",
+            line: tools(&format!("crash_logs {udid} list")),
+        },
+    ];
+    if let Some(pairing_file) = pairing_file {
+        let pairing_file = quote(&pairing_file.display().to_string());
+        commands.push(CliCommand {
+            label: "tools: ideviceinfo over the network",
+            line: format!("ideviceinfo --host <device-ip> --pairing-file {pairing_file}"),
+        });
+    }
+    commands
+}
+
+/// Sets `USBMUXD_SOCKET_ADDRESS` for the command that follows.
+fn env_prefix(value: &str) -> String {
+    if cfg!(windows) {
+        format!("set \"USBMUXD_SOCKET_ADDRESS={value}\" && ")
+    } else {
+        format!("USBMUXD_SOCKET_ADDRESS={} ", quote(value))
+    }
+}
+
+/// `arg` quoted for the platform's shell if it needs it.
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@,+=".contains(c));
+    if plain {
+        arg.to_string()
+    } else if cfg!(windows) {
+        format!("\"{arg}\"")
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
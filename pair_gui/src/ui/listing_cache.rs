@@ -0,0 +1,102 @@
+// src/ui/listing_cache.rs
+
+use std::time::{Duration, Instant};
+
+/// Most directories remembered across all devices.
+const CAPACITY: usize = 32;
+
+struct Entry {
+    udid: String,
+    /// AFC service the listing came from, e.g. `com.apple.afc`.
+    source: String,
+    path: String,
+    entries: Vec<String>,
+    fetched: Instant,
+}
+
+/// Recently seen AFC directory listings, most recently used first.
+///
+/// Lets the Files view show a directory instantly on navigation while the
+/// worker re-lists it in the background.
+pub struct ListingCache {
+    entries: Vec<Entry>,
+    ttl: Duration,
+}
+
+impl ListingCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Vec::new(),
+            ttl,
+        }
+    }
+
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// Fresh listing for `path` on `udid`, if any.
+    pub fn get(&mut self, udid: &str, source: &str, path: &str) -> Option<Vec<String>> {
+        let i = self.position(udid, source, path)?;
+        if self.entries[i].fetched.elapsed() > self.ttl {
+            self.entries.remove(i);
+            return None;
+        }
+        let entry = self.entries.remove(i);
+        let listing = entry.entries.clone();
+        self.entries.insert(0, entry);
+        Some(listing)
+    }
+
+    pub fn insert(&mut self, udid: &str, source: &str, path: &str, entries: Vec<String>) {
+        if let Some(i) = self.position(udid, source, path) {
+            self.entries.remove(i);
+        }
+        self.entries.insert(
+            0,
+            Entry {
+                udid: udid.to_string(),
+                source: source.to_string(),
+                path: path.to_string(),
+                entries,
+                fetched: Instant::now(),
+            },
+        );
+        self.entries.truncate(CAPACITY);
+    }
+
+    /// Forget `dir` after something inside it changed.
+    pub fn invalidate(&mut self, udid: &str, source: &str, dir: &str) {
+        if let Some(i) = self.position(udid, source, dir) {
+            self.entries.remove(i);
+        }
+    }
+
+    /// Forget every listing for a device that went away.
+    pub fn remove_device(&mut self, udid: &str) {
+        self.entries.retain(|e| e.udid != udid);
+    }
+
+    fn position(&self, udid: &str, source: &str, path: &str) -> Option<usize> {
+        let path = normalize(path);
+        self.entries
+            .iter()
+            .position(|e| e.udid == udid && e.source == source && normalize(&e.path) == path)
+    }
+}
+
+/// `/DCIM/` and `/DCIM` are the same directory.
+fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        p => p,
+    }
+}
+
+/// Directory containing `path`.
+pub fn parent_dir(path: &str) -> &str {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/",
+        Some((parent, _)) => parent,
+    }
+}
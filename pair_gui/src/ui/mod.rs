@@ -1,2 +1,5 @@
 // src/ui/mod.rs
 pub mod app;
+pub mod cli;
+pub mod listing_cache;
+pub mod plist_tree;
@@ -0,0 +1,107 @@
+// src/ui/plist_tree.rs
+
+use base64::Engine;
+use chrono::{DateTime, Local};
+use egui::{CollapsingHeader, Id, Ui};
+use plist::Value;
+
+/// Levels shown before the rest of a branch is elided, so a hostile or
+/// corrupt plist can't recurse the UI into a stack overflow.
+const MAX_DEPTH: usize = 32;
+/// Bytes of a data value shown as hex inline; the copy buttons take all of it.
+const DATA_PREVIEW_BYTES: usize = 32;
+
+/// Draw `value` as a tree: dictionaries and arrays expand and collapse,
+/// everything else is shown inline. Ids derive from the `ui`'s, so two
+/// trees need separate parents.
+pub fn plist_tree_ui(ui: &mut Ui, value: &Value) {
+    let id = ui.id().with("plist_tree");
+    match value {
+        Value::Dictionary(dict) => {
+            for (k, v) in dict {
+                node(ui, id.with(k), k, v, 0);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                node(ui, id.with(i), &format!("[{i}]"), v, 0);
+            }
+        }
+        leaf => node(ui, id, "value", leaf, 0),
+    }
+}
+
+fn node(ui: &mut Ui, id: Id, key: &str, value: &Value, depth: usize) {
+    if depth >= MAX_DEPTH && matches!(value, Value::Dictionary(_) | Value::Array(_)) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{key}: "));
+            ui.weak("nested too deeply to show");
+        });
+        return;
+    }
+    match value {
+        Value::Dictionary(dict) => {
+            CollapsingHeader::new(format!("{key} ({} keys)", dict.len()))
+                .id_salt(id)
+                .show(ui, |ui| {
+                    for (k, v) in dict {
+                        node(ui, id.with(k), k, v, depth + 1);
+                    }
+                });
+        }
+        Value::Array(items) => {
+            CollapsingHeader::new(format!("{key} [{} items]", items.len()))
+                .id_salt(id)
+                .show(ui, |ui| {
+                    for (i, v) in items.iter().enumerate() {
+                        node(ui, id.with(i), &format!("[{i}]"), v, depth + 1);
+                    }
+                });
+        }
+        Value::Data(data) => {
+            ui.horizontal(|ui| {
+                ui.label(format!("{key}: "));
+                let shown = hex(&data[..data.len().min(DATA_PREVIEW_BYTES)]);
+                let more = if data.len() > DATA_PREVIEW_BYTES {
+                    " ..."
+                } else {
+                    ""
+                };
+                ui.monospace(format!("{shown}{more}"));
+                ui.weak(format!("({} bytes)", data.len()));
+                if ui.small_button("Copy hex").clicked() {
+                    ui.ctx().copy_text(hex(data));
+                }
+                if ui.small_button("Copy base64").clicked() {
+                    ui.ctx()
+                        .copy_text(base64::engine::general_purpose::STANDARD.encode(data));
+                }
+            });
+        }
+        scalar => {
+            ui.horizontal(|ui| {
+                ui.label(format!("{key}: "));
+                ui.monospace(scalar_text(scalar));
+            });
+        }
+    }
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(r) => r.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Date(date) => {
+            let time: DateTime<Local> = std::time::SystemTime::from(*date).into();
+            time.format("%Y-%m-%d %H:%M:%S %Z").to_string()
+        }
+        Value::Uid(uid) => format!("UID {}", uid.get()),
+        other => format!("{other:?}"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -1,10 +1,60 @@
 // src/util.rs
+use base64::Engine;
 use plist::Value;
-use std::{collections::HashMap, path::Path, path::PathBuf};
 use std::process::Command as SysCmd;
+use std::{collections::HashMap, path::Path, path::PathBuf};
 
-/// Recursively extract plist values into a flat key-value map
-pub fn extract_values(prefix: &str, value: &Value, info: &mut HashMap<String, String>) {
+use crate::prefs::{DataEncoding, FlattenMode};
+use crate::types::PreviewKind;
+
+/// Extract plist values into a flat key-value map. `Dotted` recurses into
+/// everything; `TopLevel` keeps only the first level and summarizes what's
+/// nested under it.
+pub fn extract_values(
+    prefix: &str,
+    value: &Value,
+    mode: FlattenMode,
+    info: &mut HashMap<String, String>,
+) {
+    visit_flat(prefix, value, mode, &mut |key, v| {
+        let shown = match mode {
+            FlattenMode::Dotted => process_value(v),
+            FlattenMode::TopLevel => summarize_value(v),
+        };
+        info.insert(key, shown);
+    });
+}
+
+/// The data values `extract_values` lists, under the same keys, so they can
+/// be shown in full rather than by size.
+pub fn extract_data(
+    prefix: &str,
+    value: &Value,
+    mode: FlattenMode,
+    data: &mut HashMap<String, Vec<u8>>,
+) {
+    visit_flat(prefix, value, mode, &mut |key, v| {
+        if let Value::Data(d) = v {
+            data.insert(key, d.clone());
+        }
+    });
+}
+
+/// Call `visit` with each flat key `mode` produces and the value under it.
+fn visit_flat(
+    prefix: &str,
+    value: &Value,
+    mode: FlattenMode,
+    visit: &mut impl FnMut(String, &Value),
+) {
+    if mode == FlattenMode::TopLevel {
+        if let Value::Dictionary(dict) = value {
+            for (k, v) in dict {
+                visit(k.clone(), v);
+            }
+        }
+        return;
+    }
     match value {
         Value::Dictionary(dict) => {
             for (k, v) in dict {
@@ -13,16 +63,16 @@ pub fn extract_values(prefix: &str, value: &Value, info: &mut HashMap<String, St
                 } else {
                     format!("{}.{}", prefix, k)
                 };
-                extract_values(&new_prefix, v, info);
-                info.insert(new_prefix.clone(), process_value(v));
+                visit_flat(&new_prefix, v, mode, visit);
+                visit(new_prefix, v);
             }
         }
         Value::Array(arr) => {
             if arr.len() <= 10 {
                 for (i, v) in arr.iter().enumerate() {
                     let idx_prefix = format!("{}[{}]", prefix, i);
-                    extract_values(&idx_prefix, v, info);
-                    info.insert(idx_prefix.clone(), process_value(v));
+                    visit_flat(&idx_prefix, v, mode, visit);
+                    visit(idx_prefix, v);
                 }
             }
         }
@@ -30,19 +80,45 @@ pub fn extract_values(prefix: &str, value: &Value, info: &mut HashMap<String, St
     }
 }
 
+/// Like `process_value`, but a dictionary or array is shown by its size.
+fn summarize_value(value: &Value) -> String {
+    match value {
+        Value::Dictionary(dict) => format!("[dict, {} keys]", dict.len()),
+        Value::Array(arr) => format!("[array, {} items]", arr.len()),
+        _ => process_value(value),
+    }
+}
+
 /// Format plist values for display
 pub fn process_value(value: &Value) -> String {
     match value {
         Value::String(s) => s.clone(),
         Value::Integer(i) => i.to_string(),
         Value::Boolean(b) => b.to_string(),
-        Value::Data(d) => format!("[{} bytes]", d.len()),
+        Value::Data(d) => data_summary(d),
         Value::Date(dt) => format!("{:?}", dt),
         Value::Uid(u) => format!("{:?}", u),
         _ => format!("{:?}", value),
     }
 }
 
+/// Collapsed form of a data value: its size
+pub fn data_summary(data: &[u8]) -> String {
+    format!("[{} bytes]", data.len())
+}
+
+/// A data value in full, e.g. a certificate as base64
+pub fn format_data(data: &[u8], encoding: DataEncoding) -> String {
+    match encoding {
+        DataEncoding::Base64 => base64::engine::general_purpose::STANDARD.encode(data),
+        DataEncoding::Hex => data
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
 /// Reveal file or directory in OS file browser
 pub fn reveal_in_file_browser(path: &Path) {
     #[cfg(target_os = "windows")]
@@ -61,6 +137,92 @@ pub fn reveal_in_file_browser(path: &Path) {
     }
 }
 
+/// Open a file with the OS default handler
+pub fn open_with_default_app(path: &Path) {
+    #[cfg(target_os = "windows")]
+    {
+        let _ = SysCmd::new("cmd")
+            .args(["/C", "start", ""])
+            .arg(path)
+            .spawn();
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let _ = SysCmd::new("open").arg(path).spawn();
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        let _ = SysCmd::new("xdg-open").arg(path).spawn();
+    }
+}
+
+/// Extensions that would run code rather than open a document
+const RISKY_EXTENSIONS: &[&str] = &[
+    "app", "bat", "cmd", "com", "command", "deb", "dmg", "exe", "jar", "js", "msi", "pkg", "ps1",
+    "scr", "sh", "vbs",
+];
+
+/// Whether opening `path` with the default handler might execute it
+pub fn is_risky_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| RISKY_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Where the OS's usbmuxd keeps pairing records, for libimobiledevice and
+/// other tools to find them
+pub fn system_lockdown_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        std::env::var_os("ProgramData").map(|d| PathBuf::from(d).join("Apple").join("Lockdown"))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        Some(PathBuf::from("/var/db/lockdown"))
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        Some(PathBuf::from("/var/lib/lockdown"))
+    }
+}
+
+/// Directory name used for saving when the user hasn't picked one
+pub const DEFAULT_OUTPUT_DIR: &str = "pairings";
+
+/// Location of the default output directory, whether or not it exists
+fn default_output_dir() -> PathBuf {
+    std::env::current_dir()
+        .map(|d| d.join(DEFAULT_OUTPUT_DIR))
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_OUTPUT_DIR))
+}
+
+/// Check that `dir` still exists and can be written to before saving into it
+///
+/// The app's default directory is silently recreated if it was deleted; a
+/// user-chosen one is not, and the error asks the user to pick another.
+pub fn ensure_writable_dir(dir: &Path) -> Result<(), String> {
+    if !dir.is_dir() {
+        if dir != default_output_dir() {
+            return Err(format!(
+                "Save directory {} no longer exists, choose a new folder with Browse",
+                dir.display()
+            ));
+        }
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to recreate {}: {e}", dir.display()))?;
+    }
+    let probe = dir.join(".pair_gui_write_test");
+    std::fs::write(&probe, b"").map_err(|e| {
+        format!(
+            "Save directory {} is not writable ({e}), choose a new folder with Browse",
+            dir.display()
+        )
+    })?;
+    let _ = std::fs::remove_file(probe);
+    Ok(())
+}
+
 /// Ensure a directory exists, returning its canonical path
 pub fn canonical_or_create(dirname: &str) -> PathBuf {
     let path = PathBuf::from(dirname);
@@ -69,3 +231,206 @@ pub fn canonical_or_create(dirname: &str) -> PathBuf {
     }
     path.canonicalize().unwrap_or(path)
 }
+
+/// Extensions shown as text in the Files preview
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "log", "json", "plist", "xml", "csv", "md", "ini", "conf", "strings", "html", "css",
+];
+
+/// Whether `bytes` open like an XML plist, within the first few hundred bytes
+fn is_xml_plist(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+    head.trim_start().starts_with("<?xml")
+        && (head.contains("<!DOCTYPE plist") || head.contains("<plist"))
+}
+
+/// Bytes per line of `hex_line`.
+pub const HEX_LINE_BYTES: usize = 16;
+
+/// One hex viewer line: offset, up to `HEX_LINE_BYTES` bytes in hex, and
+/// the same bytes as ASCII
+pub fn hex_line(offset: usize, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{offset:08x}  {:<47}  {ascii}", hex.join(" "))
+}
+
+/// Guess how to preview a file from its magic bytes, then its extension
+pub fn detect_preview_kind(path: &str, bytes: &[u8]) -> PreviewKind {
+    const IMAGE_MAGIC: &[&[u8]] = &[b"\x89PNG\r\n\x1a\n", b"\xff\xd8\xff", b"GIF87a", b"GIF89a"];
+    if IMAGE_MAGIC.iter().any(|m| bytes.starts_with(m)) {
+        return PreviewKind::Image;
+    }
+    if bytes.starts_with(b"bplist00") || is_xml_plist(bytes) {
+        return PreviewKind::Plist;
+    }
+    let ext = path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    let text_ext = ext.is_some_and(|e| TEXT_EXTENSIONS.contains(&e.as_str()));
+    if text_ext || (!bytes.contains(&0) && std::str::from_utf8(bytes).is_ok()) {
+        PreviewKind::Text
+    } else {
+        PreviewKind::Binary
+    }
+}
+
+/// The UDID in `text`, if it is one: 40 hex digits for older devices, or
+/// 8 and 16 hex digits joined by a dash for newer ones
+pub fn parse_udid(text: &str) -> Option<&str> {
+    let udid = text.trim();
+    let hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit());
+    let valid = match udid.split_once('-') {
+        Some((chip, ecid)) => hex(chip, 8) && hex(ecid, 16),
+        None => hex(udid, 40),
+    };
+    valid.then_some(udid)
+}
+
+/// Human readable byte count, e.g. `1.5 GiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Total size of a file, or of every file under a directory
+pub fn local_size(path: &Path) -> std::io::Result<u64> {
+    let meta = std::fs::metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        total += local_size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// `text` as a URL the app can download from, which must be http or https
+pub fn parse_download_url(text: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(text.trim()).map_err(|e| format!("invalid URL: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "only http and https URLs are supported, not {}:",
+            url.scheme()
+        ));
+    }
+    Ok(url)
+}
+
+/// Name for a file downloaded from `url`: its last path segment, if it has one
+pub fn url_file_name(url: &reqwest::Url) -> Option<String> {
+    let name = url.path_segments()?.next_back()?;
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `{ Name: "phone", Baseband: { Status: 1, Keys: [true, false] } }`
+    fn nested() -> Value {
+        let mut baseband = plist::Dictionary::new();
+        baseband.insert("Status".into(), 1.into());
+        baseband.insert("Keys".into(), Value::Array(vec![true.into(), false.into()]));
+        let mut root = plist::Dictionary::new();
+        root.insert("Name".into(), "phone".into());
+        root.insert("Baseband".into(), Value::Dictionary(baseband));
+        Value::Dictionary(root)
+    }
+
+    #[test]
+    fn dotted_mode_lists_every_nested_value() {
+        let mut info = HashMap::new();
+        extract_values("", &nested(), FlattenMode::Dotted, &mut info);
+        assert_eq!(info["Name"], "phone");
+        assert_eq!(info["Baseband.Status"], "1");
+        assert_eq!(info["Baseband.Keys[0]"], "true");
+        assert_eq!(info["Baseband.Keys[1]"], "false");
+        assert!(info.contains_key("Baseband"));
+        assert!(info.contains_key("Baseband.Keys"));
+        assert_eq!(info.len(), 6);
+    }
+
+    #[test]
+    fn top_level_mode_summarizes_nested_values() {
+        let mut info = HashMap::new();
+        extract_values("", &nested(), FlattenMode::TopLevel, &mut info);
+        assert_eq!(info.len(), 2);
+        assert_eq!(info["Name"], "phone");
+        assert_eq!(info["Baseband"], "[dict, 2 keys]");
+    }
+
+    #[test]
+    fn data_is_listed_under_its_flat_key() {
+        let mut cert = plist::Dictionary::new();
+        cert.insert("Certificate".into(), Value::Data(vec![0x30, 0x82, 0xff]));
+        let mut root = plist::Dictionary::new();
+        root.insert("Name".into(), "phone".into());
+        root.insert("Pairing".into(), Value::Dictionary(cert));
+        let root = Value::Dictionary(root);
+
+        let mut data = HashMap::new();
+        extract_data("", &root, FlattenMode::Dotted, &mut data);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data["Pairing.Certificate"], [0x30, 0x82, 0xff]);
+
+        let mut info = HashMap::new();
+        extract_values("", &root, FlattenMode::Dotted, &mut info);
+        assert_eq!(info["Pairing.Certificate"], "[3 bytes]");
+
+        data.clear();
+        extract_data("", &root, FlattenMode::TopLevel, &mut data);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn data_formats() {
+        let data = [0x30, 0x82, 0x01, 0xff];
+        assert_eq!(format_data(&data, DataEncoding::Base64), "MIIB/w==");
+        assert_eq!(format_data(&data, DataEncoding::Hex), "30 82 01 ff");
+        assert_eq!(format_data(&[], DataEncoding::Hex), "");
+        assert_eq!(data_summary(&data), "[4 bytes]");
+    }
+
+    #[test]
+    fn only_http_urls_are_downloadable() {
+        assert!(parse_download_url(" https://example.com/a.ipa ").is_ok());
+        assert!(parse_download_url("http://10.0.0.2:8000/photo.jpg").is_ok());
+        assert!(parse_download_url("file:///etc/passwd").is_err());
+        assert!(parse_download_url("ftp://example.com/a.txt").is_err());
+        assert!(parse_download_url("example.com/a.txt").is_err());
+    }
+
+    #[test]
+    fn file_name_is_the_last_path_segment() {
+        let name = |s| url_file_name(&parse_download_url(s).unwrap());
+        assert_eq!(
+            name("https://example.com/files/report.pdf?dl=1").as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(name("https://example.com/files/"), None);
+        assert_eq!(name("https://example.com"), None);
+    }
+}
@@ -1,30 +1,733 @@
-use std::path::PathBuf;
+use std::{
+    io::{SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
 use idevice::{
-    afc::{opcode::AfcFopenMode, AfcClient},
+    afc::{
+        name::display_name, opcode::AfcFopenMode, AfcClient, AfcScope, DirEntries, RemoveSummary,
+    },
     house_arrest::HouseArrestClient,
-    IdeviceService,
+    IdeviceError, IdeviceService,
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    types::{AfcSource, PreviewKind},
+    util::{
+        detect_preview_kind, ensure_writable_dir, format_bytes, local_size, parse_download_url,
+    },
+    worker::{common::get_provider, pool::SharedPool},
 };
 
-use crate::util::get_provider;
+async fn connect_afc(
+    udid: &str,
+    source: &AfcSource,
+) -> Result<AfcClient, Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+
+    let afc_client = match source {
+        AfcSource::Scope(scope) => AfcClient::connect_scoped(&provider, *scope).await?,
+        AfcSource::Container(bundle_id) => {
+            let h = HouseArrestClient::connect(&provider).await?;
+            h.vend_container(bundle_id)
+                .await
+                .map_err(|e| vend_error(bundle_id, e))?
+        }
+        AfcSource::Documents(bundle_id) => {
+            let h = HouseArrestClient::connect(&provider).await?;
+            h.vend_documents(bundle_id)
+                .await
+                .map_err(|e| vend_error(bundle_id, e))?
+        }
+    };
+    Ok(afc_client)
+}
+
+/// Check out an AFC client for `source` on `udid`, connecting if the pool
+/// has none. Put it back with `put_afc` under `source.service_key()`.
+async fn checkout_afc(
+    pool: &SharedPool,
+    udid: &str,
+    source: &AfcSource,
+) -> Result<AfcClient, Box<dyn std::error::Error>> {
+    let cached = pool.lock().unwrap().take_afc(udid, &source.service_key());
+    match cached {
+        Some(afc_client) => Ok(afc_client),
+        None => connect_afc(udid, source).await,
+    }
+}
+
+/// house_arrest couldn't find the app, or for Documents, the app doesn't
+/// enable file sharing. The device doesn't say which.
+#[derive(Debug)]
+pub struct AppUnavailable(pub String);
+
+impl std::fmt::Display for AppUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} isn't installed or doesn't enable file sharing",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for AppUnavailable {}
+
+/// Turn house_arrest's lookup failures for `bundle_id` into [`AppUnavailable`].
+fn vend_error(bundle_id: &str, e: IdeviceError) -> Box<dyn std::error::Error> {
+    match e {
+        IdeviceError::UnknownErrorType(ref kind) if kind.ends_with("LookupFailed") => {
+            AppUnavailable(bundle_id.to_string()).into()
+        }
+        e => e.into(),
+    }
+}
+
+/// Whether the device exposes `com.apple.afc2`, i.e. is jailbroken with AFC2 installed.
+///
+/// A successful probe leaves its client in the pool for the first listing.
+pub async fn probe_afc2(pool: &SharedPool, udid: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let source = AfcSource::Scope(AfcScope::Full);
+    let key = source.service_key();
+    let cached = pool.lock().unwrap().take_afc(udid, &key);
+    if let Some(afc_client) = cached {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Ok(true);
+    }
+    let provider = get_provider(udid, None).await?;
+    match AfcClient::connect_scoped(&provider, AfcScope::Full).await {
+        Ok(afc_client) => {
+            pool.lock().unwrap().put_afc(udid, &key, afc_client);
+            Ok(true)
+        }
+        Err(IdeviceError::Afc2Unavailable) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Create each missing directory leading up to `remote_path`.
+async fn ensure_remote_parents(
+    afc_client: &mut AfcClient,
+    remote_path: &str,
+) -> Result<(), IdeviceError> {
+    let parent = remote_path.rsplit_once('/').map(|(p, _)| p).unwrap_or("");
+    let mut prefix = String::new();
+    for part in parent.split('/').filter(|p| !p.is_empty()) {
+        prefix.push('/');
+        prefix.push_str(part);
+        if !afc_client.is_dir(prefix.as_str()).await? {
+            afc_client.mk_dir(prefix.as_str()).await?;
+        }
+    }
+    Ok(())
+}
 
 pub async fn list_files(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+) -> Result<DirEntries, Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    let list = afc_client.list_dir_iter(path).await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(list)
+}
+
+/// Whether `path` is a directory on the device.
+pub async fn is_remote_dir(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    let is_dir = afc_client.is_dir(path).await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(is_dir)
+}
+
+/// Upload `local_path` to `remote_path`, replacing any existing file.
+///
+/// `on_progress` is called with the bytes written so far and the total.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_file(
+    pool: &SharedPool,
     udid: &str,
+    local_path: &Path,
+    remote_path: &str,
+    source: &AfcSource,
+    chunk_size: usize,
+    skip_space_check: bool,
+    on_progress: impl FnMut(u64, u64),
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let needed = local_size(local_path)?;
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    if !skip_space_check {
+        let free = afc_client.free_space().await? as u64;
+        if needed > free {
+            pool.lock().unwrap().put_afc(udid, &key, afc_client);
+            return Err(format!(
+                "not enough space on device ({} free, {} needed)",
+                format_bytes(free),
+                format_bytes(needed)
+            )
+            .into());
+        }
+    }
+
+    let local = tokio::fs::File::open(local_path).await?;
+    afc_client.set_chunk_size(chunk_size);
+    ensure_remote_parents(&mut afc_client, remote_path).await?;
+    // Streamed one chunk at a time, written beside the target and renamed
+    // over it, so a failed upload leaves an existing file intact
+    afc_client
+        .upload_reader(remote_path, local, needed, on_progress)
+        .await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(needed as usize)
+}
+
+/// Largest download `upload_from_url` copies to a device. Servers that don't
+/// send a length are cut off here too, so a stream can't fill the device.
+pub const URL_UPLOAD_MAX_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Download `url` straight onto the device at `remote_path`, replacing any
+/// existing file. The body is written as it arrives rather than buffered.
+///
+/// `on_progress` is called with the bytes written so far and the total,
+/// which is the bytes so far when the server didn't say how long it is.
+pub async fn upload_from_url(
+    pool: &SharedPool,
+    udid: &str,
+    url: &str,
+    remote_path: &str,
+    source: &AfcSource,
+    chunk_size: usize,
+    on_progress: impl FnMut(u64, u64),
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let url = parse_download_url(url)?;
+    let response = reqwest::get(url.clone()).await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("{url} answered HTTP {status}").into());
+    }
+    let total = response.content_length();
+    if let Some(len) = total.filter(|&len| len > URL_UPLOAD_MAX_BYTES) {
+        return Err(format!(
+            "{url} is {}, over the {} limit",
+            format_bytes(len),
+            format_bytes(URL_UPLOAD_MAX_BYTES)
+        )
+        .into());
+    }
+
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    let free = afc_client.free_space().await? as u64;
+    if let Some(len) = total.filter(|&len| len > free) {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Err(format!(
+            "not enough space on device ({} free, {} needed)",
+            format_bytes(free),
+            format_bytes(len)
+        )
+        .into());
+    }
+
+    afc_client.set_chunk_size(chunk_size);
+    ensure_remote_parents(&mut afc_client, remote_path).await?;
+    // Like `AfcClient::upload`, written beside the target and renamed over
+    // it, so a failed download leaves an existing file intact
+    let temp = format!("{remote_path}.uploading");
+    let limit = total.unwrap_or(URL_UPLOAD_MAX_BYTES).min(free);
+    match stream_response(&mut afc_client, &temp, response, total, limit, on_progress).await {
+        Ok(written) => {
+            afc_client.rename(temp.as_str(), remote_path).await?;
+            pool.lock().unwrap().put_afc(udid, &key, afc_client);
+            Ok(written)
+        }
+        Err(e) => {
+            if afc_client.remove(temp.as_str()).await.is_ok() {
+                pool.lock().unwrap().put_afc(udid, &key, afc_client);
+            }
+            Err(e as Box<dyn std::error::Error>)
+        }
+    }
+}
+
+/// Write the body of `response` to a new file at `path`, failing once it
+/// passes `limit` bytes. The error is `Send` so the caller can clean up
+/// after it on the device task.
+async fn stream_response(
+    afc_client: &mut AfcClient,
     path: &str,
-    container: Option<&str>,
-    documents: Option<&str>,
-) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let provider = get_provider(Some(udid), None, None, "afc-gui").await?;
+    mut response: reqwest::Response,
+    total: Option<u64>,
+    limit: u64,
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let mut fd = afc_client.open(path, AfcFopenMode::WrOnly).await?;
+    let mut written = 0u64;
+    let res: Result<(), Box<dyn std::error::Error + Send + Sync>> = loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(format!("download failed: {e}").into()),
+        };
+        written += chunk.len() as u64;
+        if written > limit {
+            break Err(format!("download is larger than {}", format_bytes(limit)).into());
+        }
+        if let Err(e) = fd.write(&chunk).await {
+            break Err(e.into());
+        }
+        on_progress(written, total.unwrap_or(written));
+    };
+    fd.close().await?;
+    res?;
+    if let Some(total) = total.filter(|&total| total != written) {
+        return Err(format!("download ended after {written} of {total} bytes").into());
+    }
+    Ok(written)
+}
 
-    let mut afc_client = if let Some(bundle_id) = container {
-        let h = HouseArrestClient::connect(&*provider).await?;
-        h.vend_container(bundle_id).await?
-    } else if let Some(bundle_id) = documents {
-        let h = HouseArrestClient::connect(&*provider).await?;
-        h.vend_documents(bundle_id).await?
+/// `dir/stem copy.ext`, or `dir/stem copy N.ext` for the n-th duplicate.
+fn copy_name(path: &str, n: usize) -> String {
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{ext}")),
+        _ => (name, String::new()),
+    };
+    let suffix = if n == 1 {
+        " copy".to_string()
     } else {
-        AfcClient::connect(&*provider).await?
+        format!(" copy {n}")
     };
+    format!("{dir}/{stem}{suffix}{ext}")
+}
 
-    let list = afc_client.list_dir(path).await?;
-    Ok(list)
+/// Copy `path` next to itself on the device, returning the new path.
+pub async fn duplicate_file(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+    chunk_size: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    afc_client.set_chunk_size(chunk_size);
+    let mut n = 1;
+    let mut target = copy_name(path, n);
+    while afc_client.exists(target.as_str()).await? {
+        n += 1;
+        target = copy_name(path, n);
+    }
+    afc_client.copy(path, target.as_str()).await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(target)
+}
+
+/// Delete `path` and everything under it, calling `on_progress` with
+/// (done, total, entry) as each entry goes. Failed entries are collected in
+/// the summary instead of stopping the delete.
+pub async fn delete_path(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+    on_progress: impl FnMut(usize, usize, &str),
+) -> Result<RemoveSummary, Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    let summary = afc_client
+        .remove_all_with_progress(path, on_progress)
+        .await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(summary)
+}
+
+/// Create the directory `path`; its parent must already exist.
+pub async fn make_dir(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    if afc_client.exists(path).await? {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Err(format!("{path} already exists").into());
+    }
+    afc_client.mk_dir(path).await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(())
+}
+
+/// Rename `from` to `to`, refusing to replace anything already at `to`.
+pub async fn rename_path(
+    pool: &SharedPool,
+    udid: &str,
+    from: &str,
+    to: &str,
+    source: &AfcSource,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    if afc_client.exists(to).await? {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Err(format!("{to} already exists").into());
+    }
+    afc_client.rename(from, to).await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(())
+}
+
+/// Files above this size are not fetched for the inline preview.
+pub const PREVIEW_MAX_BYTES: usize = 1024 * 1024;
+
+/// Read a small file into memory for previewing, without touching disk.
+///
+/// Also returns the file's modification time so a later save can tell
+/// whether it changed in the meantime.
+pub async fn preview_file(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+) -> Result<(Vec<u8>, PreviewKind, NaiveDateTime), Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    let info = afc_client.get_file_info(path).await?;
+    if info.st_ifmt == "S_IFDIR" {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Err(format!("{path} is a directory").into());
+    }
+    if info.size > PREVIEW_MAX_BYTES {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Ok((Vec::new(), PreviewKind::TooLarge, info.modified));
+    }
+
+    let mut fd = afc_client.open(path, AfcFopenMode::RdOnly).await?;
+    // Capped again in case the file grew since it was stat'ed
+    let data = match fd.read_capped(PREVIEW_MAX_BYTES).await {
+        Ok(data) => Some(data),
+        Err(IdeviceError::AfcFileTooLarge { .. }) => None,
+        Err(e) => return Err(e.into()),
+    };
+    fd.close().await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    let Some(data) = data else {
+        return Ok((Vec::new(), PreviewKind::TooLarge, info.modified));
+    };
+
+    let kind = detect_preview_kind(path, &data);
+    Ok((data, kind, info.modified))
+}
+
+/// Read up to `len` bytes of `path` from `offset`, for paging through a file
+/// too large to preview whole. Also returns the file's size.
+pub async fn read_range(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    source: &AfcSource,
+    offset: u64,
+    len: usize,
+) -> Result<(Vec<u8>, u64), Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    let size = afc_client.get_file_info(path).await?.size as u64;
+    let mut fd = afc_client.open(path, AfcFopenMode::RdOnly).await?;
+    let mut data = vec![0; len.min(size.saturating_sub(offset) as usize)];
+    let n = fd.read_at(offset, &mut data).await?;
+    data.truncate(n);
+    fd.close().await?;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok((data, size))
+}
+
+/// The file on the device changed after it was previewed.
+#[derive(Debug)]
+pub struct SaveConflict;
+
+impl std::fmt::Display for SaveConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the file changed on the device since it was opened")
+    }
+}
+
+impl std::error::Error for SaveConflict {}
+
+/// Replace `path` with `contents`, returning the file's new modification time.
+///
+/// The text goes to a temporary file beside the original which is then
+/// renamed over it, so an interrupted save never leaves a truncated file.
+/// With `expected_modified`, fails with [`SaveConflict`] instead if the file
+/// was changed or removed since then.
+pub async fn save_text(
+    pool: &SharedPool,
+    udid: &str,
+    path: &str,
+    contents: &str,
+    source: &AfcSource,
+    expected_modified: Option<NaiveDateTime>,
+) -> Result<NaiveDateTime, Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    if let Some(expected) = expected_modified {
+        let current = afc_client
+            .get_file_info(path)
+            .await
+            .ok()
+            .map(|i| i.modified);
+        if current != Some(expected) {
+            pool.lock().unwrap().put_afc(udid, &key, afc_client);
+            return Err(SaveConflict.into());
+        }
+    }
+
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let temp = format!("{dir}/.{name}.pair_gui-save");
+    let mut fd = afc_client.open(temp.as_str(), AfcFopenMode::WrOnly).await?;
+    fd.write(contents.as_bytes()).await?;
+    fd.close().await?;
+    if let Err(e) = afc_client.rename(temp.as_str(), path).await {
+        let _ = afc_client.remove(temp.as_str()).await;
+        return Err(e.into());
+    }
+    let modified = afc_client.get_file_info(path).await?.modified;
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok(modified)
+}
+
+/// Download `remote_path` into `out_dir`, streaming it to disk chunk by chunk.
+///
+/// With `resume`, an existing local file no larger than the remote one is
+/// treated as a partial download and appended to; a larger one means the
+/// remote file changed, so it is replaced.
+///
+/// Returns the local file path and the offset the download resumed from.
+/// `on_progress` is called with the bytes on disk so far and the total.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_file(
+    pool: &SharedPool,
+    udid: &str,
+    remote_path: &str,
+    source: &AfcSource,
+    out_dir: &Path,
+    chunk_size: usize,
+    resume: bool,
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<(PathBuf, u64), Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+
+    afc_client.set_chunk_size(chunk_size);
+    if !afc_client.exists(remote_path).await? {
+        pool.lock().unwrap().put_afc(udid, &key, afc_client);
+        return Err(format!("{remote_path} does not exist on the device").into());
+    }
+    let remote_size = afc_client.get_file_info(remote_path).await?.size as u64;
+
+    ensure_writable_dir(out_dir)?;
+    // Bytes the device allows in a name but this computer may not are
+    // replaced locally; the remote path keeps them
+    let name = display_name(remote_path.rsplit('/').next().unwrap_or(remote_path));
+    let local_path = out_dir.join(name.as_ref());
+    let partial = match std::fs::metadata(&local_path) {
+        Ok(meta) if resume && meta.len() <= remote_size => meta.len(),
+        _ => 0,
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(partial > 0)
+        .truncate(partial == 0)
+        .open(&local_path)?;
+    // A partial file is only worth keeping if a later download can resume it
+    let unfinished = PartialFile((!resume).then(|| local_path.clone()));
+
+    let mut fd = afc_client.open(remote_path, AfcFopenMode::RdOnly).await?;
+    if partial > 0 {
+        fd.seek(SeekFrom::Start(partial)).await?;
+    }
+    let mut remaining = remote_size - partial;
+    while remaining > 0 {
+        let chunk = fd.read_chunk(chunk_size).await?;
+        if chunk.is_empty() {
+            break;
+        }
+        file.write_all(&chunk)?;
+        remaining = remaining.saturating_sub(chunk.len() as u64);
+        on_progress(remote_size - remaining, remote_size);
+    }
+    fd.close().await?;
+    unfinished.keep();
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok((local_path, partial))
+}
+
+/// A local file being downloaded, removed if the download stops before
+/// `keep` is called. That includes its task being aborted, as when the
+/// transfer is cancelled or the device goes away.
+struct PartialFile(Option<PathBuf>);
+
+impl PartialFile {
+    fn keep(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Extensions of formats that are already compressed; deflating them again
+/// only costs time.
+const STORED_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "heic", "heif", "png", "gif", "mp4", "mov", "m4v", "m4a", "mp3", "aac", "zip",
+    "ipa", "gz", "xz", "bz2", "7z",
+];
+
+/// How a file named `name` is stored in a zip.
+fn zip_method(name: &str) -> CompressionMethod {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match ext {
+        Some(ext) if STORED_EXTENSIONS.contains(&ext.as_str()) => CompressionMethod::Stored,
+        _ => CompressionMethod::Deflated,
+    }
+}
+
+/// `dir/stem.ext`, or `dir/stem (n).ext` for the first n that isn't taken.
+fn free_local_path(dir: &Path, stem: &str, ext: &str) -> PathBuf {
+    let mut path = dir.join(format!("{stem}.{ext}"));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{stem} ({n}).{ext}"));
+        n += 1;
+    }
+    path
+}
+
+/// Stream `names` in the remote directory `dir`, with everything under the
+/// ones that are directories, into a new zip archive in `out_dir`.
+///
+/// Symbolic links are left out unless `follow_symlinks` is set; links that
+/// lead back into the walk are left out either way.
+///
+/// Compression happens on this side only; the device sends the files as
+/// usual. `on_progress` is called with the bytes archived so far and the
+/// total. Returns the archive's path and the links left out.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_zip(
+    pool: &SharedPool,
+    udid: &str,
+    dir: &str,
+    names: &[String],
+    source: &AfcSource,
+    out_dir: &Path,
+    chunk_size: usize,
+    follow_symlinks: bool,
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<(PathBuf, Vec<String>), Box<dyn std::error::Error>> {
+    let key = source.service_key();
+    let mut afc_client = checkout_afc(pool, udid, source).await?;
+    afc_client.set_chunk_size(chunk_size);
+
+    // (remote path, name in the archive, size) of every file to include
+    let mut files = Vec::new();
+    let mut skipped_links = Vec::new();
+    for name in names {
+        let remote = format!("{}/{name}", dir.trim_end_matches('/'));
+        let walk = afc_client.walk(remote.as_str(), follow_symlinks).await?;
+        if let Some((path, e)) = walk.failed.into_iter().next() {
+            return Err(format!("{path}: {e}").into());
+        }
+        for (path, info) in walk.entries {
+            if info.st_ifmt == "S_IFREG" {
+                let archived =
+                    display_name(&format!("{name}{}", &path[remote.len()..])).into_owned();
+                files.push((path, archived, info.size as u64));
+            }
+        }
+        skipped_links.extend(walk.skipped_links);
+    }
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    let total: u64 = files.iter().map(|(_, _, size)| size).sum();
+
+    ensure_writable_dir(out_dir)?;
+    let stem = match dir.trim_end_matches('/').rsplit('/').next() {
+        Some("") | None => "device".into(),
+        Some(name) => display_name(name),
+    };
+    let archive = free_local_path(out_dir, &stem, "zip");
+    // Don't leave a truncated archive that looks complete
+    let unfinished = PartialFile(Some(archive.clone()));
+    write_zip(&mut afc_client, &files, &archive, chunk_size, |done| {
+        on_progress(done, total)
+    })
+    .await?;
+    unfinished.keep();
+    pool.lock().unwrap().put_afc(udid, &key, afc_client);
+    Ok((archive, skipped_links))
+}
+
+/// Write `files` as (remote path, name in the archive, size) into a new zip
+/// at `archive`, calling `on_progress` with the bytes written so far.
+async fn write_zip(
+    afc_client: &mut AfcClient,
+    files: &[(String, String, u64)],
+    archive: &Path,
+    chunk_size: usize,
+    mut on_progress: impl FnMut(u64),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut zip = ZipWriter::new(std::fs::File::create(archive)?);
+    let mut done = 0;
+    for (remote, archived, _) in files {
+        let options = SimpleFileOptions::default()
+            .compression_method(zip_method(archived))
+            .large_file(true);
+        zip.start_file(archived.as_str(), options)?;
+        let mut fd = afc_client
+            .open(remote.as_str(), AfcFopenMode::RdOnly)
+            .await?;
+        loop {
+            let chunk = fd.read_chunk(chunk_size).await?;
+            if chunk.is_empty() {
+                break;
+            }
+            zip.write_all(&chunk)?;
+            done += chunk.len() as u64;
+            on_progress(done);
+        }
+        fd.close().await?;
+    }
+    zip.finish()?;
+    Ok(())
 }
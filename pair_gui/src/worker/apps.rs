@@ -0,0 +1,40 @@
+// src/worker/apps.rs
+use idevice::{installation_proxy::InstallationProxyClient, IdeviceService};
+use plist::Value;
+
+use crate::{types::InstalledApp, worker::common::get_provider};
+
+/// User-installed apps on `udid`, sorted by bundle ID.
+pub async fn list_installed_apps(
+    udid: &str,
+) -> Result<Vec<InstalledApp>, Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+    let mut client = InstallationProxyClient::connect(&provider).await?;
+    let apps = client.get_apps(Some("User".to_string()), None).await?;
+
+    let mut list: Vec<InstalledApp> = apps
+        .into_iter()
+        .map(|(bundle_id, info)| {
+            let dict = info.as_dictionary();
+            let string = |key: &str| {
+                dict.and_then(|d| d.get(key))
+                    .and_then(Value::as_string)
+                    .map(str::to_string)
+            };
+            let name = string("CFBundleDisplayName")
+                .or_else(|| string("CFBundleName"))
+                .unwrap_or_else(|| bundle_id.clone());
+            let file_sharing = dict
+                .and_then(|d| d.get("UIFileSharingEnabled"))
+                .and_then(Value::as_boolean)
+                .unwrap_or(false);
+            InstalledApp {
+                bundle_id,
+                name,
+                file_sharing,
+            }
+        })
+        .collect();
+    list.sort_by(|a, b| a.bundle_id.cmp(&b.bundle_id));
+    Ok(list)
+}
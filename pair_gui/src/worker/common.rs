@@ -1,37 +1,216 @@
 // pair_gui/src/worker/common.rs
 
-use std::{fs, path::Path};
+use anyhow::Result;
+use crossbeam::channel::Sender;
 use idevice::{
     lockdown::LockdownClient,
-    provider::{BoxedProvider, IdeviceProvider},
+    pairing_file::PairingFile,
+    provider::UsbmuxdProvider,
     usbmuxd::{UsbmuxdAddr, UsbmuxdConnection},
-    IdeviceService,
+    IdeviceError, IdeviceService,
 };
-use anyhow::Result;
+use std::{future::Future, net::AddrParseError, path::Path, sync::Mutex, time::Duration};
+
+use crate::{prefs::DEFAULT_CLIENT_LABEL, types::GuiEvent};
+
+/// How many times to try reaching usbmuxd before giving up.
+const USBMUXD_ATTEMPTS: u32 = 5;
+/// Delay before the first retry; doubled after each failed attempt.
+const USBMUXD_BACKOFF: Duration = Duration::from_millis(250);
+
+/// usbmuxd's host BUID, fetched once and reused for every pairing.
+pub static HOST_BUID: BuidCache = BuidCache::new();
+
+/// usbmuxd address picked in the settings; `None` uses the platform default.
+static USBMUXD_ADDR: Mutex<Option<UsbmuxdAddr>> = Mutex::new(None);
+
+/// Label picked in the settings; `None` uses `DEFAULT_CLIENT_LABEL`.
+static CLIENT_LABEL: Mutex<Option<String>> = Mutex::new(None);
+
+/// The usbmuxd address every connection should go to.
+pub fn usbmuxd_addr() -> UsbmuxdAddr {
+    USBMUXD_ADDR.lock().unwrap().clone().unwrap_or_default()
+}
+
+/// Use `socket` (a socket path or `ip:port`) for usbmuxd from now on. An
+/// empty string goes back to the platform default.
+pub fn set_usbmuxd_socket(socket: &str) -> Result<(), AddrParseError> {
+    let socket = socket.trim();
+    let addr = if socket.is_empty() {
+        None
+    } else {
+        Some(UsbmuxdAddr::parse(socket)?)
+    };
+    *USBMUXD_ADDR.lock().unwrap() = addr;
+    // Another daemon may have another BUID
+    HOST_BUID.invalidate();
+    Ok(())
+}
+
+/// The label every provider should be created with. usbmuxd shows it in its
+/// logs, so it says which app a connection came from.
+pub fn client_label() -> String {
+    CLIENT_LABEL
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CLIENT_LABEL.to_string())
+}
+
+/// Use `label` for connections from now on. An empty string goes back to
+/// the default.
+pub fn set_client_label(label: &str) {
+    let label = label.trim();
+    *CLIENT_LABEL.lock().unwrap() = (!label.is_empty()).then(|| label.to_string());
+}
+
+/// Caches the host BUID, which only changes if usbmuxd's config is reset.
+pub struct BuidCache {
+    buid: Mutex<Option<String>>,
+}
+
+impl BuidCache {
+    pub const fn new() -> Self {
+        Self {
+            buid: Mutex::new(None),
+        }
+    }
+
+    /// The cached BUID, or the result of `fetch` if there is none yet.
+    pub async fn get_or_fetch<F, Fut, E>(&self, fetch: F) -> Result<String, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+    {
+        if let Some(buid) = self.buid.lock().unwrap().clone() {
+            return Ok(buid);
+        }
+        let buid = fetch().await?;
+        *self.buid.lock().unwrap() = Some(buid.clone());
+        Ok(buid)
+    }
+
+    /// Forget the BUID so the next pairing asks usbmuxd again.
+    pub fn invalidate(&self) {
+        *self.buid.lock().unwrap() = None;
+    }
+}
+
+/// Whether `e` means the connection to the device broke, as opposed to the
+/// device refusing the request.
+pub fn is_connection_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    match e.downcast_ref::<IdeviceError>() {
+        Some(IdeviceError::Socket(_) | IdeviceError::NoEstablishedConnection) => true,
+        Some(_) => false,
+        None => e.is::<std::io::Error>(),
+    }
+}
+
+/// Connect to usbmuxd, retrying while the daemon comes up.
+///
+/// A status line is sent to the GUI before each retry so a slow start
+/// (e.g. the Apple service restarting on Windows) doesn't look like a hang.
+pub async fn connect_usbmuxd(tx: &Sender<GuiEvent>) -> Result<UsbmuxdConnection, IdeviceError> {
+    let res = usbmuxd_addr()
+        .connect_with_retry_and_notify(USBMUXD_ATTEMPTS, USBMUXD_BACKOFF, |attempt, delay| {
+            let _ = tx.send(GuiEvent::Status(format!(
+                "Waiting for usbmuxd (attempt {attempt}/{USBMUXD_ATTEMPTS}), retrying in {}ms...",
+                delay.as_millis()
+            )));
+        })
+        .await;
+    if res.is_err() {
+        // usbmuxd is down or restarting; don't trust the cached BUID afterwards
+        HOST_BUID.invalidate();
+    }
+    res
+}
+
+/// Connect to the configured usbmuxd once and describe what answered.
+pub async fn check_usbmuxd() -> Result<String, String> {
+    let mut mux = usbmuxd_addr().connect(0).await.map_err(|e| e.to_string())?;
+    let devices = mux.get_devices().await.map_err(|e| e.to_string())?;
+    Ok(format!("Connected, {} device(s)", devices.len()))
+}
+
+/// A pairing record whose embedded UDID names another device.
+#[derive(Debug)]
+pub struct PairingMismatch {
+    pub device: String,
+    pub pairing_file: String,
+}
+
+impl std::fmt::Display for PairingMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pairing file is for a different device ({}, not {})",
+            self.pairing_file, self.device
+        )
+    }
+}
+
+impl std::error::Error for PairingMismatch {}
+
+/// Make sure `pairing` was made for `udid`.
+///
+/// Records without an embedded UDID (older tools didn't write one) can't be
+/// checked and are let through; lockdown will still reject a wrong one.
+pub fn check_pairing_udid(pairing: &PairingFile, udid: &str) -> Result<(), PairingMismatch> {
+    match &pairing.udid {
+        Some(found) if !found.eq_ignore_ascii_case(udid) => Err(PairingMismatch {
+            device: udid.to_string(),
+            pairing_file: found.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
 
 /// Load or perform pairing, then return a ready-to-use provider.
 ///
 /// - `udid`: device identifier (e.g. "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx").
 /// - `pairing_file`: optional path to an existing `.mobiledevicepairing`.
 /// - `tag`: a human-readable tag for usbmuxd.
-pub async fn get_provider(
-    udid: &str,
-    pairing_file: Option<&Path>,
-    tag: &str,
-) -> Result<BoxedProvider> {
+pub async fn get_provider(udid: &str, pairing_file: Option<&Path>) -> Result<UsbmuxdProvider> {
     // connect to usbmuxd and grab the device handle
-    let mut mux = UsbmuxdConnection::default().await?;
+    let addr = usbmuxd_addr();
+    let mut mux = addr.connect(0).await?;
     let dev = mux.get_device(udid).await?;
-    let provider = dev.to_provider(UsbmuxdAddr::default(), tag);
+    let provider = dev.to_provider(addr, client_label());
 
     // if the user supplied a pairing file, read it and start a lockdown session
     if let Some(pf_path) = pairing_file {
-        let raw = fs::read(pf_path)?;
-        // NOTE: your crate’s PairingRecord type has a from_bytes or similar:
-        let pairing = idevice::pairing_file::PairingRecord::deserialize(&raw)?;
+        let pairing = PairingFile::read_from_file(pf_path)?;
+        // Checked up front: lockdown's InvalidHostID would only confuse
+        check_pairing_udid(&pairing, udid)?;
         let mut lockdown = LockdownClient::connect(&provider).await?;
         lockdown.start_session(&pairing).await?;
     }
 
     Ok(provider)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn buid_is_fetched_once_until_invalidated() {
+        let cache = BuidCache::new();
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, IdeviceError>("BUID-1".to_string())
+        };
+
+        assert_eq!(cache.get_or_fetch(fetch).await.unwrap(), "BUID-1");
+        assert_eq!(cache.get_or_fetch(fetch).await.unwrap(), "BUID-1");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        cache.invalidate();
+        cache.get_or_fetch(fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,69 @@
+// src/worker/crash.rs
+use std::path::{Component, Path, PathBuf};
+
+use crossbeam::channel::Sender;
+use idevice::{
+    crashreportcopymobile::{flush_reports, CrashReportCopyMobileClient},
+    IdeviceService,
+};
+use tracing::warn;
+
+use crate::{types::GuiEvent, worker::common::get_provider};
+
+/// Crash reports on `udid`, after asking the device to move new ones into
+/// the crash logs directory.
+pub async fn list_crash_reports(udid: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+    // Reports moved by earlier flushes can still be listed
+    if let Err(e) = flush_reports(&provider).await {
+        warn!(error = %e, "flushing crash reports failed");
+    }
+    let mut client = CrashReportCopyMobileClient::connect(&provider).await?;
+    Ok(client.ls_recursive().await?)
+}
+
+/// Save `reports` under `out_dir/crash_logs/<udid>`, keeping their
+/// subdirectories. With `remove`, each report is deleted from the device once
+/// its local copy is written, so a failed download never loses one.
+///
+/// Returns the directory and the reports left on the device.
+pub async fn download_crash_reports(
+    tx: &Sender<GuiEvent>,
+    udid: &str,
+    reports: &[String],
+    out_dir: &Path,
+    remove: bool,
+) -> Result<(PathBuf, Vec<String>), Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+    let mut client = CrashReportCopyMobileClient::connect(&provider).await?;
+    let dir = out_dir.join("crash_logs").join(udid);
+
+    for (i, report) in reports.iter().enumerate() {
+        let relative = Path::new(report);
+        // Names come from the device; don't let one escape the directory
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            warn!(%report, "skipping crash report with an unsafe path");
+            continue;
+        }
+        let _ = tx.send(GuiEvent::Status(format!(
+            "Downloading crash report {}/{}: {report}",
+            i + 1,
+            reports.len()
+        )));
+        let data = client.pull(report.as_str()).await?;
+        let local = dir.join(relative);
+        if let Some(parent) = local.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&local, data)?;
+        if remove {
+            client.remove(report.as_str()).await?;
+        }
+    }
+
+    let remaining = client.ls_recursive().await?;
+    Ok((dir, remaining))
+}
@@ -1,108 +1,627 @@
 // src/worker/device.rs
-use idevice::usbmuxd::{Connection as UsbConnection, UsbmuxdAddr, UsbmuxdConnection};
-use idevice::lockdown::LockdownClient;
-use idevice::IdeviceService;
+use crossbeam::channel::Sender;
+use idevice::amfi::AmfiClient;
+use idevice::lockdown::{is_lock_refusal, LockdownClient};
+use idevice::pairing_file::PairingFile;
 use idevice::provider::IdeviceProvider;
+use idevice::usbmuxd::{Connection as UsbConnection, UsbmuxdDevice};
+use idevice::{IdeviceError, IdeviceService};
 use plist::Value;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    path::Path,
+    sync::Arc,
+};
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::util::{extract_values, process_value, reveal_in_file_browser};
+use crate::prefs::{FlattenMode, ReportFormat};
+use crate::types::{DeviceInfo, GuiEvent, TrustState, UsbLink};
+use crate::util::{
+    ensure_writable_dir, extract_data, extract_values, process_value, system_lockdown_dir,
+};
+use crate::worker::common::{
+    check_pairing_udid, client_label, connect_usbmuxd, get_provider, usbmuxd_addr, HOST_BUID,
+};
+use crate::worker::pool::SharedPool;
 
-/// Scan connected USB devices and return their UDIDs
-pub async fn scan_devices() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let mut mux = UsbmuxdConnection::default().await?;
-    let devices = mux.get_devices().await?;
-    Ok(devices
+/// Scan connected USB devices and return their UDIDs with how they're attached
+pub async fn scan_devices(
+    tx: &Sender<GuiEvent>,
+) -> Result<Vec<(String, UsbLink)>, Box<dyn std::error::Error>> {
+    let mut mux = connect_usbmuxd(tx).await?;
+    Ok(usb_devices(mux.get_devices().await?))
+}
+
+/// One entry per USB device, in usbmuxd's order. A device on both USB and
+/// Wi-Fi is listed by usbmuxd twice; it appears once here, marked as also
+/// on Wi-Fi. Devices only on the network are left out.
+fn usb_devices(devices: Vec<UsbmuxdDevice>) -> Vec<(String, UsbLink)> {
+    let wifi: HashSet<String> = devices
+        .iter()
+        .filter(|d| matches!(d.connection_type, UsbConnection::Network(_)))
+        .map(|d| d.udid.clone())
+        .collect();
+    let mut seen = HashSet::new();
+    devices
         .into_iter()
-        .filter(|d| d.connection_type == UsbConnection::Usb)
-        .map(|d| d.udid)
-        .collect())
+        .filter(|d| d.connection_type == UsbConnection::Usb && seen.insert(d.udid.clone()))
+        .map(|d| {
+            let link = UsbLink {
+                speed: d.connection_speed,
+                interface: d.interface_index,
+                wifi: wifi.contains(&d.udid),
+            };
+            (d.udid, link)
+        })
+        .collect()
 }
 
-/// Retrieve just the device name
-pub async fn get_device_name(udid: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let mut mux = UsbmuxdConnection::default().await?;
+/// Lockdown values a device gives out without a session, which are still
+/// read when the pairing record is refused. Everything else needs the session.
+const SESSIONLESS_KEYS: &[&str] = &[
+    "DeviceName",
+    "ProductType",
+    "ProductVersion",
+    "BuildVersion",
+    "DeviceClass",
+    "UniqueDeviceID",
+];
+
+/// The device has a pairing record but wouldn't start a session with it,
+/// usually because the device forgot the pairing. Shared so the pool can
+/// hand the same refusal to later reads.
+#[derive(Debug, Clone)]
+pub struct SessionFailed(pub Arc<IdeviceError>);
+
+impl std::fmt::Display for SessionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "session failed \u{2014} re-pair may be required ({})",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for SessionFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+/// A lockdown client for `udid` without a session, and its pairing record
+/// if usbmuxd has one.
+async fn connect_lockdown(
+    udid: &str,
+) -> Result<(LockdownClient, Option<PairingFile>), IdeviceError> {
+    let mut mux = usbmuxd_addr().connect(0).await?;
     let dev = mux.get_device(udid).await?;
-    let provider = dev.to_provider(UsbmuxdAddr::default(), "pair-gui");
-    let mut lockdown = LockdownClient::connect(&provider).await?;
-    if let Ok(pf) = provider.get_pairing_file().await {
-        let _ = lockdown.start_session(&pf).await;
+    let provider = dev.to_provider(usbmuxd_addr(), client_label());
+    let lockdown = LockdownClient::connect(&provider).await?;
+    Ok((lockdown, provider.get_pairing_file().await.ok()))
+}
+
+/// Check out a lockdown client for `udid`, connecting if the pool has none.
+///
+/// A session is started when a pairing record is available, and a record
+/// the device refuses fails with `SessionFailed`. The refusal is kept in the
+/// pool and returned without asking again until the device reconnects or is
+/// paired. Devices without a record get a client without a session for the
+/// values they answer anyway.
+async fn checkout_lockdown(
+    pool: &SharedPool,
+    udid: &str,
+) -> Result<LockdownClient, Box<dyn std::error::Error>> {
+    {
+        let mut pool = pool.lock().unwrap();
+        if let Some(refusal) = pool.session_refusal(udid) {
+            return Err(refusal.into());
+        }
+        if let Some(lockdown) = pool.take_lockdown(udid) {
+            return Ok(lockdown);
+        }
     }
-    match lockdown.get_value("DeviceName", None).await {
-        Ok(val) => {
-            if let Value::String(s) = val {
-                Ok(s)
-            } else {
-                Ok(udid.to_string())
-            }
+    let (mut lockdown, pf) = connect_lockdown(udid).await?;
+    if let Some(pf) = pf {
+        if let Err(e) = lockdown.start_session(&pf).await {
+            let refusal = SessionFailed(Arc::new(e));
+            pool.lock()
+                .unwrap()
+                .mark_session_refused(udid, refusal.clone());
+            return Err(refusal.into());
         }
-        Err(_) => Ok(udid.to_string()),
     }
+    Ok(lockdown)
 }
 
-/// Retrieve just the device model identifier
-pub async fn get_device_model(udid: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let mut mux = UsbmuxdConnection::default().await?;
-    let dev = mux.get_device(udid).await?;
-    let provider = dev.to_provider(UsbmuxdAddr::default(), "pair-gui");
-    let mut lockdown = LockdownClient::connect(&provider).await?;
-    if let Ok(pf) = provider.get_pairing_file().await {
-        let _ = lockdown.start_session(&pf).await;
+/// Read lockdown value `key` through a session, falling back to a
+/// connection without one if the session was refused and `key` is in
+/// `SESSIONLESS_KEYS`.
+async fn read_value(
+    pool: &SharedPool,
+    udid: &str,
+    key: &str,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let session = async {
+        let mut lockdown = checkout_lockdown(pool, udid).await?;
+        let value = lockdown.get_value(key, None).await?;
+        pool.lock().unwrap().put_lockdown(udid, lockdown);
+        Ok::<_, Box<dyn std::error::Error>>(value)
+    };
+    let sessionless = || async {
+        let cached = pool.lock().unwrap().take_sessionless_lockdown(udid);
+        let mut lockdown = match cached {
+            Some(lockdown) => lockdown,
+            None => connect_lockdown(udid).await?.0,
+        };
+        let value = lockdown.get_value(key, None).await?;
+        pool.lock()
+            .unwrap()
+            .put_sessionless_lockdown(udid, lockdown);
+        Ok::<_, Box<dyn std::error::Error>>(value)
+    };
+    without_session_if_refused(key, session.await, sessionless).await
+}
+
+/// `res`, unless it failed with `SessionFailed` and `key` can be read
+/// without a session, in which case `sessionless` is asked instead.
+async fn without_session_if_refused<F, Fut>(
+    key: &str,
+    res: Result<Value, Box<dyn std::error::Error>>,
+    sessionless: F,
+) -> Result<Value, Box<dyn std::error::Error>>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Value, Box<dyn std::error::Error>>>,
+{
+    match res {
+        Err(e) if e.is::<SessionFailed>() && SESSIONLESS_KEYS.contains(&key) => {
+            warn!(key, error = %e, "reading without a session");
+        }
+        res => return res,
     }
-    match lockdown.get_value("ProductType", None).await {
-        Ok(val) => {
-            if let Value::String(s) = val {
-                Ok(s)
-            } else {
-                Ok(String::new())
+    sessionless().await
+}
+
+/// Check whether the stored pairing record still opens a lockdown session
+///
+/// On success the session is kept in the pool for the next lookup.
+pub async fn probe_trust(pool: &SharedPool, udid: &str) -> TrustState {
+    let probe = async {
+        let mut mux = usbmuxd_addr().connect(0).await?;
+        let dev = mux.get_device(udid).await?;
+        let provider = dev.to_provider(usbmuxd_addr(), client_label());
+        let pf = match provider.get_pairing_file().await {
+            Ok(pf) => pf,
+            Err(_) => return Ok(TrustState::Untrusted),
+        };
+        let mut lockdown = LockdownClient::connect(&provider).await?;
+        match lockdown.start_session(&pf).await {
+            Ok(()) => {
+                let mut pool = pool.lock().unwrap();
+                pool.forget_session_refusal(udid);
+                pool.put_lockdown(udid, lockdown);
+                Ok(TrustState::Trusted)
+            }
+            Err(IdeviceError::PasswordProtected | IdeviceError::DeviceLocked) => {
+                Ok(TrustState::Locked)
             }
+            Err(IdeviceError::InvalidHostID) => Ok(TrustState::Untrusted),
+            Err(e) => Err(e),
+        }
+    };
+    let res: Result<TrustState, IdeviceError> = probe.await;
+    res.unwrap_or(TrustState::Unknown)
+}
+
+/// Whether `udid` is passcode-locked right now, or `None` if it couldn't be
+/// checked. Asks over the pooled session, so polling it is one request.
+pub async fn probe_lock(pool: &SharedPool, udid: &str) -> Option<bool> {
+    {
+        // A session refused because the device was locked may work now
+        let mut pool = pool.lock().unwrap();
+        if pool
+            .session_refusal(udid)
+            .is_some_and(|refusal| is_lock_refusal(&refusal.0))
+        {
+            pool.forget_session_refusal(udid);
         }
-        Err(_) => Ok(String::new()),
+    }
+    let mut lockdown = match checkout_lockdown(pool, udid).await {
+        Ok(lockdown) => lockdown,
+        Err(e) => {
+            let refusal = e.downcast_ref::<SessionFailed>()?;
+            return is_lock_refusal(&refusal.0).then_some(true);
+        }
+    };
+    let locked = lockdown.is_locked().await.ok()?;
+    pool.lock().unwrap().put_lockdown(udid, lockdown);
+    Some(locked)
+}
+
+/// Retrieve just the device name
+pub async fn get_device_name(
+    pool: &SharedPool,
+    udid: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match read_value(pool, udid, "DeviceName").await? {
+        Value::String(s) => Ok(s),
+        _ => Ok(udid.to_string()),
+    }
+}
+
+/// Retrieve just the device model identifier
+pub async fn get_device_model(
+    pool: &SharedPool,
+    udid: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match read_value(pool, udid, "ProductType").await? {
+        Value::String(s) => Ok(s),
+        _ => Ok(String::new()),
+    }
+}
+
+/// "Name (Model)" label for the device list, falling back to the UDID
+pub async fn get_device_summary(pool: &SharedPool, udid: &str) -> String {
+    let name = get_device_name(pool, udid).await.unwrap_or_else(|e| {
+        warn!(error = %e, "reading the device name failed");
+        udid.to_string()
+    });
+    let model = get_device_model(pool, udid).await.unwrap_or_else(|e| {
+        warn!(error = %e, "reading the device model failed");
+        String::new()
+    });
+    if model.is_empty() {
+        name
+    } else {
+        format!("{name} ({model})")
     }
 }
 
 /// Pair with a device and save the pairing file
+///
+/// Cached clients for the device are discarded first since their sessions
+/// predate the new trust relationship; the freshly paired lockdown client is
+/// pooled afterwards.
 pub async fn pair_one(
+    pool: &SharedPool,
     output_dir: &Path,
     udid: &str,
 ) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
-    let mut mux = UsbmuxdConnection::default().await?;
+    // Check before pairing so a dead directory doesn't cost us the record
+    ensure_writable_dir(output_dir)?;
+    pool.lock().unwrap().remove_device(udid);
+
+    let mut mux = match usbmuxd_addr().connect(0).await {
+        Ok(mux) => mux,
+        Err(e) => {
+            HOST_BUID.invalidate();
+            return Err(e.into());
+        }
+    };
     let dev = mux.get_device(udid).await?;
-    let provider = dev.to_provider(UsbmuxdAddr::default(), "pair-gui");
+    let provider = dev.to_provider(usbmuxd_addr(), client_label());
     let mut lockdown = LockdownClient::connect(&provider).await?;
 
     let host_id = Uuid::new_v4().to_string().to_uppercase();
-    let buid = mux.get_buid().await?;
+    let buid = HOST_BUID.get_or_fetch(|| mux.get_buid()).await?;
     let mut pf = lockdown.pair(host_id, buid).await?;
     let _ = lockdown.start_session(&pf).await?;
+    pool.lock().unwrap().put_lockdown(udid, lockdown);
 
     pf.udid = Some(dev.udid.clone());
     let data = pf.serialize()?;
+    ensure_writable_dir(output_dir)?;
     let out_path = output_dir.join(format!("{}.mobiledevicepairing", udid));
     std::fs::write(&out_path, data)?;
     Ok(output_dir.to_path_buf())
 }
 
-/// Retrieve all device info as a flat map
-pub async fn get_device_info(
+/// lockdown domain holding the Wi-Fi connection switch
+const WIRELESS_LOCKDOWN_DOMAIN: &str = "com.apple.mobile.wireless_lockdown";
+
+/// Let the device accept connections over Wi-Fi from now on
+///
+/// The pairing record is passed in since one we just made isn't in usbmuxd's
+/// store yet. Returns whether the device kept the setting; devices that don't
+/// support it report `false` rather than an error.
+pub async fn enable_wireless_connections(
+    provider: &dyn IdeviceProvider,
+    pairing_file: &PairingFile,
+) -> Result<bool, IdeviceError> {
+    let mut lockdown = LockdownClient::connect(provider).await?;
+    lockdown.start_session(pairing_file).await?;
+    match lockdown
+        .set_value(
+            "EnableWifiConnections",
+            true.into(),
+            Some(WIRELESS_LOCKDOWN_DOMAIN.to_string()),
+        )
+        .await
+    {
+        Ok(()) => {}
+        Err(
+            IdeviceError::SetProhibited
+            | IdeviceError::UnknownErrorType(_)
+            | IdeviceError::UnexpectedResponse,
+        ) => return Ok(false),
+        Err(e) => return Err(e),
+    }
+    // Some devices acknowledge the request without changing anything
+    let value = lockdown
+        .get_value(
+            "EnableWifiConnections",
+            Some(WIRELESS_LOCKDOWN_DOMAIN.to_string()),
+        )
+        .await;
+    Ok(matches!(value, Ok(Value::Boolean(true))))
+}
+
+/// Enable Wi-Fi connections using the record `pair_one` saved in `output_dir`
+pub async fn enable_wifi_sync(
+    output_dir: &Path,
     udid: &str,
-) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
-    let mut mux = UsbmuxdConnection::default().await?;
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let path = output_dir.join(format!("{}.mobiledevicepairing", udid));
+    let pf = PairingFile::read_from_file(&path)?;
+    let mut mux = usbmuxd_addr().connect(0).await?;
     let dev = mux.get_device(udid).await?;
-    let provider = dev.to_provider(UsbmuxdAddr::default(), "pair-gui");
+    let provider = dev.to_provider(usbmuxd_addr(), client_label());
+    Ok(enable_wireless_connections(&provider, &pf).await?)
+}
+
+/// Copy the record `pair_one` saved in `output_dir` into the system usbmuxd
+/// directory as `<UDID>.plist`, returning where it went
+///
+/// The directory is normally writable only by root/administrators; that case
+/// gets an error saying so rather than a bare "permission denied".
+pub fn install_to_system_lockdown(
+    output_dir: &Path,
+    udid: &str,
+) -> Result<std::path::PathBuf, String> {
+    let dir = system_lockdown_dir().ok_or("no system lockdown directory on this OS")?;
+    if !dir.is_dir() {
+        return Err(format!(
+            "{} doesn't exist, is usbmuxd installed?",
+            dir.display()
+        ));
+    }
+    let from = output_dir.join(format!("{}.mobiledevicepairing", udid));
+    let to = dir.join(format!("{udid}.plist"));
+    match std::fs::copy(&from, &to) {
+        Ok(_) => Ok(to),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Err(format!(
+            "not permitted to write to {}, run pair_gui as {} to install pairing records there",
+            dir.display(),
+            if cfg!(windows) {
+                "administrator"
+            } else {
+                "root"
+            }
+        )),
+        Err(e) => Err(format!("copying to {} failed: {e}", to.display())),
+    }
+}
+
+/// Have the device forget our pairing and delete the record saved in
+/// `output_dir`
+///
+/// Without a saved record usbmuxd's is revoked instead. A device that had
+/// already forgotten the record counts as success, so a retry after a
+/// half-finished unpair cleans up the file. Returns the deleted file, if any.
+pub async fn unpair_one(
+    pool: &SharedPool,
+    output_dir: &Path,
+    udid: &str,
+) -> Result<Option<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    let saved = output_dir.join(format!("{}.mobiledevicepairing", udid));
+    let mut mux = usbmuxd_addr().connect(0).await?;
+    let dev = mux.get_device(udid).await?;
+    let provider = dev.to_provider(usbmuxd_addr(), client_label());
+    let pf = match PairingFile::read_from_file(&saved) {
+        Ok(pf) => pf,
+        Err(_) => provider.get_pairing_file().await?,
+    };
+    check_pairing_udid(&pf, udid)?;
+
+    // Pooled sessions were opened with the record we're revoking
+    pool.lock().unwrap().remove_device(udid);
     let mut lockdown = LockdownClient::connect(&provider).await?;
-    if let Ok(pf) = provider.get_pairing_file().await {
-        let _ = lockdown.start_session(&pf).await;
+    lockdown.unpair(&pf).await?;
+
+    match std::fs::remove_file(&saved) {
+        Ok(()) => Ok(Some(saved)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("unpaired, but deleting {} failed: {e}", saved.display()).into()),
     }
-    let dict = lockdown.get_all_values().await?;
-    let mut info = HashMap::new();
-    extract_values("", &Value::Dictionary(dict.clone()), &mut info);
+}
+
+/// Copy an existing pairing file for `udid` into `output_dir`
+///
+/// The file must name this device (if it names one at all) and open a
+/// lockdown session before it's saved.
+pub async fn import_pairing_file(
+    pool: &SharedPool,
+    output_dir: &Path,
+    udid: &str,
+    path: &Path,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    ensure_writable_dir(output_dir)?;
+    let mut pf = PairingFile::read_from_file(path)?;
+    check_pairing_udid(&pf, udid)?;
+
+    let mut mux = usbmuxd_addr().connect(0).await?;
+    let dev = mux.get_device(udid).await?;
+    let provider = dev.to_provider(usbmuxd_addr(), client_label());
+    let mut lockdown = LockdownClient::connect(&provider).await?;
+    lockdown.start_session(&pf).await?;
+    pool.lock().unwrap().remove_device(udid);
+    pool.lock().unwrap().put_lockdown(udid, lockdown);
+
+    pf.udid = Some(udid.to_string());
+    let out_path = output_dir.join(format!("{}.mobiledevicepairing", udid));
+    std::fs::write(&out_path, pf.serialize()?)?;
+    Ok(out_path)
+}
+
+/// Fetch the device's info and write it to `output_dir` in `format`,
+/// returning the new file's path
+pub async fn save_device_report(
+    pool: &SharedPool,
+    output_dir: &Path,
+    udid: &str,
+    format: ReportFormat,
+    flatten: FlattenMode,
+) -> Result<(DeviceInfo, std::path::PathBuf), Box<dyn std::error::Error>> {
+    ensure_writable_dir(output_dir)?;
+    let (info, _) = get_device_info(pool, udid, flatten).await?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let out_path = output_dir.join(format!("{udid}-report-{stamp}.{}", format.extension()));
+    std::fs::write(&out_path, info.to_report(udid, format)?)?;
+    Ok((info, out_path))
+}
+
+/// Retrieve all device info, typed where possible with the flat map alongside,
+/// and the lockdown values as they came for browsing as a tree
+pub async fn get_device_info(
+    pool: &SharedPool,
+    udid: &str,
+    flatten: FlattenMode,
+) -> Result<(DeviceInfo, Value), Box<dyn std::error::Error>> {
+    let mut lockdown = checkout_lockdown(pool, udid).await?;
+    let dict = Value::Dictionary(lockdown.get_all_values().await?);
+    let mut raw = HashMap::new();
+    extract_values("", &dict, flatten, &mut raw);
+    let mut data = HashMap::new();
+    extract_data("", &dict, flatten, &mut data);
     if let Ok(value) = lockdown.get_value("ProductVersion", None).await {
-        info.insert("ProductVersion".to_string(), process_value(&value));
+        raw.insert("ProductVersion".to_string(), process_value(&value));
     }
     if let Ok(device_type) = lockdown.idevice.get_type().await {
-        info.insert("DeviceType".to_string(), device_type);
+        raw.insert("DeviceType".to_string(), device_type);
+    }
+    let battery = match lockdown.battery_info().await {
+        Ok(battery) => Some(battery),
+        Err(IdeviceError::BatteryInfoUnavailable) => None,
+        Err(e) => {
+            warn!(error = %e, "reading battery info failed");
+            None
+        }
+    };
+    let developer_mode = match lockdown.developer_mode_status().await {
+        Ok(status) => Some(status),
+        Err(e) => {
+            warn!(error = %e, "reading developer mode status failed");
+            None
+        }
+    };
+    pool.lock().unwrap().put_lockdown(udid, lockdown);
+
+    // A field with an unexpected type shouldn't cost us the whole dump
+    let mut info: DeviceInfo = plist::from_value(&dict).unwrap_or_default();
+    info.raw = raw;
+    info.data = data;
+    info.battery = battery;
+    info.developer_mode = developer_mode;
+    Ok((info, dict))
+}
+
+/// Ask `udid` to turn on Developer Mode. Returns `true` if it's restarting
+/// to do so; a device with a passcode refuses, so the setting is revealed
+/// for the user to turn on instead and `false` is returned.
+pub async fn request_developer_mode(udid: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+    let mut amfi = AmfiClient::connect(&provider).await?;
+    match amfi.enable_developer_mode().await {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            warn!(error = %e, "enabling developer mode failed, revealing the setting");
+            amfi.reveal_developer_mode_option_in_ui().await?;
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn device(udid: &str, device_id: u32, connection_type: UsbConnection) -> UsbmuxdDevice {
+        UsbmuxdDevice {
+            connection_type,
+            udid: udid.to_string(),
+            device_id,
+            connection_speed: Some(480_000_000),
+            interface_index: None,
+        }
+    }
+
+    #[test]
+    fn usb_and_wifi_duplicate_is_listed_once() {
+        let wifi = UsbConnection::Network(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)));
+        let devices = vec![
+            device("dup", 1, wifi.clone()),
+            device("usb-only", 2, UsbConnection::Usb),
+            device("dup", 3, UsbConnection::Usb),
+            device("wifi-only", 4, wifi),
+        ];
+        let listed = usb_devices(devices);
+        let udids: Vec<&str> = listed.iter().map(|(u, _)| u.as_str()).collect();
+        assert_eq!(udids, ["usb-only", "dup"]);
+        assert!(!listed[0].1.wifi);
+        assert!(listed[1].1.wifi);
+        assert_eq!(
+            listed[1].1.label().unwrap(),
+            "USB 2 (480 Mbit/s), also on Wi-Fi"
+        );
+    }
+
+    fn refused() -> Result<Value, Box<dyn std::error::Error>> {
+        Err(SessionFailed(Arc::new(IdeviceError::InvalidHostID)).into())
+    }
+
+    /// Runs `without_session_if_refused`, returning its result and whether
+    /// it asked for the value without a session
+    async fn fallback(
+        key: &str,
+        res: Result<Value, Box<dyn std::error::Error>>,
+    ) -> (Result<Value, String>, bool) {
+        let asked = std::cell::Cell::new(false);
+        let sessionless = || {
+            asked.set(true);
+            async { Ok::<_, Box<dyn std::error::Error>>(Value::String("sessionless".into())) }
+        };
+        let res = without_session_if_refused(key, res, sessionless).await;
+        (res.map_err(|e| e.to_string()), asked.get())
+    }
+
+    #[tokio::test]
+    async fn refused_session_still_reads_sessionless_keys() {
+        let (res, asked) = fallback("DeviceName", refused()).await;
+        assert!(asked);
+        assert_eq!(res.unwrap(), Value::String("sessionless".into()));
+    }
+
+    #[tokio::test]
+    async fn refused_session_is_reported_for_other_keys() {
+        let (res, asked) = fallback("WiFiAddress", refused()).await;
+        assert!(!asked);
+        assert!(res.unwrap_err().contains("re-pair may be required"));
+    }
+
+    #[tokio::test]
+    async fn session_values_and_other_errors_pass_through() {
+        let (res, asked) = fallback("DeviceName", Ok(Value::String("phone".into()))).await;
+        assert!(!asked);
+        assert_eq!(res.unwrap(), Value::String("phone".into()));
+
+        let (res, asked) =
+            fallback("DeviceName", Err(IdeviceError::UnexpectedResponse.into())).await;
+        assert!(!asked);
+        assert!(res.is_err());
     }
-    Ok(info)
 }
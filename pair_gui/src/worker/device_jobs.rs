@@ -0,0 +1,136 @@
+// src/worker/device_jobs.rs
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crossbeam::channel::Sender;
+use tokio::task::AbortHandle;
+use tracing::Instrument;
+
+use crate::{
+    types::{GuiEvent, Operation},
+    worker::device_locks::DeviceLocks,
+};
+
+/// A job's device, the operation the GUI is waiting on, if any, and the
+/// handle that cancels it.
+type RunningJob = (String, Option<Operation>, AbortHandle);
+
+/// Runs AFC and install work on its own task, one job per device at a time
+/// through `devices`, and keeps the handles so the jobs of a device that
+/// goes away can be stopped instead of waiting on a dead connection.
+///
+/// Cloning shares the same jobs.
+#[derive(Clone)]
+pub struct DeviceJobs {
+    next_id: Arc<AtomicU64>,
+    devices: DeviceLocks,
+    tx: Sender<GuiEvent>,
+    jobs: Arc<Mutex<HashMap<u64, RunningJob>>>,
+}
+
+impl DeviceJobs {
+    pub fn new(devices: DeviceLocks, tx: Sender<GuiEvent>) -> Self {
+        Self {
+            next_id: Arc::new(AtomicU64::new(1)),
+            devices,
+            tx,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Run `job` once no other work holds `udid`. `op`, if given, is
+    /// reported `Finished` once the job ends, however it ends.
+    pub fn spawn(
+        &self,
+        udid: &str,
+        op: Option<Operation>,
+        job: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (devices, udid) = (self.devices.clone(), udid.to_string());
+        let job_udid = udid.clone();
+        let jobs_done = self.jobs.clone();
+        let tx = self.tx.clone();
+        // Held across the spawn so a quick job can't remove itself before it
+        // has been added
+        let mut jobs = self.jobs.lock().unwrap();
+        let handle = tokio::spawn(
+            async move {
+                let _device = devices.lock(&udid).await;
+                job.await;
+                if jobs_done.lock().unwrap().remove(&id).is_none() {
+                    // Cancelled after the job finished but before this ran;
+                    // `cancel_device` has already reported it
+                    return;
+                }
+                if let Some(op) = op {
+                    let _ = tx.send(GuiEvent::Finished(op));
+                }
+            }
+            .in_current_span(),
+        );
+        jobs.insert(id, (job_udid, op, handle.abort_handle()));
+    }
+
+    /// Stop every job on `udid`, running or waiting for the device. Returns
+    /// how many were stopped.
+    pub fn cancel_device(&self, udid: &str) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        let ids: Vec<u64> = jobs
+            .iter()
+            .filter(|(_, (device, _, _))| device == udid)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            let Some((_, op, handle)) = jobs.remove(id) else {
+                continue;
+            };
+            handle.abort();
+            if let Some(op) = op {
+                let _ = self.tx.send(GuiEvent::Finished(op));
+            }
+        }
+        ids.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crossbeam::channel::unbounded;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_device_stops_only_that_device() {
+        let (tx, rx) = unbounded();
+        let jobs = DeviceJobs::new(DeviceLocks::default(), tx);
+        let (done_tx, done_rx) = unbounded();
+        jobs.spawn("device-a", Some(Operation::Install), std::future::pending());
+        jobs.spawn("device-b", None, async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let _ = done_tx.send("device-b");
+        });
+
+        assert_eq!(jobs.cancel_device("device-a"), 1);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(GuiEvent::Finished(Operation::Install))
+        ));
+        assert_eq!(jobs.cancel_device("device-a"), 0);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(done_rx.try_recv(), Ok("device-b"));
+        assert!(
+            rx.try_recv().is_err(),
+            "a job without an operation reported one"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+// src/worker/device_locks.rs
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// One async lock per device, so AFC work on a device runs one operation at
+/// a time while other devices carry on in parallel.
+///
+/// Cloning shares the same locks.
+#[derive(Clone, Default)]
+pub struct DeviceLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+impl DeviceLocks {
+    /// Wait until nothing else holds `udid`, then hold it until the guard drops.
+    /// Waiters get the device in the order they asked.
+    pub async fn lock(&self, udid: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(udid.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn other_devices_are_not_blocked() {
+        let locks = DeviceLocks::default();
+        let _a = locks.lock("device-a").await;
+        let b = tokio::time::timeout(Duration::from_millis(100), locks.lock("device-b")).await;
+        assert!(b.is_ok(), "device-b waited on device-a");
+    }
+
+    #[tokio::test]
+    async fn same_device_waits_its_turn() {
+        let locks = DeviceLocks::default();
+        let first = locks.lock("device-a").await;
+
+        let (tx, mut rx) = oneshot::channel();
+        let waiting = locks.clone();
+        let second = tokio::spawn(async move {
+            let _guard = waiting.lock("device-a").await;
+            let _ = tx.send(());
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(
+            rx.try_recv().is_err(),
+            "second operation ran while the first held the device"
+        );
+
+        // Device B is free the whole time
+        drop(locks.lock("device-b").await);
+
+        drop(first);
+        second.await.unwrap();
+        assert!(rx.await.is_ok());
+    }
+}
@@ -0,0 +1,82 @@
+// src/worker/install.rs
+use std::path::Path;
+
+use crossbeam::channel::Sender;
+use idevice::{
+    afc::{AfcClient, AfcScope},
+    installation_proxy::InstallationProxyClient,
+    IdeviceService,
+};
+
+use crate::{
+    types::{GuiEvent, InstallPhase},
+    worker::common::get_provider,
+};
+
+/// Where installation_proxy expects packages, relative to the AFC media root.
+const STAGING_DIR: &str = "/PublicStaging";
+
+fn send_progress(tx: &Sender<GuiEvent>, phase: InstallPhase, percent: u8) {
+    let _ = tx.send(GuiEvent::InstallProgress { phase, percent });
+}
+
+/// Stage `ipa` over AFC and install it, reporting each phase's progress.
+///
+/// If the install fails after the upload succeeded, the staged package is
+/// removed so it doesn't sit in the media partition.
+pub async fn install_app(
+    udid: &str,
+    ipa: &Path,
+    chunk_size: usize,
+    tx: &Sender<GuiEvent>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = ipa
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or("not a file")?;
+    let local = tokio::fs::File::open(ipa).await?;
+    let len = local.metadata().await?.len();
+    let provider = get_provider(udid, None).await?;
+
+    let mut afc_client = AfcClient::connect_scoped(&provider, AfcScope::Media).await?;
+    if !afc_client.is_dir(STAGING_DIR).await? {
+        afc_client.mk_dir(STAGING_DIR).await?;
+    }
+    let staged = format!("{STAGING_DIR}/{name}");
+    send_progress(tx, InstallPhase::Upload, 0);
+    afc_client.set_chunk_size(chunk_size);
+    let mut last = 0;
+    afc_client
+        .upload_reader(staged.as_str(), local, len, |sent, total| {
+            let percent = (sent * 100 / total.max(1)) as u8;
+            if percent != last {
+                send_progress(tx, InstallPhase::Upload, percent);
+                last = percent;
+            }
+        })
+        .await?;
+    send_progress(tx, InstallPhase::Upload, 100);
+
+    send_progress(tx, InstallPhase::Install, 0);
+    let res = async {
+        let mut instproxy = InstallationProxyClient::connect(&provider).await?;
+        instproxy
+            .install_with_callback(
+                staged.as_str(),
+                None,
+                |(percent, tx): (u64, Sender<GuiEvent>)| async move {
+                    send_progress(&tx, InstallPhase::Install, percent.min(100) as u8);
+                },
+                tx.clone(),
+            )
+            .await
+    }
+    .await;
+    if let Err(e) = res {
+        // Best effort: the install error is the one worth reporting
+        let _ = afc_client.remove(staged.as_str()).await;
+        return Err(e.into());
+    }
+    send_progress(tx, InstallPhase::Install, 100);
+    Ok(())
+}
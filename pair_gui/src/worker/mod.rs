@@ -1,5 +1,15 @@
 // src/worker/mod.rs
+pub mod afc;
+pub mod apps;
+pub mod common;
+pub mod crash;
 pub mod device;
+pub mod device_jobs;
+pub mod device_locks;
+pub mod install;
+pub mod pool;
+pub mod profiles;
+pub mod selftest;
+pub mod transcript;
+pub mod transfers;
 pub mod worker_loop;
-pub mod afc;
-pub mod afc;
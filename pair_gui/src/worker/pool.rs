@@ -0,0 +1,178 @@
+// src/worker/pool.rs
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use idevice::{afc::AfcClient, lockdown::LockdownClient, IdeviceService};
+
+use crate::worker::device::SessionFailed;
+
+/// Idle clients older than this are dropped on the next eviction pass.
+pub const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pool key for lockdown clients without a session, kept apart from the
+/// session ones so neither is handed out as the other.
+const SESSIONLESS_LOCKDOWN: &str = "com.apple.mobile.lockdown (no session)";
+
+/// Pool shared between the worker's command handlers.
+pub type SharedPool = Arc<Mutex<ConnectionPool>>;
+
+/// A cached service client.
+pub enum PooledClient {
+    Lockdown(LockdownClient),
+    Afc(AfcClient),
+}
+
+struct Entry {
+    client: PooledClient,
+    last_used: Instant,
+}
+
+/// Caches live service clients per `(udid, service_name)`.
+///
+/// Clients are checked out with `take_*` and handed back with `put_*` once an
+/// operation succeeded, so the lock is never held across device I/O. A client
+/// whose operation failed is simply dropped instead of being returned.
+pub struct ConnectionPool {
+    entries: HashMap<(String, String), Entry>,
+    /// Devices that refused a session with their pairing record, so reads
+    /// don't ask again until the device reconnects or is paired again
+    refused: HashMap<String, SessionFailed>,
+    idle_timeout: Duration,
+    hits: u64,
+    misses: u64,
+}
+
+impl ConnectionPool {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            refused: HashMap::new(),
+            idle_timeout,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn shared(idle_timeout: Duration) -> SharedPool {
+        Arc::new(Mutex::new(Self::new(idle_timeout)))
+    }
+
+    fn take(&mut self, udid: &str, service: &str) -> Option<PooledClient> {
+        let key = (udid.to_string(), service.to_string());
+        match self.entries.remove(&key) {
+            Some(entry) if entry.last_used.elapsed() <= self.idle_timeout => {
+                self.hits += 1;
+                Some(entry.client)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn put(&mut self, udid: &str, service: &str, client: PooledClient) {
+        self.entries.insert(
+            (udid.to_string(), service.to_string()),
+            Entry {
+                client,
+                last_used: Instant::now(),
+            },
+        );
+    }
+
+    pub fn take_lockdown(&mut self, udid: &str) -> Option<LockdownClient> {
+        match self.take(udid, LockdownClient::service_name()) {
+            Some(PooledClient::Lockdown(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn put_lockdown(&mut self, udid: &str, client: LockdownClient) {
+        self.put(
+            udid,
+            LockdownClient::service_name(),
+            PooledClient::Lockdown(client),
+        );
+    }
+
+    pub fn take_sessionless_lockdown(&mut self, udid: &str) -> Option<LockdownClient> {
+        match self.take(udid, SESSIONLESS_LOCKDOWN) {
+            Some(PooledClient::Lockdown(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn put_sessionless_lockdown(&mut self, udid: &str, client: LockdownClient) {
+        self.put(udid, SESSIONLESS_LOCKDOWN, PooledClient::Lockdown(client));
+    }
+
+    /// Remember that `udid` refused a session with its pairing record.
+    pub fn mark_session_refused(&mut self, udid: &str, refusal: SessionFailed) {
+        self.refused.insert(udid.to_string(), refusal);
+    }
+
+    /// Forget a refusal once `udid` has accepted a session after all.
+    pub fn forget_session_refusal(&mut self, udid: &str) {
+        self.refused.remove(udid);
+    }
+
+    /// The refusal `udid` last gave, if it hasn't reconnected or been paired since.
+    pub fn session_refusal(&self, udid: &str) -> Option<SessionFailed> {
+        self.refused.get(udid).cloned()
+    }
+
+    /// `service` distinguishes afc/afc2/house_arrest vends for the same device.
+    pub fn take_afc(&mut self, udid: &str, service: &str) -> Option<AfcClient> {
+        match self.take(udid, service) {
+            Some(PooledClient::Afc(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn put_afc(&mut self, udid: &str, service: &str, client: AfcClient) {
+        self.put(udid, service, PooledClient::Afc(client));
+    }
+
+    /// Drop every cached client for `udid`, and forget a refused session.
+    pub fn remove_device(&mut self, udid: &str) {
+        self.entries.retain(|(u, _), _| u != udid);
+        self.refused.remove(udid);
+    }
+
+    /// Drop every cached AFC client for `udid`, keeping its lockdown session.
+    pub fn remove_afc(&mut self, udid: &str) {
+        self.entries
+            .retain(|(u, _), e| u != udid || !matches!(e.client, PooledClient::Afc(_)));
+    }
+
+    /// Drop clients for devices that are no longer connected.
+    pub fn retain_devices(&mut self, udids: &[String]) {
+        self.entries.retain(|(u, _), _| udids.contains(u));
+        self.refused.retain(|u, _| udids.contains(u));
+    }
+
+    /// Drop clients that have been idle longer than the timeout.
+    pub fn evict_idle(&mut self) {
+        let timeout = self.idle_timeout;
+        self.entries.retain(|_, e| e.last_used.elapsed() <= timeout);
+    }
+
+    /// Fraction of checkouts served from the cache, in `0.0..=1.0`.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+
+    pub fn stats(&self) -> (u64, u64, usize) {
+        (self.hits, self.misses, self.entries.len())
+    }
+}
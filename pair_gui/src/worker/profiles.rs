@@ -0,0 +1,42 @@
+// src/worker/profiles.rs
+use std::path::Path;
+
+use idevice::{
+    mobileconfig::{validate_profile, MobileConfigClient, ProfileInfo, ProfileInstall},
+    IdeviceService,
+};
+
+use crate::worker::common::get_provider;
+
+/// Configuration profiles installed on `udid`.
+pub async fn list_profiles(udid: &str) -> Result<Vec<ProfileInfo>, Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+    let mut client = MobileConfigClient::connect(&provider).await?;
+    Ok(client.get_profile_list().await?)
+}
+
+/// Remove the profile `identifier` from `udid` and return what's left.
+pub async fn remove_profile(
+    udid: &str,
+    identifier: &str,
+) -> Result<Vec<ProfileInfo>, Box<dyn std::error::Error>> {
+    let provider = get_provider(udid, None).await?;
+    let mut client = MobileConfigClient::connect(&provider).await?;
+    client.remove_profile(identifier).await?;
+    Ok(client.get_profile_list().await?)
+}
+
+/// Install the `.mobileconfig` at `path` on `udid`. Returns how the device
+/// took it and the profiles installed afterwards.
+pub async fn install_profile(
+    udid: &str,
+    path: &Path,
+) -> Result<(ProfileInstall, Vec<ProfileInfo>), Box<dyn std::error::Error>> {
+    let data = std::fs::read(path)?;
+    // Checked before connecting so a wrong file fails fast
+    validate_profile(&data)?;
+    let provider = get_provider(udid, None).await?;
+    let mut client = MobileConfigClient::connect(&provider).await?;
+    let outcome = client.install_profile(&data).await?;
+    Ok((outcome, client.get_profile_list().await?))
+}
@@ -0,0 +1,58 @@
+// src/worker/selftest.rs
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+
+use crossbeam::channel::Sender;
+use idevice::tunneld::{get_tunneld_devices, DEFAULT_PORT};
+
+use crate::types::{GuiEvent, SelfTestCheck, SelfTestReport};
+use crate::util::ensure_writable_dir;
+use crate::worker::device::{get_device_summary, probe_trust, scan_devices};
+use crate::worker::pool::SharedPool;
+
+/// Check what pairing and file transfers depend on and list the devices
+/// found, for attaching to a bug report.
+///
+/// Every check runs even if an earlier one fails.
+pub async fn run_self_test(
+    pool: &SharedPool,
+    tx: &Sender<GuiEvent>,
+    output_dir: &Path,
+) -> SelfTestReport {
+    let mut checks = Vec::new();
+
+    let devices = scan_devices(tx).await;
+    checks.push(SelfTestCheck {
+        name: "usbmuxd reachable",
+        result: match &devices {
+            Ok(devices) => Ok(format!("{} USB device(s)", devices.len())),
+            Err(e) => Err(e.to_string()),
+        },
+    });
+
+    let tunneld = SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_PORT));
+    checks.push(SelfTestCheck {
+        name: "tunneld reachable",
+        result: match get_tunneld_devices(tunneld).await {
+            Ok(devices) => Ok(format!("{} tunneled device(s) at {tunneld}", devices.len())),
+            Err(e) => Err(format!("{tunneld}: {e}")),
+        },
+    });
+
+    checks.push(SelfTestCheck {
+        name: "Save directory writable",
+        result: ensure_writable_dir(output_dir).map(|()| output_dir.display().to_string()),
+    });
+
+    let mut report_devices = Vec::new();
+    for (udid, _) in devices.unwrap_or_default() {
+        let display = get_device_summary(pool, &udid).await;
+        let trust = probe_trust(pool, &udid).await;
+        report_devices.push((udid, display, trust));
+    }
+
+    SelfTestReport {
+        checks,
+        devices: report_devices,
+    }
+}
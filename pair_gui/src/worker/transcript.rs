@@ -0,0 +1,193 @@
+// src/worker/transcript.rs
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crossbeam::channel::{unbounded, Sender};
+use tracing::warn;
+
+use crate::prefs::TRANSCRIPT_FILE;
+
+/// Size at which the transcript is rotated.
+const TRANSCRIPT_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Rotated transcripts kept as `afc-transcript.1.log` (newest) and up.
+const TRANSCRIPT_KEEP: usize = 5;
+
+/// Lines for the thread writing the transcript; `None` while the setting is
+/// off. The files are written on their own thread so operations finishing on
+/// the worker's tasks never wait on the disk.
+static WRITER: Mutex<Option<Sender<String>>> = Mutex::new(None);
+
+/// Start writing the transcript into `dir`, or stop with `None`. Lines
+/// already recorded are still written to the directory they were meant for.
+pub fn set_transcript_dir(dir: Option<PathBuf>) {
+    let writer = dir.map(|dir| {
+        let (tx, rx) = unbounded::<String>();
+        std::thread::spawn(move || {
+            let mut transcript = Transcript { dir, file: None };
+            // Ends once the sender is replaced or dropped
+            for line in rx {
+                if let Err(e) = transcript.append(&line) {
+                    warn!(dir = %transcript.dir.display(), error = %e, "writing the AFC transcript failed");
+                    // Opened again next time, in case the directory came back
+                    transcript.file = None;
+                }
+            }
+        });
+        tx
+    });
+    *WRITER.lock().unwrap() = writer;
+}
+
+/// Add a line for an AFC operation on `udid` that just finished, if the
+/// transcript is on. `result` is what the user was told about it.
+pub fn record(udid: &str, op: &str, path: &str, result: Result<&str, &str>) {
+    let writer = WRITER.lock().unwrap();
+    let Some(writer) = writer.as_ref() else {
+        return;
+    };
+    let stamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z");
+    let _ = writer.send(transcript_line(&stamp.to_string(), udid, op, path, result));
+}
+
+/// One tab-separated transcript line. Paths and messages are escaped so
+/// each operation stays on its own line.
+fn transcript_line(
+    stamp: &str,
+    udid: &str,
+    op: &str,
+    path: &str,
+    result: Result<&str, &str>,
+) -> String {
+    let (status, detail) = match result {
+        Ok(detail) => ("ok", detail),
+        Err(e) => ("error", e),
+    };
+    format!(
+        "{stamp}\t{udid}\t{op}\t{}\t{status}\t{}\n",
+        path.escape_debug(),
+        detail.escape_debug()
+    )
+}
+
+/// The transcript file in `dir`, rotated by size.
+struct Transcript {
+    dir: PathBuf,
+    file: Option<File>,
+}
+
+impl Transcript {
+    fn append(&mut self, line: &str) -> std::io::Result<()> {
+        let path = self.dir.join(TRANSCRIPT_FILE);
+        let len = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if len > 0 && len + line.len() as u64 > TRANSCRIPT_MAX_BYTES {
+            self.file = None;
+            rotate(&self.dir)?;
+        }
+        if self.file.is_none() {
+            std::fs::create_dir_all(&self.dir)?;
+            self.file = Some(OpenOptions::new().create(true).append(true).open(&path)?);
+        }
+        let file = self.file.as_mut().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+}
+
+/// `afc-transcript.N.log`, or the live file for 0.
+fn rotated_path(dir: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        dir.join(TRANSCRIPT_FILE)
+    } else {
+        dir.join(format!("afc-transcript.{n}.log"))
+    }
+}
+
+/// Shift each transcript in `dir` up one number, dropping the oldest, so the
+/// live file is free for new lines.
+fn rotate(dir: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(rotated_path(dir, TRANSCRIPT_KEEP)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for n in (0..TRANSCRIPT_KEEP).rev() {
+        let from = rotated_path(dir, n);
+        if from.exists() {
+            std::fs::rename(&from, rotated_path(dir, n + 1))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    fn scratch_dir() -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!(
+            "pair-gui-transcript-{}-{nanos}",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn lines_stay_on_one_line() {
+        let line = transcript_line("T", "UDID", "rename", "/a\tb\nc", Err("no\nspace"));
+        assert_eq!(line, "T\tUDID\trename\t/a\\tb\\nc\terror\tno\\nspace\n");
+        let line = transcript_line("T", "UDID", "mkdir", "/Café 📷", Ok("created"));
+        assert_eq!(line, "T\tUDID\tmkdir\t/Café 📷\tok\tcreated\n");
+    }
+
+    #[test]
+    fn full_transcripts_rotate() {
+        let dir = scratch_dir();
+        let mut transcript = Transcript {
+            dir: dir.clone(),
+            file: None,
+        };
+        let line = format!("{}\n", "x".repeat(1023));
+        // Two and a half files' worth
+        for _ in 0..(TRANSCRIPT_MAX_BYTES as usize / line.len()) * 5 / 2 {
+            transcript.append(&line).unwrap();
+        }
+        let size = |n| {
+            std::fs::metadata(rotated_path(&dir, n))
+                .map(|m| m.len())
+                .ok()
+        };
+        assert_eq!(size(1), Some(TRANSCRIPT_MAX_BYTES));
+        assert_eq!(size(2), Some(TRANSCRIPT_MAX_BYTES));
+        assert_eq!(size(0), Some(TRANSCRIPT_MAX_BYTES / 2));
+        assert_eq!(size(3), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_drops_the_oldest() {
+        let dir = scratch_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        for n in 0..=TRANSCRIPT_KEEP {
+            std::fs::write(rotated_path(&dir, n), n.to_string()).unwrap();
+        }
+        rotate(&dir).unwrap();
+        assert!(!rotated_path(&dir, 0).exists());
+        for n in 1..=TRANSCRIPT_KEEP {
+            assert_eq!(
+                std::fs::read_to_string(rotated_path(&dir, n)).unwrap(),
+                (n - 1).to_string()
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
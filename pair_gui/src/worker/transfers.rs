@@ -0,0 +1,188 @@
+// src/worker/transfers.rs
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crossbeam::channel::Sender;
+use tokio::{sync::Semaphore, task::AbortHandle};
+use tracing::Instrument;
+
+use crate::{
+    types::{GuiEvent, Operation, TransferId, TransferState},
+    worker::device_locks::DeviceLocks,
+};
+
+/// Transfers allowed to run at once until the GUI sends its preference.
+pub const DEFAULT_MAX_TRANSFERS: usize = 2;
+
+/// A transfer's device, what it is doing, and the handle that cancels it.
+type RunningTransfer = (String, Operation, AbortHandle);
+
+/// Runs uploads and downloads on their own tasks, at most `limit` at a time,
+/// so the worker can keep handling commands while they're in flight.
+///
+/// Transfers on the same device wait for each other through `devices`;
+/// transfers on different devices only share the slots. Clones share the
+/// same queue, so a device job can start a transfer of its own.
+#[derive(Clone)]
+pub struct TransferQueue {
+    next_id: Arc<AtomicU64>,
+    limit: Arc<Mutex<usize>>,
+    slots: Arc<Semaphore>,
+    devices: DeviceLocks,
+    /// Queued and running transfers with their device, for cancelling them.
+    tasks: Arc<Mutex<HashMap<TransferId, RunningTransfer>>>,
+}
+
+impl TransferQueue {
+    pub fn new(limit: usize, devices: DeviceLocks) -> Self {
+        let limit = limit.max(1);
+        Self {
+            next_id: Arc::new(AtomicU64::new(1)),
+            limit: Arc::new(Mutex::new(limit)),
+            slots: Arc::new(Semaphore::new(limit)),
+            devices,
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Change how many transfers may run at once. Running transfers are left
+    /// alone; lowering the limit takes effect as they finish.
+    pub fn set_limit(&self, limit: usize) {
+        let limit = limit.max(1);
+        let mut current = self.limit.lock().unwrap();
+        if limit > *current {
+            self.slots.add_permits(limit - *current);
+        } else if limit < *current {
+            let slots = self.slots.clone();
+            let surplus = (*current - limit) as u32;
+            tokio::spawn(async move {
+                if let Ok(permits) = slots.acquire_many_owned(surplus).await {
+                    permits.forget();
+                }
+            });
+        }
+        *current = limit;
+    }
+
+    /// Queue `job` and tell the GUI about it. `job` is given the transfer's
+    /// ID for its progress events and returns a line for the Transfers panel.
+    ///
+    /// `op` is reported `Finished` once the transfer ends, however it ends.
+    pub fn spawn<F>(
+        &self,
+        tx: &Sender<GuiEvent>,
+        udid: &str,
+        label: String,
+        op: Operation,
+        job: impl FnOnce(TransferId) -> F,
+    ) where
+        F: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let _ = tx.send(GuiEvent::TransferQueued {
+            id,
+            udid: udid.to_string(),
+            label,
+        });
+
+        let job = job(id);
+        let (devices, udid) = (self.devices.clone(), udid.to_string());
+        let task_udid = udid.clone();
+        let slots = self.slots.clone();
+        let tasks_done = self.tasks.clone();
+        let tx = tx.clone();
+        // Held across the spawn so a quick transfer can't remove itself
+        // before it has been added
+        let mut tasks = self.tasks.lock().unwrap();
+        let handle = tokio::spawn(
+            async move {
+                // The device first, so a transfer stuck behind another on
+                // its device doesn't hold a slot another device could use
+                let _device = devices.lock(&udid).await;
+                let _slot = slots.acquire_owned().await;
+                send_update(&tx, id, TransferState::Active);
+                let state = match job.await {
+                    Ok(message) => TransferState::Done(message),
+                    Err(e) => TransferState::Failed(e),
+                };
+                if tasks_done.lock().unwrap().remove(&id).is_none() {
+                    // Cancelled after the job finished but before this ran;
+                    // `cancel` has already reported it
+                    return;
+                }
+                send_update(&tx, id, state);
+                let _ = tx.send(GuiEvent::Finished(op));
+            }
+            .in_current_span(),
+        );
+        tasks.insert(id, (task_udid, op, handle.abort_handle()));
+    }
+
+    /// Stop transfer `id` whether it's running or still queued. A transfer
+    /// that already ended is left alone.
+    pub fn cancel(&self, tx: &Sender<GuiEvent>, id: TransferId) {
+        let Some((_, op, handle)) = self.tasks.lock().unwrap().remove(&id) else {
+            return;
+        };
+        // Dropping the task drops its AFC client mid-transfer, so it isn't
+        // returned to the pool in an unknown state
+        handle.abort();
+        send_update(tx, id, TransferState::Cancelled);
+        let _ = tx.send(GuiEvent::Finished(op));
+    }
+
+    /// Stop every transfer on `udid`, which has gone away, instead of leaving
+    /// them to wait on a dead connection. Returns how many were stopped.
+    pub fn cancel_device(&self, tx: &Sender<GuiEvent>, udid: &str) -> usize {
+        let mut tasks = self.tasks.lock().unwrap();
+        let ids: Vec<TransferId> = tasks
+            .iter()
+            .filter(|(_, (device, _, _))| device == udid)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            let Some((_, op, handle)) = tasks.remove(id) else {
+                continue;
+            };
+            handle.abort();
+            send_update(tx, *id, TransferState::Failed("device disconnected".into()));
+            let _ = tx.send(GuiEvent::Finished(op));
+        }
+        ids.len()
+    }
+}
+
+fn send_update(tx: &Sender<GuiEvent>, id: TransferId, state: TransferState) {
+    let _ = tx.send(GuiEvent::TransferUpdate { id, state });
+}
+
+/// Progress callback for transfer `id` that sends `AfcProgress` at most once
+/// per percent, so a large file doesn't flood the GUI with events.
+pub fn progress_reporter(
+    tx: &Sender<GuiEvent>,
+    udid: &str,
+    id: TransferId,
+    path: &str,
+) -> impl FnMut(u64, u64) + Send + 'static {
+    let (tx, udid, path) = (tx.clone(), udid.to_string(), path.to_string());
+    let mut last_percent = None;
+    move |done, total| {
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        if last_percent != Some(percent) {
+            last_percent = Some(percent);
+            let _ = tx.send(GuiEvent::AfcProgress {
+                udid: udid.clone(),
+                transfer: Some(id),
+                done,
+                total,
+                path: path.clone(),
+            });
+        }
+    }
+}
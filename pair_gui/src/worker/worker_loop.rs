@@ -1,77 +1,1116 @@
 use crate::{
-    types::{Command, GuiEvent},
-    worker::{device::*, afc::list_files},
+    prefs::FlattenMode,
+    types::{
+        AfcOutcome, AfcSource, Command, GuiEvent, Operation, PairOutcome, TrustState, UsbLink,
+    },
+    util::reveal_in_file_browser,
+    worker::{
+        afc::{
+            delete_path, download_file, download_zip, duplicate_file, is_remote_dir, list_files,
+            make_dir, preview_file, probe_afc2, read_range, rename_path, save_text, upload_file,
+            upload_from_url, AppUnavailable, SaveConflict, PREVIEW_MAX_BYTES,
+        },
+        apps::list_installed_apps,
+        common::{check_usbmuxd, is_connection_error, set_client_label, set_usbmuxd_socket},
+        crash::{download_crash_reports, list_crash_reports},
+        device::*,
+        device_jobs::DeviceJobs,
+        device_locks::DeviceLocks,
+        install::install_app,
+        pool::{ConnectionPool, SharedPool, POOL_IDLE_TIMEOUT},
+        profiles::{install_profile, list_profiles, remove_profile},
+        selftest::run_self_test,
+        transcript::{self, set_transcript_dir},
+        transfers::{progress_reporter, TransferQueue, DEFAULT_MAX_TRANSFERS},
+    },
 };
 use crossbeam::channel::{Receiver, Sender};
+use futures::stream::{self, StreamExt};
+use idevice::{afc::DEFAULT_CHUNK_SIZE, mobileconfig::ProfileInstall, IdeviceError};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+
+/// Entries per `AfcListBatch`, so huge directories reach the GUI in pieces.
+const LISTING_BATCH: usize = 2000;
+
+/// Devices queried at once during a refresh.
+const REFRESH_CONCURRENCY: usize = 4;
+
+/// Devices paired at once by `PairAll`.
+const PAIR_CONCURRENCY: usize = 4;
 
 pub async fn worker_loop(rx: Receiver<Command>, tx: Sender<GuiEvent>) {
+    let pool = ConnectionPool::shared(POOL_IDLE_TIMEOUT);
+    let mut trust_cache = HashMap::new();
+    let mut chunk_size = DEFAULT_CHUNK_SIZE;
+    let mut flatten_mode = FlattenMode::default();
+    let devices = DeviceLocks::default();
+    let transfers = TransferQueue::new(DEFAULT_MAX_TRANSFERS, devices.clone());
+    let jobs = DeviceJobs::new(devices, tx.clone());
+    let mut first_scan = true;
+    // Devices seen by the last scan, to notice the ones that go away
+    let mut connected: Vec<String> = Vec::new();
     loop {
-        match rx.recv() {
-            Ok(Command::Refresh) => {
-                let _ = tx.send(GuiEvent::Status("Refreshing...".into()));
-                let udids = match scan_devices().await {
-                    Ok(udids) => udids,
-                    Err(e) => {
-                        let _ = tx.send(GuiEvent::Status(format!("Error: {e}")));
-                        continue;
-                    }
-                };
-                for udid in &udids {
-                    if let Ok(info) = get_device_info(udid).await {
-                        let _ = tx.send(GuiEvent::DeviceInfo {
-                            udid: udid.clone(),
-                            info,
+        let cmd = match rx.recv() {
+            Ok(cmd) => cmd,
+            Err(_) => break,
+        };
+        let span = command_span(&cmd);
+        async {
+            match cmd {
+                Command::Refresh { probe_trust: reprobe } => {
+                    let status = if first_scan { "Scanning..." } else { "Refreshing..." };
+                    let _ = tx.send(GuiEvent::Status(status.into()));
+                    let devices = match scan_devices(&tx).await {
+                        Ok(devices) => devices,
+                        Err(e) => {
+                            warn!(error = %e, "device scan failed");
+                            let _ = tx.send(GuiEvent::Status(format!("Error: {e}")));
+                            return;
+                        }
+                    };
+                    let links: HashMap<String, UsbLink> = devices.iter().cloned().collect();
+                    let udids: Vec<String> = devices.into_iter().map(|(udid, _)| udid).collect();
+                    if first_scan {
+                        // Show the UDIDs right away; names replace them as they arrive
+                        let _ = tx.send(GuiEvent::Devices {
+                            list: udids.iter().map(|u| (u.clone(), u.clone())).collect(),
+                            links: links.clone(),
                         });
+                        first_scan = false;
+                    }
+                    {
+                        let mut pool = pool.lock().unwrap();
+                        pool.retain_devices(&udids);
+                        pool.evict_idle();
+                        trust_cache.retain(|udid, _| udids.contains(udid));
+                        let (hits, misses, live) = pool.stats();
+                        debug!(
+                            "connection pool: {hits} hits, {misses} misses ({:.0}% hit rate), {live} live",
+                            pool.hit_rate() * 100.0
+                        );
+                    }
+                    stop_gone_devices(&tx, &transfers, &jobs, &mut connected, &udids);
+                    // Only the cheap name/model lookups happen here; full info is
+                    // fetched on demand via GetDeviceInfo. Devices are handled
+                    // concurrently, a few at a time so usbmuxd isn't swamped.
+                    let cached: Vec<bool> = udids.iter().map(|u| trust_cache.contains_key(u)).collect();
+                    let mut results: Vec<(usize, String, Option<TrustState>)> = stream::iter(udids.iter().enumerate())
+                        .map(|(i, udid)| {
+                            let (pool, tx) = (&pool, &tx);
+                            let reprobe = reprobe || !cached[i];
+                            async move {
+                                // Names first: they're what the list is waiting on
+                                let display = get_device_summary(pool, udid).await;
+                                let _ = tx.send(GuiEvent::DeviceSummary {
+                                    udid: udid.clone(),
+                                    display: display.clone(),
+                                });
+                                let trust = if reprobe {
+                                    let state = probe_trust(pool, udid).await;
+                                    let _ = tx.send(GuiEvent::Trust {
+                                        udid: udid.clone(),
+                                        state,
+                                    });
+                                    Some(state)
+                                } else {
+                                    None
+                                };
+                                // Polled every refresh so the GUI notices an unlock
+                                if let Some(locked) = probe_lock(pool, udid).await {
+                                    let _ = tx.send(GuiEvent::LockState {
+                                        udid: udid.clone(),
+                                        locked,
+                                    });
+                                }
+                                (i, display, trust)
+                            }
+                        })
+                        .buffer_unordered(REFRESH_CONCURRENCY)
+                        .collect()
+                        .await;
+                    // Keep usbmuxd's ordering regardless of completion order
+                    results.sort_by_key(|(i, _, _)| *i);
+                    let mut list = Vec::with_capacity(results.len());
+                    for (i, display, trust) in results {
+                        if let Some(state) = trust {
+                            trust_cache.insert(udids[i].clone(), state);
+                        }
+                        list.push((udids[i].clone(), display));
                     }
+                    let _ = tx.send(GuiEvent::Devices { list, links });
                 }
-                let list = udids
-                    .iter()
-                    .map(|udid| (udid.clone(), udid.clone()))
-                    .collect();
-                let _ = tx.send(GuiEvent::Devices(list));
-            }
 
-            Ok(Command::Pair { udid, out_dir }) => {
-                let res = pair_one(&out_dir, &udid).await;
-                let _ = match res {
-                    Ok(_) => tx.send(GuiEvent::Status(format!("Paired {udid}"))),
-                    Err(e) => tx.send(GuiEvent::Status(format!("Pair error: {e}"))),
-                };
-            }
+                Command::CheckDevices => {
+                    // Quietly: this runs while the GUI holds back refreshes
+                    let udids: Vec<String> = match scan_devices(&tx).await {
+                        Ok(devices) => devices.into_iter().map(|(udid, _)| udid).collect(),
+                        Err(e) => {
+                            debug!(error = %e, "device check failed");
+                            return;
+                        }
+                    };
+                    pool.lock().unwrap().retain_devices(&udids);
+                    stop_gone_devices(&tx, &transfers, &jobs, &mut connected, &udids);
+                }
+
+                Command::Pair { udid, out_dir, enable_wifi, install_system } => {
+                    let msg = match pair_and_report(&pool, &tx, &out_dir, &udid, enable_wifi, install_system).await {
+                        PairOutcome::Paired { note } => {
+                            trust_cache.insert(udid.clone(), TrustState::Trusted);
+                            format!("Paired {udid}{note}")
+                        }
+                        PairOutcome::Locked => format!("Pair error: {udid} is locked, unlock it and try again"),
+                        PairOutcome::Failed(e) => format!("Pair error: {e}"),
+                    };
+                    let _ = tx.send(GuiEvent::Status(msg));
+                    let _ = tx.send(GuiEvent::Finished(Operation::Pair));
+                }
+
+                Command::PairAll { udids, out_dir, enable_wifi, install_system } => {
+                    let total = udids.len();
+                    let _ = tx.send(GuiEvent::Status(format!("Pairing {total} device(s)...")));
+                    let paired: Vec<String> = stream::iter(udids)
+                        .map(|udid| {
+                            let (pool, tx, out_dir) = (&pool, &tx, &out_dir);
+                            async move {
+                                let outcome =
+                                    pair_and_report(pool, tx, out_dir, &udid, enable_wifi, install_system).await;
+                                let ok = matches!(outcome, PairOutcome::Paired { .. });
+                                let _ = tx.send(GuiEvent::PairResult { udid: udid.clone(), outcome });
+                                ok.then_some(udid)
+                            }
+                        })
+                        .buffer_unordered(PAIR_CONCURRENCY)
+                        .filter_map(|udid| async move { udid })
+                        .collect()
+                        .await;
+                    let _ = tx.send(GuiEvent::Status(format!("Paired {} of {total} device(s)", paired.len())));
+                    for udid in paired {
+                        trust_cache.insert(udid, TrustState::Trusted);
+                    }
+                    let _ = tx.send(GuiEvent::Finished(Operation::Pair));
+                }
 
-            Ok(Command::GetDeviceInfo { udid }) => {
-                let res = get_device_info(&udid).await;
-                match res {
-                    Ok(info) => {
-                        let _ = tx.send(GuiEvent::DeviceInfo { udid, info });
+                Command::ImportPairingFile { udid, path, out_dir } => {
+                    match import_pairing_file(&pool, &out_dir, &udid, &path).await {
+                        Ok(saved) => {
+                            trust_cache.insert(udid.clone(), TrustState::Trusted);
+                            let _ = tx.send(GuiEvent::Trust {
+                                udid: udid.clone(),
+                                state: TrustState::Trusted,
+                            });
+                            let _ = tx.send(GuiEvent::Status(format!("Imported pairing file to {}", saved.display())));
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "pairing file import failed");
+                            let _ = tx.send(GuiEvent::Status(format!("Import error: {e}")));
+                        }
+                    }
+                }
+
+                Command::Unpair { udid, out_dir } => {
+                    match unpair_one(&pool, &out_dir, &udid).await {
+                        Ok(deleted) => {
+                            trust_cache.insert(udid.clone(), TrustState::Untrusted);
+                            let _ = tx.send(GuiEvent::Trust {
+                                udid: udid.clone(),
+                                state: TrustState::Untrusted,
+                            });
+                            let msg = match deleted {
+                                Some(path) => format!("Unpaired {udid}, deleted {}", path.display()),
+                                None => format!("Unpaired {udid}"),
+                            };
+                            let _ = tx.send(GuiEvent::Status(msg));
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "unpairing failed");
+                            let _ = tx.send(GuiEvent::Status(format!("Unpair error: {e}")));
+                        }
+                    }
+                }
+
+                Command::EnableDeveloperMode { udid } => match request_developer_mode(&udid).await {
+                    Ok(true) => {
+                        let _ = tx.send(GuiEvent::Status(format!(
+                            "{udid} is restarting to turn on Developer Mode. Once it's back, unlock it and \
+                             tap Turn On when asked."
+                        )));
+                    }
+                    Ok(false) => {
+                        let _ = tx.send(GuiEvent::Status(
+                            "The device won't turn on Developer Mode remotely while it has a passcode. Turn it \
+                             on in Settings > Privacy & Security > Developer Mode; the device restarts and asks \
+                             to confirm."
+                                .into(),
+                        ));
                     }
                     Err(e) => {
-                        let _ = tx.send(GuiEvent::Status(format!("Error: {e}")));
+                        warn!(error = %e, "requesting developer mode failed");
+                        let _ = tx.send(GuiEvent::Status(format!("Developer Mode error: {e}")));
+                    }
+                },
+
+                Command::GetDeviceInfo { udid } => {
+                    let res = get_device_info(&pool, &udid, flatten_mode).await;
+                    match res {
+                        Ok((info, value)) => {
+                            let _ = tx.send(GuiEvent::DeviceInfo { udid: udid.clone(), info });
+                            let _ = tx.send(GuiEvent::DeviceInfoTree { udid, value });
+                        }
+                        Err(e) => {
+                            let _ = tx.send(GuiEvent::Status(format!("Error: {e}")));
+                        }
                     }
                 }
-            }
 
-            Ok(Command::AfcList {
-                udid,
-                path,
-                container,
-                documents,
-            }) => {
-                let _ = tx.send(GuiEvent::Status(format!("Listing: {path}")));
-                match list_files(&udid, &path, container.as_deref(), documents.as_deref()).await {
-                    Ok(list) => {
-                        let output = list.join("\n");
+                Command::AfcList { udid, path, source } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        send_listing(&pool, &tx, &udid, path, &source).await;
+                    });
+                }
+
+                Command::AppDocuments { udid, bundle_id } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        send_listing(&pool, &tx, &udid, "/".into(), &AfcSource::Documents(bundle_id)).await;
+                    });
+                }
+
+                Command::AfcUpload {
+                    udid,
+                    local_path,
+                    remote_path,
+                    source,
+                    skip_space_check,
+                } => {
+                    let device = udid.clone();
+                    let label = format!("Upload {} to {remote_path}", local_path.display());
+                    let (pool, events) = (pool.clone(), tx.clone());
+                    transfers.spawn(&tx, &device, label, Operation::Upload, move |id| async move {
+                        let progress = progress_reporter(&events, &udid, id, &remote_path);
+                        let res = upload_file(
+                            &pool,
+                            &udid,
+                            &local_path,
+                            &remote_path,
+                            &source,
+                            chunk_size,
+                            skip_space_check,
+                            progress,
+                        )
+                        .await;
+                        let res = match res {
+                            Ok(len) => Ok(format!("Uploaded {len} bytes to {remote_path}")),
+                            Err(e) => {
+                                check_afc_connection(&events, &udid, &*e);
+                                send_afc_status(&events, &udid, format!("Upload error: {e}"));
+                                Err(e.to_string())
+                            }
+                        };
+                        transcript::record(&udid, "upload", &remote_path, res.as_deref().map_err(String::as_str));
+                        res
+                    });
+                }
+
+                Command::AfcUploadFromUrl { udid, url, remote, source } => {
+                    let device = udid.clone();
+                    let label = format!("Upload {url} to {remote}");
+                    let (pool, events) = (pool.clone(), tx.clone());
+                    transfers.spawn(&tx, &device, label, Operation::Upload, move |id| async move {
+                        let progress = progress_reporter(&events, &udid, id, &remote);
+                        let res = match upload_from_url(&pool, &udid, &url, &remote, &source, chunk_size, progress).await {
+                            Ok(len) => Ok(format!("Uploaded {len} bytes from {url} to {remote}")),
+                            Err(e) => {
+                                check_afc_connection(&events, &udid, &*e);
+                                send_afc_status(&events, &udid, format!("Upload error: {e}"));
+                                Err(e.to_string())
+                            }
+                        };
+                        transcript::record(&udid, "upload_url", &remote, res.as_deref().map_err(String::as_str));
+                        res
+                    });
+                }
+
+                Command::AfcDownload {
+                    udid,
+                    remote_path,
+                    source,
+                    out_dir,
+                    resume,
+                } => {
+                    queue_download(&transfers, &pool, &tx, udid, remote_path, source, out_dir, chunk_size, resume);
+                }
+
+                Command::AfcDownloadZip {
+                    udid,
+                    dir,
+                    names,
+                    source,
+                    out_dir,
+                    follow_symlinks,
+                } => {
+                    let device = udid.clone();
+                    let label = format!("Zip {} item(s) from {dir}", names.len());
+                    let (pool, events) = (pool.clone(), tx.clone());
+                    transfers.spawn(&tx, &device, label, Operation::Download, move |id| async move {
+                        let progress = progress_reporter(&events, &udid, id, &dir);
+                        let res = download_zip(
+                            &pool,
+                            &udid,
+                            &dir,
+                            &names,
+                            &source,
+                            &out_dir,
+                            chunk_size,
+                            follow_symlinks,
+                            progress,
+                        )
+                        .await;
+                        let res = match res {
+                            Ok((archive, skipped_links)) => {
+                                let mut message = format!("Saved archive to {}", archive.display());
+                                if !skipped_links.is_empty() {
+                                    info!(?skipped_links, "symbolic links left out of the archive");
+                                    message += &format!(", leaving out {} symbolic link(s)", skipped_links.len());
+                                }
+                                let _ = events.send(GuiEvent::AfcDownloaded {
+                                    udid: udid.clone(),
+                                    remote_path: dir.clone(),
+                                    local_path: archive,
+                                });
+                                Ok(message)
+                            }
+                            Err(e) => {
+                                check_afc_connection(&events, &udid, &*e);
+                                send_afc_status(&events, &udid, format!("Zip error: {e}"));
+                                Err(e.to_string())
+                            }
+                        };
+                        transcript::record(&udid, "download_zip", &dir, res.as_deref().map_err(String::as_str));
+                        res
+                    });
+                }
+
+                Command::AfcOpen {
+                    udid,
+                    path,
+                    source,
+                    out_dir,
+                    resume,
+                } => {
+                    let (device, pool, tx, transfers) = (udid.clone(), pool.clone(), tx.clone(), transfers.clone());
+                    jobs.spawn(&device, None, async move {
+                        let res = afc_result(&tx, &udid, is_remote_dir(&pool, &udid, &path, &source).await);
+                        match res {
+                            Ok(true) => send_listing(&pool, &tx, &udid, path, &source).await,
+                            // Queued behind this job, which holds the device until it returns
+                            Ok(false) => {
+                                queue_download(&transfers, &pool, &tx, udid, path, source, out_dir, chunk_size, resume)
+                            }
+                            Err(e) => send_afc_status(&tx, &udid, format!("AFC error: {e}")),
+                        }
+                    });
+                }
+
+                Command::AfcDuplicate { udid, path, source } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        send_afc_status(&tx, &udid, format!("Duplicating {path}..."));
+                        let res = afc_result(&tx, &udid, duplicate_file(&pool, &udid, &path, &source, chunk_size).await);
+                        match res {
+                            Ok(copy) => {
+                                transcript::record(&udid, "duplicate", &path, Ok(&format!("copied to {copy}")));
+                                send_afc_status(&tx, &udid, format!("Copied {path} to {copy}"));
+                                send_listing(&pool, &tx, &udid, parent_dir(&path), &source).await;
+                            }
+                            Err(e) => {
+                                transcript::record(&udid, "duplicate", &path, Err(&e));
+                                send_afc_status(&tx, &udid, format!("Duplicate error: {e}"));
+                            }
+                        }
+                    });
+                }
+
+                Command::AfcDelete { udid, path, source } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        let on_progress = |done: usize, total: usize, entry: &str| {
+                            let _ = tx.send(GuiEvent::AfcProgress {
+                                udid: udid.clone(),
+                                transfer: None,
+                                done: done as u64,
+                                total: total as u64,
+                                path: entry.to_string(),
+                            });
+                        };
+                        let res = afc_result(&tx, &udid, delete_path(&pool, &udid, &path, &source, on_progress).await);
+                        let outcome = match res {
+                            Ok(summary) if summary.failed.is_empty() => {
+                                send_listing(&pool, &tx, &udid, parent_dir(&path), &source).await;
+                                AfcOutcome::Deleted {
+                                    removed: summary.removed,
+                                }
+                            }
+                            Ok(summary) => {
+                                for (entry, e) in &summary.failed {
+                                    warn!(%entry, error = %e, "delete failed");
+                                }
+                                send_listing(&pool, &tx, &udid, parent_dir(&path), &source).await;
+                                let (first, e) = &summary.failed[0];
+                                AfcOutcome::Failed(format!(
+                                    "Deleted {} items from {path}, {} failed (first: {first}: {e})",
+                                    summary.removed,
+                                    summary.failed.len()
+                                ))
+                            }
+                            Err(e) => AfcOutcome::Failed(format!("Delete error: {e}")),
+                        };
+                        record_outcome(&udid, "delete", &path, &outcome);
+                        let _ = tx.send(GuiEvent::AfcOperationResult { udid, path, outcome });
+                    });
+                }
+
+                Command::AfcMkDir { udid, path, source } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        let res = afc_result(&tx, &udid, make_dir(&pool, &udid, &path, &source).await);
+                        let outcome = match res {
+                            Ok(()) => {
+                                send_listing(&pool, &tx, &udid, parent_dir(&path), &source).await;
+                                AfcOutcome::Created
+                            }
+                            Err(e) => AfcOutcome::Failed(format!("Couldn't create {path}: {e}")),
+                        };
+                        record_outcome(&udid, "mkdir", &path, &outcome);
+                        let _ = tx.send(GuiEvent::AfcOperationResult { udid, path, outcome });
+                    });
+                }
+
+                Command::AfcRename { udid, path, to, source } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        let res = afc_result(&tx, &udid, rename_path(&pool, &udid, &path, &to, &source).await);
+                        let outcome = match res {
+                            Ok(()) => {
+                                send_listing(&pool, &tx, &udid, parent_dir(&to), &source).await;
+                                AfcOutcome::Renamed { to }
+                            }
+                            Err(e) => AfcOutcome::Failed(format!("Couldn't rename {path}: {e}")),
+                        };
+                        record_outcome(&udid, "rename", &path, &outcome);
+                        let _ = tx.send(GuiEvent::AfcOperationResult { udid, path, outcome });
+                    });
+                }
+
+                Command::AfcPreview { udid, path, source } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        match preview_file(&pool, &udid, &path, &source).await {
+                            Ok((bytes, kind, modified)) => {
+                                let _ = tx.send(GuiEvent::AfcPreviewData {
+                                    udid,
+                                    path,
+                                    source,
+                                    bytes,
+                                    kind,
+                                    modified,
+                                });
+                            }
+                            Err(e) => {
+                                check_afc_connection(&tx, &udid, &*e);
+                                send_afc_status(&tx, &udid, format!("Preview error: {e}"));
+                            }
+                        }
+                    });
+                }
+
+                Command::AfcPreviewRange { udid, path, source, offset } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        match read_range(&pool, &udid, &path, &source, offset, PREVIEW_MAX_BYTES).await {
+                            Ok((bytes, size)) => {
+                                let _ = tx.send(GuiEvent::AfcPreviewRange { udid, path, offset, bytes, size });
+                            }
+                            Err(e) => {
+                                check_afc_connection(&tx, &udid, &*e);
+                                send_afc_status(&tx, &udid, format!("Preview error: {e}"));
+                            }
+                        }
+                    });
+                }
+
+                Command::AfcSaveText {
+                    udid,
+                    path,
+                    contents,
+                    source,
+                    expected_modified,
+                } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        let outcome =
+                            match save_text(&pool, &udid, &path, &contents, &source, expected_modified).await {
+                                Ok(modified) => AfcOutcome::Saved { modified },
+                                Err(e) if e.is::<SaveConflict>() => AfcOutcome::Conflict,
+                                Err(e) => {
+                                    warn!(error = %e, "saving text failed");
+                                    check_afc_connection(&tx, &udid, &*e);
+                                    AfcOutcome::Failed(format!("Save error: {e}"))
+                                }
+                            };
+                        record_outcome(&udid, "save", &path, &outcome);
+                        let _ = tx.send(GuiEvent::AfcOperationResult { udid, path, outcome });
+                    });
+                }
+
+                Command::AfcDisconnect { udid } => {
+                    pool.lock().unwrap().remove_afc(&udid);
+                }
+
+                Command::ProbeAfc2 { udid } => {
+                    let (device, pool, tx) = (udid.clone(), pool.clone(), tx.clone());
+                    jobs.spawn(&device, None, async move {
+                        match probe_afc2(&pool, &udid).await.map_err(|e| e.to_string()) {
+                            Ok(available) => {
+                                let _ = tx.send(GuiEvent::Afc2Available { udid, available });
+                            }
+                            Err(e) => warn!(error = %e, "AFC2 probe failed"),
+                        }
+                    });
+                }
+
+                Command::InstallApp { udid, ipa_path } => {
+                    let _ = tx.send(GuiEvent::Status(format!("Installing {}...", ipa_path.display())));
+                    let tx = tx.clone();
+                    // A large package takes minutes to stage and install, so
+                    // it runs beside the worker rather than holding it up
+                    jobs.spawn(&udid.clone(), Some(Operation::Install), async move {
+                        let res = install_app(&udid, &ipa_path, chunk_size, &tx)
+                            .await
+                            .map_err(|e| e.to_string());
+                        match res {
+                            Ok(()) => {
+                                let _ = tx.send(GuiEvent::Status(format!("Installed {}", ipa_path.display())));
+                            }
+                            Err(e) => {
+                                warn!(error = %e, "install failed");
+                                let _ = tx.send(GuiEvent::Status(format!("Install error: {e}")));
+                            }
+                        }
+                    });
+                }
+
+                Command::ListApps { udid } => match list_installed_apps(&udid).await {
+                    Ok(apps) => {
+                        let _ = tx.send(GuiEvent::InstalledApps { udid, apps });
+                    }
+                    Err(e) => {
+                        warn!(error = %e, "app lookup failed");
+                        send_afc_status(&tx, &udid, format!("Couldn't list installed apps: {e}"));
+                    }
+                },
+
+                Command::SetAfcChunkSize { bytes } => {
+                    chunk_size = bytes.unwrap_or(DEFAULT_CHUNK_SIZE);
+                }
+
+                Command::SetInfoFlattenMode { mode } => {
+                    flatten_mode = mode;
+                }
+
+                Command::SetMaxConcurrentTransfers { limit } => {
+                    transfers.set_limit(limit);
+                }
+
+                Command::CancelTransfer { id } => {
+                    transfers.cancel(&tx, id);
+                }
+
+                Command::SetUsbmuxdSocket { socket } => {
+                    let res = match set_usbmuxd_socket(&socket) {
+                        Ok(()) => {
+                            // Pooled connections and trust went through the old daemon
+                            pool.lock().unwrap().retain_devices(&[]);
+                            trust_cache.clear();
+                            check_usbmuxd().await
+                        }
+                        Err(e) => Err(format!("not a socket path or ip:port: {e}")),
+                    };
+                    let _ = tx.send(GuiEvent::UsbmuxdCheck(res));
+                }
+
+                Command::SetAfcTranscript { dir } => {
+                    if let Some(dir) = &dir {
+                        info!(dir = %dir.display(), "recording AFC transcript");
+                    }
+                    set_transcript_dir(dir);
+                }
+
+                Command::SetClientLabel { label } => {
+                    set_client_label(&label);
+                    // Pooled connections keep the label they were made with
+                    pool.lock().unwrap().retain_devices(&[]);
+                }
+
+                Command::SaveDeviceReport { udid, out_dir, format } => {
+                    match save_device_report(&pool, &out_dir, &udid, format, flatten_mode).await {
+                        Ok((info, path)) => {
+                            let _ = tx.send(GuiEvent::DeviceInfo { udid, info });
+                            reveal_in_file_browser(&path);
+                            let _ = tx.send(GuiEvent::Status(format!("Saved device report to {}", path.display())));
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "saving device report failed");
+                            let _ = tx.send(GuiEvent::Status(format!("Report error: {e}")));
+                        }
+                    }
+                }
+
+                Command::ListCrashReports { udid } => match list_crash_reports(&udid).await {
+                    Ok(reports) => {
+                        let _ = tx.send(GuiEvent::Status(format!("{} crash report(s) on {udid}", reports.len())));
+                        let _ = tx.send(GuiEvent::CrashReports { udid, reports });
+                    }
+                    Err(e) => {
+                        warn!(error = %e, "listing crash reports failed");
+                        let _ = tx.send(GuiEvent::Status(format!("Crash log error: {e}")));
+                    }
+                },
+
+                Command::DownloadCrashReports { udid, reports, out_dir, remove } => {
+                    match download_crash_reports(&tx, &udid, &reports, &out_dir, remove).await {
+                        Ok((dir, remaining)) => {
+                            reveal_in_file_browser(&dir);
+                            let _ = tx.send(GuiEvent::Status(format!(
+                                "Saved {} crash report(s) to {}",
+                                reports.len(),
+                                dir.display()
+                            )));
+                            let _ = tx.send(GuiEvent::CrashReports { udid, reports: remaining });
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "downloading crash reports failed");
+                            let _ = tx.send(GuiEvent::Status(format!("Crash log error: {e}")));
+                        }
+                    }
+                }
+
+                Command::ListProfiles { udid } => match list_profiles(&udid).await {
+                    Ok(profiles) => {
+                        let _ = tx.send(GuiEvent::Status(format!("{} profile(s) on {udid}", profiles.len())));
+                        let _ = tx.send(GuiEvent::Profiles { udid, profiles });
+                    }
+                    Err(e) => {
+                        warn!(error = %e, "listing profiles failed");
+                        let _ = tx.send(GuiEvent::Status(format!("Profile error: {e}")));
+                    }
+                },
+
+                Command::RemoveProfile { udid, identifier } => match remove_profile(&udid, &identifier).await {
+                    Ok(profiles) => {
+                        let _ = tx.send(GuiEvent::Status(format!("Removed profile {identifier}")));
+                        let _ = tx.send(GuiEvent::Profiles { udid, profiles });
+                    }
+                    Err(e) if matches!(e.downcast_ref::<IdeviceError>(), Some(IdeviceError::ProfileRemovalDisallowed)) => {
                         let _ = tx.send(GuiEvent::Status(format!(
-                            "Contents of {path}:\n{output}"
+                            "{identifier} is locked: it was installed as non-removable, usually by the \
+                             organization supervising the device. Only they can remove it."
                         )));
                     }
                     Err(e) => {
-                        let _ = tx.send(GuiEvent::Status(format!("AFC error: {e}")));
+                        warn!(error = %e, "removing profile failed");
+                        let _ = tx.send(GuiEvent::Status(format!("Profile error: {e}")));
                     }
+                },
+
+                Command::InstallProfile { udid, path } => {
+                    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                    match install_profile(&udid, &path).await {
+                        Ok((outcome, profiles)) => {
+                            let status = match outcome {
+                                ProfileInstall::Installed => format!("Installed profile {name}"),
+                                ProfileInstall::NeedsUserApproval => format!(
+                                    "{name} is waiting on the device: iOS 12.2 and later only install profiles \
+                                     from Settings, so open Settings > General > VPN & Device Management and \
+                                     install it from there"
+                                ),
+                            };
+                            let _ = tx.send(GuiEvent::Status(status));
+                            let _ = tx.send(GuiEvent::Profiles { udid, profiles });
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "installing profile failed");
+                            let _ = tx.send(GuiEvent::Status(format!("Couldn't install {name}: {e}")));
+                        }
+                    }
+                }
+
+                Command::SelfTest { out_dir } => {
+                    let _ = tx.send(GuiEvent::Status("Running self-test...".into()));
+                    let report = run_self_test(&pool, &tx, &out_dir).await;
+                    let failed = report.checks.iter().filter(|c| c.result.is_err()).count();
+                    let _ = tx.send(GuiEvent::Status(format!("Self-test done, {failed} check(s) failed")));
+                    let _ = tx.send(GuiEvent::SelfTestReport(report));
+                }
+
+                Command::RevealPath { path } => {
+                    reveal_in_file_browser(&path);
+                    let _ = tx.send(GuiEvent::Status(format!("Showing {}", path.display())));
+                }
+
+                Command::TrashPath { path } => {
+                    let msg = match trash::delete(&path) {
+                        Ok(()) => {
+                            let msg = format!("Moved {} to the trash", path.display());
+                            let _ = tx.send(GuiEvent::Trashed { path });
+                            msg
+                        }
+                        Err(e) => format!("Failed to trash {}: {e}", path.display()),
+                    };
+                    let _ = tx.send(GuiEvent::Status(msg));
                 }
             }
+        }
+        .instrument(span)
+        .await;
+    }
+}
 
-            Err(_) => break,
+/// Pair `udid`, telling the GUI it's trusted on success, then enable Wi-Fi
+/// sync and install the record system-wide if asked. The pairing stands
+/// whatever happens with those.
+async fn pair_and_report(
+    pool: &SharedPool,
+    tx: &Sender<GuiEvent>,
+    out_dir: &Path,
+    udid: &str,
+    enable_wifi: bool,
+    install_system: bool,
+) -> PairOutcome {
+    if let Err(e) = pair_one(pool, out_dir, udid).await {
+        warn!(error = %e, %udid, "pairing failed");
+        return match e.downcast_ref::<IdeviceError>() {
+            Some(IdeviceError::PasswordProtected | IdeviceError::DeviceLocked) => {
+                PairOutcome::Locked
+            }
+            Some(IdeviceError::DeviceNotFound) => PairOutcome::Failed(format!(
+                "{udid} isn't connected, or usbmuxd doesn't see it yet"
+            )),
+            _ => PairOutcome::Failed(e.to_string()),
+        };
+    }
+    let _ = tx.send(GuiEvent::Trust {
+        udid: udid.to_string(),
+        state: TrustState::Trusted,
+    });
+    let mut note = if !enable_wifi {
+        String::new()
+    } else {
+        match enable_wifi_sync(out_dir, udid).await {
+            Ok(true) => ", Wi-Fi sync enabled".to_string(),
+            Ok(false) => ", but the device doesn't support Wi-Fi sync".to_string(),
+            Err(e) => {
+                warn!(error = %e, %udid, "enabling Wi-Fi sync failed");
+                format!(", but enabling Wi-Fi sync failed: {e}")
+            }
+        }
+    };
+    if install_system {
+        match install_to_system_lockdown(out_dir, udid) {
+            Ok(path) => note.push_str(&format!(", installed to {}", path.display())),
+            Err(e) => {
+                warn!(error = %e, %udid, "installing pairing record system-wide failed");
+                note.push_str(&format!(", but installing it for other tools failed: {e}"));
+            }
+        }
+    }
+    PairOutcome::Paired { note }
+}
+
+/// Stop the transfers and device jobs of every device in `connected` that
+/// `udids` no longer lists, instead of leaving them to wait on a dead
+/// connection, then remember `udids` for the next scan.
+fn stop_gone_devices(
+    tx: &Sender<GuiEvent>,
+    transfers: &TransferQueue,
+    jobs: &DeviceJobs,
+    connected: &mut Vec<String>,
+    udids: &[String],
+) {
+    for gone in connected.iter().filter(|udid| !udids.contains(udid)) {
+        let stopped = transfers.cancel_device(tx, gone) + jobs.cancel_device(gone);
+        if stopped > 0 {
+            info!(udid = %gone, stopped, "device disconnected, operations stopped");
+            send_afc_status(tx, gone, format!("Device disconnected, stopped {stopped} operation(s)"));
+        }
+    }
+    *connected = udids.to_vec();
+}
+
+/// List `path` and report the entries, or the error, to the GUI.
+async fn send_listing(
+    pool: &SharedPool,
+    tx: &Sender<GuiEvent>,
+    udid: &str,
+    path: String,
+    source: &AfcSource,
+) {
+    let _ = tx.send(GuiEvent::Status(format!("Listing: {path}")));
+    match list_files(pool, udid, &path, source).await {
+        Ok(mut list) => {
+            let mut total = 0;
+            loop {
+                let entries: Vec<String> = list.by_ref().take(LISTING_BATCH).collect();
+                let done = entries.len() < LISTING_BATCH;
+                total += entries.len();
+                let _ = tx.send(GuiEvent::AfcListBatch {
+                    udid: udid.to_string(),
+                    path: path.clone(),
+                    entries,
+                    done,
+                });
+                if done {
+                    break;
+                }
+            }
+            send_afc_status(tx, udid, format!("{total} entries in {path}"));
+            let _ = tx.send(GuiEvent::AfcConnection {
+                udid: udid.to_string(),
+                connected: true,
+            });
+        }
+        Err(e) if e.is::<AppUnavailable>() => send_afc_status(tx, udid, e.to_string()),
+        Err(e) => {
+            check_afc_connection(tx, udid, &*e);
+            send_afc_status(tx, udid, format!("AFC error: {e}"));
+        }
+    }
+}
+
+/// Directory containing the remote `path`.
+fn parent_dir(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some(("", _)) | None => "/".to_string(),
+        Some((parent, _)) => parent.to_string(),
+    }
+}
+
+/// Status line for the Files view showing `udid`.
+fn send_afc_status(tx: &Sender<GuiEvent>, udid: &str, message: String) {
+    let _ = tx.send(GuiEvent::AfcStatus {
+        udid: udid.to_string(),
+        message,
+    });
+}
+
+/// Flag `udid`'s AFC connection as broken if `e` came from the connection itself.
+fn check_afc_connection(tx: &Sender<GuiEvent>, udid: &str, e: &(dyn std::error::Error + 'static)) {
+    if is_connection_error(e) {
+        let _ = tx.send(GuiEvent::AfcConnection {
+            udid: udid.to_string(),
+            connected: false,
+        });
+    }
+}
+
+/// `res` with its error as text, after reporting a lost AFC connection. The
+/// boxed error isn't `Send`, so device tasks convert it before awaiting again.
+fn afc_result<T>(
+    tx: &Sender<GuiEvent>,
+    udid: &str,
+    res: Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, String> {
+    res.map_err(|e| {
+        check_afc_connection(tx, udid, &*e);
+        e.to_string()
+    })
+}
+
+/// Record a Files view operation's outcome in the AFC transcript.
+fn record_outcome(udid: &str, op: &str, path: &str, outcome: &AfcOutcome) {
+    let result = match outcome {
+        AfcOutcome::Saved { modified } => Ok(format!("saved, modified {modified}")),
+        AfcOutcome::Created => Ok("created".to_string()),
+        AfcOutcome::Renamed { to } => Ok(format!("renamed to {to}")),
+        AfcOutcome::Deleted { removed } => Ok(format!("deleted {removed} item(s)")),
+        AfcOutcome::Conflict => {
+            Err("changed on the device since it was opened, left alone".to_string())
+        }
+        AfcOutcome::Failed(e) => Err(e.clone()),
+    };
+    transcript::record(udid, op, path, result.as_deref().map_err(String::as_str));
+}
+
+/// Queue a download of `remote_path` into `out_dir`; its outcome is reported
+/// to the GUI when it finishes.
+#[allow(clippy::too_many_arguments)]
+fn queue_download(
+    transfers: &TransferQueue,
+    pool: &SharedPool,
+    tx: &Sender<GuiEvent>,
+    udid: String,
+    remote_path: String,
+    source: AfcSource,
+    out_dir: PathBuf,
+    chunk_size: usize,
+    resume: bool,
+) {
+    let device = udid.clone();
+    let label = format!("Download {remote_path}");
+    let (pool, events) = (pool.clone(), tx.clone());
+    transfers.spawn(
+        tx,
+        &device,
+        label,
+        Operation::Download,
+        move |id| async move {
+            let progress = progress_reporter(&events, &udid, id, &remote_path);
+            let res = send_download(
+                &pool,
+                &events,
+                &udid,
+                remote_path.clone(),
+                &source,
+                &out_dir,
+                chunk_size,
+                resume,
+                progress,
+            )
+            .await;
+            transcript::record(
+                &udid,
+                "download",
+                &remote_path,
+                res.as_deref().map_err(String::as_str),
+            );
+            res
+        },
+    );
+}
+
+/// Download `remote_path` into `out_dir` and report the outcome to the GUI.
+#[allow(clippy::too_many_arguments)]
+async fn send_download(
+    pool: &SharedPool,
+    tx: &Sender<GuiEvent>,
+    udid: &str,
+    remote_path: String,
+    source: &AfcSource,
+    out_dir: &Path,
+    chunk_size: usize,
+    resume: bool,
+    on_progress: impl FnMut(u64, u64),
+) -> Result<String, String> {
+    match download_file(
+        pool,
+        udid,
+        &remote_path,
+        source,
+        out_dir,
+        chunk_size,
+        resume,
+        on_progress,
+    )
+    .await
+    {
+        Ok((local_path, resumed_at)) => {
+            let message = if resumed_at > 0 {
+                format!(
+                    "Resumed at {resumed_at} bytes, saved to {}",
+                    local_path.display()
+                )
+            } else {
+                format!("Saved to {}", local_path.display())
+            };
+            let _ = tx.send(GuiEvent::AfcDownloaded {
+                udid: udid.to_string(),
+                remote_path,
+                local_path,
+            });
+            Ok(message)
+        }
+        Err(e) => {
+            check_afc_connection(tx, udid, &*e);
+            send_afc_status(tx, udid, format!("Download error: {e}"));
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Span for one command, tagged with the device and path it concerns so
+/// concurrent work on several devices can be told apart in the logs.
+fn command_span(cmd: &Command) -> Span {
+    match cmd {
+        Command::Refresh { probe_trust } => info_span!("refresh", probe_trust),
+        Command::CheckDevices => info_span!("check_devices"),
+        Command::Pair { udid, .. } => info_span!("pair", %udid),
+        Command::PairAll { udids, .. } => info_span!("pair_all", count = udids.len()),
+        Command::ImportPairingFile { udid, .. } => info_span!("import_pairing", %udid),
+        Command::Unpair { udid, .. } => info_span!("unpair", %udid),
+        Command::GetDeviceInfo { udid } => info_span!("device_info", %udid),
+        Command::EnableDeveloperMode { udid } => info_span!("enable_developer_mode", %udid),
+        Command::AfcList { udid, path, .. } => info_span!("afc_list", %udid, %path),
+        Command::AppDocuments { udid, bundle_id } => info_span!("app_documents", %udid, %bundle_id),
+        Command::AfcUpload {
+            udid, remote_path, ..
+        } => info_span!("afc_upload", %udid, path = %remote_path),
+        Command::AfcUploadFromUrl {
+            udid, url, remote, ..
+        } => {
+            info_span!("afc_upload_from_url", %udid, %url, path = %remote)
+        }
+        Command::AfcDownload {
+            udid, remote_path, ..
+        } => info_span!("afc_download", %udid, path = %remote_path),
+        Command::AfcDownloadZip {
+            udid, dir, names, ..
+        } => {
+            info_span!("afc_download_zip", %udid, %dir, count = names.len())
+        }
+        Command::AfcOpen { udid, path, .. } => info_span!("afc_open", %udid, %path),
+        Command::AfcDuplicate { udid, path, .. } => info_span!("afc_duplicate", %udid, %path),
+        Command::AfcMkDir { udid, path, .. } => info_span!("afc_mkdir", %udid, %path),
+        Command::AfcRename { udid, path, to, .. } => info_span!("afc_rename", %udid, %path, %to),
+        Command::AfcDelete { udid, path, .. } => info_span!("afc_delete", %udid, %path),
+        Command::AfcPreview { udid, path, .. } => info_span!("afc_preview", %udid, %path),
+        Command::AfcPreviewRange {
+            udid, path, offset, ..
+        } => info_span!("afc_preview_range", %udid, %path, offset),
+        Command::AfcSaveText { udid, path, .. } => info_span!("afc_save_text", %udid, %path),
+        Command::AfcDisconnect { udid } => info_span!("afc_disconnect", %udid),
+        Command::ProbeAfc2 { udid } => info_span!("probe_afc2", %udid),
+        Command::InstallApp { udid, ipa_path } => {
+            info_span!("install", %udid, ipa = %ipa_path.display())
+        }
+        Command::ListApps { udid } => info_span!("list_apps", %udid),
+        Command::ListCrashReports { udid } => info_span!("list_crash_reports", %udid),
+        Command::DownloadCrashReports { udid, reports, .. } => {
+            info_span!("download_crash_reports", %udid, count = reports.len())
+        }
+        Command::ListProfiles { udid } => info_span!("list_profiles", %udid),
+        Command::RemoveProfile { udid, identifier } => {
+            info_span!("remove_profile", %udid, %identifier)
+        }
+        Command::InstallProfile { udid, path } => {
+            info_span!("install_profile", %udid, path = %path.display())
+        }
+        Command::SetAfcChunkSize { bytes } => info_span!("set_chunk_size", ?bytes),
+        Command::SetInfoFlattenMode { mode } => info_span!("set_info_flatten_mode", ?mode),
+        Command::SetMaxConcurrentTransfers { limit } => info_span!("set_max_transfers", limit),
+        Command::CancelTransfer { id } => info_span!("cancel_transfer", id),
+        Command::SetUsbmuxdSocket { socket } => info_span!("set_usbmuxd_socket", %socket),
+        Command::SetClientLabel { label } => info_span!("set_client_label", %label),
+        Command::SetAfcTranscript { dir } => info_span!("set_afc_transcript", ?dir),
+        Command::SaveDeviceReport { udid, format, .. } => {
+            info_span!("device_report", %udid, ?format)
         }
+        Command::SelfTest { .. } => info_span!("self_test"),
+        Command::RevealPath { path } => info_span!("reveal", path = %path.display()),
+        Command::TrashPath { path } => info_span!("trash", path = %path.display()),
     }
 }
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 
 use clap::{value_parser, Arg, Command};
 use idevice::{
-    afc::{opcode::AfcFopenMode, AfcClient},
+    afc::{glob, opcode::AfcFopenMode, AfcClient},
     house_arrest::HouseArrestClient,
     IdeviceService,
 };
@@ -57,7 +57,19 @@ async fn main() {
         .subcommand(
             Command::new("list")
                 .about("Lists the items in the directory")
-                .arg(Arg::new("path").required(true).index(1)),
+                .arg(Arg::new("path").required(true).index(1))
+                .arg(
+                    Arg::new("glob")
+                        .long("glob")
+                        .value_name("PATTERN")
+                        .help("Only show entries matching a wildcard pattern, e.g. '*.jpg'"),
+                )
+                .arg(
+                    Arg::new("case_sensitive")
+                        .long("case-sensitive")
+                        .help("Match the glob pattern case-sensitively")
+                        .action(clap::ArgAction::SetTrue),
+                ),
         )
         .subcommand(
             Command::new("download")
@@ -89,7 +101,13 @@ async fn main() {
         .subcommand(
             Command::new("remove_all")
                 .about("Remove a provisioning profile")
-                .arg(Arg::new("path").required(true).index(1)),
+                .arg(Arg::new("path").required(true).index(1))
+                .arg(
+                    Arg::new("progress")
+                        .long("progress")
+                        .help("Remove one entry at a time, printing each and continuing past failures")
+                        .action(clap::ArgAction::SetTrue),
+                ),
         )
         .subcommand(
             Command::new("info")
@@ -140,7 +158,19 @@ async fn main() {
     if let Some(matches) = matches.subcommand_matches("list") {
         let path = matches.get_one::<String>("path").expect("No path passed");
         let res = afc_client.list_dir(path).await.expect("Failed to read dir");
-        println!("{path}\n{res:#?}");
+        match matches.get_one::<String>("glob") {
+            Some(pattern) => {
+                let case_sensitive = matches.get_flag("case_sensitive");
+                let total = res.len();
+                let res: Vec<String> = res
+                    .into_iter()
+                    .filter(|name| glob::matches(pattern, name, case_sensitive))
+                    .collect();
+                println!("{path}\n{res:#?}");
+                println!("{} of {total} entries match {pattern}", res.len());
+            }
+            None => println!("{path}\n{res:#?}"),
+        }
     } else if let Some(matches) = matches.subcommand_matches("mkdir") {
         let path = matches.get_one::<String>("path").expect("No path passed");
         afc_client.mk_dir(path).await.expect("Failed to mkdir");
@@ -161,19 +191,36 @@ async fn main() {
         let file = matches.get_one::<PathBuf>("file").expect("No path passed");
         let path = matches.get_one::<String>("path").expect("No path passed");
 
-        let bytes = tokio::fs::read(file).await.expect("Failed to read file");
-        let mut file = afc_client
-            .open(path, AfcFopenMode::WrOnly)
+        let local = tokio::fs::File::open(file)
             .await
-            .expect("Failed to open");
-
-        file.write(&bytes).await.expect("Failed to upload bytes");
+            .expect("Failed to open file");
+        let len = local.metadata().await.expect("Failed to stat file").len();
+        afc_client
+            .upload_reader(path, local, len, |_, _| {})
+            .await
+            .expect("Failed to upload file");
     } else if let Some(matches) = matches.subcommand_matches("remove") {
         let path = matches.get_one::<String>("path").expect("No path passed");
         afc_client.remove(path).await.expect("Failed to remove");
     } else if let Some(matches) = matches.subcommand_matches("remove_all") {
         let path = matches.get_one::<String>("path").expect("No path passed");
-        afc_client.remove_all(path).await.expect("Failed to remove");
+        if matches.get_flag("progress") {
+            let summary = afc_client
+                .remove_all_with_progress(path, |done, total, entry| {
+                    println!("[{done}/{total}] {entry}");
+                })
+                .await
+                .expect("Failed to remove");
+            println!("Removed {} entries", summary.removed);
+            for (entry, e) in &summary.failed {
+                eprintln!("Failed: {entry}: {e}");
+            }
+            if !summary.failed.is_empty() {
+                std::process::exit(1);
+            }
+        } else {
+            afc_client.remove_all(path).await.expect("Failed to remove");
+        }
     } else if let Some(matches) = matches.subcommand_matches("info") {
         let path = matches.get_one::<String>("path").expect("No path passed");
         let res = afc_client
@@ -1,7 +1,11 @@
 // Jackson Coxson
 
 use clap::{Arg, Command};
-use idevice::{amfi::AmfiClient, IdeviceService};
+use idevice::{
+    amfi::AmfiClient,
+    lockdown::{DeveloperModeStatus, LockdownClient},
+    IdeviceService,
+};
 
 mod common;
 
@@ -35,6 +39,7 @@ async fn main() {
                 .help("Show about information")
                 .action(clap::ArgAction::SetTrue),
         )
+        .subcommand(Command::new("status").about("Shows whether developer mode is on"))
         .subcommand(Command::new("show").about("Shows the developer mode option in settings"))
         .subcommand(Command::new("enable").about("Enables developer mode"))
         .subcommand(Command::new("accept").about("Shows the accept dialogue for developer mode"))
@@ -58,6 +63,33 @@ async fn main() {
         }
     };
 
+    if matches.subcommand_matches("status").is_some() {
+        let mut lockdown_client = LockdownClient::connect(&*provider)
+            .await
+            .expect("Failed to connect to lockdown");
+        lockdown_client
+            .start_session(
+                &provider
+                    .get_pairing_file()
+                    .await
+                    .expect("Failed to get pairing file"),
+            )
+            .await
+            .expect("Failed to start session");
+        let status = lockdown_client
+            .developer_mode_status()
+            .await
+            .expect("Failed to get developer mode status");
+        match status {
+            DeveloperModeStatus::Enabled => println!("Developer mode is enabled"),
+            DeveloperModeStatus::Disabled => println!("Developer mode is disabled"),
+            DeveloperModeStatus::NotApplicable => {
+                println!("This device predates developer mode")
+            }
+        }
+        return;
+    }
+
     let mut amfi_client = AmfiClient::connect(&*provider)
         .await
         .expect("Failed to connect to amfi");
@@ -12,12 +12,24 @@ use idevice::{
     usbmuxd::{UsbmuxdAddr, UsbmuxdConnection},
 };
 
+/// Overrides the label every tool gives its connections
+pub const LABEL_ENV: &str = "IDEVICE_LABEL";
+
+/// `label`, unless `IDEVICE_LABEL` is set. usbmuxd and lockdown log the
+/// label of each connection, so scripts can give a run its own.
+pub fn provider_label(label: &str) -> String {
+    std::env::var(LABEL_ENV).unwrap_or_else(|_| label.to_string())
+}
+
+// selftest and unpair only use the label
+#[allow(dead_code)]
 pub async fn get_provider(
     udid: Option<&String>,
     host: Option<&String>,
     pairing_file: Option<&String>,
     label: &str,
 ) -> Result<Box<dyn IdeviceProvider>, String> {
+    let label = provider_label(label);
     let provider: Box<dyn IdeviceProvider> = if udid.is_some() {
         let udid = udid.unwrap();
 
@@ -57,7 +69,7 @@ pub async fn get_provider(
         Box::new(TcpProvider {
             addr: host,
             pairing_file,
-            label: "ideviceinfo-jkcoxson".to_string(),
+            label,
         })
     } else {
         let mut usbmuxd = if let Ok(var) = std::env::var("USBMUXD_SOCKET_ADDRESS") {
@@ -1,20 +1,32 @@
 // Jackson Coxson
 
 use std::{
+    collections::HashMap,
     io::Write,
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     str::FromStr,
+    time::Duration,
 };
 
-use clap::{Arg, Command};
+use clap::{value_parser, Arg, Command};
 use idevice::{
-    core_device_proxy::CoreDeviceProxy, debug_proxy::DebugProxyClient,
-    tunneld::get_tunneld_devices, xpc::XPCDevice, IdeviceService, ReadWrite,
+    core_device_proxy::CoreDeviceProxy,
+    debug_proxy::DebugProxyClient,
+    tunneld::{get_tunneld_devices, TunneldDevice, DEFAULT_PORT},
+    xpc::XPCDevice,
+    IdeviceService, ReadWrite,
 };
-use tokio::net::TcpStream;
+use tokio::{io::AsyncWriteExt, net::TcpStream};
 
 mod common;
 
+/// Attempts at reaching tunneld, which may still be starting up
+const TUNNELD_ATTEMPTS: u32 = 3;
+const TUNNELD_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How long the stream must stay quiet after a timed-out command before the
+/// shell takes the next one
+const DRAIN_IDLE: Duration = Duration::from_millis(500);
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
@@ -45,6 +57,20 @@ async fn main() {
                 .help("Use tunneld")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("tunneld_port")
+                .long("tunneld-port")
+                .value_name("PORT")
+                .help("Port tunneld listens on, if not the default")
+                .value_parser(value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("MS")
+                .help("Give up on a command after this many milliseconds; 0 waits forever")
+                .value_parser(value_parser!(u64)),
+        )
         .arg(
             Arg::new("about")
                 .long("about")
@@ -64,13 +90,17 @@ async fn main() {
     let host = matches.get_one::<String>("host");
 
     let mut dp: DebugProxyClient<Box<dyn ReadWrite>> = if matches.get_flag("tunneld") {
-        let socket = SocketAddr::new(
-            IpAddr::from_str("127.0.0.1").unwrap(),
-            idevice::tunneld::DEFAULT_PORT,
-        );
-        let mut devices = get_tunneld_devices(socket)
-            .await
-            .expect("Failed to get tunneld devices");
+        let port = matches
+            .get_one::<u16>("tunneld_port")
+            .copied()
+            .unwrap_or(DEFAULT_PORT);
+        let mut devices = match tunneld_devices(port).await {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
 
         let (_udid, device) = match udid {
             Some(u) => (
@@ -137,23 +167,120 @@ async fn main() {
         DebugProxyClient::new(Box::new(adapter))
     };
 
-    println!("Shell connected!");
+    let mut timeout = matches
+        .get_one::<u64>("timeout")
+        .copied()
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis);
+
+    println!("Shell connected! `timeout <ms>` limits how long a command may take");
     loop {
         print!("> ");
         std::io::stdout().flush().unwrap();
 
         let mut buf = String::new();
-        std::io::stdin().read_line(&mut buf).unwrap();
+        match std::io::stdin().read_line(&mut buf) {
+            // End of piped input, e.g. `echo info | debug_proxy`
+            Ok(0) => {
+                println!();
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to read stdin: {e}");
+                break;
+            }
+        }
 
         let buf = buf.trim();
 
         if buf == "exit" {
             break;
         }
+        if let Some(arg) = buf.strip_prefix("timeout") {
+            match arg.trim() {
+                "" => match timeout {
+                    Some(t) => println!("timeout is {}ms", t.as_millis()),
+                    None => println!("no timeout"),
+                },
+                ms => match ms.parse::<u64>() {
+                    Ok(0) => timeout = None,
+                    Ok(ms) => timeout = Some(Duration::from_millis(ms)),
+                    Err(_) => println!("usage: timeout <ms>, 0 for none"),
+                },
+            }
+            continue;
+        }
 
-        let res = dp.send_command(buf.into()).await.expect("Failed to send");
-        if let Some(res) = res {
+        let send = dp.send_command(buf.into());
+        let res = match timeout {
+            Some(t) => match tokio::time::timeout(t, send).await {
+                Ok(res) => res,
+                Err(_) => {
+                    // The reply may still be on its way; left unread it would
+                    // be taken as the answer to the next command
+                    println!("command timed out, discarding any late reply");
+                    match drain(&mut dp).await {
+                        Ok(0) => {}
+                        Ok(n) => println!("discarded {n} bytes"),
+                        Err(e) => {
+                            eprintln!("{e}");
+                            break;
+                        }
+                    }
+                    println!(
+                        "A reply arriving later would still be out of step; \
+                         restart the session if answers stop matching commands"
+                    );
+                    continue;
+                }
+            },
+            None => send.await,
+        };
+        if let Some(res) = res.expect("Failed to send") {
             println!("{res}");
         }
     }
+
+    std::io::stdout().flush().unwrap();
+    if let Err(e) = dp.into_inner().shutdown().await {
+        log::debug!("Failed to close the debug proxy connection: {e:?}");
+    }
+}
+
+/// Read and throw away whatever arrives until the stream has been quiet for
+/// `DRAIN_IDLE`, returning how many bytes were dropped
+async fn drain(dp: &mut DebugProxyClient<Box<dyn ReadWrite>>) -> Result<usize, String> {
+    let mut discarded = 0;
+    while let Ok(res) = tokio::time::timeout(DRAIN_IDLE, dp.read(1024)).await {
+        let chunk = res.map_err(|e| format!("Failed to read from the debug proxy: {e:?}"))?;
+        if chunk.is_empty() {
+            return Err("The debug proxy closed the connection".to_string());
+        }
+        discarded += chunk.len();
+    }
+    Ok(discarded)
+}
+
+/// Fetch tunneld's devices, retrying briefly in case it's still starting
+async fn tunneld_devices(port: u16) -> Result<HashMap<String, TunneldDevice>, String> {
+    let socket = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut attempt = 1;
+    loop {
+        match get_tunneld_devices(socket).await {
+            Ok(devices) => return Ok(devices),
+            Err(e) if attempt >= TUNNELD_ATTEMPTS => {
+                return Err(format!(
+                    "tunneld isn't reachable at {socket}: {e}\n\
+                     Start it with `sudo pymobiledevice3 remote tunneld`, \
+                     or pass --tunneld-port if it listens on another port"
+                ));
+            }
+            Err(e) => {
+                log::debug!("tunneld attempt {attempt}/{TUNNELD_ATTEMPTS} failed: {e:?}");
+                tokio::time::sleep(TUNNELD_RETRY_DELAY).await;
+                attempt += 1;
+            }
+        }
+    }
 }
@@ -0,0 +1,137 @@
+// Jackson Coxson
+// Converts pairing files between binary and XML plists
+
+use std::path::PathBuf;
+
+use clap::{value_parser, Arg, Command};
+use idevice::pairing_file::PairingFile;
+
+/// Keys every pairing record needs before lockdown will accept it
+const REQUIRED_KEYS: &[&str] = &[
+    "DeviceCertificate",
+    "HostCertificate",
+    "HostPrivateKey",
+    "RootCertificate",
+    "RootPrivateKey",
+    "HostID",
+    "SystemBUID",
+    "EscrowBag",
+    "WiFiMACAddress",
+];
+
+fn main() {
+    env_logger::init();
+
+    let matches = Command::new("pairconvert")
+        .about("Convert a pairing file between binary and XML plist formats")
+        .arg(
+            Arg::new("input")
+                .value_name("INPUT")
+                .help("Pairing file to read, in either format")
+                .value_parser(value_parser!(PathBuf))
+                .index(1),
+        )
+        .arg(
+            Arg::new("output")
+                .value_name("OUTPUT")
+                .help("Where to write the converted file; stdout if omitted")
+                .value_parser(value_parser!(PathBuf))
+                .index(2),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Format to write")
+                .value_parser(["binary", "xml"])
+                .default_value("xml"),
+        )
+        .arg(
+            Arg::new("about")
+                .long("about")
+                .help("Show about information")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .get_matches();
+
+    if matches.get_flag("about") {
+        println!("pairconvert - convert pairing files between binary and XML plists");
+        println!("Copyright (c) 2025 Jackson Coxson");
+        return;
+    }
+
+    let Some(input) = matches.get_one::<PathBuf>("input") else {
+        eprintln!("No input file given, pass -h for help");
+        std::process::exit(1);
+    };
+    let output = matches.get_one::<PathBuf>("output");
+    let binary = matches.get_one::<String>("format").map(String::as_str) == Some("binary");
+
+    // plist detects binary vs XML from the header on its own
+    let value = match plist::Value::from_file(input) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to read {} as a plist: {e}", input.display());
+            std::process::exit(1);
+        }
+    };
+    let Some(dict) = value.as_dictionary() else {
+        eprintln!(
+            "{} is not a pairing file: the root is not a dictionary",
+            input.display()
+        );
+        std::process::exit(1);
+    };
+    let missing: Vec<&str> = REQUIRED_KEYS
+        .iter()
+        .copied()
+        .filter(|k| !dict.contains_key(k))
+        .collect();
+    if !missing.is_empty() {
+        eprintln!(
+            "{} is missing required keys: {}",
+            input.display(),
+            missing.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let pairing_file = match PairingFile::read_from_file(input) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Invalid pairing file: {e:?}");
+            std::process::exit(1);
+        }
+    };
+    if pairing_file.udid.is_none() {
+        eprintln!("Warning: the pairing file has no UDID; some tools need one to pick the device");
+    }
+
+    let mut data = pairing_file
+        .serialize()
+        .expect("Failed to serialize pairing file");
+    if binary {
+        let value =
+            plist::Value::from_reader_xml(data.as_slice()).expect("Serialized plist was invalid");
+        data.clear();
+        value
+            .to_writer_binary(&mut data)
+            .expect("Failed to write binary plist");
+    }
+
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, &data) {
+                eprintln!("Unable to write {}: {e}", path.display());
+                std::process::exit(1);
+            }
+            println!("Wrote {}", path.display());
+        }
+        None => {
+            use std::io::Write;
+            std::io::stdout()
+                .write_all(&data)
+                .expect("Failed to write to stdout");
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// Jackson Coxson
+// Checks the environment idevice needs, for attaching to bug reports
+
+mod common;
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use clap::{Arg, Command};
+use idevice::{
+    lockdown::LockdownClient,
+    provider::IdeviceProvider,
+    tunneld::{get_tunneld_devices, DEFAULT_PORT},
+    usbmuxd::{UsbmuxdAddr, UsbmuxdConnection},
+    IdeviceError, IdeviceService,
+};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let matches = Command::new("selftest")
+        .about(
+            "Check usbmuxd, tunneld and a save directory, and list devices with their trust state",
+        )
+        .arg(
+            Arg::new("output_dir")
+                .long("output-dir")
+                .value_name("PATH")
+                .help("Directory pairing files are saved to; checked for write access"),
+        )
+        .arg(
+            Arg::new("about")
+                .long("about")
+                .help("Show about information")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .get_matches();
+
+    if matches.get_flag("about") {
+        println!("selftest - diagnose the host environment for bug reports");
+        println!("Copyright (c) 2025 Jackson Coxson");
+        return;
+    }
+
+    println!(
+        "idevice-tools {} on {} {}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let mut failed = false;
+
+    let devices = match UsbmuxdConnection::default().await {
+        Ok(mut mux) => mux.get_devices().await,
+        Err(e) => Err(e),
+    };
+    let res = devices
+        .as_ref()
+        .map(|d| format!("{} device(s)", d.len()))
+        .map_err(|e| e.to_string());
+    failed |= report("usbmuxd reachable", res);
+
+    let tunneld = SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_PORT));
+    let res = get_tunneld_devices(tunneld)
+        .await
+        .map(|d| format!("{} tunneled device(s) at {tunneld}", d.len()))
+        .map_err(|e| format!("{tunneld}: {e}"));
+    failed |= report("tunneld reachable", res);
+
+    if let Some(dir) = matches.get_one::<String>("output_dir") {
+        failed |= report(
+            "Save directory writable",
+            check_writable(PathBuf::from(dir)),
+        );
+    }
+
+    let devices = devices.unwrap_or_default();
+    println!("\nDevices ({}):", devices.len());
+    for dev in devices {
+        let provider = dev.to_provider(
+            UsbmuxdAddr::from_env_var().unwrap(),
+            common::provider_label("selftest-jkcoxson"),
+        );
+        let trust = match trust_state(&provider).await {
+            Ok(state) => state.to_string(),
+            Err(e) => format!("unknown ({e})"),
+        };
+        println!("  {}  {:?}  {trust}", dev.udid, dev.connection_type);
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Print one check's result, returning whether it failed
+fn report(name: &str, result: Result<String, String>) -> bool {
+    match result {
+        Ok(detail) => {
+            println!("[ok  ] {name}: {detail}");
+            false
+        }
+        Err(detail) => {
+            println!("[FAIL] {name}: {detail}");
+            true
+        }
+    }
+}
+
+fn check_writable(dir: PathBuf) -> Result<String, String> {
+    let probe = dir.join(".selftest_write_test");
+    std::fs::write(&probe, b"").map_err(|e| format!("{}: {e}", dir.display()))?;
+    let _ = std::fs::remove_file(probe);
+    Ok(dir.display().to_string())
+}
+
+/// Whether usbmuxd's pairing record for the device opens a lockdown session
+async fn trust_state(provider: &dyn IdeviceProvider) -> Result<&'static str, IdeviceError> {
+    let pairing_file = match provider.get_pairing_file().await {
+        Ok(p) => p,
+        Err(_) => return Ok("untrusted (no pairing record)"),
+    };
+    let mut lockdown = LockdownClient::connect(provider).await?;
+    match lockdown.start_session(&pairing_file).await {
+        Ok(()) => Ok("trusted"),
+        Err(IdeviceError::PasswordProtected | IdeviceError::DeviceLocked) => Ok("locked"),
+        Err(IdeviceError::InvalidHostID) => Ok("untrusted (pairing record rejected)"),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,90 @@
+// Jackson Coxson
+
+mod common;
+
+use clap::{Arg, Command};
+use idevice::{
+    lockdown::LockdownClient,
+    pairing_file::PairingFile,
+    provider::IdeviceProvider,
+    usbmuxd::{Connection, UsbmuxdAddr, UsbmuxdConnection},
+    IdeviceService,
+};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let matches = Command::new("unpair")
+        .about("Remove the device's trust in a pairing record")
+        .arg(
+            Arg::new("udid")
+                .value_name("UDID")
+                .help("UDID of the device")
+                .index(1),
+        )
+        .arg(
+            Arg::new("pairing_file")
+                .long("pairing-file")
+                .value_name("PATH")
+                .help("Pairing file to revoke (defaults to usbmuxd's record for the device)"),
+        )
+        .arg(
+            Arg::new("about")
+                .long("about")
+                .help("Show about information")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .get_matches();
+
+    if matches.get_flag("about") {
+        println!("unpair - remove the device's trust in a pairing record");
+        println!("Copyright (c) 2025 Jackson Coxson");
+        return;
+    }
+
+    let udid = matches.get_one::<String>("udid");
+
+    let mut u = UsbmuxdConnection::default()
+        .await
+        .expect("Failed to connect to usbmuxd");
+    let dev = match udid {
+        Some(udid) => u
+            .get_device(udid)
+            .await
+            .expect("Failed to get device with specific udid"),
+        None => u
+            .get_devices()
+            .await
+            .expect("Failed to get devices")
+            .into_iter()
+            .find(|x| x.connection_type == Connection::Usb)
+            .expect("No devices connected via USB"),
+    };
+    let provider = dev.to_provider(
+        UsbmuxdAddr::from_env_var().unwrap(),
+        common::provider_label("unpair-jkcoxson"),
+    );
+
+    let pairing_file = match matches.get_one::<String>("pairing_file") {
+        Some(path) => PairingFile::read_from_file(path).expect("Failed to read pairing file"),
+        None => provider
+            .get_pairing_file()
+            .await
+            .expect("No pairing record for the device in usbmuxd"),
+    };
+
+    let mut lockdown_client = match LockdownClient::connect(&provider).await {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("Unable to connect to lockdown: {e:?}");
+            return;
+        }
+    };
+
+    lockdown_client
+        .unpair(&pairing_file)
+        .await
+        .expect("Failed to unpair");
+    println!("Unpaired {}", dev.udid);
+}